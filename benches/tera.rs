@@ -118,23 +118,50 @@ fn bench_rendering_only_variable(b: &mut test::Bencher) {
     let mut tera = Tera::default();
     tera.add_raw_template("test.html", VARIABLE_ONLY).unwrap();
     let mut context = Context::new();
-    context.add("product", &Product::new());
-    context.add("username", &"bob");
+    context.insert("product", &Product::new());
+    context.insert("username", &"bob");
 
     b.iter(|| tera.render("test.html", context.clone()));
 }
 
+#[bench]
+fn bench_rendering_only_variable_to_writer(b: &mut test::Bencher) {
+    // Goes through the same path as templates with inheritance, to compare with `bench_rendering_only_variable`
+    let mut tera = Tera::default();
+    tera.add_raw_template("test.html", VARIABLE_ONLY).unwrap();
+    let mut context = Context::new();
+    context.insert("product", &Product::new());
+    context.insert("username", &"bob");
+
+    b.iter(|| {
+        let mut output = Vec::new();
+        tera.render_to("test.html", context.clone(), &mut output).unwrap();
+        output
+    });
+}
+
 #[bench]
 fn bench_rendering_basic_template(b: &mut test::Bencher) {
     let mut tera = Tera::default();
     tera.add_raw_template("bench.html", SIMPLE_TEMPLATE).unwrap();
     let mut context = Context::new();
-    context.add("product", &Product::new());
-    context.add("username", &"bob");
+    context.insert("product", &Product::new());
+    context.insert("username", &"bob");
 
     b.iter(|| tera.render("bench.html", context.clone()));
 }
 
+#[bench]
+fn bench_rendering_basic_template_borrowed_context(b: &mut test::Bencher) {
+    let mut tera = Tera::default();
+    tera.add_raw_template("bench.html", SIMPLE_TEMPLATE).unwrap();
+    let mut context = Context::new();
+    context.insert("product", &Product::new());
+    context.insert("username", &"bob");
+
+    b.iter(|| tera.render_ref("bench.html", &context));
+}
+
 #[bench]
 fn bench_rendering_only_parent(b: &mut test::Bencher) {
     let mut tera = Tera::default();
@@ -142,8 +169,8 @@ fn bench_rendering_only_parent(b: &mut test::Bencher) {
         ("parent.html", PARENT_TEMPLATE),
     ]).unwrap();
     let mut context = Context::new();
-    context.add("product", &Product::new());
-    context.add("username", &"bob");
+    context.insert("product", &Product::new());
+    context.insert("username", &"bob");
 
     b.iter(|| tera.render("parent.html", context.clone()));
 }
@@ -152,11 +179,12 @@ fn bench_rendering_only_parent(b: &mut test::Bencher) {
 fn bench_rendering_only_macro_call(b: &mut test::Bencher) {
     let mut tera = Tera::default();
     tera.add_raw_templates(vec![
+        ("macros.html", MACRO_TEMPLATE),
         ("hey.html", USE_MACRO_TEMPLATE),
     ]).unwrap();
     let mut context = Context::new();
-    context.add("product", &Product::new());
-    context.add("username", &"bob");
+    context.insert("product", &Product::new());
+    context.insert("username", &"bob");
 
     b.iter(|| tera.render("hey.html", context.clone()));
 }
@@ -169,8 +197,8 @@ fn bench_rendering_only_inheritance(b: &mut test::Bencher) {
         ("child.html", CHILD_TEMPLATE),
     ]).unwrap();
     let mut context = Context::new();
-    context.add("product", &Product::new());
-    context.add("username", &"bob");
+    context.insert("product", &Product::new());
+    context.insert("username", &"bob");
 
     b.iter(|| tera.render("child.html", context.clone()));
 }
@@ -184,12 +212,39 @@ fn bench_rendering_inheritance_and_macros(b: &mut test::Bencher) {
         ("macros.html", MACRO_TEMPLATE),
     ]).unwrap();
     let mut context = Context::new();
-    context.add("product", &Product::new());
-    context.add("username", &"bob");
+    context.insert("product", &Product::new());
+    context.insert("username", &"bob");
 
     b.iter(|| tera.render("child.html", context.clone()));
 }
 
+// Cloning only bumps the reference counts of the templates...
+#[bench]
+fn bench_cloning_tera(b: &mut test::Bencher) {
+    let mut tera = Tera::default();
+    tera.add_raw_templates(vec![
+        ("parent.html", PARENT_TEMPLATE),
+        ("child.html", CHILD_TEMPLATE_WITH_MACRO),
+        ("macros.html", MACRO_TEMPLATE),
+        ("bench.html", SIMPLE_TEMPLATE),
+    ]).unwrap();
+
+    b.iter(|| tera.clone());
+}
+
+// ...compared to copying their ASTs like cloning used to require
+#[bench]
+fn bench_deep_cloning_templates(b: &mut test::Bencher) {
+    let mut tera = Tera::default();
+    tera.add_raw_templates(vec![
+        ("parent.html", PARENT_TEMPLATE),
+        ("child.html", CHILD_TEMPLATE_WITH_MACRO),
+        ("macros.html", MACRO_TEMPLATE),
+        ("bench.html", SIMPLE_TEMPLATE),
+    ]).unwrap();
+
+    b.iter(|| tera.templates.values().map(|t| Template::clone(t)).collect::<Vec<_>>());
+}
 
 #[bench]
 fn bench_escape_html(b: &mut test::Bencher) {
@@ -25,10 +25,10 @@ pub fn do_nothing_filter(value: Value, _: HashMap<String, Value>) -> Result<Valu
 
 fn main() {
     let mut context = Context::new();
-    context.add("username", &"Bob");
-    context.add("numbers", &vec![1,2,3]);
-    context.add("show_all", &false);
-    context.add("bio", &"<script>alert('pwnd');</script>");
+    context.insert("username", &"Bob");
+    context.insert("numbers", &vec![1,2,3]);
+    context.insert("show_all", &false);
+    context.insert("bio", &"<script>alert('pwnd');</script>");
 
     // A one off template
     Tera::one_off("hello", Context::new(), true).unwrap();
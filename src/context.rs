@@ -1,7 +1,7 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 
 use serde::ser::Serialize;
-use serde_json::value::{Value, to_value};
+use serde_json::value::{Map, Value, to_value};
 
 use errors::{Result as TeraResult, ResultExt};
 
@@ -22,17 +22,69 @@ impl Context {
         }
     }
 
-    /// Converts the `val` parameter to `Value` and insert it into the context
+    /// Converts the `val` parameter to `Value` and insert it into the context,
+    /// replacing the previous value of `key` if there was one
     ///
     /// ```rust,ignore
     /// let mut context = Context::new();
     /// // user is an instance of a struct implementing `Serialize`
-    /// context.add("number_users", 42);
+    /// context.insert("user", &user);
+    /// context.insert("number_users", &42);
     /// ```
-    pub fn add<T: Serialize>(&mut self, key: &str, val: &T) {
+    pub fn insert<T: Serialize>(&mut self, key: &str, val: &T) {
         self.data.insert(key.to_owned(), to_value(val).unwrap());
     }
 
+    /// Converts the `val` parameter to `Value` and insert it into the context
+    ///
+    /// Deprecated in favour of `Context::insert` which behaves the same way
+    #[deprecated(since = "0.7.0", note = "Use `Context::insert` instead")]
+    pub fn add<T: Serialize>(&mut self, key: &str, val: &T) {
+        self.insert(key, val);
+    }
+
+    /// Returns the value at the top-level `key` of the context, if there is one
+    ///
+    /// ```rust,ignore
+    /// let mut context = Context::new();
+    /// context.insert("number_users", &42);
+    /// assert_eq!(context.get("number_users"), Some(&to_value(42).unwrap()));
+    /// ```
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.data.get(key)
+    }
+
+    /// Removes the top-level `key` from the context and returns its previous value,
+    /// if there was one
+    ///
+    /// ```rust,ignore
+    /// let mut context = base_context.clone();
+    /// let user = context.remove("user");
+    /// ```
+    pub fn remove(&mut self, key: &str) -> Option<Value> {
+        self.data.remove(key)
+    }
+
+    /// Builds a context from any value implementing `Serialize`, such as a struct
+    /// or a `HashMap`, using its top-level keys as variables.
+    /// Errors if the value doesn't serialize to a JSON object.
+    ///
+    /// ```rust,ignore
+    /// // user is an instance of a struct implementing `Serialize`
+    /// let context = Context::from_serialize(&user)?;
+    /// ```
+    pub fn from_serialize<T: Serialize>(value: &T) -> TeraResult<Context> {
+        let obj = to_value(value).chain_err(|| "Failed to convert data to JSON")?;
+        match obj {
+            Value::Object(map) => Ok(Context { data: map.into_iter().collect() }),
+            _ => bail!(
+                "Creating a Context from a value requires it to be a JSON object but got {}. \
+                The value needs to be a key-value object: struct, hashmap for example.",
+                obj
+            ),
+        }
+    }
+
     #[doc(hidden)]
     pub fn as_json(&self) -> TeraResult<Value> {
         to_value(&self.data).chain_err(|| "Failed to convert data to JSON")
@@ -43,11 +95,11 @@ impl Context {
     ///
     /// ```rust,ignore
     /// let mut target = Context::new();
-    /// target.add("a", 1);
-    /// target.add("b", 2);
+    /// target.insert("a", 1);
+    /// target.insert("b", 2);
     /// let mut source = Context::new();
-    /// source.add("b", 3);
-    /// source.add("d", 4);
+    /// source.insert("b", 3);
+    /// source.insert("d", 4);
     /// target.extend(source);
     /// ```
     pub fn extend(&mut self, mut source: Context) {
@@ -61,6 +113,37 @@ impl Default for Context {
     }
 }
 
+/// Uses the keys of the map as the top-level variables of the context
+///
+/// ```rust,ignore
+/// tera.render("hello.html", map.into())?;
+/// ```
+impl From<Map<String, Value>> for Context {
+    fn from(map: Map<String, Value>) -> Context {
+        Context { data: map.into_iter().collect() }
+    }
+}
+
+/// Uses the keys of the map as the top-level variables of the context
+impl From<HashMap<String, Value>> for Context {
+    fn from(map: HashMap<String, Value>) -> Context {
+        Context { data: map.into_iter().collect() }
+    }
+}
+
+/// Uses the keys of the object as the top-level variables of the context.
+///
+/// Panics if the value isn't an object, use `Context::from_serialize` to get
+/// an error instead.
+impl From<Value> for Context {
+    fn from(value: Value) -> Context {
+        match value {
+            Value::Object(map) => map.into(),
+            _ => panic!("Creating a Context from a value requires it to be a JSON object but got {}", value),
+        }
+    }
+}
+
 pub trait ValueRender {
     fn render(&self) -> String;
 }
@@ -141,13 +224,90 @@ pub fn get_json_pointer(key: &str) -> String {
 #[test]
 fn test_extend() {
     let mut target = Context::new();
-    target.add("a", &1);
-    target.add("b", &2);
+    target.insert("a", &1);
+    target.insert("b", &2);
     let mut source = Context::new();
-    source.add("b", &3);
-    source.add("c", &4);
+    source.insert("b", &3);
+    source.insert("c", &4);
     target.extend(source);
     assert_eq!(*target.data.get("a").unwrap(), to_value(1).unwrap());
     assert_eq!(*target.data.get("b").unwrap(), to_value(3).unwrap());
     assert_eq!(*target.data.get("c").unwrap(), to_value(4).unwrap());
 }
+
+#[test]
+#[allow(deprecated)]
+fn test_insert_same_as_add() {
+    let mut inserted = Context::new();
+    inserted.insert("a", &1);
+    inserted.insert("b", &vec!["c", "d"]);
+    let mut added = Context::new();
+    added.add("a", &1);
+    added.add("b", &vec!["c", "d"]);
+    assert_eq!(inserted.data, added.data);
+}
+
+#[test]
+fn test_from_serialize_hashmap() {
+    let mut map = ::std::collections::HashMap::new();
+    map.insert("a", vec![1, 2]);
+    let context = Context::from_serialize(&map).unwrap();
+    assert_eq!(*context.data.get("a").unwrap(), to_value(vec![1, 2]).unwrap());
+}
+
+#[test]
+fn test_from_serialize_non_object() {
+    let result = Context::from_serialize(&vec![1, 2, 3]);
+    assert!(result.is_err());
+    let result = Context::from_serialize(&1);
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_from_json_map() {
+    let mut map = Map::new();
+    map.insert("a".to_string(), to_value(1).unwrap());
+    map.insert("b".to_string(), to_value(vec!["c"]).unwrap());
+    let context: Context = map.into();
+    assert_eq!(context.get("a"), Some(&to_value(1).unwrap()));
+    assert_eq!(context.get("b"), Some(&to_value(vec!["c"]).unwrap()));
+}
+
+#[test]
+fn test_from_hashmap() {
+    let mut map = HashMap::new();
+    map.insert("a".to_string(), to_value(1).unwrap());
+    let context = Context::from(map);
+    assert_eq!(context.get("a"), Some(&to_value(1).unwrap()));
+}
+
+#[test]
+fn test_from_json_object_value() {
+    let mut map = Map::new();
+    map.insert("a".to_string(), to_value(1).unwrap());
+    let context = Context::from(Value::Object(map));
+    assert_eq!(context.get("a"), Some(&to_value(1).unwrap()));
+}
+
+#[test]
+#[should_panic(expected = "requires it to be a JSON object")]
+fn test_from_json_non_object_value() {
+    let _ = Context::from(to_value(vec![1, 2]).unwrap());
+}
+
+#[test]
+fn test_get() {
+    let mut context = Context::new();
+    context.insert("a", &1);
+    assert_eq!(context.get("a"), Some(&to_value(1).unwrap()));
+    assert_eq!(context.get("b"), None);
+}
+
+#[test]
+fn test_remove() {
+    let mut context = Context::new();
+    context.insert("a", &1);
+    assert_eq!(context.remove("a"), Some(to_value(1).unwrap()));
+    assert_eq!(context.get("a"), None);
+    assert_eq!(context.remove("a"), None);
+}
@@ -0,0 +1,394 @@
+//! Delimiters of the tags, to write templates with something else than `{{ }}`, `{% %}` and `{# #}`.
+//! The parser only knows about the default ones so the templates using other delimiters are
+//! translated to the default ones before being parsed, keeping track of where each part of the
+//! translation comes from to give the positions in the original template in errors
+use errors::Result;
+
+// The parser only knows about those
+const DEFAULT_VARIABLE: (&str, &str) = ("{{", "}}");
+const DEFAULT_BLOCK: (&str, &str) = ("{%", "%}");
+const DEFAULT_COMMENT: (&str, &str) = ("{#", "#}");
+
+/// Put before the `%` of each `{%` in the content of raw blocks, and before itself, so a
+/// `{% endraw %}` in the content doesn't end the translated raw block early.
+/// The parser removes it from the raw nodes with `unescape_raw`
+pub const RAW_ESCAPE: char = '\u{E000}';
+
+
+/// The delimiters of the tags of the templates, each given as an `(opening, closing)` pair.
+/// Changing them is useful when the default ones clash with the content of the templates,
+/// for example in LaTeX or JavaScript.
+///
+/// ```rust,ignore
+/// let delimiters = Delimiters { variable: ("<<", ">>"), ..Delimiters::default() };
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Delimiters {
+    /// Delimiters of variable blocks, `("{{", "}}")` by default
+    pub variable: (&'static str, &'static str),
+    /// Delimiters of tags such as `if` or `for`, `("{%", "%}")` by default
+    pub block: (&'static str, &'static str),
+    /// Delimiters of comments, `("{#", "#}")` by default
+    pub comment: (&'static str, &'static str),
+}
+
+impl Default for Delimiters {
+    fn default() -> Delimiters {
+        Delimiters {
+            variable: DEFAULT_VARIABLE,
+            block: DEFAULT_BLOCK,
+            comment: DEFAULT_COMMENT,
+        }
+    }
+}
+
+/// A template rewritten by `Delimiters::translate` to use the default delimiters
+#[derive(Debug)]
+pub struct Translation {
+    /// The template using the default delimiters
+    pub output: String,
+    input_len: usize,
+    // Where each part of the output starts, where the text it comes from starts in the input
+    // and whether the part is a copy of that text
+    parts: Vec<(usize, usize, bool)>,
+}
+
+impl Translation {
+    fn new(input_len: usize) -> Translation {
+        Translation { output: String::with_capacity(input_len), input_len, parts: vec![] }
+    }
+
+    fn push(&mut self, text: &str, input_start: usize, copied: bool) {
+        if !text.is_empty() {
+            self.parts.push((self.output.len(), input_start, copied));
+            self.output.push_str(text);
+        }
+    }
+
+    // Adds text that is the same in the input, starting at `input_start`
+    fn copy(&mut self, text: &str, input_start: usize) {
+        self.push(text, input_start, true);
+    }
+
+    // Adds text replacing what starts at `input_start` in the input
+    fn replace(&mut self, text: &str, input_start: usize) {
+        self.push(text, input_start, false);
+    }
+
+    // Adds text, putting anything that would be the start of a tag with the default delimiters
+    // in a `raw` block so it stays text.
+    // If a tag comes right after, a `{` at the end of the text would be the start of one as well
+    fn push_text(&mut self, text: &str, input_start: usize, before_tag: bool) {
+        let mut copied = 0;
+        let mut i = 0;
+        while i < text.len() {
+            let rest = &text[i..];
+            let is_opening = [DEFAULT_VARIABLE.0, DEFAULT_BLOCK.0, DEFAULT_COMMENT.0].iter().any(|o| rest.starts_with(o));
+            if is_opening || (before_tag && rest == "{") {
+                let len = if is_opening { 2 } else { 1 };
+                self.copy(&text[copied..i], input_start + copied);
+                self.replace("{% raw %}", input_start + i);
+                self.copy(&rest[..len], input_start + i);
+                self.replace("{% endraw %}", input_start + i + len);
+                i += len;
+                copied = i;
+            } else {
+                i += rest.chars().next().unwrap().len_utf8();
+            }
+        }
+        self.copy(&text[copied..], input_start + copied);
+    }
+
+    // Adds the content of a raw block, escaped with `RAW_ESCAPE`
+    fn push_raw_content(&mut self, content: &str, input_start: usize) {
+        let mut escape = [0; 4];
+        let escape = RAW_ESCAPE.encode_utf8(&mut escape);
+        let mut copied = 0;
+        for (i, c) in content.char_indices() {
+            if c == RAW_ESCAPE || (c == '%' && content[..i].ends_with('{')) {
+                self.copy(&content[copied..i], input_start + copied);
+                self.replace(escape, input_start + i);
+                copied = i;
+            }
+        }
+        self.copy(&content[copied..], input_start + copied);
+    }
+
+    /// Where the text at the byte `pos` of the output comes from in the input, the text added by
+    /// the translation being located at the start of what it replaces
+    pub fn original_offset(&self, pos: usize) -> usize {
+        if pos >= self.output.len() {
+            return self.input_len;
+        }
+        // The first part starts at 0 as the output isn't empty
+        let i = match self.parts.binary_search_by_key(&pos, |&(start, _, _)| start) {
+            Ok(i) => i,
+            Err(i) => i - 1,
+        };
+        let (start, input_start, copied) = self.parts[i];
+        if copied { input_start + pos - start } else { input_start }
+    }
+}
+
+/// Removes the `RAW_ESCAPE` added by `Delimiters::translate` from the content of a raw block
+pub fn unescape_raw(content: &str) -> String {
+    let mut output = String::with_capacity(content.len());
+    let mut chars = content.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            RAW_ESCAPE => output.extend(chars.next()),
+            _ => output.push(c),
+        }
+    }
+    output
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Tag {
+    Variable,
+    Block,
+    Comment,
+}
+
+// Finds where the tag starting at the beginning of `input` ends, ignoring the closing
+// delimiter when it is in a string
+fn find_closing(input: &str, closing: &str, skip_strings: bool) -> Option<usize> {
+    let mut in_string = false;
+    for (i, c) in input.char_indices() {
+        if skip_strings && c == '"' {
+            in_string = !in_string;
+        } else if !in_string && input[i..].starts_with(closing) {
+            return Some(i);
+        }
+    }
+    None
+}
+
+// Whether the content of a block tag is only `name`, whitespace control included
+fn is_tag_named(inner: &str, name: &str) -> bool {
+    inner.trim_matches(|c: char| c == '-' || c.is_whitespace()) == name
+}
+
+impl Delimiters {
+    /// Checks that the delimiters can be told apart: none of them can be empty and the
+    /// opening ones need to be different
+    pub fn validate(&self) -> Result<()> {
+        let all = [self.variable.0, self.variable.1, self.block.0, self.block.1, self.comment.0, self.comment.1];
+        if all.iter().any(|d| d.is_empty()) {
+            bail!("Delimiters can't be empty but got {:?}", self);
+        }
+        if self.variable.0 == self.block.0 || self.variable.0 == self.comment.0 || self.block.0 == self.comment.0 {
+            bail!("The opening delimiters of variables, blocks and comments need to be different but got {:?}", self);
+        }
+        Ok(())
+    }
+
+    /// Whether these are the `{{ }}`, `{% %}` and `{# #}` delimiters
+    pub fn is_default(&self) -> bool {
+        *self == Delimiters::default()
+    }
+
+    fn get(&self, tag: Tag) -> ((&'static str, &'static str), (&'static str, &'static str)) {
+        match tag {
+            Tag::Variable => (self.variable, DEFAULT_VARIABLE),
+            Tag::Block => (self.block, DEFAULT_BLOCK),
+            Tag::Comment => (self.comment, DEFAULT_COMMENT),
+        }
+    }
+
+    // Finds the next tag of `input`, the longest opening delimiter winning if several start
+    // at the same position
+    fn find_opening(&self, input: &str) -> Option<(usize, Tag)> {
+        [Tag::Variable, Tag::Block, Tag::Comment].iter()
+            .filter_map(|tag| {
+                let opening = self.get(*tag).0 .0;
+                input.find(opening).map(|pos| (pos, *tag, opening.len()))
+            })
+            .min_by_key(|&(pos, _, len)| (pos, usize::MAX - len))
+            .map(|(pos, tag, _)| (pos, tag))
+    }
+
+    // Finds the `endraw` tag closing a raw block, returning where it starts, its content and
+    // where it ends
+    fn find_endraw<'a>(&self, input: &'a str) -> Option<(usize, &'a str, usize)> {
+        let (opening, closing) = self.block;
+        let mut offset = 0;
+        while let Some(pos) = input[offset..].find(opening) {
+            let start = offset + pos;
+            let inner_start = start + opening.len();
+            if let Some(end) = find_closing(&input[inner_start..], closing, false) {
+                let inner = &input[inner_start..inner_start + end];
+                if is_tag_named(inner, "endraw") {
+                    return Some((start, inner, inner_start + end + closing.len()));
+                }
+            }
+            offset = inner_start;
+        }
+        None
+    }
+
+    /// Rewrites a template written with these delimiters to use the default ones, which are
+    /// the only ones the parser knows about.
+    /// Text looking like a tag with the default delimiters is put in `raw` blocks and the
+    /// content of raw blocks is kept as it is, with the `{%` in it escaped.
+    /// The translation keeps track of where its parts come from in `input` so errors can give
+    /// the positions in `input`
+    pub fn translate(&self, input: &str) -> Translation {
+        let mut translation = Translation::new(input.len());
+        let mut offset = 0;
+
+        while let Some((start, tag)) = self.find_opening(&input[offset..]) {
+            let start = offset + start;
+            let ((opening, closing), (default_opening, default_closing)) = self.get(tag);
+            let inner_start = start + opening.len();
+            translation.push_text(&input[offset..start], offset, true);
+            translation.replace(default_opening, start);
+
+            let end = match find_closing(&input[inner_start..], closing, tag != Tag::Comment) {
+                Some(e) => inner_start + e,
+                // The parser will give the error for the unclosed tag
+                None => {
+                    translation.copy(&input[inner_start..], inner_start);
+                    return translation;
+                }
+            };
+            let inner = &input[inner_start..end];
+            if tag == Tag::Comment {
+                // Whatever is in a comment is ignored as long as it doesn't end it early
+                translation.replace(&inner.replace(DEFAULT_COMMENT.1, "##"), inner_start);
+            } else {
+                translation.copy(inner, inner_start);
+            }
+            translation.replace(default_closing, end);
+            offset = end + closing.len();
+
+            if tag == Tag::Block && is_tag_named(inner, "raw") {
+                match self.find_endraw(&input[offset..]) {
+                    Some((content_end, endraw, after)) => {
+                        let endraw_start = offset + content_end + self.block.0.len();
+                        translation.push_raw_content(&input[offset..offset + content_end], offset);
+                        translation.replace(DEFAULT_BLOCK.0, offset + content_end);
+                        translation.copy(endraw, endraw_start);
+                        translation.replace(DEFAULT_BLOCK.1, endraw_start + endraw.len());
+                        offset += after;
+                    },
+                    None => {
+                        translation.push_raw_content(&input[offset..], offset);
+                        return translation;
+                    }
+                }
+            }
+        }
+
+        translation.push_text(&input[offset..], offset, false);
+        translation
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Delimiters, unescape_raw};
+
+    fn custom() -> Delimiters {
+        Delimiters { variable: ("<<", ">>"), block: ("<%", "%>"), comment: ("<#", "#>") }
+    }
+
+    #[test]
+    fn test_translate_tags() {
+        let input = "<% if user %>Hello <<- user.name | upper ->><% endif %><#- note -#>";
+        assert_eq!(
+            custom().translate(input).output,
+            "{% if user %}Hello {{- user.name | upper -}}{% endif %}{#- note -#}"
+        );
+    }
+
+    #[test]
+    fn test_translate_keeps_default_delimiters_as_text() {
+        assert_eq!(
+            custom().translate("\\frac{{<< a >>}}{2} {% x %} {#").output,
+            "\\frac{% raw %}{{{% endraw %}{{ a }}}}{2} {% raw %}{%{% endraw %} x %} {% raw %}{#{% endraw %}"
+        );
+        // a `{` right before a tag
+        assert_eq!(custom().translate("{<< a >>}").output, "{% raw %}{{% endraw %}{{ a }}}");
+    }
+
+    #[test]
+    fn test_translate_ignores_closing_delimiter_in_strings() {
+        assert_eq!(custom().translate("<< \">>\" ~ a >>!").output, "{{ \">>\" ~ a }}!");
+    }
+
+    #[test]
+    fn test_translate_raw() {
+        assert_eq!(
+            custom().translate("<% raw %><< a >> {{ b }}<% endraw %><< c >>").output,
+            "{% raw %}<< a >> {{ b }}{% endraw %}{{ c }}"
+        );
+    }
+
+    #[test]
+    fn test_translate_comment_with_default_closing() {
+        assert_eq!(custom().translate("<# a #} b #>").output, "{# a ## b #}");
+    }
+
+    #[test]
+    fn test_translate_unclosed_tag() {
+        assert_eq!(custom().translate("a << b").output, "a {{ b");
+        assert_eq!(custom().translate("<% raw %> a").output, "{% raw %} a");
+    }
+
+    #[test]
+    fn test_translate_keeps_lines() {
+        let input = "a {{\n<% if b %>\n<# \n #>\nc";
+        assert_eq!(custom().translate(input).output.lines().count(), input.lines().count());
+    }
+
+    #[test]
+    fn test_translate_escapes_raw_content() {
+        let translation = custom().translate("<% raw %>{% raw %}a{%- endraw %}\u{E000}<% endraw %>");
+        assert_eq!(
+            translation.output,
+            "{% raw %}{\u{E000}% raw %}a{\u{E000}%- endraw %}\u{E000}\u{E000}{% endraw %}"
+        );
+        assert_eq!(unescape_raw("{\u{E000}% raw %}a{\u{E000}%- endraw %}\u{E000}\u{E000}"), "{% raw %}a{%- endraw %}\u{E000}");
+    }
+
+    #[test]
+    fn test_translation_original_offset() {
+        let delimiters = Delimiters { variable: ("[[", "]]"), block: ("[[%", "%]]"), comment: ("[#", "#]") };
+        let input = "ab [[% if c %]]{{ d }}";
+        let translation = delimiters.translate(input);
+        assert_eq!(translation.output, "ab {% if c %}{% raw %}{{{% endraw %} d }}");
+        let original = |s: &str| translation.original_offset(translation.output.find(s).unwrap());
+        // copied text keeps its position
+        assert_eq!(original("b"), 1);
+        assert_eq!(original(" c"), 9);
+        assert_eq!(original("d }}"), 18);
+        // added text is located where what it replaces starts
+        assert_eq!(original("{% if"), 3);
+        assert_eq!(original("% if"), 3);
+        assert_eq!(original("%}{% raw"), 12);
+        assert_eq!(original("{% raw"), 15);
+        assert_eq!(original("{{{%"), 15);
+        assert_eq!(translation.original_offset(translation.output.len()), input.len());
+    }
+
+    #[test]
+    fn test_translate_longest_opening_first() {
+        let delimiters = Delimiters { variable: ("[[", "]]"), block: ("[[%", "%]]"), comment: ("[#", "#]") };
+        assert_eq!(delimiters.translate("[[% set a = 1 %]][[ a ]]").output, "{% set a = 1 %}{{ a }}");
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(Delimiters::default().validate().is_ok());
+        assert!(custom().validate().is_ok());
+        assert!(Delimiters { variable: ("", "}}"), ..Delimiters::default() }.validate().is_err());
+        assert!(Delimiters { block: ("{{", "%}"), ..Delimiters::default() }.validate().is_err());
+    }
+
+    #[test]
+    fn test_is_default() {
+        assert!(Delimiters::default().is_default());
+        assert!(!custom().is_default());
+    }
+}
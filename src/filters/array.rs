@@ -1,28 +1,54 @@
 /// Filters operating on array
+use std::cmp::Ordering;
 use std::collections::HashMap;
 
-use serde_json::value::{Value, to_value};
-use context::ValueRender;
+use serde_json::value::{Map, Value, to_value};
+use context::{ValueRender, ValueTruthy, get_json_pointer};
 use errors::Result;
 
 /// Returns the first value of an array
-/// If the array is empty, returns empty string
+/// Errors if the array is empty
 pub fn first(value: Value, _: HashMap<String, Value>) -> Result<Value> {
     let mut arr = try_get_value!("first", "value", Vec<Value>, value);
 
     if arr.is_empty() {
-        Ok(to_value("").unwrap())
-    } else {
-        Ok(arr.swap_remove(0))
+        bail!("Filter `first` was called on an empty array");
     }
+    Ok(arr.swap_remove(0))
 }
 
 /// Returns the last value of an array
-/// If the array is empty, returns empty string
+/// Errors if the array is empty
 pub fn last(value: Value, _: HashMap<String, Value>) -> Result<Value> {
     let mut arr = try_get_value!("last", "value", Vec<Value>, value);
 
-    Ok(arr.pop().unwrap_or_else(|| to_value("").unwrap()))
+    match arr.pop() {
+        Some(val) => Ok(val),
+        None => bail!("Filter `last` was called on an empty array"),
+    }
+}
+
+/// Returns the value at the zero-based index `n` of an array, a negative `n` counting from
+/// the end: -1 is the last value.
+/// Errors if the index is out of range, unless a `default` argument is given which is then
+/// returned instead
+pub fn nth(value: Value, mut args: HashMap<String, Value>) -> Result<Value> {
+    let mut arr = try_get_value!("nth", "value", Vec<Value>, value);
+    let n = match args.remove("n") {
+        Some(val) => try_get_value!("nth", "n", i64, val),
+        None => bail!("Filter `nth` expected an arg called `n`"),
+    };
+
+    let len = arr.len() as i64;
+    let index = if n < 0 { n + len } else { n };
+    if index >= 0 && index < len {
+        return Ok(arr.swap_remove(index as usize));
+    }
+
+    match args.remove("default") {
+        Some(default) => Ok(default),
+        None => bail!("Filter `nth` was called with `n={}` on an array of {} values", n, len),
+    }
 }
 
 /// Joins all values in the array by the `sep` argument given
@@ -40,6 +66,333 @@ pub fn join(value: Value, mut args: HashMap<String, Value>) -> Result<Value> {
     Ok(to_value(&rendered.join(&sep))?)
 }
 
+// Gets the array the `min`, `max` and `sum` filters are called on, erroring if one
+// of its items is not a number
+fn get_numbers(filter_name: &str, value: Value) -> Result<Vec<Value>> {
+    let arr = try_get_value!(filter_name, "value", Vec<Value>, value);
+    if let Some(v) = arr.iter().find(|v| !v.is_number()) {
+        bail!("Filter `{}` only works on arrays of numbers but got `{}` in the array", filter_name, v);
+    }
+    Ok(arr)
+}
+
+// Finds the item of the array that should be chosen over all the others according to `prefer`.
+// Items are compared as floats but the item itself is returned so integers stay integers
+fn pick_number(filter_name: &str, value: Value, prefer: fn(f64, f64) -> bool) -> Result<Value> {
+    let arr = get_numbers(filter_name, value)?;
+    let mut picked: Option<Value> = None;
+    for v in arr {
+        picked = match picked {
+            Some(p) => {
+                // Safe unwraps, they are all numbers
+                if prefer(v.as_f64().unwrap(), p.as_f64().unwrap()) { Some(v) } else { Some(p) }
+            },
+            None => Some(v),
+        };
+    }
+
+    match picked {
+        Some(v) => Ok(v),
+        None => bail!("Filter `{}` was called on an empty array", filter_name),
+    }
+}
+
+/// Returns the smallest number of an array
+/// Errors if the array is empty or contains something else than numbers
+pub fn min(value: Value, _: HashMap<String, Value>) -> Result<Value> {
+    pick_number("min", value, |a, b| a < b)
+}
+
+/// Returns the biggest number of an array
+/// Errors if the array is empty or contains something else than numbers
+pub fn max(value: Value, _: HashMap<String, Value>) -> Result<Value> {
+    pick_number("max", value, |a, b| a > b)
+}
+
+/// Returns the sum of all the numbers of an array, 0 if the array is empty.
+/// The result is an integer if all the numbers are integers, a float otherwise.
+/// Errors if the array contains something else than numbers
+pub fn sum(value: Value, _: HashMap<String, Value>) -> Result<Value> {
+    let arr = get_numbers("sum", value)?;
+
+    let mut int_sum: Option<i64> = Some(0);
+    let mut float_sum = 0.0;
+    for v in &arr {
+        // Falls back to floats on the first float or on overflow
+        int_sum = match (int_sum, v.as_i64()) {
+            (Some(total), Some(i)) => total.checked_add(i),
+            _ => None,
+        };
+        float_sum += v.as_f64().unwrap();
+    }
+
+    match int_sum {
+        Some(i) => Ok(to_value(i)?),
+        None => Ok(to_value(float_sum)?),
+    }
+}
+
+// The kind of values `sort` can compare, `None` for the ones it can't
+fn sort_kind(value: &Value) -> Option<&'static str> {
+    match *value {
+        Value::Number(_) => Some("number"),
+        Value::String(_) => Some("string"),
+        Value::Bool(_) => Some("bool"),
+        _ => None,
+    }
+}
+
+// Only called on values of the same kind
+fn compare_values(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (Value::String(a), Value::String(b)) => a.cmp(b),
+        (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+        // Safe unwraps, they are both numbers
+        _ => a.as_f64().unwrap().partial_cmp(&b.as_f64().unwrap()).unwrap_or(Ordering::Equal),
+    }
+}
+
+/// Sorts an array of numbers, strings or booleans. For an array of objects, the `attribute`
+/// argument is the dotted path of the value to sort them by, like `user.age`.
+/// The sort is stable: equal items stay in their original order, even when `reverse` is true.
+/// Errors if the values to sort are not all of the same type.
+pub fn sort(value: Value, mut args: HashMap<String, Value>) -> Result<Value> {
+    let arr = try_get_value!("sort", "value", Vec<Value>, value);
+    let attribute = match args.remove("attribute") {
+        Some(val) => Some(get_json_pointer(&try_get_value!("sort", "attribute", String, val))),
+        None => None,
+    };
+    let reverse = match args.remove("reverse") {
+        Some(val) => try_get_value!("sort", "reverse", bool, val),
+        None => false,
+    };
+
+    let mut keyed = Vec::with_capacity(arr.len());
+    let mut kind = None;
+    for item in arr {
+        let key = match attribute {
+            Some(ref pointer) => match item.pointer(pointer) {
+                Some(k) => k.clone(),
+                None => bail!(
+                    "Filter `sort` tried to sort by `{}` but it is missing in `{}`",
+                    &pointer[1..].replace("/", "."), item
+                ),
+            },
+            None => item.clone(),
+        };
+        let key_kind = match sort_kind(&key) {
+            Some(k) => k,
+            None => bail!("Filter `sort` can only sort numbers, strings or booleans but got `{}`", key),
+        };
+        match kind {
+            Some(k) if k != key_kind => bail!(
+                "Filter `sort` can't sort values of different types: got a {} and a {}", k, key_kind
+            ),
+            _ => kind = Some(key_kind),
+        }
+        keyed.push((key, item));
+    }
+
+    keyed.sort_by(|a, b| {
+        let ordering = compare_values(&a.0, &b.0);
+        if reverse { ordering.reverse() } else { ordering }
+    });
+
+    Ok(to_value(keyed.into_iter().map(|(_, item)| item).collect::<Vec<_>>())?)
+}
+
+/// Sorts the entries of an object by key, or by value with `by="value"`, returning an array of
+/// `[key, value]` pairs that can be iterated on with `{% for key, value in obj | dictsort %}`.
+/// Setting `reverse` to true sorts in descending order.
+/// Errors if the values are sorted on and are not all numbers, strings or booleans of the same type.
+pub fn dictsort(value: Value, mut args: HashMap<String, Value>) -> Result<Value> {
+    let obj = try_get_value!("dictsort", "value", Map<String, Value>, value);
+    let by_value = match args.remove("by") {
+        Some(val) => match try_get_value!("dictsort", "by", String, val).as_str() {
+            "key" => false,
+            "value" => true,
+            other => bail!("Filter `dictsort` can sort by `key` or `value` but got `{}`", other),
+        },
+        None => false,
+    };
+    let reverse = match args.remove("reverse") {
+        Some(val) => try_get_value!("dictsort", "reverse", bool, val),
+        None => false,
+    };
+
+    let mut entries = obj.into_iter().collect::<Vec<_>>();
+    if by_value {
+        let mut kind = None;
+        for (_, v) in &entries {
+            let value_kind = match sort_kind(v) {
+                Some(k) => k,
+                None => bail!("Filter `dictsort` can only sort numbers, strings or booleans but got `{}`", v),
+            };
+            match kind {
+                Some(k) if k != value_kind => bail!(
+                    "Filter `dictsort` can't sort values of different types: got a {} and a {}", k, value_kind
+                ),
+                _ => kind = Some(value_kind),
+            }
+        }
+    }
+
+    entries.sort_by(|a, b| {
+        let ordering = if by_value { compare_values(&a.1, &b.1) } else { a.0.cmp(&b.0) };
+        if reverse { ordering.reverse() } else { ordering }
+    });
+
+    let pairs = entries.into_iter()
+        .map(|(k, v)| Value::Array(vec![Value::String(k), v]))
+        .collect::<Vec<_>>();
+    Ok(to_value(pairs)?)
+}
+
+/// Groups an array of objects by the value at the dotted path `attribute`, returning an
+/// object mapping each distinct value, as a string, to the array of the objects having it.
+/// Objects where the attribute is missing or null are skipped.
+/// Errors if the attribute is an array or an object.
+pub fn group_by(value: Value, mut args: HashMap<String, Value>) -> Result<Value> {
+    let arr = try_get_value!("group_by", "value", Vec<Value>, value);
+    let pointer = match args.remove("attribute") {
+        Some(val) => get_json_pointer(&try_get_value!("group_by", "attribute", String, val)),
+        None => bail!("Filter `group_by` expected an arg called `attribute`"),
+    };
+
+    let mut groups = Map::new();
+    for item in arr {
+        let key = match item.pointer(&pointer) {
+            Some(k @ &Value::Array(_)) | Some(k @ &Value::Object(_)) => bail!(
+                "Filter `group_by` can only group by a string, a number or a boolean but got `{}`", k
+            ),
+            Some(&Value::Null) | None => continue,
+            Some(k) => k.render(),
+        };
+        match *groups.entry(key).or_insert_with(|| Value::Array(vec![])) {
+            Value::Array(ref mut group) => group.push(item),
+            _ => unreachable!(),
+        }
+    }
+
+    Ok(Value::Object(groups))
+}
+
+/// Returns an array of the values at the dotted path `attribute` of each item of the array.
+/// Items where the attribute is missing give `null`, unless `skip_missing` is true in which
+/// case they are left out.
+pub fn map(value: Value, mut args: HashMap<String, Value>) -> Result<Value> {
+    let arr = try_get_value!("map", "value", Vec<Value>, value);
+    let pointer = match args.remove("attribute") {
+        Some(val) => get_json_pointer(&try_get_value!("map", "attribute", String, val)),
+        None => bail!("Filter `map` expected an arg called `attribute`"),
+    };
+    let skip_missing = match args.remove("skip_missing") {
+        Some(val) => try_get_value!("map", "skip_missing", bool, val),
+        None => false,
+    };
+
+    let mut res = Vec::with_capacity(arr.len());
+    for item in &arr {
+        match item.pointer(&pointer) {
+            Some(v) => res.push(v.clone()),
+            None if skip_missing => (),
+            None => res.push(Value::Null),
+        }
+    }
+
+    Ok(to_value(res)?)
+}
+
+/// Returns the items of the array whose value at the dotted path `attribute` is equal to `value`,
+/// keeping their order. Numbers are compared like with `==` so `1` is equal to `1.0`.
+/// If `value` is not given, the items where the attribute is truthy are kept instead.
+/// Items missing the attribute are never kept.
+pub fn filter(value: Value, mut args: HashMap<String, Value>) -> Result<Value> {
+    let arr = try_get_value!("filter", "value", Vec<Value>, value);
+    let pointer = match args.remove("attribute") {
+        Some(val) => get_json_pointer(&try_get_value!("filter", "attribute", String, val)),
+        None => bail!("Filter `filter` expected an arg called `attribute`"),
+    };
+    let expected = args.remove("value");
+
+    let res = arr.into_iter().filter(|item| {
+        match (item.pointer(&pointer), &expected) {
+            (None, _) => false,
+            (Some(v), None) => v.is_truthy(),
+            (Some(v), Some(e)) => match (v.as_f64(), e.as_f64()) {
+                (Some(a), Some(b)) => a == b,
+                _ => v == e,
+            },
+        }
+    }).collect::<Vec<_>>();
+
+    Ok(to_value(res)?)
+}
+
+/// Splits the array into arrays of `count` items, the last one being shorter if the
+/// length of the array isn't a multiple of `count`.
+/// If `fill_with` is given, it is added to the last array until it has `count` items.
+pub fn batch(value: Value, mut args: HashMap<String, Value>) -> Result<Value> {
+    let arr = try_get_value!("batch", "value", Vec<Value>, value);
+    let count = match args.remove("count") {
+        Some(val) => try_get_value!("batch", "count", usize, val),
+        None => bail!("Filter `batch` expected an arg called `count`"),
+    };
+    if count == 0 {
+        bail!("Filter `batch` was called with a `count` of 0 but it needs to be positive");
+    }
+    let fill_with = args.remove("fill_with");
+
+    let mut res = arr.chunks(count).map(|c| c.to_vec()).collect::<Vec<_>>();
+    if let (Some(last), Some(fill)) = (res.last_mut(), fill_with) {
+        last.resize(count, fill);
+    }
+
+    Ok(to_value(res)?)
+}
+
+/// Removes the duplicate items of an array, keeping the first occurrence of each.
+/// For an array of objects, the `attribute` argument is the dotted path of the value to
+/// compare them by, like `user.email`: items missing it are compared as `null`.
+/// Numbers are compared like with `==` so `1` is equal to `1.0` and strings are compared
+/// ignoring their case if `case_sensitive` is false.
+pub fn unique(value: Value, mut args: HashMap<String, Value>) -> Result<Value> {
+    let arr = try_get_value!("unique", "value", Vec<Value>, value);
+    let pointer = match args.remove("attribute") {
+        Some(val) => Some(get_json_pointer(&try_get_value!("unique", "attribute", String, val))),
+        None => None,
+    };
+    let case_sensitive = match args.remove("case_sensitive") {
+        Some(val) => try_get_value!("unique", "case_sensitive", bool, val),
+        None => true,
+    };
+
+    let mut seen: Vec<Value> = Vec::new();
+    let mut res = Vec::new();
+    for item in arr {
+        let key = {
+            let key = match pointer {
+                Some(ref p) => item.pointer(p).unwrap_or(&Value::Null),
+                None => &item,
+            };
+            match *key {
+                Value::String(ref s) if !case_sensitive => Value::String(s.to_lowercase()),
+                ref k => k.clone(),
+            }
+        };
+        let duplicate = seen.iter().any(|s| match (s.as_f64(), key.as_f64()) {
+            (Some(a), Some(b)) => a == b,
+            _ => *s == key,
+        });
+        if !duplicate {
+            seen.push(key);
+            res.push(item);
+        }
+    }
+
+    Ok(to_value(res)?)
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
@@ -53,13 +406,42 @@ mod tests {
         assert_eq!(result.unwrap(), to_value(&1).unwrap());
     }
 
+    #[test]
+    fn test_first_single_element() {
+        let result = first(to_value(vec!["Hello"]).unwrap(), HashMap::new());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("Hello").unwrap());
+    }
+
+    #[test]
+    fn test_first_keeps_type() {
+        let mut obj = HashMap::new();
+        obj.insert("name", "Bob");
+        let result = first(to_value(vec![obj.clone()]).unwrap(), HashMap::new());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(obj).unwrap());
+    }
+
     #[test]
     fn test_first_empty() {
         let v: Vec<Value> = Vec::new();
 
         let result = first(to_value(&v).unwrap(), HashMap::new());
-        assert!(result.is_ok());
-        assert_eq!(result.ok().unwrap(), to_value("").unwrap());
+        assert!(result.is_err());
+        assert_eq!(
+            result.err().unwrap().description(),
+            "Filter `first` was called on an empty array"
+        );
+    }
+
+    #[test]
+    fn test_first_non_array() {
+        let result = first(to_value("Hello").unwrap(), HashMap::new());
+        assert!(result.is_err());
+        assert_eq!(
+            result.err().unwrap().description(),
+            "Filter `first` was called on an incorrect value: got `\"Hello\"` but expected a Vec<Value>"
+        );
     }
 
     #[test]
@@ -69,13 +451,82 @@ mod tests {
         assert_eq!(result.unwrap(), to_value("World").unwrap());
     }
 
+    #[test]
+    fn test_last_single_element() {
+        let result = last(to_value(vec![1.5]).unwrap(), HashMap::new());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(1.5).unwrap());
+    }
+
+    #[test]
+    fn test_last_non_array() {
+        let result = last(to_value(1).unwrap(), HashMap::new());
+        assert!(result.is_err());
+    }
+
     #[test]
     fn test_last_empty() {
         let v: Vec<Value> = Vec::new();
 
         let result = last(to_value(&v).unwrap(), HashMap::new());
+        assert!(result.is_err());
+        assert_eq!(
+            result.err().unwrap().description(),
+            "Filter `last` was called on an empty array"
+        );
+    }
+
+    // values of different types to check they are returned as they are
+    fn nth_array() -> Value {
+        Value::Array(vec![to_value("a").unwrap(), to_value(2).unwrap(), to_value(3).unwrap(), to_value(vec![4]).unwrap()])
+    }
+
+    #[test]
+    fn test_nth() {
+        let tests = vec![
+            (0, to_value("a").unwrap()),
+            (2, to_value(3).unwrap()),
+            (-1, to_value(vec![4]).unwrap()),
+            (-4, to_value("a").unwrap()),
+        ];
+        for (n, expected) in tests {
+            let mut args = HashMap::new();
+            args.insert("n".to_string(), to_value(n).unwrap());
+            let result = nth(nth_array(), args);
+            assert!(result.is_ok());
+            assert_eq!(result.unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_nth_out_of_range() {
+        for n in &[4, -5] {
+            let mut args = HashMap::new();
+            args.insert("n".to_string(), to_value(n).unwrap());
+            let result = nth(nth_array(), args);
+            assert!(result.is_err());
+            assert_eq!(
+                result.unwrap_err().to_string(),
+                format!("Filter `nth` was called with `n={}` on an array of 4 values", n)
+            );
+        }
+    }
+
+    #[test]
+    fn test_nth_out_of_range_with_default() {
+        let mut args = HashMap::new();
+        args.insert("n".to_string(), to_value(10).unwrap());
+        args.insert("default".to_string(), to_value("none").unwrap());
+        let result = nth(nth_array(), args);
         assert!(result.is_ok());
-        assert_eq!(result.ok().unwrap(), to_value("").unwrap());
+        assert_eq!(result.unwrap(), to_value("none").unwrap());
+    }
+
+    #[test]
+    fn test_nth_without_n() {
+        let result = nth(nth_array(), HashMap::new());
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err().to_string(), "Filter `nth` expected an arg called `n`");
     }
 
     #[test]
@@ -105,4 +556,504 @@ mod tests {
         assert!(result.is_ok());
         assert_eq!(result.unwrap(), to_value(&"").unwrap());
     }
+
+    #[test]
+    fn test_min_max_integers() {
+        let result = min(to_value(vec![3, -1, 2]).unwrap(), HashMap::new());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(-1).unwrap());
+
+        let result = max(to_value(vec![3, -1, 2]).unwrap(), HashMap::new());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(3).unwrap());
+    }
+
+    #[test]
+    fn test_min_max_floats() {
+        let result = min(to_value(vec![1.5, 0.5, 2.0]).unwrap(), HashMap::new());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(0.5).unwrap());
+
+        let result = max(to_value(vec![to_value(1).unwrap(), to_value(2.5).unwrap()]).unwrap(), HashMap::new());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(2.5).unwrap());
+    }
+
+    #[test]
+    fn test_min_max_empty() {
+        let v: Vec<Value> = Vec::new();
+
+        let result = min(to_value(&v).unwrap(), HashMap::new());
+        assert!(result.is_err());
+        assert_eq!(result.err().unwrap().to_string(), "Filter `min` was called on an empty array");
+
+        let result = max(to_value(&v).unwrap(), HashMap::new());
+        assert!(result.is_err());
+        assert_eq!(result.err().unwrap().to_string(), "Filter `max` was called on an empty array");
+    }
+
+    #[test]
+    fn test_sum_integers() {
+        let result = sum(to_value(vec![1, 2, 3]).unwrap(), HashMap::new());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(6).unwrap());
+    }
+
+    #[test]
+    fn test_sum_floats() {
+        let result = sum(to_value(vec![to_value(1).unwrap(), to_value(2.5).unwrap()]).unwrap(), HashMap::new());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(3.5).unwrap());
+    }
+
+    #[test]
+    fn test_sum_empty() {
+        let v: Vec<Value> = Vec::new();
+        let result = sum(to_value(&v).unwrap(), HashMap::new());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(0).unwrap());
+    }
+
+    #[test]
+    fn test_aggregates_non_array() {
+        assert!(sum(to_value(1).unwrap(), HashMap::new()).is_err());
+        assert!(min(to_value("hello").unwrap(), HashMap::new()).is_err());
+
+        let result = max(to_value(vec![to_value(1).unwrap(), to_value("2").unwrap()]).unwrap(), HashMap::new());
+        assert!(result.is_err());
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "Filter `max` only works on arrays of numbers but got `\"2\"` in the array"
+        );
+    }
+
+    #[test]
+    fn test_sort_strings() {
+        let result = sort(to_value(vec!["b", "c", "a"]).unwrap(), HashMap::new());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(vec!["a", "b", "c"]).unwrap());
+    }
+
+    #[test]
+    fn test_sort_numbers() {
+        let v = vec![to_value(3).unwrap(), to_value(1.5).unwrap(), to_value(-2).unwrap()];
+        let result = sort(to_value(v).unwrap(), HashMap::new());
+        assert!(result.is_ok());
+        assert_eq!(
+            result.unwrap(),
+            to_value(vec![to_value(-2).unwrap(), to_value(1.5).unwrap(), to_value(3).unwrap()]).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_sort_reverse() {
+        let mut args = HashMap::new();
+        args.insert("reverse".to_string(), to_value(true).unwrap());
+        let result = sort(to_value(vec![2, 3, 1]).unwrap(), args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(vec![3, 2, 1]).unwrap());
+    }
+
+    #[test]
+    fn test_sort_objects_by_attribute() {
+        let mut bob = HashMap::new();
+        bob.insert("name".to_string(), to_value("Bob").unwrap());
+        bob.insert("age".to_string(), to_value(42).unwrap());
+        let mut alice = HashMap::new();
+        alice.insert("name".to_string(), to_value("Alice").unwrap());
+        alice.insert("age".to_string(), to_value(30).unwrap());
+        let mut carl = HashMap::new();
+        carl.insert("name".to_string(), to_value("Carl").unwrap());
+        carl.insert("age".to_string(), to_value(42).unwrap());
+        let users = vec![bob.clone(), alice.clone(), carl.clone()];
+
+        let mut args = HashMap::new();
+        args.insert("attribute".to_string(), to_value("age").unwrap());
+        let result = sort(to_value(&users).unwrap(), args.clone());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(vec![alice.clone(), bob.clone(), carl.clone()]).unwrap());
+
+        // equal items keep their order when reversed
+        args.insert("reverse".to_string(), to_value(true).unwrap());
+        let result = sort(to_value(&users).unwrap(), args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(vec![bob, carl, alice]).unwrap());
+    }
+
+    #[test]
+    fn test_sort_nested_attribute() {
+        let mut args = HashMap::new();
+        args.insert("attribute".to_string(), to_value("meta.rank").unwrap());
+        let items: Vec<Value> = vec![
+            serde_json::from_str(r#"{"meta": {"rank": 2}}"#).unwrap(),
+            serde_json::from_str(r#"{"meta": {"rank": 1}}"#).unwrap(),
+        ];
+        let result = sort(to_value(&items).unwrap(), args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(vec![items[1].clone(), items[0].clone()]).unwrap());
+    }
+
+    #[test]
+    fn test_sort_empty() {
+        let v: Vec<Value> = Vec::new();
+        let result = sort(to_value(&v).unwrap(), HashMap::new());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(&v).unwrap());
+    }
+
+    #[test]
+    fn test_sort_mixed_types() {
+        let v = vec![to_value(1).unwrap(), to_value("a").unwrap()];
+        let result = sort(to_value(v).unwrap(), HashMap::new());
+        assert!(result.is_err());
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "Filter `sort` can't sort values of different types: got a number and a string"
+        );
+    }
+
+    #[test]
+    fn test_sort_missing_attribute() {
+        let mut args = HashMap::new();
+        args.insert("attribute".to_string(), to_value("age").unwrap());
+        let items: Vec<Value> = vec![serde_json::from_str(r#"{"name": "Bob"}"#).unwrap()];
+        let result = sort(to_value(&items).unwrap(), args);
+        assert!(result.is_err());
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "Filter `sort` tried to sort by `age` but it is missing in `{\"name\":\"Bob\"}`"
+        );
+    }
+
+    #[test]
+    fn test_dictsort() {
+        let obj: Value = serde_json::from_str(r#"{"b": 1, "c": 3, "a": 2}"#).unwrap();
+        let tests: Vec<(Vec<(&str, Value)>, Value)> = vec![
+            (vec![], serde_json::from_str(r#"[["a", 2], ["b", 1], ["c", 3]]"#).unwrap()),
+            (vec![("by", to_value("key").unwrap())], serde_json::from_str(r#"[["a", 2], ["b", 1], ["c", 3]]"#).unwrap()),
+            (vec![("reverse", to_value(true).unwrap())], serde_json::from_str(r#"[["c", 3], ["b", 1], ["a", 2]]"#).unwrap()),
+            (vec![("by", to_value("value").unwrap())], serde_json::from_str(r#"[["b", 1], ["a", 2], ["c", 3]]"#).unwrap()),
+            (
+                vec![("by", to_value("value").unwrap()), ("reverse", to_value(true).unwrap())],
+                serde_json::from_str(r#"[["c", 3], ["a", 2], ["b", 1]]"#).unwrap()
+            ),
+        ];
+        for (arg_list, expected) in tests {
+            let mut args = HashMap::new();
+            for (k, v) in arg_list {
+                args.insert(k.to_string(), v);
+            }
+            let result = dictsort(obj.clone(), args);
+            assert!(result.is_ok());
+            assert_eq!(result.unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_dictsort_errors() {
+        let mut args = HashMap::new();
+        args.insert("by".to_string(), to_value("value").unwrap());
+        let mixed: Value = serde_json::from_str(r#"{"a": 1, "b": "x"}"#).unwrap();
+        let result = dictsort(mixed, args);
+        assert!(result.is_err());
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Filter `dictsort` can't sort values of different types: got a number and a string"
+        );
+
+        let mut args = HashMap::new();
+        args.insert("by".to_string(), to_value("size").unwrap());
+        let result = dictsort(serde_json::from_str(r#"{"a": 1}"#).unwrap(), args);
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err().to_string(), "Filter `dictsort` can sort by `key` or `value` but got `size`");
+
+        assert!(dictsort(to_value(vec![1, 2]).unwrap(), HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_group_by() {
+        let items: Vec<Value> = serde_json::from_str(r#"[
+            {"name": "apple", "category": "fruit"},
+            {"name": "leek", "category": "vegetable"},
+            {"name": "pear", "category": "fruit"}
+        ]"#).unwrap();
+        let mut args = HashMap::new();
+        args.insert("attribute".to_string(), to_value("category").unwrap());
+        let result = group_by(to_value(&items).unwrap(), args);
+        assert!(result.is_ok());
+        let expected: Value = serde_json::from_str(r#"{
+            "fruit": [{"name": "apple", "category": "fruit"}, {"name": "pear", "category": "fruit"}],
+            "vegetable": [{"name": "leek", "category": "vegetable"}]
+        }"#).unwrap();
+        assert_eq!(result.unwrap(), expected);
+    }
+
+    #[test]
+    fn test_group_by_number_nested_attribute() {
+        let items: Vec<Value> = serde_json::from_str(r#"[
+            {"name": "Bob", "meta": {"year": 2016}},
+            {"name": "Alice", "meta": {"year": 2017}}
+        ]"#).unwrap();
+        let mut args = HashMap::new();
+        args.insert("attribute".to_string(), to_value("meta.year").unwrap());
+        let result = group_by(to_value(&items).unwrap(), args);
+        assert!(result.is_ok());
+        let expected: Value = serde_json::from_str(r#"{
+            "2016": [{"name": "Bob", "meta": {"year": 2016}}],
+            "2017": [{"name": "Alice", "meta": {"year": 2017}}]
+        }"#).unwrap();
+        assert_eq!(result.unwrap(), expected);
+    }
+
+    #[test]
+    fn test_group_by_skips_missing_attribute() {
+        let items: Vec<Value> = serde_json::from_str(r#"[
+            {"name": "apple", "category": "fruit"},
+            {"name": "rock"},
+            {"name": "void", "category": null}
+        ]"#).unwrap();
+        let mut args = HashMap::new();
+        args.insert("attribute".to_string(), to_value("category").unwrap());
+        let result = group_by(to_value(&items).unwrap(), args);
+        assert!(result.is_ok());
+        let expected: Value = serde_json::from_str(r#"{
+            "fruit": [{"name": "apple", "category": "fruit"}]
+        }"#).unwrap();
+        assert_eq!(result.unwrap(), expected);
+    }
+
+    #[test]
+    fn test_group_by_errors() {
+        let items: Vec<Value> = serde_json::from_str(r#"[{"tags": ["a"]}]"#).unwrap();
+        assert!(group_by(to_value(&items).unwrap(), HashMap::new()).is_err());
+
+        let mut args = HashMap::new();
+        args.insert("attribute".to_string(), to_value("tags").unwrap());
+        let result = group_by(to_value(&items).unwrap(), args);
+        assert!(result.is_err());
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "Filter `group_by` can only group by a string, a number or a boolean but got `[\"a\"]`"
+        );
+    }
+
+    #[test]
+    fn test_map() {
+        let users: Vec<Value> = serde_json::from_str(r#"[
+            {"name": "Bob", "email": "bob@example.com"},
+            {"name": "Alice", "email": "alice@example.com"}
+        ]"#).unwrap();
+        let mut args = HashMap::new();
+        args.insert("attribute".to_string(), to_value("email").unwrap());
+        let result = map(to_value(&users).unwrap(), args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(vec!["bob@example.com", "alice@example.com"]).unwrap());
+    }
+
+    #[test]
+    fn test_map_nested_attribute() {
+        let users: Vec<Value> = serde_json::from_str(r#"[
+            {"name": "Bob", "address": {"city": "Paris"}},
+            {"name": "Alice", "address": {"city": "Tokyo"}}
+        ]"#).unwrap();
+        let mut args = HashMap::new();
+        args.insert("attribute".to_string(), to_value("address.city").unwrap());
+        let result = map(to_value(&users).unwrap(), args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(vec!["Paris", "Tokyo"]).unwrap());
+    }
+
+    #[test]
+    fn test_map_missing_attribute() {
+        let users: Vec<Value> = serde_json::from_str(r#"[
+            {"name": "Bob", "email": "bob@example.com"},
+            {"name": "Alice"}
+        ]"#).unwrap();
+        let mut args = HashMap::new();
+        args.insert("attribute".to_string(), to_value("email").unwrap());
+        let result = map(to_value(&users).unwrap(), args.clone());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(vec![to_value("bob@example.com").unwrap(), Value::Null]).unwrap());
+
+        args.insert("skip_missing".to_string(), to_value(true).unwrap());
+        let result = map(to_value(&users).unwrap(), args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(vec!["bob@example.com"]).unwrap());
+    }
+
+    #[test]
+    fn test_map_errors() {
+        let mut args = HashMap::new();
+        args.insert("attribute".to_string(), to_value("email").unwrap());
+        assert!(map(to_value("bob").unwrap(), args).is_err());
+        assert!(map(to_value(vec![1]).unwrap(), HashMap::new()).is_err());
+    }
+
+    fn products() -> Vec<Value> {
+        serde_json::from_str(r#"[
+            {"name": "apple", "in_stock": true, "price": 1, "meta": {"origin": "fr"}},
+            {"name": "pear", "in_stock": false, "price": 2.0, "meta": {"origin": "es"}},
+            {"name": "leek", "in_stock": true, "price": 2, "meta": {"origin": "fr"}},
+            {"name": "rock"}
+        ]"#).unwrap()
+    }
+
+    fn names(value: Value) -> Vec<String> {
+        value.as_array().unwrap().iter().map(|p| p["name"].as_str().unwrap().to_string()).collect()
+    }
+
+    #[test]
+    fn test_filter_equality() {
+        let mut args = HashMap::new();
+        args.insert("attribute".to_string(), to_value("in_stock").unwrap());
+        args.insert("value".to_string(), to_value(false).unwrap());
+        let result = filter(to_value(products()).unwrap(), args.clone());
+        assert!(result.is_ok());
+        assert_eq!(names(result.unwrap()), vec!["pear"]);
+
+        args.insert("attribute".to_string(), to_value("price").unwrap());
+        args.insert("value".to_string(), to_value(2).unwrap());
+        let result = filter(to_value(products()).unwrap(), args);
+        assert!(result.is_ok());
+        assert_eq!(names(result.unwrap()), vec!["pear", "leek"]);
+    }
+
+    #[test]
+    fn test_filter_truthy() {
+        let mut args = HashMap::new();
+        args.insert("attribute".to_string(), to_value("in_stock").unwrap());
+        let result = filter(to_value(products()).unwrap(), args);
+        assert!(result.is_ok());
+        assert_eq!(names(result.unwrap()), vec!["apple", "leek"]);
+    }
+
+    #[test]
+    fn test_filter_nested_attribute() {
+        let mut args = HashMap::new();
+        args.insert("attribute".to_string(), to_value("meta.origin").unwrap());
+        args.insert("value".to_string(), to_value("fr").unwrap());
+        let result = filter(to_value(products()).unwrap(), args);
+        assert!(result.is_ok());
+        assert_eq!(names(result.unwrap()), vec!["apple", "leek"]);
+    }
+
+    #[test]
+    fn test_filter_empty_result() {
+        let mut args = HashMap::new();
+        args.insert("attribute".to_string(), to_value("name").unwrap());
+        args.insert("value".to_string(), to_value("banana").unwrap());
+        let result = filter(to_value(products()).unwrap(), args);
+        assert!(result.is_ok());
+        let empty: Vec<Value> = vec![];
+        assert_eq!(result.unwrap(), to_value(empty).unwrap());
+    }
+
+    #[test]
+    fn test_filter_errors() {
+        let mut args = HashMap::new();
+        args.insert("attribute".to_string(), to_value("name").unwrap());
+        assert!(filter(to_value("apple").unwrap(), args).is_err());
+        assert!(filter(to_value(products()).unwrap(), HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_batch_evenly_divisible() {
+        let mut args = HashMap::new();
+        args.insert("count".to_string(), to_value(2).unwrap());
+        let result = batch(to_value([1, 2, 3, 4]).unwrap(), args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value([[1, 2], [3, 4]]).unwrap());
+    }
+
+    #[test]
+    fn test_batch_remainder() {
+        let mut args = HashMap::new();
+        args.insert("count".to_string(), to_value(3).unwrap());
+        let result = batch(to_value([1, 2, 3, 4, 5]).unwrap(), args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), serde_json::from_str::<Value>("[[1, 2, 3], [4, 5]]").unwrap());
+    }
+
+    #[test]
+    fn test_batch_fill_with() {
+        let mut args = HashMap::new();
+        args.insert("count".to_string(), to_value(3).unwrap());
+        args.insert("fill_with".to_string(), to_value("").unwrap());
+        let result = batch(to_value(["a", "b", "c", "d"]).unwrap(), args.clone());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value([["a", "b", "c"], ["d", "", ""]]).unwrap());
+
+        // nothing to fill
+        let result = batch(to_value(["a", "b", "c"]).unwrap(), args.clone());
+        assert_eq!(result.unwrap(), to_value([["a", "b", "c"]]).unwrap());
+        let empty: Vec<Value> = Vec::new();
+        let result = batch(to_value(&empty).unwrap(), args);
+        assert_eq!(result.unwrap(), to_value(empty).unwrap());
+    }
+
+    #[test]
+    fn test_batch_invalid_count() {
+        let mut args = HashMap::new();
+        args.insert("count".to_string(), to_value(0).unwrap());
+        let result = batch(to_value([1, 2]).unwrap(), args);
+        assert!(result.is_err());
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "Filter `batch` was called with a `count` of 0 but it needs to be positive"
+        );
+
+        let mut args = HashMap::new();
+        args.insert("count".to_string(), to_value(-1).unwrap());
+        assert!(batch(to_value([1, 2]).unwrap(), args).is_err());
+
+        let result = batch(to_value([1, 2]).unwrap(), HashMap::new());
+        assert_eq!(result.err().unwrap().to_string(), "Filter `batch` expected an arg called `count`");
+    }
+
+    #[test]
+    fn test_unique() {
+        let result = unique(to_value([3, 1, 3, 2, 1]).unwrap(), HashMap::new());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value([3, 1, 2]).unwrap());
+
+        let result = unique(serde_json::from_str("[1, 1.0, \"1\", true, null, null]").unwrap(), HashMap::new());
+        assert_eq!(result.unwrap(), serde_json::from_str::<Value>("[1, \"1\", true, null]").unwrap());
+    }
+
+    #[test]
+    fn test_unique_by_attribute() {
+        let mut args = HashMap::new();
+        args.insert("attribute".to_string(), to_value("meta.origin").unwrap());
+        let result = unique(to_value(products()).unwrap(), args);
+        assert!(result.is_ok());
+        assert_eq!(names(result.unwrap()), ["apple", "pear", "rock"]);
+
+        let mut args = HashMap::new();
+        args.insert("attribute".to_string(), to_value("price").unwrap());
+        let result = unique(to_value(products()).unwrap(), args);
+        assert_eq!(names(result.unwrap()), ["apple", "pear", "rock"]);
+    }
+
+    #[test]
+    fn test_unique_case_insensitive() {
+        let tags = to_value(["Rust", "web", "rust", "Web", "RUST", "cli"]).unwrap();
+        let result = unique(tags.clone(), HashMap::new());
+        assert_eq!(result.unwrap(), to_value(["Rust", "web", "rust", "Web", "RUST", "cli"]).unwrap());
+
+        let mut args = HashMap::new();
+        args.insert("case_sensitive".to_string(), to_value(false).unwrap());
+        let result = unique(tags, args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(["Rust", "web", "cli"]).unwrap());
+    }
+
+    #[test]
+    fn test_unique_errors() {
+        let result = unique(to_value("apple").unwrap(), HashMap::new());
+        assert!(result.is_err());
+
+        let mut args = HashMap::new();
+        args.insert("case_sensitive".to_string(), to_value("no").unwrap());
+        assert!(unique(to_value([1, 2]).unwrap(), args).is_err());
+    }
 }
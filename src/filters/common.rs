@@ -2,22 +2,33 @@
 use std::collections::HashMap;
 use std::iter::FromIterator;
 
+use serde_json::{to_string, to_string_pretty};
 use serde_json::value::{Value, to_value};
 use errors::Result;
+use context::{ValueRender, ValueTruthy};
+use filters::value_kind;
+use utils::value_to_int;
 
 use chrono::{NaiveDateTime, DateTime, FixedOffset};
 
-// Returns the number of items in an array or the number of characters in a string.
-// Returns 0 if not an array or string.
+/// Returns the number of items in an array, the number of keys in an object
+/// or the number of characters in a string.
+/// Errors on any other type.
 pub fn length(value: Value, _: HashMap<String, Value>) -> Result<Value> {
     match value {
         Value::Array(arr) => Ok(to_value(&arr.len()).unwrap()),
+        Value::Object(m) => Ok(to_value(m.len()).unwrap()),
         Value::String(s) => Ok(to_value(&s.chars().count()).unwrap()),
-        _ => Ok(to_value(0).unwrap()),
+        _ => bail!(
+            "Filter `length` was called on {}: got `{}` but expected an array, an object or a string",
+            value_kind(&value),
+            value
+        ),
     }
 }
 
-// Reverses the elements of an array or the characters in a string.
+/// Reverses the elements of an array or the characters in a string.
+/// Errors on any other type.
 pub fn reverse(value: Value, _: HashMap<String, Value>) -> Result<Value> {
     match value {
         Value::Array(mut arr) => {
@@ -25,15 +36,35 @@ pub fn reverse(value: Value, _: HashMap<String, Value>) -> Result<Value> {
             Ok(to_value(&arr)?)
         }
         Value::String(s) => Ok(to_value(&String::from_iter(s.chars().rev()))?),
-        _ => {
-            bail!(
-                "Filter `reverse` received an incorrect type for arg `value`: got `{}` but expected Array|String",
-                value.to_string()
-            );
-        }
+        _ => bail!(
+            "Filter `reverse` was called on {}: got `{}` but expected an array or a string",
+            value_kind(&value),
+            value
+        ),
     }
 }
 
+/// Returns the `value` argument if the value is undefined or null, the value itself otherwise.
+/// If `boolean` is true, falsy values like empty strings and arrays are replaced as well.
+///
+/// Undefined variables are given to this filter as `null` by the renderer
+/// as long as it is the first filter applied.
+pub fn default(value: Value, mut args: HashMap<String, Value>) -> Result<Value> {
+    let default_value = match args.remove("value") {
+        Some(val) => val,
+        None => bail!("Filter `default` expected an arg called `value`")
+    };
+    let boolean = match args.remove("boolean") {
+        Some(val) => try_get_value!("default", "boolean", bool, val),
+        None => false,
+    };
+
+    if value.is_null() || (boolean && !value.is_truthy()) {
+        Ok(default_value)
+    } else {
+        Ok(value)
+    }
+}
 
 /// Returns a formatted time according to the given `format` argument.
 /// `format` defaults to the ISO 8601 `YYYY-MM-DD` format.
@@ -54,7 +85,7 @@ pub fn date(value: Value, mut args: HashMap<String, Value>) -> Result<Value> {
         Value::String(s) => {
             match s.parse::<DateTime<FixedOffset>>() {
                 Ok(val) => val.naive_local(),
-                Err(_) => bail!("Error parsing `{:?}` as rfc3339 date", s)
+                Err(_) => bail!("Filter `date` could not parse `{}` as a RFC3339 date", s)
             }
         },
         _ => {
@@ -73,6 +104,302 @@ pub fn date(value: Value, mut args: HashMap<String, Value>) -> Result<Value> {
     Ok(to_value(&dt.format(&format).to_string())?)
 }
 
+/// Returns the value serialized as JSON, indented if `pretty` is true.
+///
+/// `</` is written as `<\/`, which is equivalent in JSON, so the output can't close a
+/// `<script>` tag it is embedded in.
+pub fn json_encode(value: Value, mut args: HashMap<String, Value>) -> Result<Value> {
+    let pretty = match args.remove("pretty") {
+        Some(val) => try_get_value!("json_encode", "pretty", bool, val),
+        None => false,
+    };
+
+    let json = if pretty {
+        to_string_pretty(&value)?
+    } else {
+        to_string(&value)?
+    };
+
+    Ok(to_value(json.replace("</", "<\\/"))?)
+}
+
+// Writes `value` in `out` with its type after it, nested values being indented
+// by 2 more spaces than `indent`
+fn pprint_value(value: &Value, indent: usize, out: &mut String) {
+    let pad = " ".repeat(indent + 2);
+    match *value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(b) => out.push_str(&format!("{} (bool)", b)),
+        Value::Number(ref n) if n.is_f64() => out.push_str(&format!("{} (float)", n)),
+        Value::Number(ref n) => out.push_str(&format!("{} (integer)", n)),
+        // Serializing a string can't fail
+        Value::String(ref s) => out.push_str(&format!("{} (string)", to_string(s).unwrap())),
+        Value::Array(ref arr) => {
+            out.push('[');
+            for item in arr {
+                out.push('\n');
+                out.push_str(&pad);
+                pprint_value(item, indent + 2, out);
+                out.push(',');
+            }
+            if !arr.is_empty() {
+                out.push('\n');
+                out.push_str(&" ".repeat(indent));
+            }
+            out.push_str(&format!("] (array, {} item{})", arr.len(), if arr.len() == 1 { "" } else { "s" }));
+        },
+        Value::Object(ref map) => {
+            out.push('{');
+            for (key, item) in map {
+                out.push('\n');
+                out.push_str(&pad);
+                out.push_str(&to_string(key).unwrap());
+                out.push_str(": ");
+                pprint_value(item, indent + 2, out);
+                out.push(',');
+            }
+            if !map.is_empty() {
+                out.push('\n');
+                out.push_str(&" ".repeat(indent));
+            }
+            out.push_str(&format!("}} (object, {} key{})", map.len(), if map.len() == 1 { "" } else { "s" }));
+        },
+    }
+}
+
+/// Returns an indented representation of any value with the type of each value
+/// written after it, to see what a variable contains when debugging a template.
+/// Unlike `json_encode` the output is not valid JSON. Never errors.
+pub fn pprint(value: Value, _: HashMap<String, Value>) -> Result<Value> {
+    let mut out = String::new();
+    pprint_value(&value, 0, &mut out);
+    Ok(to_value(out).unwrap())
+}
+
+/// Returns the member of an object at the string `key` or the item of an array at the
+/// integer `key`, allowing to access values with a key coming from a variable.
+/// Errors if the key is missing or out of range, unless a `default` argument is given.
+pub fn get(value: Value, mut args: HashMap<String, Value>) -> Result<Value> {
+    let key = match args.remove("key") {
+        Some(val) => val,
+        None => bail!("Filter `get` expected an arg called `key`"),
+    };
+    let default_value = args.remove("default");
+
+    let found = match value {
+        Value::Object(mut map) => {
+            let k = try_get_value!("get", "key", String, key);
+            match map.remove(&k) {
+                Some(v) => v,
+                None => match default_value {
+                    Some(d) => d,
+                    None => bail!("Filter `get` tried to get key `{}` but it is not in the object", k),
+                },
+            }
+        },
+        Value::Array(mut arr) => {
+            let index = match value_to_int(&key) {
+                Some(i) if i >= 0 => i as usize,
+                _ => bail!(
+                    "Filter `get` received an incorrect type for arg `key`: got `{}` but expected a positive integer to index an array",
+                    key
+                ),
+            };
+            if index < arr.len() {
+                arr.swap_remove(index)
+            } else {
+                match default_value {
+                    Some(d) => d,
+                    None => bail!(
+                        "Filter `get` tried to get index {} but the array only has {} items", index, arr.len()
+                    ),
+                }
+            }
+        },
+        _ => bail!(
+            "Filter `get` received an incorrect type for arg `value`: got `{}` but expected Object|Array",
+            value
+        ),
+    };
+
+    Ok(found)
+}
+
+/// Returns the value at the dotted `path` through nested objects and arrays, like `address.city`,
+/// integer segments being the index of an item of an array like in `items.0.name`.
+/// This is the dotted syntax of variables but with a path that can come from a variable.
+/// Errors if a segment of the path is missing, unless a `default` argument is given.
+pub fn attr(value: Value, mut args: HashMap<String, Value>) -> Result<Value> {
+    let path = match args.remove("path") {
+        Some(val) => try_get_value!("attr", "path", String, val),
+        None => bail!("Filter `attr` expected an arg called `path`"),
+    };
+    let default_value = args.remove("default");
+
+    let mut current = &value;
+    for segment in path.split('.') {
+        let next = match *current {
+            Value::Object(ref map) => map.get(segment),
+            Value::Array(ref arr) => segment.parse::<usize>().ok().and_then(|i| arr.get(i)),
+            _ => None,
+        };
+        current = match next {
+            Some(v) => v,
+            None => match default_value {
+                Some(d) => return Ok(d),
+                None => bail!("Filter `attr` tried to get `{}` but `{}` of the path doesn't exist", path, segment),
+            },
+        };
+    }
+
+    Ok(current.clone())
+}
+
+// Gets an optional integer argument of `slice`
+fn get_slice_arg(name: &str, args: &mut HashMap<String, Value>) -> Result<Option<i64>> {
+    match args.remove(name) {
+        Some(val) => match value_to_int(&val) {
+            Some(i) => Ok(Some(i)),
+            None => bail!(
+                "Filter `slice` received an incorrect type for arg `{}`: got `{}` but expected an integer",
+                name, val
+            ),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Returns a slice of an array or of the characters of a string, following the semantics
+/// of Python slices: `start` is inclusive and `end` exclusive, negative values count from
+/// the end and `step` can be negative to go backwards. Out of range bounds are clamped.
+pub fn slice(value: Value, mut args: HashMap<String, Value>) -> Result<Value> {
+    let start = get_slice_arg("start", &mut args)?;
+    let end = get_slice_arg("end", &mut args)?;
+    let step = get_slice_arg("step", &mut args)?.unwrap_or(1);
+    if step == 0 {
+        bail!("Filter `slice` was called with a `step` of 0");
+    }
+
+    // Works out the indices to take from a sequence of `len` items
+    let indices = |len: usize| -> Vec<usize> {
+        let len = len as i64;
+        // Resolves a negative index and clamps it between `lower` and `upper`
+        let bound = |index: i64, lower: i64, upper: i64| {
+            let index = if index < 0 { index + len } else { index };
+            index.max(lower).min(upper)
+        };
+        let (mut current, stop) = if step > 0 {
+            (start.map_or(0, |s| bound(s, 0, len)), end.map_or(len, |e| bound(e, 0, len)))
+        } else {
+            (start.map_or(len - 1, |s| bound(s, -1, len - 1)), end.map_or(-1, |e| bound(e, -1, len - 1)))
+        };
+
+        let mut indices = vec![];
+        while (step > 0 && current < stop) || (step < 0 && current > stop) {
+            indices.push(current as usize);
+            current += step;
+        }
+        indices
+    };
+
+    match value {
+        Value::Array(arr) => {
+            let sliced = indices(arr.len()).into_iter().map(|i| arr[i].clone()).collect::<Vec<_>>();
+            Ok(to_value(sliced)?)
+        },
+        Value::String(s) => {
+            let chars = s.chars().collect::<Vec<_>>();
+            let sliced = indices(chars.len()).into_iter().map(|i| chars[i]).collect::<String>();
+            Ok(to_value(sliced)?)
+        },
+        _ => bail!(
+            "Filter `slice` received an incorrect type for arg `value`: got `{}` but expected Array|String",
+            value
+        ),
+    }
+}
+
+// Parses a string as an integer in the given base, allowing surrounding whitespace, a sign
+// and the `0x`, `0o` or `0b` prefix matching the base
+fn parse_int(s: &str, base: u32) -> Option<i64> {
+    let s = s.trim();
+    let (sign, digits) = match s.chars().next() {
+        Some('-') => ("-", &s[1..]),
+        Some('+') => ("", &s[1..]),
+        _ => ("", s),
+    };
+    let prefix = match base {
+        16 => Some("0x"),
+        8 => Some("0o"),
+        2 => Some("0b"),
+        _ => None,
+    };
+    let digits = match prefix {
+        Some(p) if digits.len() > 2 && digits.get(..2).is_some_and(|d| d.eq_ignore_ascii_case(p)) => &digits[2..],
+        _ => digits,
+    };
+    // `from_str_radix` would accept another sign after the one we removed
+    if digits.starts_with(['-', '+']) {
+        return None;
+    }
+    i64::from_str_radix(&format!("{}{}", sign, digits), base).ok()
+}
+
+/// Converts a string to an integer, parsing it in `base` (10 by default, between 2 and 36).
+/// Integers are returned as they are and floats are truncated.
+/// If the value can't be converted, `default` is returned if it was given and it is an error otherwise.
+pub fn int(value: Value, mut args: HashMap<String, Value>) -> Result<Value> {
+    let base = match args.remove("base") {
+        Some(val) => try_get_value!("int", "base", u32, val),
+        None => 10,
+    };
+    if !(2..=36).contains(&base) {
+        bail!("Filter `int` was called with a `base` of {} but it needs to be between 2 and 36", base);
+    }
+    let default_value = args.remove("default");
+
+    let converted = match value {
+        Value::Number(ref n) if n.is_f64() => n.as_f64().map(|f| to_value(f.trunc() as i64).unwrap()),
+        Value::Number(_) => Some(value.clone()),
+        Value::String(ref s) => parse_int(s, base).map(|i| to_value(i).unwrap()),
+        _ => None,
+    };
+
+    match (converted, default_value) {
+        (Some(v), _) => Ok(v),
+        (None, Some(d)) => Ok(d),
+        (None, None) => bail!("Filter `int` couldn't convert `{}` to an integer in base {}", value, base),
+    }
+}
+
+/// Converts a string to a float. Numbers are returned as floats.
+/// If the value can't be converted, `default` is returned if it was given and it is an error otherwise.
+pub fn float(value: Value, mut args: HashMap<String, Value>) -> Result<Value> {
+    let default_value = args.remove("default");
+
+    let converted = match value {
+        Value::Number(ref n) => n.as_f64(),
+        // JSON can't represent NaN and infinity
+        Value::String(ref s) => s.trim().parse::<f64>().ok().filter(|f| f.is_finite()),
+        _ => None,
+    };
+
+    match (converted, default_value) {
+        (Some(f), _) => Ok(to_value(f).unwrap()),
+        (None, Some(d)) => Ok(d),
+        (None, None) => bail!("Filter `float` couldn't convert `{}` to a float", value),
+    }
+}
+
+/// Converts any value to the string `{{ value }}` would render, so string filters can be
+/// used on numbers or booleans. Strings are returned unchanged.
+pub fn stringify(value: Value, _: HashMap<String, Value>) -> Result<Value> {
+    match value {
+        Value::String(_) => Ok(value),
+        _ => Ok(Value::String(value.render())),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
@@ -101,11 +428,42 @@ mod tests {
         assert_eq!(result.unwrap(), to_value(&3).unwrap());
     }
 
+    #[test]
+    fn test_length_object() {
+        let mut map = HashMap::new();
+        map.insert("a", 1);
+        map.insert("b", 2);
+        let result = length(to_value(map).unwrap(), HashMap::new());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(2).unwrap());
+    }
+
+    #[test]
+    fn test_length_empty_array() {
+        let empty: Vec<Value> = vec![];
+        let result = length(to_value(empty).unwrap(), HashMap::new());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(0).unwrap());
+    }
+
     #[test]
     fn test_length_num() {
         let result = length(to_value(&15).unwrap(), HashMap::new());
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap(), to_value(&0).unwrap());
+        assert!(result.is_err());
+        assert_eq!(
+            result.err().unwrap().description(),
+            "Filter `length` was called on a number: got `15` but expected an array, an object or a string"
+        );
+    }
+
+    #[test]
+    fn test_length_bool() {
+        let result = length(to_value(true).unwrap(), HashMap::new());
+        assert!(result.is_err());
+        assert_eq!(
+            result.err().unwrap().description(),
+            "Filter `length` was called on a boolean: got `true` but expected an array, an object or a string"
+        );
     }
 
     #[test]
@@ -128,7 +486,77 @@ mod tests {
         assert!(result.is_err());
         assert_eq!(
             result.err().unwrap().description(),
-            "Filter `reverse` received an incorrect type for arg `value`: got `1.23` but expected Array|String"
+            "Filter `reverse` was called on a number: got `1.23` but expected an array or a string"
+        );
+
+        let result = reverse(::serde_json::from_str("{\"a\": 1}").unwrap(), HashMap::new());
+        assert_eq!(
+            result.err().unwrap().description(),
+            "Filter `reverse` was called on an object: got `{\"a\":1}` but expected an array or a string"
+        );
+    }
+
+    #[test]
+    fn test_reverse_multibyte_str() {
+        let result = reverse(to_value("日本語 ok").unwrap(), HashMap::new());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("ko 語本日").unwrap());
+    }
+
+    #[test]
+    fn test_reverse_empty() {
+        let result = reverse(to_value("").unwrap(), HashMap::new());
+        assert_eq!(result.unwrap(), to_value("").unwrap());
+
+        let empty: Vec<Value> = Vec::new();
+        let result = reverse(to_value(&empty).unwrap(), HashMap::new());
+        assert_eq!(result.unwrap(), to_value(&empty).unwrap());
+    }
+
+    #[test]
+    fn test_default_null() {
+        let mut args = HashMap::new();
+        args.insert("value".to_string(), to_value("Anonymous").unwrap());
+        let result = default(Value::Null, args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("Anonymous").unwrap());
+    }
+
+    #[test]
+    fn test_default_passthrough() {
+        let mut args = HashMap::new();
+        args.insert("value".to_string(), to_value("Anonymous").unwrap());
+        let result = default(to_value("Bob").unwrap(), args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("Bob").unwrap());
+    }
+
+    #[test]
+    fn test_default_empty_string() {
+        let mut args = HashMap::new();
+        args.insert("value".to_string(), to_value("Anonymous").unwrap());
+        let result = default(to_value("").unwrap(), args.clone());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("").unwrap());
+
+        args.insert("boolean".to_string(), to_value(true).unwrap());
+        let result = default(to_value("").unwrap(), args.clone());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("Anonymous").unwrap());
+
+        let empty: Vec<Value> = vec![];
+        let result = default(to_value(&empty).unwrap(), args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("Anonymous").unwrap());
+    }
+
+    #[test]
+    fn test_default_missing_arg() {
+        let result = default(Value::Null, HashMap::new());
+        assert!(result.is_err());
+        assert_eq!(
+            result.err().unwrap().description(),
+            "Filter `default` expected an arg called `value`"
         );
     }
 
@@ -157,4 +585,471 @@ mod tests {
         assert!(result.is_ok());
         assert_eq!(result.unwrap(), to_value(dt.format("%Y-%m-%d").to_string()).unwrap());
     }
+
+    #[test]
+    fn test_date_rfc3339_custom_format() {
+        let mut args = HashMap::new();
+        args.insert("format".to_string(), to_value("%d/%m/%Y %H:%M").unwrap());
+        let result = date(to_value("2017-03-05T14:30:00+02:00").unwrap(), args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("05/03/2017 14:30").unwrap());
+    }
+
+    #[test]
+    fn test_date_invalid_string() {
+        let result = date(to_value("not a date").unwrap(), HashMap::new());
+        assert!(result.is_err());
+        assert_eq!(
+            result.err().unwrap().description(),
+            "Filter `date` could not parse `not a date` as a RFC3339 date"
+        );
+    }
+
+    #[test]
+    fn test_date_invalid_type() {
+        let result = date(to_value(true).unwrap(), HashMap::new());
+        assert!(result.is_err());
+        let result = date(to_value(1.5).unwrap(), HashMap::new());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_pprint_scalars() {
+        let tests = [
+            (Value::Null, "null"),
+            (to_value(true).unwrap(), "true (bool)"),
+            (to_value(-42).unwrap(), "-42 (integer)"),
+            (to_value(1.5).unwrap(), "1.5 (float)"),
+            (to_value("say \"hi\"").unwrap(), "\"say \\\"hi\\\"\" (string)"),
+        ];
+        for (value, expected) in tests {
+            let result = pprint(value, HashMap::new());
+            assert!(result.is_ok());
+            assert_eq!(result.unwrap(), to_value(expected).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_pprint_array() {
+        let result = pprint(to_value(vec![vec![1, 2], vec![]]).unwrap(), HashMap::new());
+        assert!(result.is_ok());
+        assert_eq!(
+            result.unwrap(),
+            to_value("[\n  [\n    1 (integer),\n    2 (integer),\n  ] (array, 2 items),\n  [] (array, 0 items),\n] (array, 2 items)").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_pprint_object() {
+        let value: Value = ::serde_json::from_str(r#"{"name": "Bob", "tags": [null], "admin": {}}"#).unwrap();
+        let result = pprint(value, HashMap::new());
+        assert!(result.is_ok());
+        assert_eq!(
+            result.unwrap(),
+            to_value("{\n  \"admin\": {} (object, 0 keys),\n  \"name\": \"Bob\" (string),\n  \"tags\": [\n    null,\n  ] (array, 1 item),\n} (object, 3 keys)").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_int() {
+        let tests = [
+            (to_value("42").unwrap(), 42),
+            (to_value(" -7 ").unwrap(), -7),
+            (to_value("+3").unwrap(), 3),
+            (to_value(12).unwrap(), 12),
+            (to_value(-3.9).unwrap(), -3),
+        ];
+        for (value, expected) in tests {
+            let result = int(value, HashMap::new());
+            assert!(result.is_ok());
+            let result = result.unwrap();
+            assert!(result.is_i64() || result.is_u64());
+            assert_eq!(result, to_value(expected).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_int_base() {
+        let tests = [
+            ("ff", 16, 255),
+            ("0xFF", 16, 255),
+            ("-0x1f", 16, -31),
+            ("0o17", 8, 15),
+            ("101", 2, 5),
+            ("0b101", 2, 5),
+            ("z", 36, 35),
+        ];
+        for (value, base, expected) in tests {
+            let mut args = HashMap::new();
+            args.insert("base".to_string(), to_value(base).unwrap());
+            let result = int(to_value(value).unwrap(), args);
+            assert!(result.is_ok());
+            assert_eq!(result.unwrap(), to_value(expected).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_int_default() {
+        for value in [to_value("abc").unwrap(), to_value("1.5").unwrap(), to_value("0x").unwrap(), Value::Null] {
+            let mut args = HashMap::new();
+            args.insert("default".to_string(), to_value(0).unwrap());
+            let result = int(value, args);
+            assert!(result.is_ok());
+            assert_eq!(result.unwrap(), to_value(0).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_int_errors() {
+        let result = int(to_value("abc").unwrap(), HashMap::new());
+        assert!(result.is_err());
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "Filter `int` couldn't convert `\"abc\"` to an integer in base 10"
+        );
+        // a prefix of another base isn't allowed
+        let mut args = HashMap::new();
+        args.insert("base".to_string(), to_value(10).unwrap());
+        assert!(int(to_value("0x10").unwrap(), args).is_err());
+        assert!(int(to_value("--1").unwrap(), HashMap::new()).is_err());
+
+        let mut args = HashMap::new();
+        args.insert("base".to_string(), to_value(1).unwrap());
+        let result = int(to_value("1").unwrap(), args);
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "Filter `int` was called with a `base` of 1 but it needs to be between 2 and 36"
+        );
+    }
+
+    #[test]
+    fn test_float() {
+        let tests = [
+            (to_value("1.5").unwrap(), 1.5),
+            (to_value(" -2 ").unwrap(), -2.0),
+            (to_value("1e3").unwrap(), 1000.0),
+            (to_value(3).unwrap(), 3.0),
+            (to_value(2.25).unwrap(), 2.25),
+        ];
+        for (value, expected) in tests {
+            let result = float(value, HashMap::new());
+            assert!(result.is_ok());
+            let result = result.unwrap();
+            assert!(result.is_f64());
+            assert_eq!(result, to_value(expected).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_stringify() {
+        let tests = vec![
+            (to_value(42).unwrap(), "42"),
+            (to_value(-1.5).unwrap(), "-1.5"),
+            (to_value(true).unwrap(), "true"),
+            (to_value(false).unwrap(), "false"),
+            (Value::Null, ""),
+            (to_value("hello").unwrap(), "hello"),
+            (to_value("").unwrap(), ""),
+            (to_value(vec![1, 2]).unwrap(), "[1, 2, ]"),
+        ];
+        for (value, expected) in tests {
+            let result = stringify(value, HashMap::new());
+            assert!(result.is_ok());
+            assert_eq!(result.unwrap(), to_value(expected).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_float_default() {
+        for value in [to_value("abc").unwrap(), to_value("NaN").unwrap(), to_value(true).unwrap()] {
+            let mut args = HashMap::new();
+            args.insert("default".to_string(), to_value(0.0).unwrap());
+            let result = float(value, args);
+            assert!(result.is_ok());
+            assert_eq!(result.unwrap(), to_value(0.0).unwrap());
+        }
+
+        let result = float(to_value("abc").unwrap(), HashMap::new());
+        assert!(result.is_err());
+        assert_eq!(result.err().unwrap().to_string(), "Filter `float` couldn't convert `\"abc\"` to a float");
+    }
+
+    #[test]
+    fn test_json_encode_object() {
+        let mut map = HashMap::new();
+        map.insert("name", "Bob");
+        let result = json_encode(to_value(map).unwrap(), HashMap::new());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("{\"name\":\"Bob\"}").unwrap());
+    }
+
+    #[test]
+    fn test_json_encode_array() {
+        let result = json_encode(to_value(vec![vec![1, 2], vec![3]]).unwrap(), HashMap::new());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("[[1,2],[3]]").unwrap());
+    }
+
+    #[test]
+    fn test_json_encode_string() {
+        let result = json_encode(to_value("hello \"world\"").unwrap(), HashMap::new());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("\"hello \\\"world\\\"\"").unwrap());
+    }
+
+    #[test]
+    fn test_json_encode_escapes_closing_tags() {
+        let result = json_encode(to_value("</script>").unwrap(), HashMap::new());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("\"<\\/script>\"").unwrap());
+    }
+
+    #[test]
+    fn test_json_encode_pretty() {
+        let mut map = HashMap::new();
+        map.insert("tags", vec!["a"]);
+        let mut args = HashMap::new();
+        args.insert("pretty".to_string(), to_value(true).unwrap());
+        let result = json_encode(to_value(map).unwrap(), args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("{\n  \"tags\": [\n    \"a\"\n  ]\n}").unwrap());
+    }
+
+    #[test]
+    fn test_get_object() {
+        let mut obj = HashMap::new();
+        obj.insert("name", "Bob");
+        let mut args = HashMap::new();
+        args.insert("key".to_string(), to_value("name").unwrap());
+        let result = get(to_value(&obj).unwrap(), args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("Bob").unwrap());
+    }
+
+    #[test]
+    fn test_get_array() {
+        let mut args = HashMap::new();
+        args.insert("key".to_string(), to_value(1).unwrap());
+        let result = get(to_value(vec!["a", "b", "c"]).unwrap(), args.clone());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("b").unwrap());
+
+        args.insert("key".to_string(), to_value(2.0).unwrap());
+        let result = get(to_value(vec!["a", "b", "c"]).unwrap(), args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("c").unwrap());
+    }
+
+    #[test]
+    fn test_get_default() {
+        let obj: HashMap<String, i64> = HashMap::new();
+        let mut args = HashMap::new();
+        args.insert("key".to_string(), to_value("name").unwrap());
+        args.insert("default".to_string(), to_value("Anonymous").unwrap());
+        let result = get(to_value(&obj).unwrap(), args.clone());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("Anonymous").unwrap());
+
+        args.insert("key".to_string(), to_value(5).unwrap());
+        let result = get(to_value(vec![1, 2]).unwrap(), args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("Anonymous").unwrap());
+    }
+
+    #[test]
+    fn test_get_missing_key() {
+        let obj: HashMap<String, i64> = HashMap::new();
+        let mut args = HashMap::new();
+        args.insert("key".to_string(), to_value("name").unwrap());
+        let result = get(to_value(&obj).unwrap(), args);
+        assert!(result.is_err());
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "Filter `get` tried to get key `name` but it is not in the object"
+        );
+    }
+
+    #[test]
+    fn test_get_out_of_range() {
+        let mut args = HashMap::new();
+        args.insert("key".to_string(), to_value(2).unwrap());
+        let result = get(to_value(vec![1, 2]).unwrap(), args);
+        assert!(result.is_err());
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "Filter `get` tried to get index 2 but the array only has 2 items"
+        );
+    }
+
+    #[test]
+    fn test_get_wrong_key_type() {
+        let mut args = HashMap::new();
+        args.insert("key".to_string(), to_value("1").unwrap());
+        assert!(get(to_value(vec![1, 2]).unwrap(), args.clone()).is_err());
+
+        args.insert("key".to_string(), to_value(-1).unwrap());
+        assert!(get(to_value(vec![1, 2]).unwrap(), args.clone()).is_err());
+
+        args.insert("key".to_string(), to_value(-1.0).unwrap());
+        assert!(get(to_value(vec![1, 2]).unwrap(), args.clone()).is_err());
+
+        args.insert("key".to_string(), to_value(1.5).unwrap());
+        assert!(get(to_value(vec![1, 2]).unwrap(), args).is_err());
+
+        let mut args = HashMap::new();
+        args.insert("key".to_string(), to_value("a").unwrap());
+        assert!(get(to_value(1).unwrap(), args).is_err());
+    }
+
+    fn record() -> Value {
+        ::serde_json::from_str(r#"{
+            "name": "Bob",
+            "address": {"city": "Paris", "zip": null},
+            "tags": [{"label": "admin"}, {"label": "staff"}]
+        }"#).unwrap()
+    }
+
+    #[test]
+    fn test_attr_nested_object() {
+        let mut args = HashMap::new();
+        args.insert("path".to_string(), to_value("address.city").unwrap());
+        let result = attr(record(), args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("Paris").unwrap());
+
+        // a null value is not missing
+        let mut args = HashMap::new();
+        args.insert("path".to_string(), to_value("address.zip").unwrap());
+        args.insert("default".to_string(), to_value("00000").unwrap());
+        assert_eq!(attr(record(), args).unwrap(), Value::Null);
+    }
+
+    #[test]
+    fn test_attr_array_index() {
+        let mut args = HashMap::new();
+        args.insert("path".to_string(), to_value("tags.1.label").unwrap());
+        let result = attr(record(), args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("staff").unwrap());
+
+        let mut args = HashMap::new();
+        args.insert("path".to_string(), to_value("0").unwrap());
+        assert_eq!(attr(to_value(["a", "b"]).unwrap(), args).unwrap(), to_value("a").unwrap());
+    }
+
+    #[test]
+    fn test_attr_missing_path() {
+        let tests = [
+            ("address.country", "country"),
+            ("tags.2.label", "2"),
+            ("tags.first", "first"),
+            ("name.length", "length"),
+        ];
+        for (path, segment) in tests {
+            let mut args = HashMap::new();
+            args.insert("path".to_string(), to_value(path).unwrap());
+            let result = attr(record(), args);
+            assert!(result.is_err());
+            assert_eq!(
+                result.err().unwrap().to_string(),
+                format!("Filter `attr` tried to get `{}` but `{}` of the path doesn't exist", path, segment)
+            );
+        }
+
+        assert!(attr(record(), HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_attr_default() {
+        let mut args = HashMap::new();
+        args.insert("path".to_string(), to_value("address.country").unwrap());
+        args.insert("default".to_string(), to_value("France").unwrap());
+        let result = attr(record(), args.clone());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("France").unwrap());
+
+        args.insert("path".to_string(), to_value("tags.5").unwrap());
+        assert_eq!(attr(record(), args).unwrap(), to_value("France").unwrap());
+    }
+
+    fn slice_args(start: Option<i64>, end: Option<i64>, step: Option<i64>) -> HashMap<String, Value> {
+        let mut args = HashMap::new();
+        if let Some(s) = start {
+            args.insert("start".to_string(), to_value(s).unwrap());
+        }
+        if let Some(e) = end {
+            args.insert("end".to_string(), to_value(e).unwrap());
+        }
+        if let Some(s) = step {
+            args.insert("step".to_string(), to_value(s).unwrap());
+        }
+        args
+    }
+
+    #[test]
+    fn test_slice_array() {
+        let tests = vec![
+            (slice_args(Some(1), Some(3), None), vec![2, 3]),
+            (slice_args(None, Some(2), None), vec![1, 2]),
+            (slice_args(Some(-2), None, None), vec![4, 5]),
+            (slice_args(Some(1), Some(-1), None), vec![2, 3, 4]),
+            (slice_args(None, None, Some(2)), vec![1, 3, 5]),
+            (slice_args(None, None, Some(-1)), vec![5, 4, 3, 2, 1]),
+            (slice_args(Some(3), Some(0), Some(-2)), vec![4, 2]),
+            (slice_args(Some(-10), Some(10), None), vec![1, 2, 3, 4, 5]),
+            (slice_args(Some(10), None, None), vec![]),
+            (slice_args(Some(3), Some(1), None), vec![]),
+        ];
+        for (args, expected) in tests {
+            let result = slice(to_value(vec![1, 2, 3, 4, 5]).unwrap(), args);
+            assert!(result.is_ok());
+            assert_eq!(result.unwrap(), to_value(expected).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_slice_string() {
+        let tests = vec![
+            (slice_args(Some(0), Some(3), None), "Hél"),
+            (slice_args(Some(-3), None, None), "llo"),
+            (slice_args(None, None, Some(2)), "Hlo"),
+            (slice_args(None, None, Some(-1)), "olléH"),
+            (slice_args(Some(2), Some(100), None), "llo"),
+            (slice_args(Some(-100), Some(-4), None), "H"),
+        ];
+        for (args, expected) in tests {
+            let result = slice(to_value("Héllo").unwrap(), args);
+            assert!(result.is_ok());
+            assert_eq!(result.unwrap(), to_value(expected).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_slice_integral_float_arg() {
+        let mut args = HashMap::new();
+        args.insert("end".to_string(), to_value(2.0).unwrap());
+        let result = slice(to_value("Hello").unwrap(), args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("He").unwrap());
+    }
+
+    #[test]
+    fn test_slice_errors() {
+        assert!(slice(to_value(1).unwrap(), HashMap::new()).is_err());
+        assert!(slice(to_value("a").unwrap(), slice_args(None, None, Some(0))).is_err());
+
+        let mut args = HashMap::new();
+        args.insert("start".to_string(), to_value("1").unwrap());
+        let result = slice(to_value("Hello").unwrap(), args);
+        assert!(result.is_err());
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "Filter `slice` received an incorrect type for arg `start`: got `\"1\"` but expected an integer"
+        );
+
+        // floats too large for an i64 aren't clamped to it
+        let mut args = HashMap::new();
+        args.insert("start".to_string(), to_value(1e30).unwrap());
+        assert!(slice(to_value("Hello").unwrap(), args).is_err());
+    }
 }
@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::sync::Arc;
 
 use serde_json::value::Value;
 use errors::Result;
@@ -9,4 +10,19 @@ pub mod number;
 pub mod array;
 pub mod common;
 
-pub type FilterFn = fn(Value, HashMap<String, Value>) -> Result<Value>;
+/// The type of filters: they receive the value to filter and their keyword arguments.
+/// They need to be `Send + Sync` so a `Tera` instance can be shared between threads.
+/// They are reference-counted so they can be shared between `Tera` instances with `Tera::extend`
+pub type FilterFn = Arc<dyn Fn(Value, HashMap<String, Value>) -> Result<Value> + Send + Sync>;
+
+/// Describes the type of a value for error messages, e.g. "a string" or "null".
+pub fn value_kind(value: &Value) -> &'static str {
+    match *value {
+        Value::String(_) => "a string",
+        Value::Number(_) => "a number",
+        Value::Bool(_) => "a boolean",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+        Value::Null => "null",
+    }
+}
@@ -3,8 +3,10 @@ use std::collections::HashMap;
 
 use serde_json::value::{Value, to_value};
 use humansize::{FileSize, file_size_opts};
+use humansize::file_size_opts::FileSizeOpts;
 
 use errors::Result;
+use filters::value_kind;
 
 
 /// Returns a suffix if the value is greater or equal than 2. Suffix defaults to `s`
@@ -25,8 +27,14 @@ pub fn pluralize(value: Value, mut args: HashMap<String, Value>) -> Result<Value
 /// Returns a rounded number using the `method` arg and `precision` given.
 /// `method` defaults to `common` which will round to the nearest number.
 /// `ceil` and `floor` are also available as method.
-/// `precision` defaults to `0`, meaning it will round to an integer
+/// `precision` defaults to `0`, meaning it will round to an integer.
+/// The result is an integer when using the `common` method with a `precision` of `0`
+/// and a float otherwise, or if the integer wouldn't fit in an i64.
 pub fn round(value: Value, mut args: HashMap<String, Value>) -> Result<Value> {
+    // `try_get_value` would happily convert a string containing a number
+    if !value.is_number() {
+        bail!("Filter `round` was called on an incorrect value: got `{}` but expected a f64", value);
+    }
     let num = try_get_value!("round", "value", f64, value);
     let method = match args.remove("method") {
         Some(val) => try_get_value!("round", "method", String, val),
@@ -39,6 +47,15 @@ pub fn round(value: Value, mut args: HashMap<String, Value>) -> Result<Value> {
     let multiplier = if precision == 0 { 1.0 } else { 10.0_f64.powi(precision) } ;
 
     match method.as_ref() {
+        "common" if precision == 0 => {
+            let rounded = num.round();
+            // `i64::MAX as f64` is 2^63, which is already too large
+            if rounded >= i64::MIN as f64 && rounded < i64::MAX as f64 {
+                Ok(to_value(rounded as i64).unwrap())
+            } else {
+                Ok(to_value(rounded).unwrap())
+            }
+        },
         "common" => Ok(to_value((multiplier * num).round() / multiplier).unwrap()),
         "ceil" => Ok(to_value((multiplier * num).ceil() / multiplier).unwrap()),
         "floor" => Ok(to_value((multiplier * num).floor() / multiplier).unwrap()),
@@ -51,23 +68,330 @@ pub fn round(value: Value, mut args: HashMap<String, Value>) -> Result<Value> {
 }
 
 
-/// Returns a human-readable file size (i.e. '110 MB') from an integer
-pub fn filesizeformat(value: Value, _: HashMap<String, Value>) -> Result<Value> {
+/// Returns the absolute value of a number. Integers stay integers and floats stay floats.
+pub fn abs(value: Value, _: HashMap<String, Value>) -> Result<Value> {
+    let n = match value {
+        Value::Number(ref n) => n,
+        _ => bail!("Filter `abs` was called on {}: got `{}` but expected a number", value_kind(&value), value),
+    };
+    if let Some(i) = n.as_i64() {
+        // `i64::MIN` has no positive counterpart in an i64
+        return Ok(to_value(i.unsigned_abs()).unwrap());
+    }
+    if n.is_u64() {
+        return Ok(value);
+    }
+    Ok(to_value(n.as_f64().unwrap().abs()).unwrap())
+}
+
+/// Returns a human-readable file size (i.e. '110 MB') from an integer.
+/// Uses multiples of 1000 (`KB`, `MB`...) by default and multiples of 1024
+/// (`KiB`, `MiB`...) if `binary` is true
+pub fn filesizeformat(value: Value, mut args: HashMap<String, Value>) -> Result<Value> {
+    // `try_get_value` would happily convert a string containing a number
+    if !value.is_number() {
+        bail!("Filter `filesizeformat` was called on an incorrect value: got `{}` but expected a i64", value);
+    }
     let num = try_get_value!("filesizeformat", "value", i64, value);
+    let binary = match args.remove("binary") {
+        Some(val) => try_get_value!("filesizeformat", "binary", bool, val),
+        None => false,
+    };
+    let opts = if binary {
+        FileSizeOpts { decimal_places: 1, ..file_size_opts::BINARY }
+    } else {
+        FileSizeOpts { decimal_places: 1, ..file_size_opts::DECIMAL }
+    };
+
     match num
-        .file_size(file_size_opts::CONVENTIONAL)
+        .file_size(opts)
         .or_else(|_| Err(format!("Filter `filesizeformat` was called on a negative number: {}", num).into())) {
         Ok(r) => Ok(to_value(r).unwrap()),
         Err(e) => Err(e)
     }
 }
 
+// The largest width or precision accepted by the `format` and `num_format` filters below,
+// larger ones would only make the output huge
+const MAX_FORMAT_NUMBER: usize = 100;
+
+// A `%[flags][width][.precision]conversion` specification of the `format` filter
+struct FormatSpec {
+    left_align: bool,
+    zero_pad: bool,
+    plus_sign: bool,
+    width: usize,
+    precision: Option<usize>,
+    conversion: char,
+}
+
+// Reads the digits at the start of `chars` as a number, `None` if there are none.
+// Errors if it is over `MAX_FORMAT_NUMBER`
+fn parse_format_number<I: Iterator<Item=char>>(chars: &mut ::std::iter::Peekable<I>, fmt: &str) -> Result<Option<usize>> {
+    let mut digits = String::new();
+    while let Some(&c) = chars.peek() {
+        if !c.is_ascii_digit() {
+            break;
+        }
+        digits.push(c);
+        chars.next();
+    }
+    if digits.is_empty() {
+        return Ok(None);
+    }
+    match digits.parse() {
+        Ok(n) if n <= MAX_FORMAT_NUMBER => Ok(Some(n)),
+        _ => bail!("Filter `format` got a width or precision that is too large in `{}`", fmt),
+    }
+}
+
+// Splits the string given to `format` into the text before the conversion, the conversion and
+// the text after it. `%%` is a literal `%`
+fn parse_format(fmt: &str) -> Result<(String, FormatSpec, String)> {
+    let mut before = String::new();
+    let mut after = String::new();
+    let mut spec = None;
+    let mut chars = fmt.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let is_literal = c != '%' || chars.peek() == Some(&'%');
+        if is_literal {
+            if c == '%' {
+                chars.next();
+            }
+            if spec.is_some() { after.push(c) } else { before.push(c) }
+            continue;
+        }
+        if spec.is_some() {
+            bail!("Filter `format` expected a single conversion in `{}`", fmt);
+        }
+
+        let (mut left_align, mut zero_pad, mut plus_sign) = (false, false, false);
+        while let Some(&flag) = chars.peek() {
+            match flag {
+                '-' => left_align = true,
+                '0' => zero_pad = true,
+                '+' => plus_sign = true,
+                _ => break,
+            }
+            chars.next();
+        }
+        let width = parse_format_number(&mut chars, fmt)?.unwrap_or(0);
+        let precision = if chars.peek() == Some(&'.') {
+            chars.next();
+            Some(parse_format_number(&mut chars, fmt)?.unwrap_or(0))
+        } else {
+            None
+        };
+        let conversion = match chars.next() {
+            Some(c @ 'd') | Some(c @ 'f') | Some(c @ 'x') | Some(c @ 'X') => c,
+            Some(c) => bail!("Filter `format` doesn't support `%{}`, only `%d`, `%f`, `%x` and `%X`", c),
+            None => bail!("Filter `format` got an incomplete conversion at the end of `{}`", fmt),
+        };
+        if precision.is_some() && conversion != 'f' {
+            bail!("Filter `format` can only use a precision with `%f` but got one with `%{}`", conversion);
+        }
+        spec = Some(FormatSpec { left_align, zero_pad, plus_sign, width, precision, conversion });
+    }
+
+    match spec {
+        Some(s) => Ok((before, s, after)),
+        None => bail!("Filter `format` expected a conversion like `%d` in `{}`", fmt),
+    }
+}
+
+/// Formats a number with a printf-style `fmt` string containing a single conversion, like `%05d`.
+/// Supports the `-` (left align), `0` (zero padding) and `+` (always show the sign) flags, a width,
+/// a precision for `%f` and the `%d` (integer), `%f` (decimal number), `%x` and `%X` (hexadecimal
+/// positive integer) conversions.
+pub fn format(value: Value, mut args: HashMap<String, Value>) -> Result<Value> {
+    let fmt = match args.remove("fmt") {
+        Some(val) => try_get_value!("format", "fmt", String, val),
+        None => bail!("Filter `format` expected an arg called `fmt`"),
+    };
+    let (before, spec, after) = parse_format(&fmt)?;
+    let number = match value {
+        Value::Number(ref n) => n,
+        _ => bail!("Filter `format` was called on an incorrect value: got `{}` but expected a number", value),
+    };
+    // `u64` larger than `i64::MAX` are positive integers as well
+    let integer = number.as_i64().map(|i| (i < 0, i.unsigned_abs())).or_else(|| number.as_u64().map(|u| (false, u)));
+
+    let (negative, digits) = match (spec.conversion, integer) {
+        ('d', Some((negative, abs))) => (negative, abs.to_string()),
+        ('x', Some((false, abs))) => (false, format!("{:x}", abs)),
+        ('X', Some((false, abs))) => (false, format!("{:X}", abs)),
+        ('f', _) => {
+            let f = number.as_f64().unwrap();
+            (f.is_sign_negative() && f != 0.0, format!("{:.*}", spec.precision.unwrap_or(6), f.abs()))
+        },
+        ('d', None) => bail!("Filter `format` can't use `%d` on `{}` as it isn't an integer", value),
+        (c, _) => bail!("Filter `format` can't use `%{}` on `{}` as it isn't a positive integer", c, value),
+    };
+    let sign = if negative { "-" } else if spec.plus_sign { "+" } else { "" };
+
+    let len = sign.len() + digits.len();
+    let padding = spec.width.saturating_sub(len);
+    let formatted = if spec.left_align {
+        format!("{}{}{}", sign, digits, " ".repeat(padding))
+    } else if spec.zero_pad {
+        format!("{}{}{}", sign, "0".repeat(padding), digits)
+    } else {
+        format!("{}{}{}", " ".repeat(padding), sign, digits)
+    };
+
+    Ok(to_value(format!("{}{}{}", before, formatted, after)).unwrap())
+}
+
+/// Formats a number for humans, putting the `grouping` separator (`,` by default) between
+/// each group of 3 digits of the integer part and using `decimal` (`.` by default) before the
+/// fractional part.
+/// A `precision` gives the number of digits after the decimal separator, otherwise integers
+/// have none and floats are written with as many as needed.
+pub fn num_format(value: Value, mut args: HashMap<String, Value>) -> Result<Value> {
+    let number = match value {
+        Value::Number(ref n) => n,
+        _ => bail!("Filter `num_format` was called on an incorrect value: got `{}` but expected a number", value),
+    };
+    let grouping = match args.remove("grouping") {
+        Some(val) => try_get_value!("num_format", "grouping", String, val),
+        None => ",".to_string(),
+    };
+    let decimal = match args.remove("decimal") {
+        Some(val) => try_get_value!("num_format", "decimal", String, val),
+        None => ".".to_string(),
+    };
+    let precision = match args.remove("precision") {
+        Some(val) => Some(try_get_value!("num_format", "precision", usize, val)),
+        None => None,
+    };
+    if let Some(p) = precision {
+        if p > MAX_FORMAT_NUMBER {
+            bail!("Filter `num_format` got a precision of {} but it can't be over {}", p, MAX_FORMAT_NUMBER);
+        }
+    }
+
+    let digits = match (precision, number.as_i64(), number.as_u64()) {
+        (Some(p), _, _) => format!("{:.*}", p, number.as_f64().unwrap()),
+        (None, Some(i), _) => i.to_string(),
+        (None, None, Some(u)) => u.to_string(),
+        (None, None, None) => number.as_f64().unwrap().to_string(),
+    };
+    // A negative number rounded to 0 doesn't keep its sign
+    let (negative, digits) = match digits.strip_prefix('-') {
+        Some(abs) => (abs.chars().any(|c| c != '0' && c != '.'), abs),
+        None => (false, digits.as_str()),
+    };
+    let (integer, fraction) = match digits.find('.') {
+        Some(i) => (&digits[..i], Some(&digits[i + 1..])),
+        None => (digits, None),
+    };
+
+    let mut formatted = if negative { "-".to_string() } else { String::new() };
+    for (i, c) in integer.chars().enumerate() {
+        if i > 0 && (integer.len() - i) % 3 == 0 {
+            formatted.push_str(&grouping);
+        }
+        formatted.push(c);
+    }
+    if let Some(f) = fraction {
+        formatted.push_str(&decimal);
+        formatted.push_str(f);
+    }
+
+    Ok(to_value(formatted).unwrap())
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
     use serde_json::value::to_value;
     use super::*;
 
+    #[test]
+    fn test_num_format_integers() {
+        let tests = vec![
+            (0, "0"),
+            (999, "999"),
+            (1000, "1,000"),
+            (123456, "123,456"),
+            (1234567, "1,234,567"),
+            (-1234567, "-1,234,567"),
+            (-999, "-999"),
+        ];
+        for (input, expected) in tests {
+            let result = num_format(to_value(input).unwrap(), HashMap::new());
+            assert!(result.is_ok());
+            assert_eq!(result.unwrap(), to_value(expected).unwrap());
+        }
+        let result = num_format(to_value(u64::MAX).unwrap(), HashMap::new());
+        assert_eq!(result.unwrap(), to_value("18,446,744,073,709,551,615").unwrap());
+    }
+
+    #[test]
+    fn test_num_format_floats() {
+        let tests = vec![
+            (1234.5, None, "1,234.5"),
+            (1234567.891, Some(2), "1,234,567.89"),
+            (-1234.5, Some(0), "-1,234"),
+            (1234.0, Some(3), "1,234.000"),
+            (999.999, Some(2), "1,000.00"),
+            (-0.001, Some(2), "0.00"),
+            (-0.5, None, "-0.5"),
+        ];
+        for (input, precision, expected) in tests {
+            let mut args = HashMap::new();
+            if let Some(p) = precision {
+                args.insert("precision".to_string(), to_value(p).unwrap());
+            }
+            let result = num_format(to_value(input).unwrap(), args);
+            assert!(result.is_ok());
+            assert_eq!(result.unwrap(), to_value(expected).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_num_format_integer_with_precision() {
+        let mut args = HashMap::new();
+        args.insert("precision".to_string(), to_value(2).unwrap());
+        let result = num_format(to_value(1234).unwrap(), args);
+        assert_eq!(result.unwrap(), to_value("1,234.00").unwrap());
+    }
+
+    #[test]
+    fn test_num_format_custom_separators() {
+        let mut args = HashMap::new();
+        args.insert("grouping".to_string(), to_value(".").unwrap());
+        args.insert("decimal".to_string(), to_value(",").unwrap());
+        args.insert("precision".to_string(), to_value(2).unwrap());
+        let result = num_format(to_value(-1234567.5).unwrap(), args);
+        assert_eq!(result.unwrap(), to_value("-1.234.567,50").unwrap());
+
+        let mut args = HashMap::new();
+        args.insert("grouping".to_string(), to_value(" ").unwrap());
+        let result = num_format(to_value(1234567).unwrap(), args);
+        assert_eq!(result.unwrap(), to_value("1 234 567").unwrap());
+    }
+
+    #[test]
+    fn test_num_format_non_number() {
+        let result = num_format(to_value("1234").unwrap(), HashMap::new());
+        assert!(result.is_err());
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Filter `num_format` was called on an incorrect value: got `\"1234\"` but expected a number"
+        );
+    }
+
+    #[test]
+    fn test_num_format_precision_too_large() {
+        let mut args = HashMap::new();
+        args.insert("precision".to_string(), to_value(101).unwrap());
+        let result = num_format(to_value(1.5).unwrap(), args);
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err().to_string(), "Filter `num_format` got a precision of 101 but it can't be over 100");
+    }
+
     #[test]
     fn test_pluralize_single() {
         let result = pluralize(to_value(1).unwrap(), HashMap::new());
@@ -95,6 +419,42 @@ mod tests {
     fn test_round_default() {
         let result = round(to_value(2.1).unwrap(), HashMap::new());
         assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(2).unwrap());
+    }
+
+    #[test]
+    fn test_round_default_negative() {
+        let result = round(to_value(-2.5).unwrap(), HashMap::new());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(-3).unwrap());
+    }
+
+    #[test]
+    fn test_round_too_large_for_integer() {
+        let result = round(to_value(1e20).unwrap(), HashMap::new());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(1e20).unwrap());
+
+        let result = round(to_value(-1e20).unwrap(), HashMap::new());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(-1e20).unwrap());
+    }
+
+    #[test]
+    fn test_round_precision_greater_than_decimals() {
+        let mut args = HashMap::new();
+        args.insert("precision".to_string(), to_value(5).unwrap());
+        let result = round(to_value(2.5).unwrap(), args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(2.5).unwrap());
+    }
+
+    #[test]
+    fn test_round_precision_on_integer() {
+        let mut args = HashMap::new();
+        args.insert("precision".to_string(), to_value(2).unwrap());
+        let result = round(to_value(2).unwrap(), args);
+        assert!(result.is_ok());
         assert_eq!(result.unwrap(), to_value(2.0).unwrap());
     }
 
@@ -116,6 +476,15 @@ mod tests {
         assert_eq!(result.unwrap(), to_value(3.0).unwrap());
     }
 
+    #[test]
+    fn test_round_ceil_negative() {
+        let mut args = HashMap::new();
+        args.insert("method".to_string(), to_value("ceil").unwrap());
+        let result = round(to_value(-2.9).unwrap(), args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(-2.0).unwrap());
+    }
+
     #[test]
     fn test_round_ceil_precision() {
         let mut args = HashMap::new();
@@ -135,6 +504,34 @@ mod tests {
         assert_eq!(result.unwrap(), to_value(2.0).unwrap());
     }
 
+    #[test]
+    fn test_round_floor_negative() {
+        let mut args = HashMap::new();
+        args.insert("method".to_string(), to_value("floor").unwrap());
+        args.insert("precision".to_string(), to_value(1).unwrap());
+        let result = round(to_value(-2.91).unwrap(), args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(-3.0).unwrap());
+    }
+
+    #[test]
+    fn test_round_unknown_method() {
+        let mut args = HashMap::new();
+        args.insert("method".to_string(), to_value("up").unwrap());
+        let result = round(to_value(2.1).unwrap(), args);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_round_non_number() {
+        let result = round(to_value("2.1").unwrap(), HashMap::new());
+        assert!(result.is_err());
+        assert_eq!(
+            result.err().unwrap().description(),
+            "Filter `round` was called on an incorrect value: got `\"2.1\"` but expected a f64"
+        );
+    }
+
     #[test]
     fn test_round_floor_precision() {
         let mut args = HashMap::new();
@@ -145,11 +542,159 @@ mod tests {
         assert_eq!(result.unwrap(), to_value(2.9).unwrap());
     }
 
+    #[test]
+    fn test_abs_negative_integer() {
+        let result = abs(to_value(-5).unwrap(), HashMap::new());
+        assert!(result.is_ok());
+        let result = result.unwrap();
+        assert!(result.is_u64());
+        assert_eq!(result, to_value(5).unwrap());
+
+        let result = abs(to_value(i64::MIN).unwrap(), HashMap::new());
+        assert_eq!(result.unwrap(), to_value(9_223_372_036_854_775_808u64).unwrap());
+    }
+
+    #[test]
+    fn test_abs_negative_float() {
+        let result = abs(to_value(-2.5).unwrap(), HashMap::new());
+        assert!(result.is_ok());
+        let result = result.unwrap();
+        assert!(result.is_f64());
+        assert_eq!(result, to_value(2.5).unwrap());
+    }
+
+    #[test]
+    fn test_abs_positive() {
+        for value in [to_value(3).unwrap(), to_value(u64::MAX).unwrap(), to_value(1.5).unwrap(), to_value(0).unwrap()] {
+            let result = abs(value.clone(), HashMap::new());
+            assert!(result.is_ok());
+            assert_eq!(result.unwrap(), value);
+        }
+        // stays a float
+        assert!(abs(to_value(2.0).unwrap(), HashMap::new()).unwrap().is_f64());
+    }
+
+    #[test]
+    fn test_abs_non_number() {
+        let result = abs(to_value("-2").unwrap(), HashMap::new());
+        assert!(result.is_err());
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "Filter `abs` was called on a string: got `\"-2\"` but expected a number"
+        );
+        let result = abs(Value::Null, HashMap::new());
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "Filter `abs` was called on null: got `null` but expected a number"
+        );
+    }
+
     #[test]
     fn test_filesizeformat() {
         let args = HashMap::new();
         let result = filesizeformat(to_value(123456789).unwrap(), args);
         assert!(result.is_ok());
-        assert_eq!(result.unwrap(), to_value("117.74 MB").unwrap());
+        assert_eq!(result.unwrap(), to_value("123.5 MB").unwrap());
+    }
+
+    #[test]
+    fn test_filesizeformat_boundaries() {
+        let tests = vec![
+            (0_i64, "0 B"),
+            (999, "999 B"),
+            (1000, "1 KB"),
+            (1024, "1.0 KB"),
+            (1500000, "1.5 MB"),
+            (1000000000000000, "1 PB"),
+        ];
+        for (input, expected) in tests {
+            let result = filesizeformat(to_value(input).unwrap(), HashMap::new());
+            assert!(result.is_ok());
+            assert_eq!(result.unwrap(), to_value(expected).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_filesizeformat_binary() {
+        let tests = vec![
+            (1000, "1000 B"),
+            (1024, "1 KiB"),
+            (1536, "1.5 KiB"),
+            (123456789, "117.7 MiB"),
+        ];
+        for (input, expected) in tests {
+            let mut args = HashMap::new();
+            args.insert("binary".to_string(), to_value(true).unwrap());
+            let result = filesizeformat(to_value(input).unwrap(), args);
+            assert!(result.is_ok());
+            assert_eq!(result.unwrap(), to_value(expected).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_filesizeformat_non_number() {
+        let result = filesizeformat(to_value("1000").unwrap(), HashMap::new());
+        assert!(result.is_err());
+        let result = filesizeformat(to_value(-1000).unwrap(), HashMap::new());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_format() {
+        let tests: Vec<(Value, &str, &str)> = vec![
+            // zero padding and width
+            (to_value(42).unwrap(), "%05d", "00042"),
+            (to_value(-42).unwrap(), "%05d", "-0042"),
+            (to_value(42).unwrap(), "%5d", "   42"),
+            (to_value(42).unwrap(), "%-5d|", "42   |"),
+            (to_value(42).unwrap(), "%+d", "+42"),
+            (to_value(123456).unwrap(), "%3d", "123456"),
+            // fixed decimals
+            (to_value(1.23456).unwrap(), "%.2f", "1.23"),
+            (to_value(1.23456).unwrap(), "%08.3f", "0001.235"),
+            (to_value(-1.5).unwrap(), "%.0f", "-2"),
+            (to_value(2).unwrap(), "%f", "2.000000"),
+            // hex
+            (to_value(255).unwrap(), "%x", "ff"),
+            (to_value(255).unwrap(), "%04X", "00FF"),
+            (to_value(u64::MAX).unwrap(), "%x", "ffffffffffffffff"),
+            // text around the conversion
+            (to_value(42).unwrap(), "ID: %d (100%%)", "ID: 42 (100%)"),
+        ];
+        for (value, fmt, expected) in tests {
+            let mut args = HashMap::new();
+            args.insert("fmt".to_string(), to_value(fmt).unwrap());
+            let result = format(value, args);
+            assert!(result.is_ok(), "{}", fmt);
+            assert_eq!(result.unwrap(), to_value(expected).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_format_errors() {
+        let tests: Vec<(Value, &str, &str)> = vec![
+            (to_value(1.5).unwrap(), "%d", "Filter `format` can't use `%d` on `1.5` as it isn't an integer"),
+            (to_value(-1).unwrap(), "%x", "Filter `format` can't use `%x` on `-1` as it isn't a positive integer"),
+            (to_value("42").unwrap(), "%d", "Filter `format` was called on an incorrect value: got `\"42\"` but expected a number"),
+            (to_value(42).unwrap(), "%s", "Filter `format` doesn't support `%s`, only `%d`, `%f`, `%x` and `%X`"),
+            (to_value(42).unwrap(), "%.2d", "Filter `format` can only use a precision with `%f` but got one with `%d`"),
+            (to_value(42).unwrap(), "%d %d", "Filter `format` expected a single conversion in `%d %d`"),
+            (to_value(42).unwrap(), "42%", "Filter `format` got an incomplete conversion at the end of `42%`"),
+            (to_value(42).unwrap(), "id", "Filter `format` expected a conversion like `%d` in `id`"),
+            (to_value(42).unwrap(), "%101d", "Filter `format` got a width or precision that is too large in `%101d`"),
+            (to_value(4.2).unwrap(), "%.101f", "Filter `format` got a width or precision that is too large in `%.101f`"),
+            (to_value(42).unwrap(), "%3000000000d", "Filter `format` got a width or precision that is too large in `%3000000000d`"),
+        ];
+        for (value, fmt, expected) in tests {
+            let mut args = HashMap::new();
+            args.insert("fmt".to_string(), to_value(fmt).unwrap());
+            let result = format(value, args);
+            assert!(result.is_err());
+            assert_eq!(result.unwrap_err().to_string(), expected);
+        }
+
+        let result = format(to_value(42).unwrap(), HashMap::new());
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err().to_string(), "Filter `format` expected an arg called `fmt`");
     }
 }
@@ -1,5 +1,6 @@
 /// Filters operating on string
 use std::collections::HashMap;
+use std::sync::Mutex;
 
 use serde_json::value::{Value, to_value};
 use slug;
@@ -8,14 +9,23 @@ use url::percent_encoding::{utf8_percent_encode, EncodeSet};
 use errors::Result;
 use utils;
 
-use regex::{Regex, Captures};
+use regex::{self, Regex, Captures};
 
 
 lazy_static! {
-    static ref STRIPTAGS_RE: Regex = Regex::new(r"(<!--.*?-->|<[^>]*>)").unwrap();
-    static ref WORDS_RE: Regex = Regex::new(r"\b(?P<first>\w)(?P<rest>\w*)\b").unwrap();
+    // quoted attribute values can contain a `>`
+    static ref STRIPTAGS_RE: Regex = Regex::new(r#"(?s)(<!--.*?-->|<(?:[^>"']|"[^"]*"|'[^']*')*>)"#).unwrap();
+    // apostrophes are part of words so `don't` doesn't become `Don'T`
+    static ref WORDS_RE: Regex = Regex::new(r"\b(?P<first>\w)(?P<rest>[\w']*)\b").unwrap();
+    // The patterns of the `matches` and `findall` filters and of the `matching` tester, compiled
+    // once then shared between renders
+    static ref PATTERNS: Mutex<HashMap<String, Regex>> = Mutex::new(HashMap::new());
 }
 
+// Above that many patterns, the cache is emptied so patterns coming from the context can't
+// make it grow without end
+const MAX_CACHED_PATTERNS: usize = 256;
+
 
 /// Convert a value to uppercase.
 pub fn upper(value: Value, _: HashMap<String, Value>) -> Result<Value> {
@@ -31,28 +41,117 @@ pub fn lower(value: Value, _: HashMap<String, Value>) -> Result<Value> {
     Ok(to_value(&s.to_lowercase()).unwrap())
 }
 
-/// Strip leading and trailing whitespace.
-pub fn trim(value: Value, _: HashMap<String, Value>) -> Result<Value> {
+// Gets the optional `chars` argument of the trim filters: the characters to trim
+// instead of whitespace
+fn get_trim_chars(filter_name: &str, args: &mut HashMap<String, Value>) -> Result<Option<Vec<char>>> {
+    match args.remove("chars") {
+        Some(val) => Ok(Some(try_get_value!(filter_name, "chars", String, val).chars().collect())),
+        None => Ok(None),
+    }
+}
+
+/// Strip leading and trailing whitespace, or the characters in `chars` if given.
+pub fn trim(value: Value, mut args: HashMap<String, Value>) -> Result<Value> {
     let s = try_get_value!("trim", "value", String, value);
 
-    Ok(to_value(&s.trim()).unwrap())
+    match get_trim_chars("trim", &mut args)? {
+        Some(chars) => Ok(to_value(s.trim_matches(chars.as_slice())).unwrap()),
+        None => Ok(to_value(s.trim()).unwrap()),
+    }
+}
+
+/// Strip leading whitespace, or the characters in `chars` if given.
+pub fn trim_start(value: Value, mut args: HashMap<String, Value>) -> Result<Value> {
+    let s = try_get_value!("trim_start", "value", String, value);
+
+    match get_trim_chars("trim_start", &mut args)? {
+        Some(chars) => Ok(to_value(s.trim_start_matches(chars.as_slice())).unwrap()),
+        None => Ok(to_value(s.trim_start()).unwrap()),
+    }
+}
+
+/// Strip trailing whitespace, or the characters in `chars` if given.
+pub fn trim_end(value: Value, mut args: HashMap<String, Value>) -> Result<Value> {
+    let s = try_get_value!("trim_end", "value", String, value);
+
+    match get_trim_chars("trim_end", &mut args)? {
+        Some(chars) => Ok(to_value(s.trim_end_matches(chars.as_slice())).unwrap()),
+        None => Ok(to_value(s.trim_end()).unwrap()),
+    }
 }
 
-/// Truncates a string to the indicated length
+/// Truncates a string to the indicated length, counted in characters.
+/// `length` defaults to 255 and `end` (appended only if truncation happened) to `…`.
+/// Unless `killwords` is true, the cut backs up to the last whitespace so words
+/// are not split in half.
 pub fn truncate(value: Value, mut args: HashMap<String, Value>) -> Result<Value> {
     let s = try_get_value!("truncate", "value", String, value);
     let length = match args.remove("length") {
         Some(l) => try_get_value!("truncate", "length", usize, l),
         None => 255
     };
+    let end = match args.remove("end") {
+        Some(l) => try_get_value!("truncate", "end", String, l),
+        None => "…".to_string()
+    };
+    let killwords = match args.remove("killwords") {
+        Some(l) => try_get_value!("truncate", "killwords", bool, l),
+        None => false
+    };
 
     // Nothing to truncate?
-    if length > s.len() {
-        return Ok(to_value(&s).unwrap());
+    let byte_end = match s.char_indices().nth(length) {
+        Some((idx, _)) => idx,
+        None => return Ok(to_value(&s).unwrap()),
+    };
+
+    let mut result = &s[..byte_end];
+    // We are in the middle of a word if the first character cut off isn't a whitespace
+    let mid_word = !s[byte_end..].starts_with(char::is_whitespace);
+    if !killwords && mid_word {
+        if let Some(idx) = result.rfind(char::is_whitespace) {
+            result = &result[..idx];
+        }
+    }
+
+    Ok(to_value(&(result.trim_end().to_string() + &end)).unwrap())
+}
+
+/// Keeps the first `count` words of a string, words being separated by any whitespace.
+/// The whitespace between the words kept is left as it is.
+/// `end` (`…` by default) is appended only if some words were removed.
+pub fn truncate_words(value: Value, mut args: HashMap<String, Value>) -> Result<Value> {
+    let s = try_get_value!("truncate_words", "value", String, value);
+    let count = match args.remove("count") {
+        Some(c) => try_get_value!("truncate_words", "count", usize, c),
+        None => bail!("Filter `truncate_words` expected an arg called `count`"),
+    };
+    let end = match args.remove("end") {
+        Some(l) => try_get_value!("truncate_words", "end", String, l),
+        None => "…".to_string()
+    };
+
+    // Finds where the word after the `count` first ones starts, if there is one
+    let mut words = 0;
+    let mut in_word = false;
+    let mut byte_end = None;
+    for (idx, c) in s.char_indices() {
+        if c.is_whitespace() {
+            in_word = false;
+        } else if !in_word {
+            if words == count {
+                byte_end = Some(idx);
+                break;
+            }
+            in_word = true;
+            words += 1;
+        }
     }
 
-    let result = s[..s.char_indices().nth(length).unwrap().0].to_string() + "…";
-    Ok(to_value(&result).unwrap())
+    match byte_end {
+        Some(idx) => Ok(to_value(s[..idx].trim_end().to_string() + &end).unwrap()),
+        None => Ok(to_value(&s).unwrap()),
+    }
 }
 
 /// Gets the number of words in a string.
@@ -62,6 +161,66 @@ pub fn wordcount(value: Value, _: HashMap<String, Value>) -> Result<Value> {
     Ok(to_value(&s.split_whitespace().count()).unwrap())
 }
 
+/// Wraps the text so that no line is longer than `width` characters (79 by default),
+/// breaking lines at whitespace. Existing newlines, including a trailing one and `\r\n`, are kept.
+/// A word longer than `width` is put on its own line, or cut in chunks of `width`
+/// characters if `break_long_words` is true.
+pub fn wordwrap(value: Value, mut args: HashMap<String, Value>) -> Result<Value> {
+    let s = try_get_value!("wordwrap", "value", String, value);
+    let width = match args.remove("width") {
+        Some(w) => try_get_value!("wordwrap", "width", usize, w),
+        None => 79,
+    };
+    if width == 0 {
+        bail!("Filter `wordwrap` was called with a `width` of 0");
+    }
+    let break_long_words = match args.remove("break_long_words") {
+        Some(b) => try_get_value!("wordwrap", "break_long_words", bool, b),
+        None => false,
+    };
+
+    let mut res = String::with_capacity(s.len());
+    for original_line in s.split_inclusive('\n') {
+        // the line ending is kept as is and the breaks added in the line use the same one
+        let content = original_line.trim_end_matches(['\r', '\n']);
+        let ending = &original_line[content.len()..];
+        let newline = if ending == "\r\n" { "\r\n" } else { "\n" };
+        let mut line = String::new();
+        let mut line_width = 0;
+        for word in content.split_whitespace() {
+            let mut chunks = vec![];
+            if break_long_words {
+                let chars = word.chars().collect::<Vec<_>>();
+                for chunk in chars.chunks(width) {
+                    chunks.push(chunk.iter().collect::<String>());
+                }
+            } else {
+                chunks.push(word.to_string());
+            }
+
+            for chunk in chunks {
+                let chunk_width = chunk.chars().count();
+                if line_width > 0 && line_width + 1 + chunk_width > width {
+                    res.push_str(&line);
+                    res.push_str(newline);
+                    line.clear();
+                    line_width = 0;
+                }
+                if line_width > 0 {
+                    line.push(' ');
+                    line_width += 1;
+                }
+                line.push_str(&chunk);
+                line_width += chunk_width;
+            }
+        }
+        res.push_str(&line);
+        res.push_str(ending);
+    }
+
+    Ok(to_value(res).unwrap())
+}
+
 /// Replaces given `from` substring with `to` string.
 pub fn replace(value: Value, mut args: HashMap<String, Value>) -> Result<Value> {
     let s = try_get_value!("replace", "value", String, value);
@@ -70,6 +229,10 @@ pub fn replace(value: Value, mut args: HashMap<String, Value>) -> Result<Value>
         Some(val) => try_get_value!("replace", "from", String, val),
         None => bail!("Filter `replace` expected an arg called `from`")
     };
+    // Replacing an empty string would insert `to` between every character
+    if from.is_empty() {
+        bail!("Filter `replace` was called with an empty `from` arg");
+    }
 
     let to = match args.remove("to") {
         Some(val) => try_get_value!("replace", "to", String, val),
@@ -79,7 +242,111 @@ pub fn replace(value: Value, mut args: HashMap<String, Value>) -> Result<Value>
     Ok(to_value(&s.replace(&from, &to)).unwrap())
 }
 
-/// First letter of the string is uppercase rest is lowercase
+/// Splits the string on every occurrence of the `pat` substring and returns an array of the pieces.
+/// If `limit` is given, the string is split at most `limit` times and the last piece contains
+/// the rest of the string. An empty string gives an array containing a single empty string.
+pub fn split(value: Value, mut args: HashMap<String, Value>) -> Result<Value> {
+    let s = try_get_value!("split", "value", String, value);
+
+    let pat = match args.remove("pat") {
+        Some(val) => try_get_value!("split", "pat", String, val),
+        None => bail!("Filter `split` expected an arg called `pat`")
+    };
+    // Splitting on an empty string would give every character with empty strings around
+    if pat.is_empty() {
+        bail!("Filter `split` was called with an empty `pat` arg");
+    }
+
+    let pieces: Vec<&str> = match args.remove("limit") {
+        Some(val) => {
+            let limit = try_get_value!("split", "limit", usize, val);
+            s.splitn(limit.saturating_add(1), &pat).collect()
+        },
+        None => s.split(&pat).collect(),
+    };
+
+    Ok(to_value(pieces).unwrap())
+}
+
+/// Returns the compiled regex of `pattern`, only compiling it if it isn't in the cache yet
+pub fn get_regex(pattern: String) -> ::std::result::Result<Regex, regex::Error> {
+    let mut patterns = PATTERNS.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(re) = patterns.get(&pattern) {
+        return Ok(re.clone());
+    }
+    let re = Regex::new(&pattern)?;
+    if patterns.len() >= MAX_CACHED_PATTERNS {
+        patterns.clear();
+    }
+    patterns.insert(pattern, re.clone());
+    Ok(re)
+}
+
+// Gets the regex of the `pattern` argument of the `matches` and `findall` filters
+fn get_pattern(filter_name: &str, args: &mut HashMap<String, Value>) -> Result<Regex> {
+    let pattern = match args.remove("pattern") {
+        Some(val) => try_get_value!(filter_name, "pattern", String, val),
+        None => bail!("Filter `{}` expected an arg called `pattern`", filter_name)
+    };
+
+    match get_regex(pattern) {
+        Ok(re) => Ok(re),
+        Err(e) => bail!("Filter `{}` got an invalid regex in `pattern`: {}", filter_name, e),
+    }
+}
+
+/// Returns whether the regex given in `pattern` matches somewhere in the string, use `^` and `$`
+/// to match the whole string.
+pub fn matches(value: Value, mut args: HashMap<String, Value>) -> Result<Value> {
+    let s = try_get_value!("matches", "value", String, value);
+    let re = get_pattern("matches", &mut args)?;
+
+    Ok(to_value(re.is_match(&s)).unwrap())
+}
+
+/// Returns an array of all the non-overlapping substrings matching the regex given in `pattern`,
+/// in the order they appear in the string.
+pub fn findall(value: Value, mut args: HashMap<String, Value>) -> Result<Value> {
+    let s = try_get_value!("findall", "value", String, value);
+    let re = get_pattern("findall", &mut args)?;
+
+    let found: Vec<&str> = re.find_iter(&s).map(|m| m.as_str()).collect();
+    Ok(to_value(found).unwrap())
+}
+
+/// Prefixes each line of the string with `width` spaces, 4 by default.
+/// The first line is not indented if `first` is false and empty lines are only
+/// indented if `blank` is true. Line endings are kept as they are.
+pub fn indent(value: Value, mut args: HashMap<String, Value>) -> Result<Value> {
+    let s = try_get_value!("indent", "value", String, value);
+    let width = match args.remove("width") {
+        Some(w) => try_get_value!("indent", "width", usize, w),
+        None => 4,
+    };
+    let first = match args.remove("first") {
+        Some(f) => try_get_value!("indent", "first", bool, f),
+        None => true,
+    };
+    let blank = match args.remove("blank") {
+        Some(b) => try_get_value!("indent", "blank", bool, b),
+        None => false,
+    };
+
+    let prefix = " ".repeat(width);
+    let mut res = String::with_capacity(s.len());
+    for (i, line) in s.split_inclusive('\n').enumerate() {
+        let is_blank = line.trim_end_matches(['\r', '\n']).is_empty();
+        if (i > 0 || first) && (blank || !is_blank) {
+            res.push_str(&prefix);
+        }
+        res.push_str(line);
+    }
+
+    Ok(to_value(res).unwrap())
+}
+
+/// First character of the string is uppercase rest is lowercase, using Unicode case mapping.
+/// If the string starts with punctuation, it is left as is.
 pub fn capitalize(value: Value, _: HashMap<String, Value>) -> Result<Value> {
     let s = try_get_value!("capitalize", "value", String, value);
     let mut chars = s.chars();
@@ -141,13 +408,16 @@ pub fn addslashes(value: Value, _: HashMap<String, Value>) -> Result<Value> {
     ).unwrap())
 }
 
-/// Transform a string into a slug
+/// Transform a string into a slug.
+/// Non-ASCII characters are transliterated to ASCII by the `slug` crate
 pub fn slugify(value: Value, _: HashMap<String, Value>) -> Result<Value> {
     let s = try_get_value!("slugify", "value", String, value);
     Ok(to_value(&slug::slugify(s)).unwrap())
 }
 
-/// Capitalizes each word in the string
+/// Capitalizes each word in the string and lowercases the rest of the word.
+/// A word is a run of Unicode letters, digits, underscores and apostrophes: whitespace, hyphens
+/// and any other punctuation separate words.
 pub fn title(value: Value, _: HashMap<String, Value>) -> Result<Value> {
     let s = try_get_value!("title", "value", String, value);
 
@@ -161,10 +431,14 @@ pub fn title(value: Value, _: HashMap<String, Value>) -> Result<Value> {
 
 }
 
-/// Removes html tags from string
+/// Removes html tags and comments from string and collapses the remaining whitespace
+/// to single spaces.
+/// A `<` without a matching `>` is not considered a tag and is kept, as are HTML entities
+/// like `&amp;`.
 pub fn striptags(value: Value, _: HashMap<String, Value>) -> Result<Value> {
     let s = try_get_value!("striptags", "value", String, value);
-    Ok(to_value(&STRIPTAGS_RE.replace_all(&s, "")).unwrap())
+    let stripped = STRIPTAGS_RE.replace_all(&s, "");
+    Ok(to_value(stripped.split_whitespace().collect::<Vec<_>>().join(" ")).unwrap())
 }
 
 /// Returns the given text with ampersands, quotes and angle brackets encoded
@@ -174,6 +448,13 @@ pub fn escape_html(value: Value, _: HashMap<String, Value>) -> Result<Value> {
     Ok(to_value(utils::escape_html(&s)).unwrap())
 }
 
+/// Returns the given text escaped to be used in a JavaScript string literal, see
+/// `utils::escape_js` for the replacements.
+pub fn escape_js(value: Value, _: HashMap<String, Value>) -> Result<Value> {
+    let s = try_get_value!("escape_js", "value", String, value);
+    Ok(to_value(utils::escape_js(&s)).unwrap())
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -208,6 +489,50 @@ mod tests {
         assert_eq!(result.unwrap(), to_value("hello").unwrap());
     }
 
+    #[test]
+    fn test_trim_keeps_inner_whitespace() {
+        let result = trim(to_value("\t hello  world \n").unwrap(), HashMap::new());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("hello  world").unwrap());
+    }
+
+    #[test]
+    fn test_trim_start_and_end() {
+        let result = trim_start(to_value("  hello world  ").unwrap(), HashMap::new());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("hello world  ").unwrap());
+
+        let result = trim_end(to_value("  hello world  ").unwrap(), HashMap::new());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("  hello world").unwrap());
+    }
+
+    #[test]
+    fn test_trim_chars() {
+        let mut args = HashMap::new();
+        args.insert("chars".to_string(), to_value("-*").unwrap());
+        let input = "*-* hello-*-*";
+
+        let result = trim(to_value(input).unwrap(), args.clone());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(" hello").unwrap());
+
+        let result = trim_start(to_value(input).unwrap(), args.clone());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(" hello-*-*").unwrap());
+
+        let result = trim_end(to_value(input).unwrap(), args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("*-* hello").unwrap());
+    }
+
+    #[test]
+    fn test_trim_non_string() {
+        assert!(trim(to_value(1).unwrap(), HashMap::new()).is_err());
+        assert!(trim_start(to_value(true).unwrap(), HashMap::new()).is_err());
+        assert!(trim_end(to_value(vec![1]).unwrap(), HashMap::new()).is_err());
+    }
+
     #[test]
     fn test_truncate_smaller_than_length() {
         let mut args = HashMap::new();
@@ -226,6 +551,120 @@ mod tests {
         assert_eq!(result.unwrap(), to_value("日本…").unwrap());
     }
 
+    #[test]
+    fn test_truncate_exact_length() {
+        let mut args = HashMap::new();
+        args.insert("length".to_string(), to_value(&5).unwrap());
+        let result = truncate(to_value("hello").unwrap(), args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("hello").unwrap());
+    }
+
+    #[test]
+    fn test_truncate_multibyte_exact_length() {
+        let mut args = HashMap::new();
+        args.insert("length".to_string(), to_value(&3).unwrap());
+        let result = truncate(to_value("日本語").unwrap(), args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("日本語").unwrap());
+    }
+
+    #[test]
+    fn test_truncate_word_boundary() {
+        let mut args = HashMap::new();
+        args.insert("length".to_string(), to_value(&8).unwrap());
+        let result = truncate(to_value("Hello world, how are you?").unwrap(), args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("Hello…").unwrap());
+    }
+
+    #[test]
+    fn test_truncate_on_whitespace() {
+        let mut args = HashMap::new();
+        args.insert("length".to_string(), to_value(&12).unwrap());
+        let result = truncate(to_value("Hello world, how are you?").unwrap(), args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("Hello world,…").unwrap());
+    }
+
+    #[test]
+    fn test_truncate_killwords() {
+        let mut args = HashMap::new();
+        args.insert("length".to_string(), to_value(&8).unwrap());
+        args.insert("killwords".to_string(), to_value(&true).unwrap());
+        let result = truncate(to_value("Hello world, how are you?").unwrap(), args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("Hello wo…").unwrap());
+    }
+
+    #[test]
+    fn test_truncate_custom_end() {
+        let mut args = HashMap::new();
+        args.insert("length".to_string(), to_value(&5).unwrap());
+        args.insert("end".to_string(), to_value(&"...").unwrap());
+        let result = truncate(to_value("Hello world").unwrap(), args.clone());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("Hello...").unwrap());
+
+        let result = truncate(to_value("Hello").unwrap(), args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("Hello").unwrap());
+    }
+
+    #[test]
+    fn test_truncate_words_fewer_than_count() {
+        let mut args = HashMap::new();
+        args.insert("count".to_string(), to_value(5).unwrap());
+        let result = truncate_words(to_value("Hello  beautiful world ").unwrap(), args.clone());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("Hello  beautiful world ").unwrap());
+
+        let result = truncate_words(to_value("").unwrap(), args);
+        assert_eq!(result.unwrap(), to_value("").unwrap());
+    }
+
+    #[test]
+    fn test_truncate_words_exact_count() {
+        let mut args = HashMap::new();
+        args.insert("count".to_string(), to_value(3).unwrap());
+        let result = truncate_words(to_value("Hello beautiful world\n").unwrap(), args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("Hello beautiful world\n").unwrap());
+    }
+
+    #[test]
+    fn test_truncate_words_when_required() {
+        let mut args = HashMap::new();
+        args.insert("count".to_string(), to_value(2).unwrap());
+        let result = truncate_words(to_value("Hello,\n\tbeautiful   world of words").unwrap(), args.clone());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("Hello,\n\tbeautiful…").unwrap());
+
+        args.insert("end".to_string(), to_value(" [...]").unwrap());
+        let result = truncate_words(to_value("日本 語 ok").unwrap(), args.clone());
+        assert_eq!(result.unwrap(), to_value("日本 語 [...]").unwrap());
+
+        args.insert("count".to_string(), to_value(0).unwrap());
+        let result = truncate_words(to_value("Hello").unwrap(), args);
+        assert_eq!(result.unwrap(), to_value(" [...]").unwrap());
+    }
+
+    #[test]
+    fn test_truncate_words_errors() {
+        let result = truncate_words(to_value("Hello").unwrap(), HashMap::new());
+        assert!(result.is_err());
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "Filter `truncate_words` expected an arg called `count`"
+        );
+
+        let mut args = HashMap::new();
+        args.insert("count".to_string(), to_value(-1).unwrap());
+        assert!(truncate_words(to_value("Hello").unwrap(), args.clone()).is_err());
+        args.insert("count".to_string(), to_value(1).unwrap());
+        assert!(truncate_words(to_value(1).unwrap(), args).is_err());
+    }
+
     #[test]
     fn test_lower() {
         let result = lower(to_value("HELLO").unwrap(), HashMap::new());
@@ -240,6 +679,212 @@ mod tests {
         assert_eq!(result.unwrap(), to_value(&4).unwrap());
     }
 
+    #[test]
+    fn test_wordcount_whitespace_runs() {
+        let result = wordcount(to_value("  Joel   is\n\ta  slug ").unwrap(), HashMap::new());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(4).unwrap());
+
+        let result = wordcount(to_value("").unwrap(), HashMap::new());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(0).unwrap());
+    }
+
+    #[test]
+    fn test_wordcount_non_string() {
+        assert!(wordcount(to_value(1).unwrap(), HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_wordwrap() {
+        let tests = vec![
+            ("Joel is a slug", 7, "Joel is\na slug"),
+            // lines can be exactly `width` long
+            ("Joel is a slug", 9, "Joel is a\nslug"),
+            ("Joel   is    a slug", 9, "Joel is a\nslug"),
+            ("Joel\nis a slug", 20, "Joel\nis a slug"),
+            ("Joel is a sluggish slug", 4, "Joel\nis a\nsluggish\nslug"),
+            ("ab cd ef\n\nxx\n", 5, "ab cd\nef\n\nxx\n"),
+            ("ab cd ef\r\n\r\nxx\r\n", 5, "ab cd\r\nef\r\n\r\nxx\r\n"),
+            ("ab cd ef\n\nxx\r\n", 5, "ab cd\nef\n\nxx\r\n"),
+        ];
+        for (input, width, expected) in tests {
+            let mut args = HashMap::new();
+            args.insert("width".to_string(), to_value(width).unwrap());
+            let result = wordwrap(to_value(input).unwrap(), args);
+            assert!(result.is_ok());
+            assert_eq!(result.unwrap(), to_value(expected).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_wordwrap_break_long_words() {
+        let mut args = HashMap::new();
+        args.insert("width".to_string(), to_value(4).unwrap());
+        args.insert("break_long_words".to_string(), to_value(true).unwrap());
+        let result = wordwrap(to_value("a sluggish slug").unwrap(), args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("a\nslug\ngish\nslug").unwrap());
+    }
+
+    #[test]
+    fn test_wordwrap_default_width() {
+        let input = vec!["word"; 20].join(" ");
+        let result = wordwrap(to_value(&input).unwrap(), HashMap::new());
+        assert!(result.is_ok());
+        let expected = format!("{}\n{}", ["word"; 16].join(" "), ["word"; 4].join(" "));
+        assert_eq!(result.unwrap(), to_value(expected).unwrap());
+    }
+
+    #[test]
+    fn test_wordwrap_errors() {
+        assert!(wordwrap(to_value(1).unwrap(), HashMap::new()).is_err());
+
+        let mut args = HashMap::new();
+        args.insert("width".to_string(), to_value(0).unwrap());
+        let result = wordwrap(to_value("hello").unwrap(), args);
+        assert!(result.is_err());
+        assert_eq!(result.err().unwrap().to_string(), "Filter `wordwrap` was called with a `width` of 0");
+    }
+
+    #[test]
+    fn test_matches() {
+        let tests = vec![
+            ("123", r"^\d+$", true),
+            ("12a", r"^\d+$", false),
+            ("hello world", "wor", true),
+            ("", "^$", true),
+        ];
+        for (input, pattern, expected) in tests {
+            let mut args = HashMap::new();
+            args.insert("pattern".to_string(), to_value(pattern).unwrap());
+            let result = matches(to_value(input).unwrap(), args);
+            assert!(result.is_ok());
+            assert_eq!(result.unwrap(), to_value(expected).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_matches_invalid_pattern() {
+        let mut args = HashMap::new();
+        args.insert("pattern".to_string(), to_value("(a").unwrap());
+        let result = matches(to_value("a").unwrap(), args);
+        assert!(result.is_err());
+        let message = result.unwrap_err().to_string();
+        assert!(message.starts_with("Filter `matches` got an invalid regex in `pattern`: "));
+        assert!(message.len() > "Filter `matches` got an invalid regex in `pattern`: ".len());
+    }
+
+    #[test]
+    fn test_matches_without_pattern() {
+        let result = matches(to_value("a").unwrap(), HashMap::new());
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err().to_string(), "Filter `matches` expected an arg called `pattern`");
+    }
+
+    #[test]
+    fn test_findall() {
+        let mut args = HashMap::new();
+        args.insert("pattern".to_string(), to_value(r"\d+").unwrap());
+        let result = findall(to_value("a1 b22 c333").unwrap(), args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(["1", "22", "333"]).unwrap());
+
+        let mut args = HashMap::new();
+        args.insert("pattern".to_string(), to_value(r"\d+").unwrap());
+        let result = findall(to_value("abc").unwrap(), args);
+        assert_eq!(result.unwrap(), to_value(Vec::<String>::new()).unwrap());
+    }
+
+    #[test]
+    fn test_findall_invalid_pattern() {
+        let mut args = HashMap::new();
+        args.insert("pattern".to_string(), to_value("[a").unwrap());
+        let result = findall(to_value("a").unwrap(), args);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().starts_with("Filter `findall` got an invalid regex in `pattern`: "));
+    }
+
+    #[test]
+    fn test_patterns_are_cached() {
+        let mut args = HashMap::new();
+        args.insert("pattern".to_string(), to_value("^cached-pattern-test$").unwrap());
+        matches(to_value("a").unwrap(), args).unwrap();
+        assert!(PATTERNS.lock().unwrap().contains_key("^cached-pattern-test$"));
+    }
+
+    #[test]
+    fn test_split() {
+        let mut args = HashMap::new();
+        args.insert("pat".to_string(), to_value(",").unwrap());
+        let result = split(to_value("a,b,,c").unwrap(), args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(["a", "b", "", "c"]).unwrap());
+    }
+
+    #[test]
+    fn test_split_multi_char_pat() {
+        let mut args = HashMap::new();
+        args.insert("pat".to_string(), to_value(", ").unwrap());
+        let result = split(to_value("a, b,c, d").unwrap(), args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(["a", "b,c", "d"]).unwrap());
+    }
+
+    #[test]
+    fn test_split_no_occurrence() {
+        let mut args = HashMap::new();
+        args.insert("pat".to_string(), to_value(";").unwrap());
+        let result = split(to_value("a,b").unwrap(), args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(["a,b"]).unwrap());
+    }
+
+    #[test]
+    fn test_split_limit() {
+        let tests = [
+            (0, to_value(["a,b,c"]).unwrap()),
+            (1, to_value(["a", "b,c"]).unwrap()),
+            (2, to_value(["a", "b", "c"]).unwrap()),
+            (10, to_value(["a", "b", "c"]).unwrap()),
+        ];
+        for (limit, expected) in tests {
+            let mut args = HashMap::new();
+            args.insert("pat".to_string(), to_value(",").unwrap());
+            args.insert("limit".to_string(), to_value(limit).unwrap());
+            let result = split(to_value("a,b,c").unwrap(), args);
+            assert!(result.is_ok());
+            assert_eq!(result.unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_split_empty_input() {
+        let mut args = HashMap::new();
+        args.insert("pat".to_string(), to_value(",").unwrap());
+        let result = split(to_value("").unwrap(), args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value([""]).unwrap());
+    }
+
+    #[test]
+    fn test_split_invalid_args() {
+        let result = split(to_value("a,b").unwrap(), HashMap::new());
+        assert!(result.is_err());
+        assert_eq!(result.err().unwrap().to_string(), "Filter `split` expected an arg called `pat`");
+
+        let mut args = HashMap::new();
+        args.insert("pat".to_string(), to_value("").unwrap());
+        let result = split(to_value("a,b").unwrap(), args);
+        assert!(result.is_err());
+        assert_eq!(result.err().unwrap().to_string(), "Filter `split` was called with an empty `pat` arg");
+
+        let mut args = HashMap::new();
+        args.insert("pat".to_string(), to_value(",").unwrap());
+        args.insert("limit".to_string(), to_value(-1).unwrap());
+        assert!(split(to_value("a,b").unwrap(), args).is_err());
+    }
+
     #[test]
     fn test_replace() {
         let mut args = HashMap::new();
@@ -250,6 +895,48 @@ mod tests {
         assert_eq!(result.unwrap(), to_value("Goodbye world!").unwrap());
     }
 
+    #[test]
+    fn test_replace_multiple_occurrences() {
+        let mut args = HashMap::new();
+        args.insert("from".to_string(), to_value("aa").unwrap());
+        args.insert("to".to_string(), to_value("b").unwrap());
+        let result = replace(to_value("aaaaa aa").unwrap(), args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("bba b").unwrap());
+    }
+
+    #[test]
+    fn test_replace_no_occurrence() {
+        let mut args = HashMap::new();
+        args.insert("from".to_string(), to_value("Bye").unwrap());
+        args.insert("to".to_string(), to_value("Hi").unwrap());
+        let result = replace(to_value("Hello world!").unwrap(), args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("Hello world!").unwrap());
+    }
+
+    #[test]
+    fn test_replace_empty_from() {
+        let mut args = HashMap::new();
+        args.insert("from".to_string(), to_value("").unwrap());
+        args.insert("to".to_string(), to_value("-").unwrap());
+        let result = replace(to_value("Hello").unwrap(), args);
+        assert!(result.is_err());
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "Filter `replace` was called with an empty `from` arg"
+        );
+    }
+
+    #[test]
+    fn test_replace_non_string() {
+        let mut args = HashMap::new();
+        args.insert("from".to_string(), to_value("1").unwrap());
+        args.insert("to".to_string(), to_value("2").unwrap());
+        let result = replace(to_value(1).unwrap(), args);
+        assert!(result.is_err());
+    }
+
     #[test]
     fn test_replace_missing_arg() {
         let mut args = HashMap::new();
@@ -267,6 +954,10 @@ mod tests {
         let tests = vec![
             ("CAPITAL IZE", "Capital ize"),
             ("capital ize", "Capital ize"),
+            ("Already capitalized", "Already capitalized"),
+            ("élan VITAL", "Élan vital"),
+            ("¡Hola AMIGO", "¡hola amigo"),
+            ("", ""),
         ];
         for (input, expected) in tests {
             let result = capitalize(to_value(input).unwrap(), HashMap::new());
@@ -275,6 +966,45 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_indent() {
+        let tests = vec![
+            ("a\nb", 2, true, false, "  a\n  b"),
+            ("a\r\nb\n", 2, true, false, "  a\r\n  b\n"),
+            ("a\nb", 2, false, false, "a\n  b"),
+            ("a\n\nb", 2, true, false, "  a\n\n  b"),
+            ("a\n\r\nb", 2, true, true, "  a\n  \r\n  b"),
+            ("a", 0, true, false, "a"),
+        ];
+        for (input, width, first, blank, expected) in tests {
+            let mut args = HashMap::new();
+            args.insert("width".to_string(), to_value(width).unwrap());
+            args.insert("first".to_string(), to_value(first).unwrap());
+            args.insert("blank".to_string(), to_value(blank).unwrap());
+            let result = indent(to_value(input).unwrap(), args);
+            assert!(result.is_ok());
+            assert_eq!(result.unwrap(), to_value(expected).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_indent_defaults() {
+        let result = indent(to_value("key: value\n\nother: value").unwrap(), HashMap::new());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value("    key: value\n\n    other: value").unwrap());
+    }
+
+    #[test]
+    fn test_indent_non_string() {
+        assert!(indent(to_value(vec![1]).unwrap(), HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_case_filters_non_string() {
+        assert!(capitalize(to_value(1).unwrap(), HashMap::new()).is_err());
+        assert!(title(to_value(true).unwrap(), HashMap::new()).is_err());
+    }
+
     #[test]
     fn test_addslashes() {
         let tests = vec![
@@ -298,6 +1028,11 @@ mod tests {
         let tests = vec![
             (r#"Hello world"#, r#"hello-world"#),
             (r#"Hello 世界"#, r#"hello-shi-jie"#),
+            (r#"Hello, World!"#, r#"hello-world"#),
+            (r#"  -Hello world-  "#, r#"hello-world"#),
+            (r#"a -- b__c...d"#, r#"a-b-c-d"#),
+            (r#"HeLLo WoRLD"#, r#"hello-world"#),
+            (r#"Crème brûlée"#, r#"creme-brulee"#),
         ];
         for (input, expected) in tests {
             let result = slugify(to_value(input).unwrap(), HashMap::new());
@@ -306,6 +1041,12 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_slugify_non_string() {
+        let result = slugify(to_value(1).unwrap(), HashMap::new());
+        assert!(result.is_err());
+    }
+
     #[test]
     fn test_urlencode() {
         let tests = vec![
@@ -342,7 +1083,13 @@ mod tests {
             ("  foo  bar", "  Foo  Bar"),
             ("\tfoo\tbar\t", "\tFoo\tBar\t"),
             ("foo bar ", "Foo Bar "),
-            ("foo bar\t", "Foo Bar\t")
+            ("foo bar\t", "Foo Bar\t"),
+            ("Foo Bar", "Foo Bar"),
+            ("don't stop", "Don't Stop"),
+            ("'quoted' word", "'Quoted' Word"),
+            ("...and then", "...And Then"),
+            ("émile zola", "Émile Zola"),
+            ("ÉCOLE NORMALE", "École Normale"),
         ];
         for (input, expected) in tests {
             let result = title(to_value(input).unwrap(), HashMap::new());
@@ -351,12 +1098,33 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_striptags_non_string() {
+        assert!(striptags(to_value(1).unwrap(), HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_escape_js() {
+        let result = escape_js(to_value("var x = \"</script>\";\nalert('\\o/')").unwrap(), HashMap::new());
+        assert!(result.is_ok());
+        assert_eq!(
+            result.unwrap(),
+            to_value(r"var x \u003D \u0022\u003C/script\u003E\u0022;\nalert(\u0027\\o/\u0027)").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_escape_js_non_string() {
+        let result = escape_js(to_value(1).unwrap(), HashMap::new());
+        assert!(result.is_err());
+    }
+
     #[test]
     fn test_striptags() {
         let tests = vec![
             (r"<b>Joel</b> <button>is</button> a <span>slug</span>", "Joel is a slug"),
-            (r#"<p>just a small   \n <a href="x"> example</a> link</p>\n<p>to a webpage</p><!-- <p>and some commented stuff</p> -->"#,
-            r#"just a small   \n  example link\nto a webpage"#),
+            ("<p>just a small   \n <a href=\"x\"> example</a> link</p>\n<p>to a webpage</p><!-- <p>and some commented stuff</p> -->",
+            "just a small example link to a webpage"),
             (r"<p>See: &#39;&eacute; is an apostrophe followed by e acute</p>",r"See: &#39;&eacute; is an apostrophe followed by e acute"),
             (r"<adf>a", "a"),
             (r"</adf>a", "a"),
@@ -370,6 +1138,13 @@ mod tests {
             (r#"a<p a >b</p>c"#, "abc"),
             (r#"d<a:b c:d>e</p>f"#, "def"),
             (r#"<strong>foo</strong><a href="http://example.com">bar</a>"#, "foobar"),
+            (r#"<div><p>nested <b><i>tags</i></b></p></div>"#, "nested tags"),
+            (r#"<a title="a > b" href='x'>link</a>"#, "link"),
+            (r#"<a title='"quoted" > b'>link</a>"#, "link"),
+            ("<!-- multi\nline -->text", "text"),
+            ("<p>Tom &amp; Jerry</p>", "Tom &amp; Jerry"),
+            ("no tags at all", "no tags at all"),
+            ("  spaced\t\nout  ", "spaced out"),
         ];
         for (input, expected) in tests {
             let result = striptags(to_value(input).unwrap(), HashMap::new());
@@ -0,0 +1,179 @@
+//! Global functions, called directly in templates like `{{ now() }}`
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serde_json::value::{Value, to_value};
+use chrono::Local;
+
+use errors::Result;
+use utils::value_to_int;
+
+
+/// The type of global functions: they only receive their keyword arguments.
+/// They need to be `Send + Sync` so a `Tera` instance can be shared between threads,
+/// and are reference-counted like filters
+pub type GlobalFn = Arc<dyn Fn(HashMap<String, Value>) -> Result<Value> + Send + Sync>;
+
+
+// The longest array `range` can return, to avoid running out of memory on a typo in a template
+const MAX_RANGE_LENGTH: i64 = 100_000;
+
+// Gets an optional integer argument, erroring if it was given with the wrong type
+fn get_int_arg(fn_name: &str, arg_name: &str, args: &HashMap<String, Value>) -> Result<Option<i64>> {
+    match args.get(arg_name) {
+        Some(val) => match value_to_int(val) {
+            Some(i) => Ok(Some(i)),
+            None => bail!(
+                "Function `{}` received an incorrect type for arg `{}`: got `{}` but expected an integer",
+                fn_name, arg_name, val
+            ),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Returns an array of integers going from `start` (inclusive, defaults to 0) to
+/// `end` (exclusive) by increments of `step` (defaults to 1).
+/// Errors if the array would have more than 100 000 values.
+pub fn range(args: HashMap<String, Value>) -> Result<Value> {
+    let start = get_int_arg("range", "start", &args)?.unwrap_or(0);
+    let step = get_int_arg("range", "step", &args)?.unwrap_or(1);
+    let end = match get_int_arg("range", "end", &args)? {
+        Some(e) => e,
+        None => bail!("Function `range` was called without an `end` argument"),
+    };
+
+    if step <= 0 {
+        bail!("Function `range` was called with a `step` of {} but it needs to be positive", step);
+    }
+
+    // Computed in i128 as `end - start` can overflow an i64
+    let length = (i128::from(end) - i128::from(start) + i128::from(step) - 1) / i128::from(step);
+    if length > i128::from(MAX_RANGE_LENGTH) {
+        bail!(
+            "Function `range` would return {} values from {} to {} by {} but it can't return more than {}",
+            length, start, end, step, MAX_RANGE_LENGTH
+        );
+    }
+
+    let mut values = vec![];
+    let mut current = start;
+    while current < end {
+        values.push(current);
+        current = match current.checked_add(step) {
+            Some(c) => c,
+            None => break,
+        };
+    }
+
+    Ok(to_value(values)?)
+}
+
+/// Returns the current time as a timestamp in seconds since epoch, which can be
+/// formatted with the `date` filter.
+pub fn now(_: HashMap<String, Value>) -> Result<Value> {
+    Ok(to_value(Local::now().timestamp())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use serde_json::value::to_value;
+    use super::*;
+
+    #[test]
+    fn test_range_default_start() {
+        let mut args = HashMap::new();
+        args.insert("end".to_string(), to_value(5).unwrap());
+        let result = range(args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(vec![0, 1, 2, 3, 4]).unwrap());
+    }
+
+    #[test]
+    fn test_range_start_and_step() {
+        let mut args = HashMap::new();
+        args.insert("start".to_string(), to_value(2).unwrap());
+        args.insert("end".to_string(), to_value(10).unwrap());
+        args.insert("step".to_string(), to_value(3).unwrap());
+        let result = range(args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(vec![2, 5, 8]).unwrap());
+    }
+
+    #[test]
+    fn test_range_empty() {
+        let mut args = HashMap::new();
+        args.insert("start".to_string(), to_value(5).unwrap());
+        args.insert("end".to_string(), to_value(2).unwrap());
+        let result = range(args);
+        assert!(result.is_ok());
+        let empty: Vec<i64> = vec![];
+        assert_eq!(result.unwrap(), to_value(empty).unwrap());
+    }
+
+    #[test]
+    fn test_range_missing_end() {
+        let result = range(HashMap::new());
+        assert!(result.is_err());
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "Function `range` was called without an `end` argument"
+        );
+    }
+
+    #[test]
+    fn test_range_near_i64_max() {
+        let mut args = HashMap::new();
+        args.insert("start".to_string(), to_value(9223372036854775800i64).unwrap());
+        args.insert("end".to_string(), to_value(i64::MAX).unwrap());
+        args.insert("step".to_string(), to_value(10).unwrap());
+        let result = range(args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), to_value(vec![9223372036854775800i64]).unwrap());
+    }
+
+    #[test]
+    fn test_range_too_long() {
+        let mut args = HashMap::new();
+        args.insert("end".to_string(), to_value(100_000).unwrap());
+        assert!(range(args.clone()).is_ok());
+
+        args.insert("end".to_string(), to_value(10000000000i64).unwrap());
+        let result = range(args);
+        assert!(result.is_err());
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "Function `range` would return 10000000000 values from 0 to 10000000000 by 1 but it can't return more than 100000"
+        );
+
+        let mut args = HashMap::new();
+        args.insert("start".to_string(), to_value(i64::MIN).unwrap());
+        args.insert("end".to_string(), to_value(i64::MAX).unwrap());
+        assert!(range(args).is_err());
+    }
+
+    #[test]
+    fn test_range_invalid_args() {
+        let mut args = HashMap::new();
+        args.insert("end".to_string(), to_value("5").unwrap());
+        assert!(range(args.clone()).is_err());
+
+        args.insert("end".to_string(), to_value(2.5).unwrap());
+        assert!(range(args.clone()).is_err());
+
+        args.insert("end".to_string(), to_value(1e30).unwrap());
+        assert!(range(args.clone()).is_err());
+
+        args.insert("end".to_string(), to_value(5).unwrap());
+        args.insert("step".to_string(), to_value(0).unwrap());
+        assert!(range(args).is_err());
+    }
+
+    #[test]
+    fn test_now() {
+        let result = now(HashMap::new());
+        assert!(result.is_ok());
+        assert!(result.unwrap().is_i64());
+    }
+}
@@ -32,15 +32,19 @@ mod template;
 mod tera;
 mod filters;
 mod testers;
+mod global_functions;
 mod utils;
+mod delimiters;
 
 
 // Library exports.
 
-// Template is meant to be used internally only but is exported for test/bench.
-#[doc(hidden)] pub use template::Template;
+// Template is mostly meant to be used internally but can be parsed ahead of time and
+// added with `Tera::add_parsed_template`. Like the AST, its fields are not covered by semver
+pub use template::Template;
 pub use context::Context;
 pub use tera::Tera;
+pub use delimiters::Delimiters;
 pub use errors::{Result, Error, ErrorKind};
 pub use utils::{escape_html};
 // Re-export Value so apps/tools can encode data in Tera types
@@ -52,4 +56,5 @@ pub use serde_json::value::{Value, from_value, to_value};
 pub mod ast {
     pub use parser::Operator;
     pub use parser::Node;
+    pub use parser::Span;
 }
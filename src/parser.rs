@@ -3,6 +3,7 @@ use std::fmt;
 
 use pest::prelude::*;
 use errors::Result;
+use delimiters::{Delimiters, Translation, unescape_raw};
 
 
 #[derive(Clone, Debug, PartialEq)]
@@ -16,6 +17,8 @@ pub enum Operator {
     Mul,
     /// /
     Div,
+    /// %
+    Mod,
     /// >
     Gt,
     /// >=
@@ -28,6 +31,10 @@ pub enum Operator {
     Eq,
     /// !=
     NotEq,
+    /// in
+    In,
+    /// not in
+    NotIn,
     /// and
     And,
     /// or
@@ -41,6 +48,7 @@ impl fmt::Display for Operator {
             Operator::Sub => "-",
             Operator::Mul => "*",
             Operator::Div => "/",
+            Operator::Mod => "%",
 
             Operator::Gt => ">",
             Operator::Gte => ">=",
@@ -48,6 +56,8 @@ impl fmt::Display for Operator {
             Operator::Lte => "<=",
             Operator::Eq => "==",
             Operator::NotEq => "!=",
+            Operator::In => "in",
+            Operator::NotIn => "not in",
 
             Operator::And => "and",
             Operator::Or => "or",
@@ -55,6 +65,28 @@ impl fmt::Display for Operator {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// Where a node starts in the template source, used to give a location in errors
+pub struct Span {
+    /// 1-indexed line
+    pub line: usize,
+    /// 1-indexed column
+    pub col: usize,
+}
+
+impl Span {
+    /// Create a span from a line and column, as given by pest
+    pub fn new(line: usize, col: usize) -> Span {
+        Span { line, col }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.col)
+    }
+}
+
 
 #[derive(Clone, Debug, PartialEq)]
 /// All nodes in Tera AST
@@ -76,20 +108,38 @@ pub enum Node {
         lhs: Box<Node>,
         /// Right side of the operation
         rhs: Box<Node>,
-        /// Operator used (+, -, *, /)
+        /// Operator used (+, -, *, /, %)
         operator: Operator
     },
+    /// A numeric negation `-value`
+    Negate(Box<Node>),
     /// A logic node (comparison etc)
     Logic {
         /// Left side of the operation
         lhs: Box<Node>,
         /// Right side of the operation
         rhs: Box<Node>,
-        /// Operator used (>, <, >=, <=, ==, !=, and, or)
+        /// Operator used (>, <, >=, <=, ==, !=, in, not in, and, or)
         operator: Operator
     },
     /// Negated node
     Not(Box<Node>),
+    /// A string concatenation `lhs ~ rhs`
+    Concat {
+        /// Left side of the concatenation
+        lhs: Box<Node>,
+        /// Right side of the concatenation
+        rhs: Box<Node>,
+    },
+    /// An inline condition `then if condition else otherwise`, which is an expression
+    InlineIf {
+        /// Can be many things, from a number to a `Logic` node
+        condition: Box<Node>,
+        /// The expression returned if the condition is truthy
+        then: Box<Node>,
+        /// The expression returned otherwise, `null` if there isn't one
+        otherwise: Option<Box<Node>>,
+    },
 
     /// Contains initial if block, all elif blocks and optional else block
     /// The condition nodes are a list of `Conditional` node
@@ -107,15 +157,21 @@ pub enum Node {
         body: Box<Node>
     },
 
-    /// A for loop `{% for i in arr %}{% endfor %}
+    /// A for loop `{% for i in arr %}{% endfor %}` or `{% for key, value in obj %}{% endfor %}`
     For {
+        /// Name of the local variable holding the key when iterating on an object
+        key: Option<String>,
         /// Name of the local variable in the loop
         variable: String,
-        /// Name of the variable being iterated on
-        array: String,
+        /// What is being iterated on: an `Identifier`, `FunctionCall` or `Array` node
+        container: Box<Node>,
         /// Body of the forloop, a `List` node
         body: Box<Node>
     },
+    /// A `{% break %}` in a for loop, stopping it
+    Break,
+    /// A `{% continue %}` in a for loop, skipping to the next iteration
+    Continue,
     /// A `{% block hello %}...{% endblock hello %}` node
     Block {
         /// Name of the block
@@ -123,6 +179,9 @@ pub enum Node {
         /// Body of the block, a `List` node
         body: Box<Node>
     },
+    /// The content of a `{% spaceless %}...{% endspaceless %}` block, a `List` node.
+    /// The whitespace between the HTML tags of its output is removed
+    Spaceless(Box<Node>),
     /// A call to `{{ super() }}` in a block
     Super,
 
@@ -132,6 +191,8 @@ pub enum Node {
         name: String,
         /// Name of the macro parameters
         params: VecDeque<String>,
+        /// Default values of the parameters that have one, the Node is an expression
+        defaults: HashMap<String, Node>,
         /// Body of the macro, a `List` node
         body: Box<Node>
     },
@@ -142,16 +203,28 @@ pub enum Node {
         /// The name we give to that macro namespace
         name: String
     },
-    /// A macro call node `{{ my_macros::macro1(foo=1, bar=bar) }}`
+    /// A macro call node `{{ my_macros::macro1(foo=1, bar=bar) }}` or `{{ my_macros::macro1(1, bar=bar) }}`
     MacroCall {
         /// The macro namespace name
         namespace: String,
         /// The macro name
         name: String,
+        /// The positional args for that macro, in order, the Nodes are expressions
+        args: VecDeque<Node>,
         /// The kwargs for that macro, the Node is an expression
         params: HashMap<String, Node>
     },
 
+    /// A global function call node `{{ range(end=5) }}`
+    FunctionCall {
+        /// Name of the function
+        name: String,
+        /// kwargs for that function, the Node is an expression
+        params: HashMap<String, Node>,
+        /// Where the call is in the template
+        span: Span,
+    },
+
     /// A test node `if my_var is odd`
     Test {
         /// Which expression is evaluated
@@ -174,8 +247,14 @@ pub enum Node {
         /// Name of the variable
         name: String,
         /// Optional list of `Filter` node
-        filters: Option<VecDeque<Node>>
+        filters: Option<VecDeque<Node>>,
+        /// Where the variable is in the template
+        span: Span,
     },
+    /// An array literal `[1, "two", three]`, containing expressions
+    Array(VecDeque<Node>),
+    /// An object literal `{"a": 1, "b": two}`, with the keys in the order they were written
+    Object(VecDeque<(String, Node)>),
     /// The text between `{% raw %}` and `{% endraw %}`
     Raw(String),
     /// The `{% extends "blabla.html" %}` node, contains the template name
@@ -184,6 +263,20 @@ pub enum Node {
     VariableBlock(Box<Node>),
     /// The `{% include "blabla.html" %}` node, contains the template name
     Include(String),
+    /// A `{% set name = value %}` node
+    Set {
+        /// Name of the variable being assigned
+        name: String,
+        /// The expression being assigned to the variable
+        value: Box<Node>
+    },
+    /// A `{% set name %}...{% endset %}` node, assigning the rendered body to the variable
+    SetBlock {
+        /// Name of the variable being assigned
+        name: String,
+        /// Body of the set, a `List` node
+        body: Box<Node>
+    },
 }
 
 impl Node {
@@ -212,10 +305,23 @@ impl_rdp! {
         op_gt        = { [">"] }
         op_eq        = { ["=="] }
         op_ineq      = { ["!="] }
+        // not matching the beginning of an identifier like `index`
+        op_in        = @{ ["in"] ~ !(['a'..'z'] | ['A'..'Z'] | ["_"] | ['0'..'9']) }
+        op_not_in    = @{
+            ["not"] ~ ([" "] | ["\t"] | ["\r"] | ["\n"])+ ~ ["in"] ~
+            !(['a'..'z'] | ['A'..'Z'] | ["_"] | ['0'..'9'])
+        }
+        // keywords of inline conditions, not matching the beginning of an identifier either
+        op_if        = @{ ["if"] ~ !(['a'..'z'] | ['A'..'Z'] | ["_"] | ['0'..'9']) }
+        op_else      = @{ ["else"] ~ !(['a'..'z'] | ['A'..'Z'] | ["_"] | ['0'..'9']) }
         op_plus      = { ["+"] }
-        op_minus     = { ["-"] }
+        // not matching the whitespace control of the closing delimiters
+        op_minus     = @{ !(["-%}"] | ["-}}"]) ~ ["-"] }
         op_times     = { ["*"] }
         op_slash     = { ["/"] }
+        // not matching the closing delimiter of tags
+        op_modulo    = @{ !["%}"] ~ ["%"] }
+        op_concat    = { ["~"] }
         op_true      = { ["true"] }
         op_false     = { ["false"] }
         boolean      = _{ op_true | op_false }
@@ -254,27 +360,61 @@ impl_rdp! {
 
         // macros
         // TODO: add default arg?
-        macro_param = @{ simple_ident }
+        // the default value is evaluated when the macro is called
+        macro_param_default = !@{ expression }
+        macro_param = !@{ simple_ident ~ (["="] ~ macro_param_default)? }
         macro_params = !@{ macro_param ~ ([","] ~ macro_param )* }
         macro_definition = _{ identifier ~ ["("] ~ macro_params? ~ [")"]}
-        macro_call = { simple_ident ~ ["::"] ~ simple_ident ~ ["("] ~ fn_args? ~ [")"] }
+        // positional args have to come before the named ones, an expression followed by
+        // a single `=` is the start of a named arg
+        positional_arg = !@{ !(simple_ident ~ ["="] ~ !["="]) ~ expression }
+        macro_args = !@{ fn_args | positional_arg ~ ([","] ~ positional_arg)* ~ ([","] ~ fn_args)? }
+        macro_call = { simple_ident ~ ["::"] ~ simple_ident ~ ["("] ~ macro_args? ~ [")"] }
+
+        // global functions, unlike macros they are not namespaced
+        // the empty parentheses get their own rule so that a call without args
+        // doesn't pick up the args coming after it
+        no_fn_args = { ["("] ~ [")"] }
+        global_fn_call = { simple_ident ~ (no_fn_args | ["("] ~ fn_args ~ [")"]) }
 
         // Variable tests.
-        test_fn_param = { expression }
+        // the `else` of an inline condition is not a param of its test
+        test_fn_param = { !op_else ~ expression }
         test_fn_params = {
             test_fn_param
             | (["("] ~ test_fn_param ~ ([","] ~ test_fn_param)* ~ [")"])
         }
         test_fn = !@{ simple_ident ~ test_fn_params? }
-        test = { ["is"] ~ test_fn }
+        // needs the whitespace to not eat the beginning of a test name like `nothing`
+        test_not = @{ ["not"] ~ ([" "] | ["\t"] | ["\r"] | ["\n"]) }
+        test = { ["is"] ~ test_not? ~ test_fn }
+
+        // array and object literals, trailing commas are allowed
+        // non-atomic so whitespace is allowed inside them even in atomic rules like `fn_arg`
+        array_literal  = !@{ ["["] ~ (expression ~ ([","] ~ expression)* ~ [","]?)? ~ ["]"] }
+        object_pair    = !@{ string ~ [":"] ~ expression }
+        object_literal = !@{ ["{"] ~ (object_pair ~ ([","] ~ object_pair)* ~ [","]?)? ~ ["}"] }
+
+        // unary minus, negative number literals are handled by `int` and `float`
+        negation = { ["-"] ~ (global_fn_call | idents | group) }
+
+        // parentheses to group a sub-expression, non-atomic for the same reason as literals
+        group = !@{ ["("] ~ logic_expression ~ inline_if? ~ [")"] }
+
+        // inline condition coming after the expression it returns if the condition is truthy:
+        // `"a" if cond else "b"`. Only allowed where a whole expression is expected,
+        // `{{ }}`, `set` and parentheses, and the `else` part can be chained with another one
+        inline_if = _{ op_if ~ logic_expression ~ test? ~ (op_else ~ logic_expression ~ inline_if?)? }
 
         // Precedence climbing
         expression = _{
             // boolean first so they are not caught as identifiers
-            { boolean | string | idents | float | int }
-            comparison  = { op_gt | op_lt | op_eq | op_ineq | op_lte | op_gte }
+            { boolean | string | array_literal | object_literal | global_fn_call | idents | float | int | negation | group }
+            // `<=` and `>=` need to be tried before `<` and `>`
+            comparison  = { op_lte | op_gte | op_gt | op_lt | op_eq | op_ineq | op_in | op_not_in }
+            concat      = { op_concat }
             add_sub     = { op_plus | op_minus }
-            mul_div     = { op_times | op_slash }
+            mul_div     = { op_times | op_slash | op_modulo }
         }
 
         logic_expression = _{
@@ -286,19 +426,20 @@ impl_rdp! {
         // Tera specific things
 
         // different types of blocks
-        variable_start = _{ ["{{"] }
-        variable_end   = _{ ["}}"] }
-        tag_start      = _{ ["{%"] }
-        tag_end        = _{ ["%}"] }
-        comment_start  = _{ ["{#"] }
-        comment_end    = _{ ["#}"] }
+        // a `-` next to the delimiter removes the whitespace before/after the block
+        variable_start = _{ ["{{-"] | ["{{"] }
+        variable_end   = _{ ["-}}"] | ["}}"] }
+        tag_start      = _{ ["{%-"] | ["{%"] }
+        tag_end        = _{ ["-%}"] | ["%}"] }
+        comment_start  = _{ ["{#-"] | ["{#"] }
+        comment_end    = _{ ["-#}"] | ["#}"] }
         block_start    = _{ variable_start | tag_start | comment_start }
 
         // Actual tags
         include_tag      = !@{ tag_start ~ ["include"] ~ string ~ tag_end }
         import_macro_tag = !@{ tag_start ~ ["import"] ~ string ~ ["as"] ~ simple_ident ~ tag_end}
         extends_tag      = !@{ tag_start ~ ["extends"] ~ string ~ tag_end }
-        variable_tag     = !@{ variable_start ~ (macro_call | logic_expression) ~ variable_end }
+        variable_tag     = !@{ variable_start ~ (macro_call | logic_expression ~ inline_if?) ~ variable_end }
         super_tag        = !@{ variable_start ~ ["super()"] ~ variable_end }
         comment_tag      = !@{ comment_start ~ (!comment_end ~ any )* ~ comment_end }
         block_tag        = !@{ tag_start ~ ["block"] ~ identifier ~ tag_end }
@@ -306,13 +447,24 @@ impl_rdp! {
         if_tag           = !@{ tag_start ~ ["if"] ~ logic_expression ~ test? ~ tag_end }
         elif_tag         = !@{ tag_start ~ ["elif"] ~ logic_expression ~ test? ~ tag_end }
         else_tag         = !@{ tag_start ~ ["else"] ~ tag_end }
-        for_tag          = !@{ tag_start ~ ["for"] ~ identifier ~ ["in"] ~ idents ~ tag_end }
+        // `key, value` when iterating on an object
+        for_key_value    = { identifier ~ [","] ~ identifier }
+        for_tag          = !@{ tag_start ~ ["for"] ~ (for_key_value | identifier) ~ ["in"] ~ (array_literal | global_fn_call | idents) ~ tag_end }
         raw_tag          = !@{ tag_start ~ ["raw"] ~ tag_end }
         endraw_tag       = !@{ tag_start ~ ["endraw"] ~ tag_end }
         endblock_tag     = !@{ tag_start ~ ["endblock"] ~ identifier ~ tag_end }
         endmacro_tag     = !@{ tag_start ~ ["endmacro"] ~ identifier ~ tag_end }
         endif_tag        = !@{ tag_start ~ ["endif"] ~ tag_end }
         endfor_tag       = !@{ tag_start ~ ["endfor"] ~ tag_end }
+        // only valid in the body of a for loop, which is checked once the AST is built
+        break_tag        = !@{ tag_start ~ ["break"] ~ tag_end }
+        continue_tag     = !@{ tag_start ~ ["continue"] ~ tag_end }
+        spaceless_tag    = !@{ tag_start ~ ["spaceless"] ~ tag_end }
+        endspaceless_tag = !@{ tag_start ~ ["endspaceless"] ~ tag_end }
+        set_tag          = !@{ tag_start ~ ["set"] ~ simple_ident ~ ["="] ~ logic_expression ~ inline_if? ~ tag_end }
+        // the block form of `set`, capturing the output of its body
+        set_block_tag    = !@{ tag_start ~ ["set"] ~ simple_ident ~ tag_end }
+        endset_tag       = !@{ tag_start ~ ["endset"] ~ tag_end }
 
         elif_block = { elif_tag ~ content* }
         raw_text   = { (!endraw_tag ~ any )* }
@@ -323,8 +475,13 @@ impl_rdp! {
             include_tag |
             variable_tag |
             comment_tag |
+            set_tag |
+            break_tag |
+            continue_tag |
             if_tag ~ macro_content* ~ elif_block* ~ (else_tag ~ macro_content*)? ~ endif_tag |
             for_tag ~ macro_content* ~ endfor_tag |
+            spaceless_tag ~ macro_content* ~ endspaceless_tag |
+            set_block_tag ~ macro_content* ~ endset_tag |
             raw_tag ~ raw_text ~ endraw_tag |
             text
         }
@@ -336,9 +493,14 @@ impl_rdp! {
             super_tag |
             variable_tag |
             comment_tag |
+            set_tag |
+            break_tag |
+            continue_tag |
             block_tag ~ block_content* ~ endblock_tag |
             if_tag ~ block_content* ~ elif_block* ~ (else_tag ~ block_content*)? ~ endif_tag |
             for_tag ~ block_content* ~ endfor_tag |
+            spaceless_tag ~ block_content* ~ endspaceless_tag |
+            set_block_tag ~ block_content* ~ endset_tag |
             raw_tag ~ raw_text ~ endraw_tag |
             text
         }
@@ -348,10 +510,15 @@ impl_rdp! {
             import_macro_tag |
             variable_tag |
             comment_tag |
+            set_tag |
+            break_tag |
+            continue_tag |
             macro_tag ~ macro_content* ~ endmacro_tag |
             block_tag ~ block_content* ~ endblock_tag |
             if_tag ~ content* ~ elif_block* ~ (else_tag ~ content*)? ~ endif_tag |
             for_tag ~ content* ~ endfor_tag |
+            spaceless_tag ~ content* ~ endspaceless_tag |
+            set_block_tag ~ content* ~ endset_tag |
             raw_tag ~ raw_text ~ endraw_tag |
             text
         }
@@ -410,8 +577,22 @@ impl_rdp! {
 
         // Option since we don't want comments in the AST
         _content(&self) -> Result<Option<Node>> {
-            (&head: text) => {
-                Ok(Some(Node::Text(head.to_string())))
+            (head: text) => {
+                let mut text = self.input().slice(head.start, head.end);
+                // Whitespace control: the blocks around the text might want it trimmed
+                let before = self.input().slice(0, head.start);
+                if before.ends_with("-%}") || before.ends_with("-}}") || before.ends_with("-#}") {
+                    text = text.trim_start();
+                }
+                let after = self.input().slice(head.end, self.input().len());
+                if after.starts_with("{%-") || after.starts_with("{{-") || after.starts_with("{#-") {
+                    text = text.trim_end();
+                }
+
+                if text.is_empty() {
+                    return Ok(None);
+                }
+                Ok(Some(Node::Text(text.to_string())))
             },
             (_: include_tag, &name: string) => {
                 Ok(Some(Node::Include(name.trim_matches('"').to_string())))
@@ -422,19 +603,43 @@ impl_rdp! {
                     name: name.to_string(),
                 }))
             },
-            (_: variable_tag, _: macro_call, &namespace: simple_ident, &name: simple_ident, params: _fn_args()) => {
+            (_: variable_tag, _: macro_call, &namespace: simple_ident, &name: simple_ident, _: macro_args, args: _positional_args(), params: _fn_args()) => {
                 Ok(Some(Node::MacroCall {
                     namespace: namespace.to_string(),
                     name: name.to_string(),
+                    args: args?,
                     params: params?
                 }))
             },
-            (_: variable_tag, exp: _expression()) => {
+            (_: variable_tag, _: macro_call, &namespace: simple_ident, &name: simple_ident) => {
+                Ok(Some(Node::MacroCall {
+                    namespace: namespace.to_string(),
+                    name: name.to_string(),
+                    args: VecDeque::new(),
+                    params: HashMap::new(),
+                }))
+            },
+            (_: variable_tag, exp: _inline_if()) => {
                 Ok(Some(Node::VariableBlock(Box::new(exp?))))
             },
+            (_: set_tag, &name: simple_ident, exp: _inline_if()) => {
+                Ok(Some(Node::Set {
+                    name: name.to_string(),
+                    value: Box::new(exp?),
+                }))
+            },
             (_: raw_tag, &body: raw_text, _: endraw_tag) => {
                 Ok(Some(Node::Raw(body.to_string())))
             },
+            (_: spaceless_tag, body: _template(), _: endspaceless_tag) => {
+                Ok(Some(Node::Spaceless(Box::new(Node::List(body?)))))
+            },
+            (_: set_block_tag, &name: simple_ident, body: _template(), _: endset_tag) => {
+                Ok(Some(Node::SetBlock {
+                    name: name.to_string(),
+                    body: Box::new(Node::List(body?)),
+                }))
+            },
             (_: block_tag, &name: identifier, body: _template(), _: endblock_tag, &end_name: identifier) => {
                 if name != end_name {
                     let (line_no, col_no) = self.input().line_col(self.input.pos());
@@ -456,16 +661,34 @@ impl_rdp! {
                         end_name, line_no, col_no, name
                     );
                 }
+                let mut param_names = VecDeque::new();
+                let mut defaults = HashMap::new();
+                for (param, default) in params? {
+                    if let Some(d) = default {
+                        defaults.insert(param.clone(), d);
+                    }
+                    param_names.push_back(param);
+                }
                 Ok(Some(Node::Macro {
                     name: name.to_string(),
-                    params: params,
+                    params: param_names,
+                    defaults,
                     body: Box::new(Node::List(body?))
                 }))
             },
-            (_: for_tag, &variable: identifier, &array: identifier, body: _template(), _: endfor_tag) => {
+            (_: for_tag, _: for_key_value, &key: identifier, &variable: identifier, container: _expression(), body: _template(), _: endfor_tag) => {
                 Ok(Some(Node::For {
+                    key: Some(key.to_string()),
                     variable: variable.to_string(),
-                    array: array.to_string(),
+                    container: Box::new(container?),
+                    body: Box::new(Node::List(body?))
+                }))
+            },
+            (_: for_tag, &variable: identifier, container: _expression(), body: _template(), _: endfor_tag) => {
+                Ok(Some(Node::For {
+                    key: None,
+                    variable: variable.to_string(),
+                    container: Box::new(container?),
                     body: Box::new(Node::List(body?))
                 }))
             },
@@ -532,12 +755,27 @@ impl_rdp! {
             (_: super_tag) => {
                 Ok(Some(Node::Super))
             },
+            (_: break_tag) => {
+                Ok(Some(Node::Break))
+            },
+            (_: continue_tag) => {
+                Ok(Some(Node::Continue))
+            },
             (_: comment_tag) => {
                 Ok(None)
             }
         }
 
         _condition(&self) -> Result<Node> {
+            // Expression with a negated test.
+            (exp: _expression(), _: test, _: test_not, test_args: _test()) => {
+                let (name, params) = test_args?;
+                Ok(Node::Not(Box::new(Node::Test {
+                    expression: Box::new(exp?),
+                    name,
+                    params,
+                })))
+            },
             // Expression with a test.
             (exp: _expression(), _: test, test_args: _test()) => {
                 let (name, params) = test_args?;
@@ -553,6 +791,27 @@ impl_rdp! {
             }
         }
 
+        // An expression optionally followed by an inline condition
+        _inline_if(&self) -> Result<Node> {
+            (then: _expression(), _: op_if, cond: _condition(), _: op_else, otherwise: _inline_if()) => {
+                Ok(Node::InlineIf {
+                    condition: Box::new(cond?),
+                    then: Box::new(then?),
+                    otherwise: Some(Box::new(otherwise?)),
+                })
+            },
+            (then: _expression(), _: op_if, cond: _condition()) => {
+                Ok(Node::InlineIf {
+                    condition: Box::new(cond?),
+                    then: Box::new(then?),
+                    otherwise: None,
+                })
+            },
+            (exp: _expression()) => {
+                exp
+            }
+        }
+
         _elifs(&self) -> Result<VecDeque<Node>> {
             (_: elif_block, node: _if(), tail: _elifs()) => {
                 let mut tail2 = tail?;
@@ -593,6 +852,15 @@ impl_rdp! {
             () => Ok(HashMap::new())
         }
 
+        _positional_args(&self) -> Result<VecDeque<Node>> {
+            (_: positional_arg, exp: _expression(), tail: _positional_args()) => {
+                let mut tail2 = tail?;
+                tail2.push_front(exp?);
+                Ok(tail2)
+            },
+            () => Ok(VecDeque::new())
+        }
+
         _fn(&self) -> Result<Node> {
             (_: fn_call, &name: simple_ident, args: _fn_args()) => {
                 Ok(Node::Filter{name: name.to_string(), params: args?})
@@ -619,19 +887,23 @@ impl_rdp! {
             () => Ok(VecDeque::new())
         }
 
-        _macro_def_params(&self) -> VecDeque<String> {
-            // first arg of many
-            (_: macro_params, _: macro_param, &name: simple_ident, mut tail: _macro_def_params()) => {
-                tail.push_front(name.to_string());
+        _macro_def_params(&self) -> Result<VecDeque<(String, Option<Node>)>> {
+            // the token wrapping all the params
+            (_: macro_params, tail: _macro_def_params()) => {
                 tail
             },
-            // arguments after the first of many
-            (_: macro_param, &name: simple_ident, mut tail: _macro_def_params()) => {
-                tail.push_front(name.to_string());
-                tail
+            (_: macro_param, &name: simple_ident, _: macro_param_default, default: _expression(), tail: _macro_def_params()) => {
+                let mut tail = tail?;
+                tail.push_front((name.to_string(), Some(default?)));
+                Ok(tail)
+            },
+            (_: macro_param, &name: simple_ident, tail: _macro_def_params()) => {
+                let mut tail = tail?;
+                tail.push_front((name.to_string(), None));
+                Ok(tail)
             },
             // Base case
-            () => VecDeque::new()
+            () => Ok(VecDeque::new())
         }
 
         _test_fn_params(&self) -> (Result<VecDeque<Node>>) {
@@ -676,6 +948,7 @@ impl_rdp! {
                     operator: match sign.rule {
                         Rule::op_times => Operator::Mul,
                         Rule::op_slash => Operator::Div,
+                        Rule::op_modulo => Operator::Mod,
                         _ => unreachable!()
                     }
                 })
@@ -691,10 +964,18 @@ impl_rdp! {
                         Rule::op_ineq => Operator::NotEq,
                         Rule::op_lte => Operator::Lte,
                         Rule::op_gte => Operator::Gte,
+                        Rule::op_in => Operator::In,
+                        Rule::op_not_in => Operator::NotIn,
                         _ => unreachable!()
                     }
                 })
             },
+            (_: concat, left: _expression(), _, right: _expression()) => {
+                Ok(Node::Concat {
+                    lhs: Box::new(left?),
+                    rhs: Box::new(right?),
+                })
+            },
             (_: and, left: _expression(), _, right: _expression()) => {
                 Ok(Node::Logic {
                     lhs: Box::new(left?),
@@ -709,18 +990,63 @@ impl_rdp! {
                     operator: Operator::Or,
                 })
             },
-            (_: identifier_with_filter, &ident: identifier, tail: _filters()) => {
+            (_: identifier_with_filter, ident: identifier, tail: _filters()) => {
+                let (line, col) = self.input().line_col(ident.start);
                 Ok(Node::Identifier {
-                    name: ident.to_string(),
+                    name: self.input().slice(ident.start, ident.end).to_string(),
                     filters: Some(tail?),
+                    span: Span::new(line, col),
                 })
             },
+            // The tokens of the items are not nested in the literal token so we
+            // look at their positions to know where the literal ends
+            (array: array_literal) => {
+                let mut items = VecDeque::new();
+                while self.queue().get(self.queue_index()).is_some_and(|t| t.start < array.end) {
+                    items.push_back(self._expression()?);
+                }
+                Ok(Node::Array(items))
+            },
+            (object: object_literal) => {
+                let mut pairs = VecDeque::new();
+                while self.queue().get(self.queue_index()).is_some_and(|t| t.start < object.end) {
+                    // skip the `object_pair` token to get to the key
+                    self.inc_queue_index();
+                    let key = match self.queue().get(self.queue_index()) {
+                        Some(t) => self.input().slice(t.start, t.end).trim_matches('"').to_string(),
+                        None => unreachable!(),
+                    };
+                    self.inc_queue_index();
+                    pairs.push_back((key, self._expression()?));
+                }
+                Ok(Node::Object(pairs))
+            },
+            (call: global_fn_call, &name: simple_ident, _: no_fn_args) => {
+                let (line, col) = self.input().line_col(call.start);
+                Ok(Node::FunctionCall {name: name.to_string(), params: HashMap::new(), span: Span::new(line, col)})
+            },
+            (call: global_fn_call, &name: simple_ident, params: _fn_args()) => {
+                let (line, col) = self.input().line_col(call.start);
+                Ok(Node::FunctionCall {name: name.to_string(), params: params?, span: Span::new(line, col)})
+            },
+            (_: negation, exp: _expression()) => {
+                Ok(Node::Negate(Box::new(exp?)))
+            },
+            // the grouping is already in the shape of the tree
+            (_: group, exp: _inline_if()) => {
+                exp
+            },
             // single not used {% if not admin %} => equivalent to {% if admin == false %}
             (_: op_not, exp: _expression()) => {
                 Ok(Node::Not(Box::new(exp?)))
             },
-            (&ident: identifier) => {
-                Ok(Node::Identifier {name: ident.to_string(), filters: None })
+            (ident: identifier) => {
+                let (line, col) = self.input().line_col(ident.start);
+                Ok(Node::Identifier {
+                    name: self.input().slice(ident.start, ident.end).to_string(),
+                    filters: None,
+                    span: Span::new(line, col),
+                })
             },
             (&number: int) => {
                 Ok(Node::Int(number.parse::<i64>().unwrap()))
@@ -744,17 +1070,183 @@ impl_rdp! {
     }
 }
 
+// Shows the line `line_no` of the input with a `^` under the column `col_no`, both starting at 1.
+// Tabs are kept in the padding so the `^` is aligned however they are displayed
+fn error_snippet(input: &str, line_no: usize, col_no: usize) -> String {
+    let line = input.lines().nth(line_no - 1).unwrap_or("");
+    let padding = line.chars()
+        .take(col_no - 1)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect::<String>();
+    let gutter = " ".repeat(line_no.to_string().len());
+
+    format!("{} | {}\n{} | {}^", line_no, line, gutter, padding)
+}
+
+// Finds the position of a `{#` without a matching `#}` after it, if there is one
+fn unclosed_comment(input: &str) -> Option<usize> {
+    let start = input.rfind("{#")?;
+    if input[start + 2..].contains("#}") {
+        None
+    } else {
+        Some(start)
+    }
+}
+
+// Checks that `{% break %}` and `{% continue %}` are only used in the body of for loops.
+// Macros are rendered where they are called so they reset it
+fn check_loop_control(node: &Node, in_loop: bool) -> Result<()> {
+    match *node {
+        Node::Break | Node::Continue if !in_loop => {
+            let name = if *node == Node::Break { "break" } else { "continue" };
+            bail!("`{{% {} %}}` can only be used in the body of a for loop", name);
+        },
+        Node::List(ref nodes) => {
+            for n in nodes {
+                check_loop_control(n, in_loop)?;
+            }
+        },
+        Node::If { ref condition_nodes, ref else_node } => {
+            for n in condition_nodes {
+                check_loop_control(n, in_loop)?;
+            }
+            if let Some(ref e) = *else_node {
+                check_loop_control(e, in_loop)?;
+            }
+        },
+        Node::Conditional { ref body, .. } | Node::Block { ref body, .. } | Node::Spaceless(ref body) => {
+            check_loop_control(body, in_loop)?
+        },
+        Node::For { ref body, .. } => check_loop_control(body, true)?,
+        // the body of a set block is rendered on its own, it can't stop the loop it is in
+        Node::Macro { ref body, .. } | Node::SetBlock { ref body, .. } => check_loop_control(body, false)?,
+        _ => (),
+    }
+    Ok(())
+}
+
+// Removes the escaping added by `Delimiters::translate` to the content of the raw blocks
+fn unescape_raw_nodes(node: &mut Node) {
+    match *node {
+        Node::Raw(ref mut content) => *content = unescape_raw(content),
+        Node::List(ref mut nodes) => {
+            for n in nodes {
+                unescape_raw_nodes(n);
+            }
+        },
+        Node::If { ref mut condition_nodes, ref mut else_node } => {
+            for n in condition_nodes {
+                unescape_raw_nodes(n);
+            }
+            if let Some(ref mut e) = *else_node {
+                unescape_raw_nodes(e);
+            }
+        },
+        Node::Conditional { ref mut body, .. } | Node::Block { ref mut body, .. } | Node::Spaceless(ref mut body) |
+        Node::For { ref mut body, .. } | Node::Macro { ref mut body, .. } | Node::SetBlock { ref mut body, .. } => {
+            unescape_raw_nodes(body)
+        },
+        _ => (),
+    }
+}
+
+// The input of the parser for a template written with other delimiters than the default ones:
+// it parses the translated template but gives the lines and columns in the original one
+struct TranslatedInput<'a> {
+    translated: StringInput<'a>,
+    original: StringInput<'a>,
+    translation: &'a Translation,
+}
+
+impl<'a> Input<'a> for TranslatedInput<'a> {
+    fn len(&self) -> usize {
+        self.translated.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.translated.is_empty()
+    }
+
+    fn pos(&self) -> usize {
+        self.translated.pos()
+    }
+
+    fn set_pos(&mut self, pos: usize) {
+        self.translated.set_pos(pos)
+    }
+
+    fn slice(&self, start: usize, end: usize) -> &'a str {
+        self.translated.slice(start, end)
+    }
+
+    fn line_col(&self, pos: usize) -> (usize, usize) {
+        self.original.line_col(self.translation.original_offset(pos))
+    }
+
+    fn match_string(&mut self, string: &str) -> bool {
+        self.translated.match_string(string)
+    }
+
+    fn match_insensitive(&mut self, string: &str) -> bool {
+        self.translated.match_insensitive(string)
+    }
+
+    fn match_range(&mut self, left: char, right: char) -> bool {
+        self.translated.match_range(left, right)
+    }
+}
+
 // We need a little bit of post-processing to
 pub fn parse(input: &str) -> Result<Node> {
-    let mut parser = Rdp::new(StringInput::new(input));
+    parse_input(StringInput::new(input), input, input)
+}
+
+/// Parses a template written with `delimiters` instead of the default ones.
+/// The positions in errors and in the nodes are the ones in `input`
+pub fn parse_with_delimiters(input: &str, delimiters: Delimiters) -> Result<Node> {
+    if delimiters.is_default() {
+        return parse(input);
+    }
+    let translation = delimiters.translate(input);
+    let parser_input = TranslatedInput {
+        translated: StringInput::new(&translation.output),
+        original: StringInput::new(input),
+        translation: &translation,
+    };
+    let mut ast = parse_input(parser_input, &translation.output, input)?;
+    unescape_raw_nodes(&mut ast);
+    Ok(ast)
+}
+
+// Parses `translated`, which is `input` written with the default delimiters.
+// The lines and columns given by `parser_input` are the ones in `input`, which is the one
+// shown in errors
+fn parse_input<'a, T: Input<'a>>(parser_input: T, translated: &str, input: &str) -> Result<Node> {
+    let mut parser = Rdp::new(parser_input);
 
     if !parser.template() {
         let (_, pos) = parser.expected();
+        // Reaching the end of the input inside a comment gives an error at the very end:
+        // pointing at where the comment starts is more helpful
+        if pos == translated.len() {
+            if let Some(start) = unclosed_comment(translated) {
+                let (line_no, col_no) = parser.input().line_col(start);
+                bail!(
+                    "Unclosed comment at line {}, column {}\n{}",
+                    line_no, col_no, error_snippet(input, line_no, col_no)
+                );
+            }
+        }
         let (line_no, col_no) = parser.input().line_col(pos);
-        bail!("Invalid Tera syntax at line {}, column {}", line_no, col_no);
+        bail!(
+            "Invalid Tera syntax at line {}, column {}\n{}",
+            line_no, col_no, error_snippet(input, line_no, col_no)
+        );
     }
 
-    parser.main()
+    let ast = parser.main()?;
+    check_loop_control(&ast, false)?;
+    Ok(ast)
 }
 
 #[cfg(test)]
@@ -763,7 +1255,8 @@ mod tests {
 
     use pest::prelude::*;
 
-    use super::{Rdp, Node, parse, Operator};
+    use super::{Rdp, Node, parse, parse_with_delimiters, Operator, Span};
+    use delimiters::Delimiters;
 
     #[test]
     fn test_int() {
@@ -867,6 +1360,13 @@ mod tests {
         assert!(parser.end());
     }
 
+    #[test]
+    fn test_for_tag_key_value() {
+        let mut parser = Rdp::new(StringInput::new("{% for key, value in settings %}"));
+        assert!(parser.for_tag());
+        assert!(parser.end());
+    }
+
     #[test]
     fn test_for_tag_with_filter() {
         let mut parser = Rdp::new(StringInput::new("{% for client in clients | slice(start=1, end=9) %}"));
@@ -874,6 +1374,13 @@ mod tests {
         assert!(parser.end());
     }
 
+    #[test]
+    fn test_for_tag_with_global_fn() {
+        let mut parser = Rdp::new(StringInput::new("{% for i in range(start=1, end=5) %}"));
+        assert!(parser.for_tag());
+        assert!(parser.end());
+    }
+
     #[test]
     fn test_endfor_tag() {
         let mut parser = Rdp::new(StringInput::new("{% endfor %}"));
@@ -888,6 +1395,34 @@ mod tests {
         assert!(parser.end());
     }
 
+    #[test]
+    fn test_expression_negation_and_modulo() {
+        let mut parser = Rdp::new(StringInput::new("-price * 2 + index % 2 - -1"));
+        assert!(parser.logic_expression());
+        assert!(parser.end());
+    }
+
+    #[test]
+    fn test_expression_in() {
+        let tests = vec![
+            "user.role in allowed_roles",
+            "\"a\" not in letters",
+            "index in [1, 2] and name not   in names",
+        ];
+        for input in tests {
+            let mut parser = Rdp::new(StringInput::new(input));
+            assert!(parser.logic_expression());
+            assert!(parser.end());
+        }
+    }
+
+    #[test]
+    fn test_expression_concat() {
+        let mut parser = Rdp::new(StringInput::new("\"Hello, \" ~ name | upper ~ 1 + 2"));
+        assert!(parser.logic_expression());
+        assert!(parser.end());
+    }
+
     #[test]
     fn test_expression_identifier_logic_simple() {
         let mut parser = Rdp::new(StringInput::new("index + 1 > 1"));
@@ -938,6 +1473,13 @@ mod tests {
         assert!(parser.end());
     }
 
+    #[test]
+    fn test_if_tag_with_negated_test() {
+        let mut parser = Rdp::new(StringInput::new("{% if value is not divisibleby(3) %}"));
+        assert!(parser.if_tag());
+        assert!(parser.end());
+    }
+
     #[test]
     fn test_elif_tag_with_test() {
         let mut parser = Rdp::new(StringInput::new("{% elif value is defined %}"));
@@ -973,6 +1515,17 @@ mod tests {
         assert!(parser.end());
     }
 
+    #[test]
+    fn test_variable_tag_global_fn() {
+        let mut parser = Rdp::new(StringInput::new("{{ now() }}"));
+        assert!(parser.variable_tag());
+        assert!(parser.end());
+
+        let mut parser = Rdp::new(StringInput::new("{{ range(end=count+1) }}"));
+        assert!(parser.variable_tag());
+        assert!(parser.end());
+    }
+
     #[test]
     fn test_content() {
         let mut parser = Rdp::new(StringInput::new("{% if i18n %}世界{% else %}world{% endif %}"));
@@ -994,6 +1547,13 @@ mod tests {
         assert!(parser.end());
     }
 
+    #[test]
+    fn test_macro_tag_with_default_args() {
+        let mut parser = Rdp::new(StringInput::new("{% macro button(label, kind=\"primary\", size = 1 + 1) %}"));
+        assert!(parser.macro_tag());
+        assert!(parser.end());
+    }
+
     #[test]
     fn test_endmacro() {
         let mut parser = Rdp::new(StringInput::new("{% endmacro hello_world %}"));
@@ -1008,6 +1568,54 @@ mod tests {
         assert!(parser.end());
     }
 
+    #[test]
+    fn test_set_tag() {
+        let mut parser = Rdp::new(StringInput::new("{% set full_name = user.first | upper %}"));
+        assert!(parser.set_tag());
+        assert!(parser.end());
+    }
+
+    #[test]
+    fn test_set_tag_with_math() {
+        let mut parser = Rdp::new(StringInput::new("{% set total = price * 1.20 + 2 %}"));
+        assert!(parser.set_tag());
+        assert!(parser.end());
+    }
+
+    #[test]
+    fn test_set_block_tag() {
+        let mut parser = Rdp::new(StringInput::new("{% set greeting %}"));
+        assert!(parser.set_block_tag());
+        assert!(parser.end());
+
+        let mut parser = Rdp::new(StringInput::new("{% endset %}"));
+        assert!(parser.endset_tag());
+        assert!(parser.end());
+    }
+
+    #[test]
+    fn test_tags_with_whitespace_control() {
+        let mut parser = Rdp::new(StringInput::new("{%- for client in clients -%}"));
+        assert!(parser.for_tag());
+        assert!(parser.end());
+
+        let mut parser = Rdp::new(StringInput::new("{%- if a - 1 > 0 -%}"));
+        assert!(parser.if_tag());
+        assert!(parser.end());
+
+        let mut parser = Rdp::new(StringInput::new("{{- name | upper -}}"));
+        assert!(parser.variable_tag());
+        assert!(parser.end());
+
+        let mut parser = Rdp::new(StringInput::new("{{ a - 1 -}}"));
+        assert!(parser.variable_tag());
+        assert!(parser.end());
+
+        let mut parser = Rdp::new(StringInput::new("{#- comment -#}"));
+        assert!(parser.comment_tag());
+        assert!(parser.end());
+    }
+
     #[test]
     fn test_template() {
         let mut parser = Rdp::new(StringInput::new("
@@ -1027,7 +1635,55 @@ mod tests {
         assert!(parsed_ast.is_err());
         assert_eq!(
             parsed_ast.err().unwrap().description(),
-            "Invalid Tera syntax at line 1, column 1"
+            "Invalid Tera syntax at line 1, column 1\n1 | {% block hey \n  | ^"
+        );
+    }
+
+    #[test]
+    fn test_invalid_syntax_with_custom_delimiters() {
+        let delimiters = Delimiters { variable: ("[[", "]]"), block: ("[[%", "%]]"), comment: ("[#", "#]") };
+        let parsed_ast = parse_with_delimiters("{{ a }}\n[# b #][[% if a %]][[ b +]]", delimiters);
+        assert!(parsed_ast.is_err());
+        assert_eq!(
+            parsed_ast.err().unwrap().description(),
+            "Invalid Tera syntax at line 2, column 26\n2 | [# b #][[% if a %]][[ b +]]\n  |                          ^"
+        );
+
+        let parsed_ast = parse_with_delimiters("{{ a }}[% block a %][% endblock b %]", Delimiters { block: ("[%", "%]"), ..Delimiters::default() });
+        assert!(parsed_ast.is_err());
+        assert_eq!(
+            parsed_ast.err().unwrap().description(),
+            "Block `b` is closing at line 1, col 37 but we were expecting `a` to be closing"
+        );
+    }
+
+    #[test]
+    fn test_spans_with_custom_delimiters() {
+        let delimiters = Delimiters { variable: ("[[", "]]"), block: ("[[%", "%]]"), comment: ("[#", "#]") };
+        let parsed_ast = parse_with_delimiters("{{\n[[% if a %]][[ b ]][[% endif %]]", delimiters).unwrap();
+        match parsed_ast.get_children()[2] {
+            Node::If { ref condition_nodes, .. } => match condition_nodes[0] {
+                Node::Conditional { ref condition, ref body } => {
+                    assert_eq!(**condition, Node::Identifier { name: "a".to_string(), filters: None, span: Span::new(2, 8) });
+                    let mut expected = VecDeque::new();
+                    expected.push_back(Node::VariableBlock(Box::new(
+                        Node::Identifier { name: "b".to_string(), filters: None, span: Span::new(2, 16) }
+                    )));
+                    assert_eq!(**body, Node::List(expected));
+                },
+                ref x => panic!("unexpected {:?}", x),
+            },
+            ref x => panic!("unexpected {:?}", x),
+        }
+    }
+
+    #[test]
+    fn test_invalid_elif_after_else() {
+        let parsed_ast = parse("{% if a %}a{% else %}b{% elif c %}c{% endif %}");
+        assert!(parsed_ast.is_err());
+        assert_eq!(
+            parsed_ast.err().unwrap().description(),
+            "Invalid Tera syntax at line 1, column 23\n1 | {% if a %}a{% else %}b{% elif c %}c{% endif %}\n  |                       ^"
         );
     }
 
@@ -1037,7 +1693,28 @@ mod tests {
         assert!(parsed_ast.is_err());
         assert_eq!(
             parsed_ast.err().unwrap().description(),
-            "Invalid Tera syntax at line 1, column 27"
+            "Invalid Tera syntax at line 1, column 27\n1 | {% extends \"base.html\" %} {% extends \"base.html\" %}\n  |                           ^"
+        );
+    }
+
+    #[test]
+    fn test_invalid_syntax_multiline() {
+        let tpl = "<ul>\n{% for i in items %}\n\t<li>{{ i }</li>\n{% endfor %}\n</ul>";
+        let parsed_ast = parse(tpl);
+        assert!(parsed_ast.is_err());
+        assert_eq!(
+            parsed_ast.err().unwrap().to_string(),
+            "Invalid Tera syntax at line 3, column 11\n3 | \t<li>{{ i }</li>\n  | \t         ^"
+        );
+    }
+
+    #[test]
+    fn test_error_snippet_gutter() {
+        let input = format!("{}{{{{ a", "\n".repeat(11));
+        let parsed_ast = parse(&input);
+        assert_eq!(
+            parsed_ast.err().unwrap().to_string(),
+            "Invalid Tera syntax at line 12, column 5\n12 | {{ a\n   |     ^"
         );
     }
 
@@ -1059,7 +1736,7 @@ mod tests {
         ast.push_front(Node::Text(" ".to_string()));
         ast.push_front(Node::VariableBlock(
             Box::new(Node::Math {
-                lhs: Box::new(Node::Identifier{name: "count".to_string(), filters: None}),
+                lhs: Box::new(Node::Identifier{name: "count".to_string(), filters: None, span: Span::new(1, 11)}),
                 rhs: Box::new(Node::Math {
                     lhs: Box::new(Node::Int(1)),
                     rhs: Box::new(Node::Float(2.5)),
@@ -1075,12 +1752,176 @@ mod tests {
     }
 
     #[test]
-    fn test_ast_block() {
-        let parsed_ast = parse("{% block content %}Hello{% endblock content %}");
+    fn test_ast_negation_and_modulo() {
+        let parsed_ast = parse("{{ -a + b % 2 * 3 }}");
         let mut ast = VecDeque::new();
-        let mut inner_content = VecDeque::new();
-        inner_content.push_front(Node::Text("Hello".to_string()));
-        ast.push_front(Node::Block {
+        ast.push_front(Node::VariableBlock(
+            Box::new(Node::Math {
+                lhs: Box::new(Node::Negate(
+                    Box::new(Node::Identifier{name: "a".to_string(), filters: None, span: Span::new(1, 5)})
+                )),
+                rhs: Box::new(Node::Math {
+                    lhs: Box::new(Node::Math {
+                        lhs: Box::new(Node::Identifier{name: "b".to_string(), filters: None, span: Span::new(1, 9)}),
+                        rhs: Box::new(Node::Int(2)),
+                        operator: Operator::Mod
+                    }),
+                    rhs: Box::new(Node::Int(3)),
+                    operator: Operator::Mul
+                }),
+                operator: Operator::Add
+            })
+        ));
+
+        let root = Node::List(ast);
+        assert_eq!(parsed_ast.unwrap(), root);
+    }
+
+    #[test]
+    fn test_ast_modulo_before_closing_tag() {
+        let parsed_ast = parse("{% set x = a % 2 %}{{ x }}");
+        assert!(parsed_ast.is_ok());
+    }
+
+    #[test]
+    fn test_ast_in_and_not_in() {
+        let parsed_ast = parse("{% if a in b or c not in d ~ e %}{% endif %}");
+        let mut condition_nodes = VecDeque::new();
+        condition_nodes.push_front(Node::Conditional {
+            condition: Box::new(Node::Logic {
+                lhs: Box::new(Node::Logic {
+                    lhs: Box::new(Node::Identifier{name: "a".to_string(), filters: None, span: Span::new(1, 7)}),
+                    rhs: Box::new(Node::Identifier{name: "b".to_string(), filters: None, span: Span::new(1, 12)}),
+                    operator: Operator::In
+                }),
+                rhs: Box::new(Node::Logic {
+                    lhs: Box::new(Node::Identifier{name: "c".to_string(), filters: None, span: Span::new(1, 17)}),
+                    rhs: Box::new(Node::Concat {
+                        lhs: Box::new(Node::Identifier{name: "d".to_string(), filters: None, span: Span::new(1, 26)}),
+                        rhs: Box::new(Node::Identifier{name: "e".to_string(), filters: None, span: Span::new(1, 30)}),
+                    }),
+                    operator: Operator::NotIn
+                }),
+                operator: Operator::Or
+            }),
+            body: Box::new(Node::List(VecDeque::new())),
+        });
+        let mut ast = VecDeque::new();
+        ast.push_front(Node::If {
+            condition_nodes,
+            else_node: None,
+        });
+
+        let root = Node::List(ast);
+        assert_eq!(parsed_ast.unwrap(), root);
+    }
+
+    #[test]
+    fn test_ast_concat() {
+        let parsed_ast = parse("{{ \"Hello, \" ~ name ~ 1 + 2 }}");
+        let mut ast = VecDeque::new();
+        ast.push_front(Node::VariableBlock(
+            Box::new(Node::Concat {
+                lhs: Box::new(Node::Concat {
+                    lhs: Box::new(Node::Text("Hello, ".to_string())),
+                    rhs: Box::new(Node::Identifier{name: "name".to_string(), filters: None, span: Span::new(1, 16)}),
+                }),
+                rhs: Box::new(Node::Math {
+                    lhs: Box::new(Node::Int(1)),
+                    rhs: Box::new(Node::Int(2)),
+                    operator: Operator::Add
+                }),
+            })
+        ));
+
+        let root = Node::List(ast);
+        assert_eq!(parsed_ast.unwrap(), root);
+    }
+
+    #[test]
+    fn test_ast_gte_lte() {
+        let tests = vec![
+            ("{% if a >= 1 %}{% endif %}", Operator::Gte),
+            ("{% if a <= 1 %}{% endif %}", Operator::Lte),
+        ];
+        for (input, operator) in tests {
+            let parsed_ast = parse(input);
+            let mut condition_nodes = VecDeque::new();
+            condition_nodes.push_front(Node::Conditional {
+                condition: Box::new(Node::Logic {
+                    lhs: Box::new(Node::Identifier{name: "a".to_string(), filters: None, span: Span::new(1, 7)}),
+                    rhs: Box::new(Node::Int(1)),
+                    operator,
+                }),
+                body: Box::new(Node::List(VecDeque::new())),
+            });
+            let mut ast = VecDeque::new();
+            ast.push_front(Node::If {
+                condition_nodes,
+                else_node: None,
+            });
+            assert_eq!(parsed_ast.unwrap(), Node::List(ast));
+        }
+    }
+
+    #[test]
+    fn test_ast_concat_in_comparison() {
+        let parsed_ast = parse("{% if a ~ b == \"ab\" %}{% endif %}");
+        let mut condition_nodes = VecDeque::new();
+        condition_nodes.push_front(Node::Conditional {
+            condition: Box::new(Node::Logic {
+                lhs: Box::new(Node::Concat {
+                    lhs: Box::new(Node::Identifier{name: "a".to_string(), filters: None, span: Span::new(1, 7)}),
+                    rhs: Box::new(Node::Identifier{name: "b".to_string(), filters: None, span: Span::new(1, 11)}),
+                }),
+                rhs: Box::new(Node::Text("ab".to_string())),
+                operator: Operator::Eq
+            }),
+            body: Box::new(Node::List(VecDeque::new())),
+        });
+        let mut ast = VecDeque::new();
+        ast.push_front(Node::If {
+            condition_nodes,
+            else_node: None,
+        });
+
+        let root = Node::List(ast);
+        assert_eq!(parsed_ast.unwrap(), root);
+    }
+
+    #[test]
+    fn test_ast_whitespace_control() {
+        let parsed_ast = parse(" Hello  {{- name -}} \n {{ name -}}  !  {%- if a %}{% endif %}");
+        let mut ast = VecDeque::new();
+        ast.push_back(Node::Text(" Hello".to_string()));
+        ast.push_back(Node::VariableBlock(
+            Box::new(Node::Identifier{name: "name".to_string(), filters: None, span: Span::new(1, 13)})
+        ));
+        ast.push_back(Node::VariableBlock(
+            Box::new(Node::Identifier{name: "name".to_string(), filters: None, span: Span::new(2, 5)})
+        ));
+        ast.push_back(Node::Text("!".to_string()));
+        let mut condition_nodes = VecDeque::new();
+        condition_nodes.push_back(Node::Conditional {
+            condition: Box::new(Node::Identifier{name: "a".to_string(), filters: None, span: Span::new(2, 25)}),
+            body: Box::new(Node::List(VecDeque::new())),
+        });
+        ast.push_back(Node::If {
+            condition_nodes,
+            else_node: None,
+        });
+
+        let root = Node::List(ast);
+        assert_eq!(parsed_ast.unwrap(), root);
+    }
+
+    #[test]
+    fn test_ast_block() {
+        let parsed_ast = parse("{% block content %}Hello{% endblock content %}");
+        let mut ast = VecDeque::new();
+        let mut inner_content = VecDeque::new();
+        inner_content.push_front(Node::Text("Hello".to_string()));
+        ast.push_front(Node::Block {
             name: "content".to_string(),
             body: Box::new(Node::List(inner_content))
         });
@@ -1094,17 +1935,92 @@ mod tests {
         let mut ast = VecDeque::new();
         let mut inner_content = VecDeque::new();
         inner_content.push_front(Node::VariableBlock(
-            Box::new(Node::Identifier {name: "user.email".to_string(), filters: None})
+            Box::new(Node::Identifier {name: "user.email".to_string(), filters: None, span: Span::new(1, 26)})
         ));
         ast.push_front(Node::For {
+            key: None,
             variable: "user".to_string(),
-            array: "users".to_string(),
+            container: Box::new(Node::Identifier { name: "users".to_string(), filters: None, span: Span::new(1, 16) }),
             body: Box::new(Node::List(inner_content))
         });
         let root = Node::List(ast);
         assert_eq!(parsed_ast.unwrap(), root);
     }
 
+    #[test]
+    fn test_ast_for_key_value() {
+        let parsed_ast = parse("{% for name, value in settings %}{{name}}{% endfor %}");
+        let mut ast = VecDeque::new();
+        let mut inner_content = VecDeque::new();
+        inner_content.push_front(Node::VariableBlock(
+            Box::new(Node::Identifier {name: "name".to_string(), filters: None, span: Span::new(1, 36)})
+        ));
+        ast.push_front(Node::For {
+            key: Some("name".to_string()),
+            variable: "value".to_string(),
+            container: Box::new(Node::Identifier { name: "settings".to_string(), filters: None, span: Span::new(1, 23) }),
+            body: Box::new(Node::List(inner_content))
+        });
+        let root = Node::List(ast);
+        assert_eq!(parsed_ast.unwrap(), root);
+    }
+
+    #[test]
+    fn test_ast_break_continue() {
+        let parsed_ast = parse("{% for user in users %}{% if user.banned %}{% continue %}{% endif %}{% break %}{% endfor %}");
+        let mut condition_body = VecDeque::new();
+        condition_body.push_front(Node::Continue);
+        let mut condition_nodes = VecDeque::new();
+        condition_nodes.push_front(Node::Conditional {
+            condition: Box::new(Node::Identifier { name: "user.banned".to_string(), filters: None, span: Span::new(1, 30) }),
+            body: Box::new(Node::List(condition_body)),
+        });
+        let mut inner_content = VecDeque::new();
+        inner_content.push_back(Node::If { condition_nodes, else_node: None });
+        inner_content.push_back(Node::Break);
+        let mut ast = VecDeque::new();
+        ast.push_front(Node::For {
+            key: None,
+            variable: "user".to_string(),
+            container: Box::new(Node::Identifier { name: "users".to_string(), filters: None, span: Span::new(1, 16) }),
+            body: Box::new(Node::List(inner_content))
+        });
+        assert_eq!(parsed_ast.unwrap(), Node::List(ast));
+    }
+
+    #[test]
+    fn test_error_break_continue_outside_for_loop() {
+        let tests = [
+            ("{% break %}", "`{% break %}` can only be used in the body of a for loop"),
+            ("{% if a %}{% continue %}{% endif %}", "`{% continue %}` can only be used in the body of a for loop"),
+            ("{% block a %}{% break %}{% endblock a %}", "`{% break %}` can only be used in the body of a for loop"),
+            (
+                "{% for a in b %}{% macro m() %}{% continue %}{% endmacro m %}{% endfor %}",
+                "`{% continue %}` can only be used in the body of a for loop"
+            ),
+        ];
+
+        for &(input, expected) in &tests {
+            let parsed_ast = parse(input);
+            assert!(parsed_ast.is_err());
+            assert_eq!(parsed_ast.err().unwrap().description(), expected);
+        }
+    }
+
+    #[test]
+    fn test_ast_spaceless() {
+        let parsed_ast = parse("{% spaceless %}<p> {{ a }} </p>{% endspaceless %}");
+        let mut inner_content = VecDeque::new();
+        inner_content.push_back(Node::Text("<p> ".to_string()));
+        inner_content.push_back(Node::VariableBlock(
+            Box::new(Node::Identifier { name: "a".to_string(), filters: None, span: Span::new(1, 23) })
+        ));
+        inner_content.push_back(Node::Text(" </p>".to_string()));
+        let mut ast = VecDeque::new();
+        ast.push_front(Node::Spaceless(Box::new(Node::List(inner_content))));
+        assert_eq!(parsed_ast.unwrap(), Node::List(ast));
+    }
+
     #[test]
     fn test_ast_extends() {
         let parsed_ast = parse("{% extends \"base.html\" %}");
@@ -1114,6 +2030,83 @@ mod tests {
         assert_eq!(parsed_ast.unwrap(), root);
     }
 
+    #[test]
+    fn test_ast_set() {
+        let parsed_ast = parse("{% set admin = user.is_admin %}");
+        let mut ast = VecDeque::new();
+        ast.push_front(Node::Set {
+            name: "admin".to_string(),
+            value: Box::new(Node::Identifier {name: "user.is_admin".to_string(), filters: None, span: Span::new(1, 16)}),
+        });
+        let root = Node::List(ast);
+        assert_eq!(parsed_ast.unwrap(), root);
+    }
+
+    #[test]
+    fn test_ast_set_block() {
+        let parsed_ast = parse("{% set greeting %}Hello {{ name }}{% endset %}");
+        let mut inner_content = VecDeque::new();
+        inner_content.push_back(Node::Text("Hello ".to_string()));
+        inner_content.push_back(Node::VariableBlock(
+            Box::new(Node::Identifier { name: "name".to_string(), filters: None, span: Span::new(1, 28) })
+        ));
+        let mut ast = VecDeque::new();
+        ast.push_front(Node::SetBlock {
+            name: "greeting".to_string(),
+            body: Box::new(Node::List(inner_content)),
+        });
+        assert_eq!(parsed_ast.unwrap(), Node::List(ast));
+    }
+
+    #[test]
+    fn test_ast_set_block_in_macro_and_block() {
+        let tests = vec![
+            "{% macro hello() %}{% set a %}{% if b %}c{% endif %}{% endset %}{{ a }}{% endmacro hello %}",
+            "{% block content %}{% set a %}{% for b in c %}{{ b }}{% endfor %}{% endset %}{% endblock content %}",
+        ];
+        for input in tests {
+            assert!(parse(input).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_error_set_block_not_closed() {
+        assert!(parse("{% set a %}Hello").is_err());
+        assert!(parse("{% set a %}Hello{% endset a %}").is_err());
+    }
+
+    #[test]
+    fn test_error_break_in_set_block() {
+        let parsed_ast = parse("{% for a in b %}{% set c %}{% break %}{% endset %}{% endfor %}");
+        assert_eq!(
+            parsed_ast.unwrap_err().description(),
+            "`{% break %}` can only be used in the body of a for loop"
+        );
+    }
+
+    #[test]
+    fn test_ast_set_in_for() {
+        let parsed_ast = parse("{% for user in users %}{% set total = 2 * 3 %}{% endfor %}");
+        let mut ast = VecDeque::new();
+        let mut inner_content = VecDeque::new();
+        inner_content.push_front(Node::Set {
+            name: "total".to_string(),
+            value: Box::new(Node::Math {
+                lhs: Box::new(Node::Int(2)),
+                rhs: Box::new(Node::Int(3)),
+                operator: Operator::Mul,
+            }),
+        });
+        ast.push_front(Node::For {
+            key: None,
+            variable: "user".to_string(),
+            container: Box::new(Node::Identifier { name: "users".to_string(), filters: None, span: Span::new(1, 16) }),
+            body: Box::new(Node::List(inner_content))
+        });
+        let root = Node::List(ast);
+        assert_eq!(parsed_ast.unwrap(), root);
+    }
+
     #[test]
     fn test_ast_if() {
         let parsed_ast = parse("{% if superadmin %}Hey{% endif %}");
@@ -1123,7 +2116,7 @@ mod tests {
 
         let mut condition_nodes = VecDeque::new();
         condition_nodes.push_front(Node::Conditional {
-            condition: Box::new(Node::Identifier {name: "superadmin".to_string(), filters: None}),
+            condition: Box::new(Node::Identifier {name: "superadmin".to_string(), filters: None, span: Span::new(1, 7)}),
             body: Box::new(Node::List(body.clone()))
         });
 
@@ -1135,6 +2128,40 @@ mod tests {
         assert_eq!(parsed_ast.unwrap(), root);
     }
 
+    #[test]
+    fn test_ast_if_with_negated_test() {
+        let parsed_ast = parse("{% if number is not defined %}{% elif number is nothing %}{% endif %}");
+        let mut condition_nodes = VecDeque::new();
+        condition_nodes.push_back(Node::Conditional {
+            condition: Box::new(Node::Not(Box::new(Node::Test {
+                expression: Box::new(Node::Identifier {
+                    name: "number".to_string(), filters: None, span: Span::new(1, 7),
+                }),
+                name: "defined".to_string(),
+                params: VecDeque::new()
+            }))),
+            body: Box::new(Node::List(VecDeque::new())),
+        });
+        condition_nodes.push_back(Node::Conditional {
+            condition: Box::new(Node::Test {
+                expression: Box::new(Node::Identifier {
+                    name: "number".to_string(), filters: None, span: Span::new(1, 39),
+                }),
+                name: "nothing".to_string(),
+                params: VecDeque::new()
+            }),
+            body: Box::new(Node::List(VecDeque::new())),
+        });
+        let mut ast = VecDeque::new();
+        ast.push_front(Node::If {
+            condition_nodes,
+            else_node: None,
+        });
+
+        let root = Node::List(ast);
+        assert_eq!(parsed_ast.unwrap(), root);
+    }
+
     #[test]
     fn test_ast_if_with_test() {
         let parsed_ast = parse("{% if number is defined %}Hey{% endif %}");
@@ -1147,7 +2174,7 @@ mod tests {
             condition: Box::new(
                 Node::Test {
                     expression: Box::new(Node::Identifier {
-                        name: "number".to_string(), filters: None,
+                        name: "number".to_string(), filters: None, span: Span::new(1, 7),
                     }),
                     name: "defined".to_string(),
                     params: VecDeque::new()
@@ -1179,7 +2206,7 @@ mod tests {
             condition: Box::new(
                 Node::Test {
                     expression: Box::new(Node::Identifier {
-                        name: "pi".to_string(), filters: None,
+                        name: "pi".to_string(), filters: None, span: Span::new(1, 7),
                     }),
                     name: "equalto".to_string(),
                     params: params,
@@ -1206,19 +2233,19 @@ mod tests {
 
         let mut condition_nodes = VecDeque::new();
         condition_nodes.push_front(Node::Conditional {
-            condition: Box::new(Node::Identifier {name: "hi".to_string(), filters: None}),
+            condition: Box::new(Node::Identifier {name: "hi".to_string(), filters: None, span: Span::new(1, 7)}),
             body: Box::new(Node::List(body.clone()))
         });
 
         let mut params = VecDeque::new();
-        params.push_back(Node::Identifier { name: "a".to_string(), filters: None });
+        params.push_back(Node::Identifier { name: "a".to_string(), filters: None, span: Span::new(1, 38) });
         params.push_back(Node::Int(2));
         params.push_back(Node::Bool(true));
         condition_nodes.push_back(Node::Conditional {
             condition: Box::new(
                 Node::Test {
                     expression: Box::new(Node::Identifier {
-                        name: "admin".to_string(), filters: None,
+                        name: "admin".to_string(), filters: None, span: Span::new(1, 23),
                     }),
                     name: "oneof".to_string(),
                     params: params,
@@ -1245,7 +2272,7 @@ mod tests {
 
         let mut condition_nodes = VecDeque::new();
         condition_nodes.push_front(Node::Conditional {
-            condition: Box::new(Node::Identifier {name: "superadmin".to_string(), filters: None}),
+            condition: Box::new(Node::Identifier {name: "superadmin".to_string(), filters: None, span: Span::new(1, 7)}),
             body: Box::new(Node::List(body.clone()))
         });
 
@@ -1266,11 +2293,11 @@ mod tests {
 
         let mut condition_nodes = VecDeque::new();
         condition_nodes.push_front(Node::Conditional {
-            condition: Box::new(Node::Identifier {name: "superadmin".to_string(), filters: None}),
+            condition: Box::new(Node::Identifier {name: "superadmin".to_string(), filters: None, span: Span::new(1, 7)}),
             body: Box::new(Node::List(body.clone()))
         });
         condition_nodes.push_back(Node::Conditional {
-            condition: Box::new(Node::Identifier {name: "admin".to_string(), filters: None}),
+            condition: Box::new(Node::Identifier {name: "admin".to_string(), filters: None, span: Span::new(1, 31)}),
             body: Box::new(Node::List(body.clone()))
         });
 
@@ -1291,11 +2318,11 @@ mod tests {
 
         let mut condition_nodes = VecDeque::new();
         condition_nodes.push_back(Node::Conditional {
-            condition: Box::new(Node::Identifier {name: "admin".to_string(), filters: None}),
+            condition: Box::new(Node::Identifier {name: "admin".to_string(), filters: None, span: Span::new(1, 31)}),
             body: Box::new(Node::List(body.clone()))
         });
         condition_nodes.push_front(Node::Conditional {
-            condition: Box::new(Node::Identifier {name: "superadmin".to_string(), filters: None}),
+            condition: Box::new(Node::Identifier {name: "superadmin".to_string(), filters: None, span: Span::new(1, 7)}),
             body: Box::new(Node::List(body.clone()))
         });
 
@@ -1317,8 +2344,8 @@ mod tests {
         let mut condition_nodes = VecDeque::new();
         condition_nodes.push_back(Node::Conditional {
             condition: Box::new(Node::Logic {
-                lhs: Box::new(Node::Identifier {name: "admin".to_string(), filters: None}),
-                rhs: Box::new(Node::Not(Box::new(Node::Identifier {name: "superadmin".to_string(), filters: None}))),
+                lhs: Box::new(Node::Identifier {name: "admin".to_string(), filters: None, span: Span::new(1, 7)}),
+                rhs: Box::new(Node::Not(Box::new(Node::Identifier {name: "superadmin".to_string(), filters: None, span: Span::new(1, 21)}))),
                 operator: Operator::And,
             }),
             body: Box::new(Node::List(body.clone()))
@@ -1342,9 +2369,9 @@ mod tests {
         let mut condition_nodes = VecDeque::new();
         condition_nodes.push_back(Node::Conditional {
             condition: Box::new(Node::Logic {
-                lhs: Box::new(Node::Not(Box::new(Node::Identifier {name: "active".to_string(), filters: None}))),
+                lhs: Box::new(Node::Not(Box::new(Node::Identifier {name: "active".to_string(), filters: None, span: Span::new(1, 11)}))),
                 rhs: Box::new(Node::Logic {
-                    lhs: Box::new(Node::Identifier {name: "number_users".to_string(), filters: None}),
+                    lhs: Box::new(Node::Identifier {name: "number_users".to_string(), filters: None, span: Span::new(1, 21)}),
                     rhs: Box::new(Node::Int(10)),
                     operator: Operator::Gt,
                 }),
@@ -1361,6 +2388,178 @@ mod tests {
         assert_eq!(parsed_ast.unwrap(), root);
     }
 
+    #[test]
+    fn test_ast_and_before_or() {
+        let parsed_ast = parse("{% if a or b and c %}Yes{% endif %}");
+        let mut ast = VecDeque::new();
+        let mut body = VecDeque::new();
+        body.push_front(Node::Text("Yes".to_string()));
+
+        let mut condition_nodes = VecDeque::new();
+        condition_nodes.push_back(Node::Conditional {
+            condition: Box::new(Node::Logic {
+                lhs: Box::new(Node::Identifier {name: "a".to_string(), filters: None, span: Span::new(1, 7)}),
+                rhs: Box::new(Node::Logic {
+                    lhs: Box::new(Node::Identifier {name: "b".to_string(), filters: None, span: Span::new(1, 12)}),
+                    rhs: Box::new(Node::Identifier {name: "c".to_string(), filters: None, span: Span::new(1, 18)}),
+                    operator: Operator::And,
+                }),
+                operator: Operator::Or,
+            }),
+            body: Box::new(Node::List(body))
+        });
+
+        ast.push_front(Node::If {
+            condition_nodes,
+            else_node: None,
+        });
+        let root = Node::List(ast);
+        assert_eq!(parsed_ast.unwrap(), root);
+    }
+
+    #[test]
+    fn test_ast_grouped_condition() {
+        let parsed_ast = parse("{% if (a > 1 or not b) and c %}Yes{% endif %}");
+        let mut ast = VecDeque::new();
+        let mut body = VecDeque::new();
+        body.push_front(Node::Text("Yes".to_string()));
+
+        let mut condition_nodes = VecDeque::new();
+        condition_nodes.push_back(Node::Conditional {
+            condition: Box::new(Node::Logic {
+                lhs: Box::new(Node::Logic {
+                    lhs: Box::new(Node::Logic {
+                        lhs: Box::new(Node::Identifier {name: "a".to_string(), filters: None, span: Span::new(1, 8)}),
+                        rhs: Box::new(Node::Int(1)),
+                        operator: Operator::Gt,
+                    }),
+                    rhs: Box::new(Node::Not(Box::new(Node::Identifier {name: "b".to_string(), filters: None, span: Span::new(1, 21)}))),
+                    operator: Operator::Or,
+                }),
+                rhs: Box::new(Node::Identifier {name: "c".to_string(), filters: None, span: Span::new(1, 28)}),
+                operator: Operator::And,
+            }),
+            body: Box::new(Node::List(body))
+        });
+
+        ast.push_front(Node::If {
+            condition_nodes,
+            else_node: None,
+        });
+        let root = Node::List(ast);
+        assert_eq!(parsed_ast.unwrap(), root);
+    }
+
+    #[test]
+    fn test_ast_grouped_math() {
+        let parsed_ast = parse("{{ ( 1 + 2 ) * -(a - 4) }}");
+        let mut ast = VecDeque::new();
+        ast.push_front(Node::VariableBlock(
+            Box::new(Node::Math {
+                lhs: Box::new(Node::Math {
+                    lhs: Box::new(Node::Int(1)),
+                    rhs: Box::new(Node::Int(2)),
+                    operator: Operator::Add
+                }),
+                rhs: Box::new(Node::Negate(Box::new(Node::Math {
+                    lhs: Box::new(Node::Identifier{name: "a".to_string(), filters: None, span: Span::new(1, 18)}),
+                    rhs: Box::new(Node::Int(4)),
+                    operator: Operator::Sub
+                }))),
+                operator: Operator::Mul
+            })
+        ));
+
+        let root = Node::List(ast);
+        assert_eq!(parsed_ast.unwrap(), root);
+    }
+
+    #[test]
+    fn test_ast_inline_if() {
+        let parsed_ast = parse("{{ \"active\" if a and b or c else \"\" }}");
+        let mut ast = VecDeque::new();
+        ast.push_front(Node::VariableBlock(
+            Box::new(Node::InlineIf {
+                condition: Box::new(Node::Logic {
+                    lhs: Box::new(Node::Logic {
+                        lhs: Box::new(Node::Identifier{name: "a".to_string(), filters: None, span: Span::new(1, 16)}),
+                        rhs: Box::new(Node::Identifier{name: "b".to_string(), filters: None, span: Span::new(1, 22)}),
+                        operator: Operator::And
+                    }),
+                    rhs: Box::new(Node::Identifier{name: "c".to_string(), filters: None, span: Span::new(1, 27)}),
+                    operator: Operator::Or
+                }),
+                then: Box::new(Node::Text("active".to_string())),
+                otherwise: Some(Box::new(Node::Text("".to_string()))),
+            })
+        ));
+
+        let root = Node::List(ast);
+        assert_eq!(parsed_ast.unwrap(), root);
+    }
+
+    #[test]
+    fn test_ast_inline_if_without_else() {
+        let parsed_ast = parse("{% set x = a ~ b if iffy %}");
+        let mut ast = VecDeque::new();
+        ast.push_front(Node::Set {
+            name: "x".to_string(),
+            value: Box::new(Node::InlineIf {
+                condition: Box::new(Node::Identifier{name: "iffy".to_string(), filters: None, span: Span::new(1, 21)}),
+                then: Box::new(Node::Concat {
+                    lhs: Box::new(Node::Identifier{name: "a".to_string(), filters: None, span: Span::new(1, 12)}),
+                    rhs: Box::new(Node::Identifier{name: "b".to_string(), filters: None, span: Span::new(1, 16)}),
+                }),
+                otherwise: None,
+            }),
+        });
+
+        let root = Node::List(ast);
+        assert_eq!(parsed_ast.unwrap(), root);
+    }
+
+    #[test]
+    fn test_ast_chained_inline_if_with_test() {
+        let parsed_ast = parse("{{ (1 if a is odd else 2 if b else 3) + 1 }}");
+        let mut ast = VecDeque::new();
+        ast.push_front(Node::VariableBlock(
+            Box::new(Node::Math {
+                lhs: Box::new(Node::InlineIf {
+                    condition: Box::new(Node::Test {
+                        expression: Box::new(Node::Identifier{name: "a".to_string(), filters: None, span: Span::new(1, 10)}),
+                        name: "odd".to_string(),
+                        params: VecDeque::new(),
+                    }),
+                    then: Box::new(Node::Int(1)),
+                    otherwise: Some(Box::new(Node::InlineIf {
+                        condition: Box::new(Node::Identifier{name: "b".to_string(), filters: None, span: Span::new(1, 29)}),
+                        then: Box::new(Node::Int(2)),
+                        otherwise: Some(Box::new(Node::Int(3))),
+                    })),
+                }),
+                rhs: Box::new(Node::Int(1)),
+                operator: Operator::Add
+            })
+        ));
+
+        let root = Node::List(ast);
+        assert_eq!(parsed_ast.unwrap(), root);
+    }
+
+    #[test]
+    fn test_ast_invalid_inline_if() {
+        assert!(parse("{{ a ifb }}").is_err());
+        assert!(parse("{{ a if }}").is_err());
+        assert!(parse("{{ a if b else }}").is_err());
+        // only allowed in parentheses in args
+        assert!(parse("{{ a | default(value=1 if b) }}").is_err());
+    }
+
+    #[test]
+    fn test_ast_unclosed_group() {
+        assert!(parse("{{ (1 + 2 }}").is_err());
+    }
+
     #[test]
     fn test_nullary_test() {
         let mut parser = Rdp::new(StringInput::new("is defined"));
@@ -1406,6 +2605,51 @@ mod tests {
         assert_eq!(parsed_ast.unwrap(), root);
     }
 
+    #[test]
+    fn test_ast_raw_with_delimiters() {
+        let parsed_ast = parse("{% raw %}{{ a }} {% if b %}{# c #}{% raw %}{% endraw %}");
+        let mut ast = VecDeque::new();
+        ast.push_front(Node::Raw("{{ a }} {% if b %}{# c #}{% raw %}".to_string()));
+        let root = Node::List(ast);
+        assert_eq!(parsed_ast.unwrap(), root);
+    }
+
+    #[test]
+    fn test_ast_raw_with_custom_delimiters() {
+        let delimiters = Delimiters { block: ("<%", "%>"), ..Delimiters::default() };
+        let parsed_ast = parse_with_delimiters("<% raw %>{% raw %}a{%- endraw %} {{ b }}<% endraw %>", delimiters);
+        let mut ast = VecDeque::new();
+        ast.push_front(Node::Raw("{% raw %}a{%- endraw %} {{ b }}".to_string()));
+        let root = Node::List(ast);
+        assert_eq!(parsed_ast.unwrap(), root);
+    }
+
+    #[test]
+    fn test_ast_comment() {
+        let parsed_ast = parse("Hello {# a #}world{# b #}");
+        let mut ast = VecDeque::new();
+        ast.push_back(Node::Text("Hello ".to_string()));
+        ast.push_back(Node::Text("world".to_string()));
+        let root = Node::List(ast);
+        assert_eq!(parsed_ast.unwrap(), root);
+    }
+
+    #[test]
+    fn test_ast_multiline_comment() {
+        let parsed_ast = parse("{# TODO:\n  {% if a %}\n#}");
+        assert_eq!(parsed_ast.unwrap(), Node::List(VecDeque::new()));
+    }
+
+    #[test]
+    fn test_ast_unclosed_comment() {
+        let parsed_ast = parse("Hello\n{# TODO: fix this");
+        assert!(parsed_ast.is_err());
+        assert_eq!(
+            parsed_ast.err().unwrap().description(),
+            "Unclosed comment at line 2, column 1\n2 | {# TODO: fix this\n  | ^"
+        );
+    }
+
     #[test]
     fn test_ast_filter() {
         let parsed_ast = parse("{{ greeting | i18n(lang=user.lang, units=user.units) | truncate(limit=50, cut_word=true) }}");
@@ -1415,8 +2659,8 @@ mod tests {
         args_truncate.insert("limit".to_string(), Node::Int(50));
         args_truncate.insert("cut_word".to_string(), Node::Bool(true));
         let mut args_i18n = HashMap::new();
-        args_i18n.insert("lang".to_string(), Node::Identifier {name: "user.lang".to_string(), filters: None});
-        args_i18n.insert("units".to_string(), Node::Identifier {name: "user.units".to_string(), filters: None});
+        args_i18n.insert("lang".to_string(), Node::Identifier {name: "user.lang".to_string(), filters: None, span: Span::new(1, 25)});
+        args_i18n.insert("units".to_string(), Node::Identifier {name: "user.units".to_string(), filters: None, span: Span::new(1, 42)});
 
         filters.push_front(Node::Filter {
             name: "truncate".to_string(),
@@ -1432,12 +2676,45 @@ mod tests {
             Box::new(Node::Identifier {
                 name: "greeting".to_string(),
                 filters: Some(filters),
+                span: Span::new(1, 4),
             })
         ));
         let root = Node::List(ast);
         assert_eq!(parsed_ast.unwrap(), root);
     }
 
+    #[test]
+    fn test_ast_macro_definition_with_defaults() {
+        let parsed_ast = parse("{% macro button(label, kind=\"primary\", width=size * 2) %}{{ label }}{% endmacro button %}");
+        let mut params = VecDeque::new();
+        params.push_back("label".to_string());
+        params.push_back("kind".to_string());
+        params.push_back("width".to_string());
+        let mut defaults = HashMap::new();
+        defaults.insert("kind".to_string(), Node::Text("primary".to_string()));
+        defaults.insert("width".to_string(), Node::Math {
+            lhs: Box::new(Node::Identifier {name: "size".to_string(), filters: None, span: Span::new(1, 46)}),
+            rhs: Box::new(Node::Int(2)),
+            operator: Operator::Mul,
+        });
+
+        let mut body = VecDeque::new();
+        body.push_front(Node::VariableBlock(
+            Box::new(Node::Identifier {name: "label".to_string(), filters: None, span: Span::new(1, 61)})
+        ));
+
+        let mut ast = VecDeque::new();
+        ast.push_front(Node::Macro {
+            name: "button".to_string(),
+            params,
+            defaults,
+            body: Box::new(Node::List(body)),
+        });
+
+        let root = Node::List(ast);
+        assert_eq!(parsed_ast.unwrap(), root);
+    }
+
     #[test]
     fn test_ast_macro_definition_no_arg() {
         let parsed_ast = parse("{% macro helloworld() %}Hello{% endmacro helloworld %}");
@@ -1450,6 +2727,7 @@ mod tests {
         ast.push_front(Node::Macro {
             name: "helloworld".to_string(),
             params: params,
+            defaults: HashMap::new(),
             body: Box::new(Node::List(body.clone())),
         });
 
@@ -1470,6 +2748,7 @@ mod tests {
         ast.push_front(Node::Macro {
             name: "helloworld".to_string(),
             params: params,
+            defaults: HashMap::new(),
             body: Box::new(Node::List(body.clone())),
         });
 
@@ -1491,6 +2770,7 @@ mod tests {
         ast.push_front(Node::Macro {
             name: "helloworld".to_string(),
             params: params,
+            defaults: HashMap::new(),
             body: Box::new(Node::List(body.clone())),
         });
 
@@ -1511,6 +2791,116 @@ mod tests {
         assert_eq!(parsed_ast.unwrap(), root);
     }
 
+    #[test]
+    fn test_array_literal() {
+        let mut parser = Rdp::new(StringInput::new("[]"));
+        assert!(parser.array_literal());
+        assert!(parser.end());
+
+        let mut parser = Rdp::new(StringInput::new("[1, \"two\", [three, 4.5], ]"));
+        assert!(parser.array_literal());
+        assert!(parser.end());
+    }
+
+    #[test]
+    fn test_object_literal() {
+        let mut parser = Rdp::new(StringInput::new("{}"));
+        assert!(parser.object_literal());
+        assert!(parser.end());
+
+        let mut parser = Rdp::new(StringInput::new("{\"a\": 1, \"b\": {\"c\": [d]}}"));
+        assert!(parser.object_literal());
+        assert!(parser.end());
+    }
+
+    #[test]
+    fn test_ast_empty_literals() {
+        let parsed_ast = parse("{{ [] }}{{ {} }}");
+        let mut ast = VecDeque::new();
+        ast.push_back(Node::VariableBlock(Box::new(Node::Array(VecDeque::new()))));
+        ast.push_back(Node::VariableBlock(Box::new(Node::Object(VecDeque::new()))));
+
+        let root = Node::List(ast);
+        assert_eq!(parsed_ast.unwrap(), root);
+    }
+
+    #[test]
+    fn test_ast_nested_literals() {
+        let parsed_ast = parse("{{ macros::menu(items=[\"home\", [1, 2]], opts={\"a\": {\"b\": name}}, last=true) }}");
+        let mut inner_array = VecDeque::new();
+        inner_array.push_back(Node::Int(1));
+        inner_array.push_back(Node::Int(2));
+        let mut items = VecDeque::new();
+        items.push_back(Node::Text("home".to_string()));
+        items.push_back(Node::Array(inner_array));
+        let mut inner_object = VecDeque::new();
+        inner_object.push_back((
+            "b".to_string(),
+            Node::Identifier {name: "name".to_string(), filters: None, span: Span::new(1, 58)}
+        ));
+        let mut opts = VecDeque::new();
+        opts.push_back(("a".to_string(), Node::Object(inner_object)));
+        let mut params = HashMap::new();
+        params.insert("items".to_string(), Node::Array(items));
+        params.insert("opts".to_string(), Node::Object(opts));
+        params.insert("last".to_string(), Node::Bool(true));
+
+        let mut ast = VecDeque::new();
+        ast.push_front(Node::MacroCall {
+            namespace: "macros".to_string(),
+            name: "menu".to_string(),
+            args: VecDeque::new(),
+            params,
+        });
+        let root = Node::List(ast);
+        assert_eq!(parsed_ast.unwrap(), root);
+    }
+
+    #[test]
+    fn test_ast_global_fn_call() {
+        let parsed_ast = parse("{{ now() }}{{ range(end=5) }}");
+        let mut ast = VecDeque::new();
+        ast.push_back(Node::VariableBlock(Box::new(Node::FunctionCall {
+            name: "now".to_string(),
+            params: HashMap::new(),
+            span: Span::new(1, 4),
+        })));
+        let mut params = HashMap::new();
+        params.insert("end".to_string(), Node::Int(5));
+        ast.push_back(Node::VariableBlock(Box::new(Node::FunctionCall {
+            name: "range".to_string(),
+            params,
+            span: Span::new(1, 15),
+        })));
+
+        let root = Node::List(ast);
+        assert_eq!(parsed_ast.unwrap(), root);
+    }
+
+    #[test]
+    fn test_ast_for_global_fn_call() {
+        let parsed_ast = parse("{% for i in range(end=3) %}{{ i }}{% endfor %}");
+        let mut ast = VecDeque::new();
+        let mut inner_content = VecDeque::new();
+        inner_content.push_front(Node::VariableBlock(
+            Box::new(Node::Identifier {name: "i".to_string(), filters: None, span: Span::new(1, 31)})
+        ));
+        let mut params = HashMap::new();
+        params.insert("end".to_string(), Node::Int(3));
+        ast.push_front(Node::For {
+            key: None,
+            variable: "i".to_string(),
+            container: Box::new(Node::FunctionCall {
+                name: "range".to_string(),
+                params,
+                span: Span::new(1, 13),
+            }),
+            body: Box::new(Node::List(inner_content))
+        });
+        let root = Node::List(ast);
+        assert_eq!(parsed_ast.unwrap(), root);
+    }
+
     #[test]
     fn test_ast_macro_call_no_args() {
         let parsed_ast = parse("{{ macros::macro1() }}");
@@ -1519,6 +2909,7 @@ mod tests {
         ast.push_front(Node::MacroCall {
             namespace: "macros".to_string(),
             name: "macro1".to_string(),
+            args: VecDeque::new(),
             params: params
         });
 
@@ -1531,10 +2922,11 @@ mod tests {
         let parsed_ast = parse("{{ macros::macro1(foo=bar) }}");
         let mut ast = VecDeque::new();
         let mut params = HashMap::new();
-        params.insert("foo".to_string(), Node::Identifier {name: "bar".to_string(), filters: None});
+        params.insert("foo".to_string(), Node::Identifier {name: "bar".to_string(), filters: None, span: Span::new(1, 23)});
         ast.push_front(Node::MacroCall {
             namespace: "macros".to_string(),
             name: "macro1".to_string(),
+            args: VecDeque::new(),
             params: params
         });
 
@@ -1547,7 +2939,7 @@ mod tests {
         let parsed_ast = parse("{{ macros::macro1(foo=bar, hey=1+2) }}");
         let mut ast = VecDeque::new();
         let mut params = HashMap::new();
-        params.insert("foo".to_string(), Node::Identifier {name: "bar".to_string(), filters: None});
+        params.insert("foo".to_string(), Node::Identifier {name: "bar".to_string(), filters: None, span: Span::new(1, 23)});
         params.insert("hey".to_string(), Node::Math {
             lhs: Box::new(Node::Int(1)),
             rhs: Box::new(Node::Int(2)),
@@ -1556,6 +2948,7 @@ mod tests {
         ast.push_front(Node::MacroCall {
             namespace: "macros".to_string(),
             name: "macro1".to_string(),
+            args: VecDeque::new(),
             params: params
         });
 
@@ -1563,6 +2956,59 @@ mod tests {
         assert_eq!(parsed_ast.unwrap(), root);
     }
 
+    #[test]
+    fn test_ast_macro_call_positional_args() {
+        let parsed_ast = parse("{{ macros::macro1(1, \"hey\", a == b) }}");
+        let mut args = VecDeque::new();
+        args.push_back(Node::Int(1));
+        args.push_back(Node::Text("hey".to_string()));
+        args.push_back(Node::Logic {
+            lhs: Box::new(Node::Identifier {name: "a".to_string(), filters: None, span: Span::new(1, 29)}),
+            rhs: Box::new(Node::Identifier {name: "b".to_string(), filters: None, span: Span::new(1, 34)}),
+            operator: Operator::Eq,
+        });
+        let mut ast = VecDeque::new();
+        ast.push_front(Node::MacroCall {
+            namespace: "macros".to_string(),
+            name: "macro1".to_string(),
+            args,
+            params: HashMap::new(),
+        });
+
+        let root = Node::List(ast);
+        assert_eq!(parsed_ast.unwrap(), root);
+    }
+
+    #[test]
+    fn test_ast_macro_call_positional_and_keyword_args() {
+        let parsed_ast = parse("{{ macros::macro1(bar, 1 + 2, foo=\"hey\", baz=2) }}");
+        let mut args = VecDeque::new();
+        args.push_back(Node::Identifier {name: "bar".to_string(), filters: None, span: Span::new(1, 19)});
+        args.push_back(Node::Math {
+            lhs: Box::new(Node::Int(1)),
+            rhs: Box::new(Node::Int(2)),
+            operator: Operator::Add,
+        });
+        let mut params = HashMap::new();
+        params.insert("foo".to_string(), Node::Text("hey".to_string()));
+        params.insert("baz".to_string(), Node::Int(2));
+        let mut ast = VecDeque::new();
+        ast.push_front(Node::MacroCall {
+            namespace: "macros".to_string(),
+            name: "macro1".to_string(),
+            args,
+            params,
+        });
+
+        let root = Node::List(ast);
+        assert_eq!(parsed_ast.unwrap(), root);
+    }
+
+    #[test]
+    fn test_ast_macro_call_positional_after_keyword() {
+        assert!(parse("{{ macros::macro1(foo=1, 2) }}").is_err());
+    }
+
     #[test]
     fn test_ast_error_mismatch_endblock_name() {
         let parsed_ast = parse("{% block hey %}{% endblock ho %}");
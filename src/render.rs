@@ -1,20 +1,34 @@
-use std::collections::{VecDeque, HashMap};
+use std::collections::{VecDeque, HashMap, HashSet};
+use std::cmp::Ordering;
+use std::io::Write;
+use std::mem;
 
 use serde_json::to_string_pretty;
-use serde_json::value::{Value, to_value, Number};
+use serde_json::value::{Value, Map, to_value, Number};
 
 use context::{ValueRender, ValueNumber, ValueTruthy, get_json_pointer};
 use template::Template;
 use errors::{Result, ResultExt};
-use parser::{Node, Operator};
+use parser::{Node, Operator, Span};
 use parser::Node::*;
 use tera::Tera;
-use utils::escape_html;
+use utils::remove_whitespace_between_tags;
 
 
 
 static MAGICAL_DUMP_VAR: &'static str = "__tera_context";
 
+#[inline]
+fn is_default_filter(filter: Option<&Node>) -> bool {
+    match filter {
+        Some(Filter { name, .. }) => name == "default",
+        _ => false,
+    }
+}
+
+// The macros available, looks like {namespace: (filename, {macro_name: body node})}
+type MacroNamespaces = HashMap<String, (String, HashMap<String, Node>)>;
+
 // we need to have some data in the renderer for when we are in a ForLoop
 // For example, accessing the local variable would fail when
 // looking it up in the context
@@ -22,7 +36,15 @@ static MAGICAL_DUMP_VAR: &'static str = "__tera_context";
 struct ForLoop {
     variable_name: String,
     current: usize,
-    values: Vec<Value>
+    values: Vec<Value>,
+    // the name of the key variable and the keys when iterating on an object
+    key_name: Option<String>,
+    keys: Vec<String>,
+    // variables defined with `{% set %}` in the body of the loop, only valid
+    // for the current iteration
+    set_values: Value,
+    // the ones of `set_values` captured by a set block with autoescaping, already escaped
+    safe_set_values: HashSet<String>,
 }
 
 impl ForLoop {
@@ -30,13 +52,28 @@ impl ForLoop {
         ForLoop {
             variable_name: local,
             current: 0,
-            values: values
+            values: values,
+            key_name: None,
+            keys: vec![],
+            set_values: Value::Object(Map::new()),
+            safe_set_values: HashSet::new(),
+        }
+    }
+
+    pub fn new_key_value(key_name: String, value_name: String, pairs: Vec<(String, Value)>) -> ForLoop {
+        let (keys, values) = pairs.into_iter().unzip();
+        ForLoop {
+            key_name: Some(key_name),
+            keys,
+            ..ForLoop::new(value_name, values)
         }
     }
 
     #[inline]
     pub fn increment(&mut self) {
         self.current += 1;
+        self.set_values = Value::Object(Map::new());
+        self.safe_set_values.clear();
     }
 
     #[inline]
@@ -44,28 +81,88 @@ impl ForLoop {
         self.values.get(self.current)
     }
 
+    #[inline]
+    pub fn get_key(&self) -> Option<&String> {
+        self.keys.get(self.current)
+    }
+
     #[inline]
     pub fn len(&self) -> usize {
         self.values.len()
     }
 }
 
+// Turns an array of `[key, value]` arrays, like the output of `dictsort`, into the pairs of a
+// key/value for loop. Returns `None` if one of the items isn't a pair with a string key
+fn into_key_value_pairs(values: Vec<Value>) -> Option<Vec<(String, Value)>> {
+    values.into_iter()
+        .map(|item| match item {
+            Value::Array(mut pair) => {
+                if pair.len() != 2 || !pair[0].is_string() {
+                    return None;
+                }
+                let value = pair.pop().unwrap();
+                match pair.pop() {
+                    Some(Value::String(key)) => Some((key, value)),
+                    _ => None,
+                }
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+// The variables defined with `{% set %}` directly in a `{% block %}`, dropped when the block ends
+#[derive(Debug)]
+struct BlockScope {
+    // How many for loops and macro calls were being rendered when entering the block: the
+    // block is the innermost scope as long as there are still as many
+    for_loops: usize,
+    macro_calls: usize,
+    set_values: Value,
+    safe_set_values: HashSet<String>,
+}
+
+// What a `{% break %}` or `{% continue %}` asks the innermost for loop to do
+#[derive(Debug, PartialEq)]
+enum LoopControl {
+    Break,
+    Continue,
+}
+
 #[derive(Debug)]
 pub struct Renderer<'a> {
     template: &'a Template,
     context: Value,
     tera: &'a Tera,
     for_loops: Vec<ForLoop>,
-    // looks like Vec<filename: {macro_name: body node}>
-    macros: Vec<HashMap<String, HashMap<String, Node>>>,
+    macros: Vec<MacroNamespaces>,
     // set when rendering macros, empty if not in a macro
     macro_context: Vec<Value>,
+    // The variables of the template context, then of each macro context, captured by a set block
+    // with autoescaping: their content is already escaped
+    safe_values: Vec<HashSet<String>>,
     // Keeps track of which namespace we're on in order to resolve the `self::` syntax
     macro_namespaces: Vec<String>,
     should_escape: bool,
     // Used when super() is used in a block, to know where we are in our stack of
     // definitions and for which block (block name, hierarchy level)
     blocks: Vec<(String, usize)>,
+    // Names of the templates currently being included, used to detect include cycles
+    includes: Vec<String>,
+    // Names of the templates the nodes being rendered come from, the last one being the
+    // current one. Only used to give the right template name in errors
+    template_names: Vec<String>,
+    // Set when rendering a `{% break %}` or `{% continue %}`: nothing else is rendered until
+    // the for loop it is in handles it
+    loop_control: Option<LoopControl>,
+    // How many macro calls and includes are being rendered, to error instead of overflowing
+    // the stack when it goes over the limit of the Tera instance
+    call_depth: usize,
+    // Output to use instead of rendering the blocks with these names, given from Rust code
+    block_overrides: HashMap<String, String>,
+    // One scope for each block being rendered, the last one being the innermost
+    block_scopes: Vec<BlockScope>,
 }
 
 impl<'a> Renderer<'a> {
@@ -85,15 +182,40 @@ impl<'a> Renderer<'a> {
             for_loops: vec![],
             macros: vec![],
             macro_context: vec![],
+            safe_values: vec![HashSet::new()],
             macro_namespaces: vec![],
             should_escape: should_escape,
             blocks: vec![],
+            includes: vec![],
+            template_names: vec![],
+            loop_control: None,
+            call_depth: 0,
+            block_overrides: HashMap::new(),
+            block_scopes: vec![],
         }
     }
 
+    // The blocks with these names will be replaced by the given content, as it is,
+    // instead of being rendered
+    pub fn set_block_overrides(&mut self, overrides: HashMap<String, String>) {
+        self.block_overrides = overrides;
+    }
+
+    // The innermost block scope defining `root`, only looking at the blocks entered in the
+    // current macro call while at least `for_loops` for loops were being rendered
+    fn find_in_block_scopes(&self, root: &str, for_loops: usize) -> Option<&BlockScope> {
+        self.block_scopes.iter().rev()
+            .take_while(|b| b.macro_calls == self.macro_context.len())
+            .filter(|b| b.for_loops >= for_loops)
+            .find(|b| b.set_values.get(root).is_some())
+    }
+
     // Lookup a variable name from the context and takes into
     // account for loops variables
-    fn lookup_variable(&self, key: &str) -> Result<Value> {
+    // The span is where the variable is used, to locate the error if it isn't found.
+    // Also returns whether the value was captured by a set block with autoescaping, in which
+    // case it is already escaped
+    fn lookup_variable(&self, key: &str, span: Span) -> Result<(Value, bool)> {
         // Differentiate between macros and general context
         let context = match self.macro_context.last() {
             Some(c) => c,
@@ -102,53 +224,110 @@ impl<'a> Renderer<'a> {
 
         // Magical variable that just dumps the context
         if key == MAGICAL_DUMP_VAR {
-            return Ok(to_value(
+            return Ok((to_value(
                 to_string_pretty(context).expect("Couldn't serialize context for `__tera_context`")
-            )?);
+            )?, false));
         }
 
         // small helper fn to reduce duplication code in the 3 spots in `lookup_variable` where we
         // need to actually do the variable lookup
+        // The error message is only built if the lookup fails
         #[inline]
-        fn find_variable(context: &Value, key: &str, tpl_name: &str) -> Result<Value> {
+        fn find_variable(context: &Value, key: &str, not_found: &dyn Fn() -> String) -> Result<Value> {
             match context.pointer(&get_json_pointer(key)) {
                 Some(v) => Ok(v.clone()),
-                None => bail!("Field `{}` not found in context while rendering '{}'", key, tpl_name)
+                None => Err(not_found().into()),
             }
         }
+        let not_found = || format!("Field `{}` not found in context {}", key, self.location(span));
+        let root = key.split('.').next().unwrap_or(key);
+        // Only a whole captured value is safe, nothing can be accessed in a string anyway
+        let is_captured = |safe_values: &HashSet<String>| key == root && safe_values.contains(root);
+
+        // The context shadows the globals of the Tera instance: they are only
+        // looked at if the context doesn't have the top-level variable
+        let (context, safe_values) = if context.get(root).is_none() && self.tera.globals.get(root).is_some() {
+            (&self.tera.globals, None)
+        } else {
+            (context, self.safe_values.last())
+        };
+        // Where to look once the for loops don't have the variable
+        let find_in_context = || match self.find_in_block_scopes(root, 0) {
+            Some(b) => Ok((find_variable(&b.set_values, key, &not_found)?, is_captured(&b.safe_set_values))),
+            None => Ok((find_variable(context, key, &not_found)?, safe_values.is_some_and(is_captured))),
+        };
 
         // Look in the plain context if we aren't in a for loop
         if self.for_loops.is_empty() {
-            return find_variable(context, key, &self.template.name);
+            return find_in_context();
         }
 
-        for for_loop in self.for_loops.iter().rev() {
-            if key.starts_with(&for_loop.variable_name) {
+        for (i, for_loop) in self.for_loops.iter().enumerate().rev() {
+            // Variables set in a block inside of this loop shadow the loop
+            if let Some(b) = self.find_in_block_scopes(root, i + 1) {
+                return Ok((find_variable(&b.set_values, key, &not_found)?, is_captured(&b.safe_set_values)));
+            }
+
+            // Variables set in the loop body shadow everything else
+            if for_loop.set_values.get(root).is_some() {
+                let value = find_variable(&for_loop.set_values, key, &not_found)?;
+                return Ok((value, is_captured(&for_loop.safe_set_values)));
+            }
+
+            if for_loop.key_name.as_ref().is_some_and(|k| k == root) {
+                let k = match for_loop.get_key() {
+                    Some(k) => to_value(k)?,
+                    None => { return Ok((to_value("").unwrap(), false)); }
+                };
+                // a key is a string so there is nothing to access in it
+                if key.contains('.') {
+                    return Err(not_found().into());
+                }
+                return Ok((k, false));
+            }
+
+            if root == for_loop.variable_name {
                 let value = match for_loop.get() {
                     Some(f) => f,
-                    None => { return Ok(to_value("").unwrap()); }
+                    None => { return Ok((to_value("").unwrap(), false)); }
                 };
 
                 // might be a struct or some nested structure
                 if key.contains('.') {
                     let new_key = key.split_terminator('.').skip(1).collect::<Vec<&str>>().join(".");
-                    return find_variable(value, &new_key, &self.template.name);
+                    return Ok((find_variable(value, &new_key, &not_found)?, false));
                 } else {
-                    return Ok(value.clone());
+                    return Ok((value.clone(), false));
                 }
             } else {
-                match key {
-                    "loop.index" => { return Ok(to_value(&(for_loop.current + 1))?); },
-                    "loop.index0" => { return Ok(to_value(&for_loop.current)?); },
-                    "loop.first" => { return Ok(to_value(&(for_loop.current == 0))?); },
-                    "loop.last" => { return Ok(to_value(&(for_loop.current == for_loop.len() - 1))?); },
-                    _ => ()
+                let value = match key {
+                    "loop.index" => to_value(&(for_loop.current + 1))?,
+                    "loop.index0" => to_value(&for_loop.current)?,
+                    "loop.first" => to_value(&(for_loop.current == 0))?,
+                    "loop.last" => to_value(&(for_loop.current == for_loop.len() - 1))?,
+                    "loop.length" => to_value(for_loop.len())?,
+                    _ => continue,
                 };
+                return Ok((value, false));
             }
         }
 
         // can get there when looking a variable in the global context while in a forloop
-        find_variable(context, key, &self.template.name)
+        find_in_context()
+    }
+
+    // Name of the template the node being rendered comes from, which is not always
+    // the template being rendered: blocks, includes and macros can come from other ones
+    fn current_template_name(&self) -> &str {
+        match self.template_names.last() {
+            Some(name) => name,
+            None => &self.template.name,
+        }
+    }
+
+    // Describes where a node is, to be appended to error messages
+    fn location(&self, span: Span) -> String {
+        format!("in template \"{}\" at {}", self.current_template_name(), span)
     }
 
     // Gets an identifier and return its json value
@@ -156,9 +335,19 @@ impl<'a> Renderer<'a> {
     // an return their result
     fn eval_ident(&self, node: &Node) -> Result<Value> {
         match *node {
-            Identifier { ref name, ref filters } => {
-                let mut value = self.lookup_variable(name)?;
-                let mut is_safe = false;
+            Identifier { ref name, ref filters, span } => {
+                // A value captured by a set block with autoescaping is already escaped
+                let (mut value, mut is_safe) = match self.lookup_variable(name, span) {
+                    Ok(v) => v,
+                    Err(e) => {
+                        // The `default` filter is there to handle undefined variables so we give
+                        // it a `null` instead of erroring
+                        match *filters {
+                            Some(ref f) if is_default_filter(f.front()) => (Value::Null, false),
+                            _ => return Err(e),
+                        }
+                    }
+                };
 
                 if let Some(ref _filters) = *filters {
                     for filter in _filters {
@@ -168,12 +357,19 @@ impl<'a> Renderer<'a> {
                                     is_safe = true;
                                     continue;
                                 }
-                                let filter_fn = self.tera.get_filter(name)?;
+                                let failed = || format!("Failed to apply filter `{}` {}", name, self.location(span));
+                                let filter_fn = self.tera.get_filter(name).chain_err(failed)?;
                                 let mut all_args = HashMap::new();
                                 for (arg_name, exp) in params {
                                     all_args.insert(arg_name.to_string(), self.eval_expression(exp.clone())?);
                                 }
-                                value = filter_fn(value, all_args)?;
+                                value = filter_fn(value, all_args).chain_err(failed)?;
+                                // Don't escape twice a value that was explicitly escaped, the output of
+                                // `escape_js` doesn't contain anything that needs to be escaped for HTML
+                                // either except for `/` which would break it
+                                if name == "escape" || name == "escape_js" {
+                                    is_safe = true;
+                                }
                             },
                             _ => unreachable!(),
                         };
@@ -183,7 +379,7 @@ impl<'a> Renderer<'a> {
                 // Escaping strings if wanted for that template
                 if name != MAGICAL_DUMP_VAR && self.should_escape && !is_safe {
                     if let Value::String(s) = value {
-                        value = to_value(escape_html(s.as_str()))?;
+                        value = to_value((self.tera.escape_fn)(s.as_str()))?;
                     }
                 }
                 Ok(value)
@@ -203,22 +399,43 @@ impl<'a> Renderer<'a> {
                         ).into()
                     ))
             },
+            FunctionCall { ref name, .. } => {
+                self.eval_expression(node.clone())?
+                    .to_number()
+                    .map_err(|_| format!(
+                        "Function `{}` was used in a math operation but didn't return a number", name
+                    ).into())
+            },
             Int(s) => Ok(s as f64),
             Float(s) => Ok(s),
             Math { ref lhs, ref rhs, ref operator } => {
                 let l = self.eval_math(lhs)?;
                 let r = self.eval_math(rhs)?;
+                if r == 0.0 && (*operator == Operator::Div || *operator == Operator::Mod) {
+                    bail!("Tried to do `{}` with {} as the right side, which is a division by zero", operator, r);
+                }
                 let result = match *operator {
                     Operator::Mul => l * r,
                     Operator::Div => l / r,
+                    Operator::Mod => l % r,
                     Operator::Add => l + r,
                     Operator::Sub => l - r,
                     _ => unreachable!()
                 };
 
-                Ok(result)
-            }
+                // Adding 0 turns a -0 (eg `-4 % 2`) into a 0 so it doesn't render as `-0`
+                Ok(result + 0.0)
+            },
+            Negate(ref n) => Ok(-self.eval_math(n)? + 0.0),
             Text(ref s) => bail!("Tried to do math with a String: `{}`", s),
+            InlineIf { .. } => {
+                self.eval_expression(node.clone())?
+                    .to_number()
+                    .map_err(|_| "An inline `if` was used in a math operation but didn't return a number".into())
+            },
+            Concat { .. } => bail!("Tried to do math with a string concatenation"),
+            Array(..) => bail!("Tried to do math with an array literal"),
+            Object(..) => bail!("Tried to do math with an object literal"),
             Bool(s) => bail!("Tried to do math with a boolean: `{}`", s),
             _ => unreachable!()
         }
@@ -233,7 +450,7 @@ impl<'a> Renderer<'a> {
                 let value = self.eval_condition(l)?;
                 Ok(Value::Bool(value))
             },
-            m @ Math { .. } => {
+            m @ Math { .. } | m @ Negate(..) => {
                 let result = self.eval_math(&m)?;
                 Ok(Value::Number(Number::from_f64(result).unwrap()))
             },
@@ -249,15 +466,98 @@ impl<'a> Renderer<'a> {
             Text(t) => {
                 Ok(Value::String(t))
             },
+            n @ Not(..) => {
+                let value = self.eval_condition(n)?;
+                Ok(Value::Bool(value))
+            },
+            Concat { lhs, rhs } => {
+                let mut result = self.eval_expression(*lhs)?.render();
+                result.push_str(&self.eval_expression(*rhs)?.render());
+                Ok(Value::String(result))
+            },
+            Array(items) => {
+                let mut values = vec![];
+                for item in items {
+                    values.push(self.eval_expression(item)?);
+                }
+                Ok(Value::Array(values))
+            },
+            Object(pairs) => {
+                let mut map = Map::new();
+                for (key, value) in pairs {
+                    map.insert(key, self.eval_expression(value)?);
+                }
+                Ok(Value::Object(map))
+            },
+            InlineIf { condition, then, otherwise } => {
+                if self.eval_condition(*condition)? {
+                    self.eval_expression(*then)
+                } else {
+                    match otherwise {
+                        Some(e) => self.eval_expression(*e),
+                        None => Ok(Value::Null),
+                    }
+                }
+            },
+            FunctionCall { name, params, span } => {
+                let failed = || format!("Failed to call function `{}` {}", name, self.location(span));
+                let function = self.tera.get_global_function(&name).chain_err(failed)?;
+                let mut all_args = HashMap::new();
+                for (arg_name, exp) in params {
+                    all_args.insert(arg_name, self.eval_expression(exp)?);
+                }
+                function(all_args).chain_err(failed)
+            },
             _ => unreachable!()
         }
     }
 
+    // Assigns a variable in the innermost scope: a variable set in a for loop
+    // is only visible for the current iteration, while one set in a macro or a block
+    // disappears at the end of the macro call or of the block.
+    // Outside of those, it is added to the context of the template.
+    // `safe` is whether the value is already escaped and shouldn't be escaped again
+    fn set_variable(&mut self, name: String, value: Value, safe: bool) -> Result<()> {
+        let (for_loops, macro_calls) = (self.for_loops.len(), self.macro_context.len());
+        let (scope, safe_values) = match self.block_scopes.last_mut() {
+            Some(b) if b.for_loops == for_loops && b.macro_calls == macro_calls => {
+                (&mut b.set_values, &mut b.safe_set_values)
+            },
+            _ => match self.for_loops.last_mut() {
+                Some(for_loop) => (&mut for_loop.set_values, &mut for_loop.safe_set_values),
+                None => {
+                    // There is one more set of safe values than of macro contexts, for the template context
+                    let safe_values = self.safe_values.last_mut().unwrap();
+                    match self.macro_context.last_mut() {
+                        Some(c) => (c, safe_values),
+                        None => (&mut self.context, safe_values),
+                    }
+                },
+            },
+        };
+
+        match scope.as_object_mut() {
+            Some(map) => {
+                if safe {
+                    safe_values.insert(name.clone());
+                } else {
+                    safe_values.remove(&name);
+                }
+                map.insert(name, value);
+                Ok(())
+            },
+            None => bail!("Tried to set variable `{}` but the context is not an object", name),
+        }
+    }
+
     fn eval_condition(&self, node: Node) -> Result<bool> {
         match node {
             Identifier { .. } => {
                 Ok(self.eval_ident(&node).map(|v| v.is_truthy()).unwrap_or(false))
             },
+            FunctionCall { .. } | Array(..) | Object(..) | Math { .. } | Negate(..) | InlineIf { .. } => {
+                Ok(self.eval_expression(node)?.is_truthy())
+            },
             Test { expression, name, params } => {
                 let tester = self.tera.get_tester(&name)?;
                 let mut value_params = vec![];
@@ -277,14 +577,27 @@ impl<'a> Renderer<'a> {
                         Ok(result)
                     },
                     Operator::Gt | Operator::Gte | Operator::Lt | Operator::Lte => {
-                        let l = self.eval_math(&lhs)?;
-                        let r = self.eval_math(&rhs)?;
-                        let result = match operator {
-                            Operator::Gte => l >= r,
-                            Operator::Gt => l > r,
-                            Operator::Lte => l <= r,
-                            Operator::Lt => l < r,
-                            _ => unreachable!()
+                        let lhs_val = self.eval_expression(*lhs)?;
+                        let rhs_val = self.eval_expression(*rhs)?;
+
+                        // Two strings are compared in lexicographic order, anything else needs to be numbers
+                        let ordering = match (lhs_val.as_str(), rhs_val.as_str(), lhs_val.as_f64(), rhs_val.as_f64()) {
+                            (Some(l), Some(r), _, _) => Some(l.cmp(r)),
+                            (None, None, Some(l), Some(r)) => l.partial_cmp(&r),
+                            _ => bail!(
+                                "Tried to compare {} with {} using `{}` but only two strings or two numbers can be compared",
+                                lhs_val, rhs_val, operator
+                            ),
+                        };
+                        let result = match ordering {
+                            Some(ordering) => match operator {
+                                Operator::Gte => ordering != Ordering::Less,
+                                Operator::Gt => ordering == Ordering::Greater,
+                                Operator::Lte => ordering != Ordering::Greater,
+                                Operator::Lt => ordering == Ordering::Less,
+                                _ => unreachable!()
+                            },
+                            None => false,
                         };
                         Ok(result)
                     },
@@ -292,10 +605,11 @@ impl<'a> Renderer<'a> {
                         let mut lhs_val = self.eval_expression(*lhs)?;
                         let mut rhs_val = self.eval_expression(*rhs)?;
 
-                        // Monomorphize number vals.
+                        // Numbers are compared by value, whether they are integers or floats, and are
+                        // never equal to something that isn't a number
                         if lhs_val.is_number() || rhs_val.is_number() {
                             if !lhs_val.is_number() || !rhs_val.is_number() {
-                                return Ok(false);
+                                return Ok(operator == Operator::NotEq);
                             }
 
                             lhs_val = Value::Number(Number::from_f64(lhs_val.as_f64().unwrap()).unwrap());
@@ -310,11 +624,48 @@ impl<'a> Renderer<'a> {
 
                         Ok(result)
                     },
+                    Operator::In | Operator::NotIn => {
+                        let needle = self.eval_expression(*lhs)?;
+                        let haystack = self.eval_expression(*rhs)?;
+                        let found = match haystack {
+                            Value::Array(ref arr) => {
+                                // Numbers are compared as floats like in `==`
+                                match needle.as_f64() {
+                                    Some(n) => arr.iter().any(|v| v.as_f64() == Some(n)),
+                                    None => arr.contains(&needle),
+                                }
+                            },
+                            Value::Object(ref map) => match needle {
+                                Value::String(ref key) => map.contains_key(key),
+                                _ => bail!(
+                                    "Tried to check if `{}` is a key of an object but only strings can be keys", needle
+                                ),
+                            },
+                            Value::String(ref s) => match needle {
+                                Value::String(ref sub) => s.contains(sub.as_str()),
+                                _ => bail!(
+                                    "Tried to check if `{}` is in the string `{}` but only a string can be in a string",
+                                    needle, s
+                                ),
+                            },
+                            _ => bail!(
+                                "Tried to check if `{}` is in `{}` but `{}` only works with an array, an object or a string \
+                                on the right side",
+                                needle, haystack, operator
+                            ),
+                        };
+
+                        Ok(if operator == Operator::In { found } else { !found })
+                    },
                     _ => unreachable!()
                 }
             }
             Not(n) => {
-                Ok(self.eval_expression(*n).map(|v| !v.is_truthy()).unwrap_or(true))
+                match *n {
+                    // `is not` test, errors from the tester need to bubble up
+                    t @ Test { .. } => Ok(!self.eval_condition(t)?),
+                    e => Ok(self.eval_expression(e).map(|v| !v.is_truthy()).unwrap_or(true)),
+                }
             },
             _ => unreachable!()
         }
@@ -326,7 +677,10 @@ impl<'a> Renderer<'a> {
     fn render_variable_block(&mut self, node: Node) -> Result<String>  {
         match node {
             Identifier { .. } => Ok(self.eval_ident(&node)?.render()),
-            Math { .. } => Ok(self.eval_math(&node)?.to_string()),
+            Math { .. } | Negate(..) => Ok(self.eval_math(&node)?.to_string()),
+            Concat { .. } | FunctionCall { .. } | Array(..) | Object(..) | InlineIf { .. } => {
+                Ok(self.eval_expression(node)?.render())
+            },
             _ => unreachable!()
         }
     }
@@ -342,6 +696,8 @@ impl<'a> Renderer<'a> {
                         skip_else = true;
                         // Remove if/elif whitespace
                         output.push_str(self.render_node(*body.clone())?.trim_left());
+                        // Only the first branch with a truthy condition is rendered
+                        break;
                     }
                 },
                 _ => unreachable!()
@@ -362,25 +718,48 @@ impl<'a> Renderer<'a> {
         Ok(output.trim_right().to_string())
     }
 
-    fn render_for(&mut self, variable_name: String, array_name: String, body: Box<Node>) -> Result<String> {
-        let list = self.lookup_variable(&array_name)?;
-
-        if !list.is_array() {
-            bail!("Tried to iterate on variable `{}`, but it isn't an array", array_name);
-        }
+    fn render_for(&mut self, key_name: Option<String>, variable_name: String, container: Node, body: Box<Node>) -> Result<String> {
+        let container_name = match container {
+            Identifier { ref name, .. } => format!("variable `{}`", name),
+            FunctionCall { ref name, .. } => format!("the result of function `{}`", name),
+            Array(..) => "an array literal".to_string(),
+            _ => unreachable!("Got a node other than an Identifier, a FunctionCall or an Array in a for loop"),
+        };
+        let container_value = self.eval_expression(container)?;
 
-        // Safe unwrap
-        let deserialized = list.as_array().unwrap();
-        let length = deserialized.len();
-        self.for_loops.push(ForLoop::new(variable_name, deserialized.clone()));
+        let for_loop = match (key_name, container_value) {
+            (None, Value::Array(values)) => ForLoop::new(variable_name, values),
+            (Some(key_name), Value::Object(map)) => {
+                ForLoop::new_key_value(key_name, variable_name, map.into_iter().collect())
+            },
+            (None, _) => bail!("Tried to iterate on {}, but it isn't an array", container_name),
+            (Some(k), value) => {
+                let pairs = match value {
+                    Value::Array(values) => into_key_value_pairs(values),
+                    _ => None,
+                };
+                match pairs {
+                    Some(p) => ForLoop::new_key_value(k, variable_name, p),
+                    None => bail!(
+                        "Tried to iterate on {} with `{}, {}`, but it isn't an object or an array of `[key, value]` pairs. \
+                        Iterate on other arrays with a single variable",
+                        container_name, k, variable_name
+                    ),
+                }
+            },
+        };
+        let length = for_loop.len();
+        self.for_loops.push(for_loop);
         let mut i = 0;
         let mut output = String::new();
         if length > 0 {
             loop {
                 output.push_str(self.render_node(*body.clone())?.trim_left());
+                // A continue only stopped the rendering of the body so we go to the next iteration
+                let control = self.loop_control.take();
                 // Safe unwrap
                 self.for_loops.last_mut().unwrap().increment();
-                if i == length - 1 {
+                if i == length - 1 || control == Some(LoopControl::Break) {
                     // Don't forget to pop the for_loop is we are done
                     // otherwise it would just replay the last loop
                     // see https://github.com/Keats/tera/issues/51
@@ -397,8 +776,81 @@ impl<'a> Renderer<'a> {
         Ok(output.trim_right().to_string())
     }
 
+    // Errors if rendering one more macro call or include would go over the maximum call depth
+    fn check_call_depth(&self, what: &str) -> Result<()> {
+        if self.call_depth >= self.tera.max_call_depth {
+            bail!(
+                "Reached the maximum depth of {} nested macro calls and includes when {}, is there a recursion without end?",
+                self.tera.max_call_depth, what
+            );
+        }
+        Ok(())
+    }
+
+    // Renders the body of the macro `macro_name` defined in `filename` with the args in `context`,
+    // using the default values of the params that are not in it
+    fn render_macro_body(
+        &mut self,
+        macro_name: &str,
+        filename: String,
+        body: &Node,
+        params: &VecDeque<String>,
+        defaults: &HashMap<String, Node>,
+        context: HashMap<String, Value>,
+    ) -> Result<String> {
+        self.check_call_depth(&format!("calling the macro `{}`", macro_name))?;
+        if context.keys().any(|p| !params.contains(p)) {
+            // Sorted so the error doesn't depend on the order of the HashMap
+            let mut params_seen = context.keys().cloned().collect::<Vec<String>>();
+            params_seen.sort();
+            bail!("Macro `{}` got `{:?}` for args but was expecting `{:?}` (order does not matter)", macro_name, params_seen, params);
+        }
+        if let Some(missing) = params.iter().find(|p| !context.contains_key(*p) && !defaults.contains_key(*p)) {
+            bail!("Macro `{}` was called without the arg `{}`, which doesn't have a default value", macro_name, missing);
+        }
+        let given_params = context.keys().cloned().collect::<Vec<String>>();
+
+        // Push this context to our stack of macro context so the renderer can pick variables
+        // from it.
+        // The for loops of the caller are put aside as they are not in scope in the macro
+        self.macro_context.push(to_value(&context)?);
+        self.safe_values.push(HashSet::new());
+        let mut caller_for_loops = vec![];
+        mem::swap(&mut caller_for_loops, &mut self.for_loops);
+
+        self.template_names.push(filename);
+
+        // The defaults of the params that were not given are evaluated in the scope of the macro,
+        // in the order of the params so a default can use the params before it
+        for param_name in params {
+            if given_params.contains(param_name) {
+                continue;
+            }
+            let value = self.eval_expression(defaults[param_name].clone())?;
+            if let Some(Value::Object(macro_context)) = self.macro_context.last_mut() {
+                macro_context.insert(param_name.to_string(), value);
+            }
+        }
+
+        // We render the macro body as a normal node
+        self.call_depth += 1;
+        let mut output = String::new();
+        for node in body.get_children() {
+            output.push_str(&self.render_node(node)?);
+        }
+        self.call_depth -= 1;
+        self.template_names.pop();
+        self.for_loops = caller_for_loops;
+
+        // We remove the macro context we just rendered from our stack of contexts
+        self.macro_context.pop();
+        self.safe_values.pop();
+
+        Ok(output.trim().to_string())
+    }
+
     fn render_macro(&mut self, call_node: Node) -> Result<String> {
-        if let MacroCall {namespace, name: macro_name, params: call_params} = call_node {
+        if let MacroCall {namespace, name: macro_name, args, params: mut call_params} = call_node {
             // We need to find the active namespace in Tera if `self` is used
             // Since each macro (other than the `self` ones) pushes its own namespace
             // to the stack when being rendered, we can just lookup the last namespace that was pushed
@@ -425,36 +877,30 @@ impl<'a> Renderer<'a> {
             let macro_definition = self.macros
                 .last()
                 .and_then(|m| m.get(&active_namespace))
-                .and_then(|m| m.get(&macro_name)
-                .cloned());
-
-            if let Some(Macro {body, params, ..}) = macro_definition {
-                // fail fast if the number of args don't match
-                if params.len() != call_params.len() {
-                    let params_seen = call_params.keys().cloned().collect::<Vec<String>>();
-                    bail!("Macro `{}` got `{:?}` for args but was expecting `{:?}` (order does not matter)", macro_name, params, params_seen);
+                .and_then(|(filename, m)| m.get(&macro_name).map(|d| (filename.clone(), d.clone())));
+
+            if let Some((filename, Macro {body, params, defaults, ..})) = macro_definition {
+                // positional args are given to the params in the order they are declared
+                if args.len() > params.len() {
+                    bail!(
+                        "Macro `{}` was called with {} positional args but it only takes {}: `{:?}`",
+                        macro_name, args.len(), params.len(), params
+                    );
+                }
+                for (param_name, exp) in params.iter().zip(args) {
+                    if call_params.contains_key(param_name) {
+                        bail!("Macro `{}` got the arg `{}` both by position and by name", macro_name, param_name);
+                    }
+                    call_params.insert(param_name.clone(), exp);
                 }
 
                 // We need to make a new context for the macro from the arguments given
-                // Return an error if we get some unknown params
                 let mut context = HashMap::new();
                 for (param_name, exp) in &call_params {
-                    if !params.contains(param_name) {
-                        let params_seen = call_params.keys().cloned().collect::<Vec<String>>();
-                        bail!("Macro `{}` got `{:?}` for args but was expecting `{:?}` (order does not matter)", macro_name, params, params_seen);
-                    }
                     context.insert(param_name.to_string(), self.eval_expression(exp.clone())?);
                 }
 
-                // Push this context to our stack of macro context so the renderer can pick variables
-                // from it
-                self.macro_context.push(to_value(&context)?);
-
-                // We render the macro body as a normal node
-                let mut output = String::new();
-                for node in body.get_children() {
-                    output.push_str(&self.render_node(node)?);
-                }
+                let output = self.render_macro_body(&macro_name, filename, &body, &params, &defaults, context)?;
 
                 // If the current namespace wasn't `self`, we remove it since it's not needed anymore
                 // In the `self` case, we are still in the parent macro and its namespace is still
@@ -463,10 +909,7 @@ impl<'a> Renderer<'a> {
                     self.macro_namespaces.pop();
                 }
 
-                // We remove the macro context we just rendered from our stack of contexts
-                self.macro_context.pop();
-
-                return Ok(output.trim().to_string());
+                return Ok(output);
             } else {
                 bail!("Macro `{}` was not found in the namespace `{}`", macro_name, active_namespace);
             }
@@ -475,8 +918,36 @@ impl<'a> Renderer<'a> {
         }
     }
 
+    // Renders the macro `macro_name` of the template being rendered with the given args, as if
+    // it was called from a template importing that template as `namespace`
+    pub fn render_macro_with_args(&mut self, namespace: &str, macro_name: &str, args: HashMap<String, Value>) -> Result<String> {
+        let template = self.template;
+        let (body, params, defaults) = match template.macros.get(macro_name) {
+            Some(Macro { body, params, defaults, .. }) => (body, params, defaults),
+            _ => bail!("Macro `{}` was not found in '{}'", macro_name, template.name),
+        };
+
+        // The macro can call the macros of its own file with `self::` and the ones it imports
+        let mut map = HashMap::new();
+        for (filename, imported_namespace) in &template.imported_macro_files {
+            let macro_tpl = self.tera.get_template(filename)?;
+            map.insert(imported_namespace.to_string(), (filename.clone(), macro_tpl.macros.clone()));
+        }
+        map.insert(namespace.to_string(), (template.name.clone(), template.macros.clone()));
+        self.macros.push(map);
+        self.macro_namespaces.push(namespace.to_string());
+
+        self.render_macro_body(macro_name, template.name.clone(), body, params, defaults, args)
+            .chain_err(|| self.get_error_location())
+    }
+
     fn import_macros(&mut self, tpl_name: String) -> Result<bool> {
-        let tpl = self.tera.get_template(&tpl_name)?;
+        // The template being rendered might not be in the Tera instance, eg with `render_str`
+        let tpl = if tpl_name == self.template.name {
+            self.template
+        } else {
+            self.tera.get_template(&tpl_name)?
+        };
         if tpl.imported_macro_files.is_empty() {
             return Ok(false);
         }
@@ -484,20 +955,57 @@ impl<'a> Renderer<'a> {
 
         for &(ref filename, ref namespace) in &tpl.imported_macro_files {
             let macro_tpl = self.tera.get_template(filename)?;
-            map.insert(namespace.to_string(), macro_tpl.macros.clone());
+            map.insert(namespace.to_string(), (filename.clone(), macro_tpl.macros.clone()));
         }
         self.macros.push(map);
         Ok(true)
     }
 
+    // Renders the definition of the block `name` at `level` in its inheritance chain, 0 being the one
+    // of the template being rendered and each `super()` going up one level.
+    // The block is on the `blocks` stack while its body is rendered so a `super()` in it knows where it is
+    fn render_block(&mut self, name: String, level: usize) -> Result<String> {
+        let (tpl_name, body) = match self.template.blocks_definitions[&name][level] {
+            (ref tpl_name, Block { ref body, .. }) => (tpl_name.clone(), body.clone()),
+            ref x => unreachable!("render_block {:?}", x),
+        };
+        self.blocks.push((name, level));
+        self.template_names.push(tpl_name.clone());
+        let has_macro = self.import_macros(tpl_name)?;
+        let res = self.render_node(*body);
+        if has_macro {
+            self.macros.pop();
+        }
+        self.template_names.pop();
+        self.blocks.pop();
+        res
+    }
+
     pub fn render_node(&mut self, node: Node) -> Result<String> {
         match node {
             Include(p) => {
-                let ast = self.tera.get_template(&p)?.ast.get_children();
+                if p == self.template.name || self.includes.contains(&p) {
+                    let mut chain = vec![self.template.name.clone()];
+                    chain.extend(self.includes.iter().cloned());
+                    chain.push(p);
+                    bail!("Circular include detected: {}", chain.join(" -> "));
+                }
+
+                let ast = match self.tera.get_template(&p) {
+                    Ok(tpl) => tpl.ast.get_children(),
+                    Err(_) => bail!("Tried to include template '{}' but it doesn't exist", p),
+                };
+                self.check_call_depth(&format!("including '{}'", p))?;
+                self.includes.push(p.clone());
+                self.template_names.push(p);
+                self.call_depth += 1;
                 let mut output = String::new();
                 for node in ast {
                     output.push_str(&self.render_node(node)?);
                 }
+                self.call_depth -= 1;
+                self.template_names.pop();
+                self.includes.pop();
 
                 Ok(output.trim().to_string())
             },
@@ -508,7 +1016,7 @@ impl<'a> Renderer<'a> {
                 } else {
                     self.macros.pop().unwrap()
                 };
-                map.insert(name.to_string(), tpl.macros.clone());
+                map.insert(name.to_string(), (tpl_name.clone(), tpl.macros.clone()));
                 self.macros.push(map);
                 // In theory, the render_node should return Result<Option<String>>
                 // but in practice there's no difference so keeping this hack
@@ -525,69 +1033,77 @@ impl<'a> Renderer<'a> {
                 let mut output = String::new();
                 for n in body {
                     output.push_str(&self.render_node(n)?);
+                    if self.loop_control.is_some() {
+                        break;
+                    }
                 }
                 Ok(output)
             },
-            For {variable, array, body} => {
-                self.render_for(variable, array, body)
+            For {key, variable, container, body} => {
+                self.render_for(key, variable, *container, body)
+            },
+            Spaceless(body) => {
+                Ok(remove_whitespace_between_tags(&self.render_node(*body)?))
+            },
+            Set {name, value} => {
+                // The value is escaped when it is printed, not when it is assigned
+                let should_escape = mem::replace(&mut self.should_escape, false);
+                let value = self.eval_expression(*value);
+                self.should_escape = should_escape;
+                self.set_variable(name, value?, false)?;
+                Ok("".to_string())
+            },
+            SetBlock {name, body} => {
+                // The body is rendered like the rest of the template: with autoescaping it is
+                // already escaped so it is not escaped again when printed
+                let output = self.render_node(*body)?;
+                let safe = self.should_escape;
+                self.set_variable(name, Value::String(output), safe)?;
+                Ok("".to_string())
             },
             Block {name, body} => {
+                // Overrides given from Rust code win over all the definitions of the block
+                if let Some(content) = self.block_overrides.get(&name) {
+                    return Ok(content.clone());
+                }
+                self.block_scopes.push(BlockScope {
+                    for_loops: self.for_loops.len(),
+                    macro_calls: self.macro_context.len(),
+                    set_values: Value::Object(Map::new()),
+                    safe_set_values: HashSet::new(),
+                });
                 // We pick the first block, ie the one in the template we are rendering
                 // We will go up in "level" if we encounter a super()
-                match self.template.blocks_definitions.get(&name) {
-                    Some(b) => {
-                        // the indexing here is safe since we are rendering a block, we know we have
-                        // at least 1
-                        match &b[0] {
-                            &(ref tpl_name, Block { ref body, ..}) => {
-                                self.blocks.push((name, 0));
-                                let has_macro = self.import_macros(tpl_name.clone())?;
-                                let res = self.render_node(*body.clone());
-                                if has_macro {
-                                    self.macros.pop();
-                                }
-                                res
-                            },
-                            x => unreachable!("render_node Block {:?}", x)
-                        }
-                    },
-                    None => {
-                        self.render_node(*body)
-                    }
-                }
+                let res = if self.template.blocks_definitions.contains_key(&name) {
+                    self.render_block(name, 0)
+                } else {
+                    self.render_node(*body)
+                };
+                self.block_scopes.pop();
+                res
             },
             Super => {
-                if let Some((name, level)) = self.blocks.pop() {
-                    let new_level = level + 1;
-
-                    match self.template.blocks_definitions.get(&name) {
-                        Some(b) => {
-                            match &b[new_level] {
-                                &(ref tpl_name, Block { ref body, .. }) => {
-                                    self.blocks.push((name, new_level));
-                                    let has_macro = self.import_macros(tpl_name.clone())?;
-                                    let res = self.render_node(*body.clone());
-                                    if has_macro {
-                                        self.macros.pop();
-                                    }
-                                    // Can't go any higher for that block anymore?
-                                    if new_level == b.len() - 1 {
-                                        // then remove it from the stack, we're done with it
-                                        self.blocks.pop();
-                                    }
-                                    res
-                                },
-                                x => unreachable!("render_node Block {:?}", x)
-                            }
-                        },
-                        None => unreachable!("render_node -> didn't get block")
-                    }
-                } else {
+                // The innermost block being rendered is the one `super()` is called in
+                match self.blocks.last().cloned() {
+                    Some((name, level)) => {
+                        if level + 1 >= self.template.blocks_definitions[&name].len() {
+                            bail!("Tried to call `super()` in block `{}` but no parent template defines it", name);
+                        }
+                        self.render_block(name, level + 1)
+                    },
                     // prevented by parser already, unless it's a super in the base template
                     // TODO: add a test and see if we need to return an error instead
-                    unreachable!("Super called outside of a block or in base template")
+                    None => unreachable!("Super called outside of a block or in base template"),
                 }
             },
+            Break => {
+                self.loop_control = Some(LoopControl::Break);
+                Ok("".to_string())
+            },
+            Continue => {
+                self.loop_control = Some(LoopControl::Continue);
+                Ok("".to_string())
+            },
             Extends(_) | Macro {..} => Ok("".to_string()),
             x => unreachable!("render_node -> unexpected node: {:?}", x)
         }
@@ -624,31 +1140,78 @@ impl<'a> Renderer<'a> {
     }
 
     pub fn render(&mut self) -> Result<String> {
+        let mut output = String::new();
+        self.render_parts(|part| {
+            output.push_str(part);
+            Ok(())
+        })?;
+        Ok(output)
+    }
+
+    // Writes the output of each top-level node as soon as it is rendered
+    pub fn render_to(&mut self, writer: &mut dyn Write) -> Result<()> {
+        let template = self.template;
+        self.render_parts(|part| {
+            writer.write_all(part.as_bytes())
+                .chain_err(|| format!("Failed to write the output of '{}'", template.name))
+        })
+    }
+
+    // Renders the top-level nodes one by one, giving their output to `emit` in order
+    fn render_parts<F: FnMut(&str) -> Result<()>>(&mut self, mut emit: F) -> Result<()> {
         let ast = if !self.template.parents.is_empty() {
             let parent = self.tera.get_template(
                 self.template.parents.last().expect("Couldn't get first ancestor template")
             ).chain_err(|| format!("Failed to render '{}'", self.template.name))?;
+            // Everything outside of blocks comes from the base template
+            self.template_names.push(parent.name.clone());
             parent.ast.get_children()
         } else {
             self.template.ast.get_children()
         };
 
-        let mut output = String::new();
+        let strip_trailing_newline = !self.tera.keep_trailing_newline;
+        // When stripping the trailing newline, a newline ending the output of a node is only given
+        // to `emit` once we know other nodes come after it
+        let mut held_newline = "";
         for node in ast {
-            output.push_str(
-                &self.render_node(node).chain_err(|| self.get_error_location())?
-            );
+            let output = self.render_node(node).chain_err(|| self.get_error_location())?;
+            if output.is_empty() {
+                continue;
+            }
+            let mut content = output.as_str();
+            let newline = if !strip_trailing_newline {
+                ""
+            } else if content.ends_with("\r\n") {
+                "\r\n"
+            } else if content.ends_with('\n') {
+                "\n"
+            } else {
+                ""
+            };
+            content = &content[..content.len() - newline.len()];
+
+            emit(held_newline)?;
+            emit(content)?;
+            held_newline = newline;
         }
 
-        Ok(output)
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use serde_json::value::Value;
+
     use context::Context;
     use errors::Result;
     use tera::Tera;
+    use super::Renderer;
 
     fn render_template(content: &str, context: Context) -> Result<String> {
         let mut tera = Tera::default();
@@ -666,12 +1229,66 @@ mod tests {
         assert_eq!(result.unwrap(), "<h1>Hello world</h1>".to_owned());
     }
 
+    #[test]
+    fn test_render_nested_include() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            ("world", "world"),
+            ("greeting", "Hello {% include \"world\" %}"),
+            ("hello", "<h1>{% include \"greeting\" %} and {% include \"world\" %}</h1>"),
+        ]).unwrap();
+        let result = tera.render("hello", Context::new());
+        assert_eq!(result.unwrap(), "<h1>Hello world and world</h1>".to_owned());
+    }
+
+    #[test]
+    fn test_render_include_cycle() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            ("a", "{% include \"b\" %}"),
+            ("b", "{% include \"a\" %}"),
+        ]).unwrap();
+        let result = tera.render("a", Context::new());
+        assert_eq!(
+            result.unwrap_err().iter().nth(1).unwrap().description(),
+            "Circular include detected: a -> b -> a"
+        );
+    }
+
     #[test]
     fn test_render_simple_string() {
         let result = render_template("<h1>Hello world</h1>", Context::new());
         assert_eq!(result.unwrap(), "<h1>Hello world</h1>".to_owned());
     }
 
+    #[test]
+    fn test_render_raw() {
+        let mut context = Context::new();
+        context.insert("name", &"Bob");
+        let result = render_template(
+            "{{ name }} {% raw %}{{ name }} {% if x %}{# comment #}{% raw %}{% endraw %}",
+            context,
+        );
+        assert_eq!(result.unwrap(), "Bob {{ name }} {% if x %}{# comment #}{% raw %}".to_owned());
+    }
+
+    #[test]
+    fn test_render_for_on_split_string() {
+        let mut context = Context::new();
+        context.insert("tags", &"rust,web,templates");
+        let result = render_template("{% for tag in tags | split(pat=\",\") %}[{{ tag }}]{% endfor %}", context);
+        assert_eq!(result.unwrap(), "[rust][web][templates]".to_owned());
+    }
+
+    #[test]
+    fn test_render_conversion_filters_in_math() {
+        let mut context = Context::new();
+        context.insert("count", &"42");
+        context.insert("price", &"1.5");
+        let result = render_template("{{ count | int + 1 }} {{ price | float * 2 }}", context);
+        assert_eq!(result.unwrap(), "43 3".to_owned());
+    }
+
     #[test]
     fn test_render_math() {
         let tests = vec![
@@ -685,6 +1302,10 @@ mod tests {
             ("{{ 2.1 * 5.05 }}", "10.605".to_string()),
             ("{{ 2 / 0.5 }}", "4".to_string()),
             ("{{ 2.1 / 0.5 }}", "4.2".to_string()),
+            ("{{ 7 % 2 }}", "1".to_string()),
+            ("{{ 7.5 % 2 }}", "1.5".to_string()),
+            ("{{ -4 % 2 }}", "0".to_string()),
+            ("{{ 1 + 5 % 3 * 2 }}", "5".to_string()),
         ];
 
         for (input, expected) in tests {
@@ -693,97 +1314,772 @@ mod tests {
     }
 
     #[test]
-    fn test_render_basic_variable() {
+    fn test_render_negation() {
         let mut context = Context::new();
-        context.add("name", &"Vincent");
-        let result = render_template("My name is {{ name }}.", context);
-        assert_eq!(result.unwrap(), "My name is Vincent.".to_owned());
+        context.insert("price", &10);
+        context.insert("discount", &2.5);
+        context.insert("zero", &0);
+        let tests = vec![
+            ("{{ -price }}", "-10".to_string()),
+            ("{{ -discount }}", "-2.5".to_string()),
+            ("{{ -zero }}", "0".to_string()),
+            ("{{ 1 - -price }}", "11".to_string()),
+            ("{{ -price * 2 + discount }}", "-17.5".to_string()),
+            ("{% if -price < 0 %}negative{% endif %}", "negative".to_string()),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(render_template(input, context.clone()).unwrap(), expected);
+        }
     }
 
     #[test]
-    fn test_render_math_with_variable() {
+    fn test_render_modulo_in_loop() {
         let mut context = Context::new();
-        context.add("vat_rate", &0.20);
-        let result = render_template("Vat: £{{ 100 * vat_rate }}.", context);
-
-        assert_eq!(result.unwrap(), "Vat: £20.".to_owned());
+        context.insert("data", &vec![1, 2, 3, 4]);
+        let result = render_template(
+            "{% for i in data %}{% if loop.index % 2 == 0 %}{{ i }}{% endif %}{% endfor %}",
+            context
+        );
+        assert_eq!(result.unwrap(), "24".to_string());
     }
 
     #[test]
-    fn test_render_comment() {
-        let result = render_template("<h1>Hello {# comment #} world</h1>", Context::new());
-        assert_eq!(result.unwrap(), "<h1>Hello  world</h1>".to_owned());
-    }
+    fn test_error_division_by_zero() {
+        let mut context = Context::new();
+        context.insert("zero", &0);
+        let tests = vec![
+            ("{{ 1 / zero }}", "Tried to do `/` with 0 as the right side, which is a division by zero"),
+            ("{{ 1 % 0.0 }}", "Tried to do `%` with 0 as the right side, which is a division by zero"),
+        ];
 
-    #[test]
-    fn test_render_nested_comment() {
-        let result = render_template("<h1>Hello {# comment {# nested #} world</h1>", Context::new());
-        assert_eq!(result.unwrap(), "<h1>Hello  world</h1>".to_owned());
+        for (input, expected) in tests {
+            let result = render_template(input, context.clone());
+            assert!(result.is_err());
+            assert_eq!(result.unwrap_err().iter().nth(1).unwrap().to_string(), expected);
+        }
     }
 
     #[test]
-    fn test_ignore_variable_in_comment() {
+    fn test_render_in() {
         let mut context = Context::new();
-        context.add("name", &"Vincent");
-        let result = render_template("My name {# was {{ name }} #} is No One.", context);
+        context.insert("roles", &vec!["admin", "editor"]);
+        context.insert("numbers", &vec![1, 2, 3]);
+        let mut user = HashMap::new();
+        user.insert("name", "Bob");
+        context.insert("user", &user);
+        context.insert("role", &"admin");
+        let tests = vec![
+            ("{% if role in roles %}yes{% endif %}", "yes"),
+            ("{% if \"guest\" in roles %}yes{% else %}no{% endif %}", "no"),
+            ("{% if \"guest\" not in roles %}yes{% endif %}", "yes"),
+            ("{% if 2 in numbers %}yes{% endif %}", "yes"),
+            ("{% if 1 + 1 in numbers %}yes{% endif %}", "yes"),
+            ("{% if 4 in [1, 2] %}yes{% else %}no{% endif %}", "no"),
+            ("{% if \"name\" in user %}yes{% endif %}", "yes"),
+            ("{% if \"age\" not in user %}yes{% endif %}", "yes"),
+            ("{% if \"dm\" in role %}yes{% endif %}", "yes"),
+            ("{% if \"x\" in role %}yes{% else %}no{% endif %}", "no"),
+        ];
 
-        assert_eq!(result.unwrap(), "My name  is No One.".to_owned());
+        for (input, expected) in tests {
+            assert_eq!(render_template(input, context.clone()).unwrap(), expected.to_string());
+        }
     }
 
     #[test]
-    fn test_render_if_simple() {
+    fn test_error_in_wrong_right_side() {
         let mut context = Context::new();
-        context.add("is_admin", &true);
-        let result = render_template("{% if is_admin %}Admin{% endif %}", context);
+        context.insert("role", &"admin");
+        let tests = vec![
+            (
+                "{% if role in 1 %}{% endif %}",
+                "Tried to check if `\"admin\"` is in `1` but `in` only works with an array, an object or a string on the right side"
+            ),
+            (
+                "{% if 1 in role %}{% endif %}",
+                "Tried to check if `1` is in the string `admin` but only a string can be in a string"
+            ),
+        ];
 
-        assert_eq!(result.unwrap(), "Admin".to_owned());
+        for (input, expected) in tests {
+            let result = render_template(input, context.clone());
+            assert!(result.is_err());
+            assert_eq!(result.unwrap_err().iter().nth(1).unwrap().to_string(), expected);
+        }
     }
 
     #[test]
-    fn test_render_if_or_conditions() {
+    fn test_render_concat() {
         let mut context = Context::new();
-        context.add("is_adult", &false);
-        context.add("age", &18);
-        let result = render_template("{% if is_adult or age + 1 > 18 %}Adult{% endif %}", context);
-
+        context.insert("name", &"Bob");
+        context.insert("age", &42);
+        context.insert("admin", &true);
+        context.insert("nothing", &());
+        let tests = vec![
+            ("{{ \"Hello, \" ~ name ~ \"!\" }}", "Hello, Bob!".to_string()),
+            ("{{ name ~ \" is \" ~ age }}", "Bob is 42".to_string()),
+            ("{{ name ~ \" is \" ~ age + 1 }}", "Bob is 43".to_string()),
+            ("{{ name ~ \" is admin: \" ~ admin }}", "Bob is admin: true".to_string()),
+            ("{{ name ~ nothing ~ \"!\" }}", "Bob!".to_string()),
+            ("{{ name | upper ~ 1.5 }}", "BOB1.5".to_string()),
+            ("{% if name ~ age == \"Bob42\" %}yes{% endif %}", "yes".to_string()),
+            ("{% set greeting = \"Hi \" ~ name %}{{ greeting }}", "Hi Bob".to_string()),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(render_template(input, context.clone()).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_render_basic_variable() {
+        let mut context = Context::new();
+        context.insert("name", &"Vincent");
+        let result = render_template("My name is {{ name }}.", context);
+        assert_eq!(result.unwrap(), "My name is Vincent.".to_owned());
+    }
+
+    #[test]
+    fn test_render_math_with_variable() {
+        let mut context = Context::new();
+        context.insert("vat_rate", &0.20);
+        let result = render_template("Vat: £{{ 100 * vat_rate }}.", context);
+
+        assert_eq!(result.unwrap(), "Vat: £20.".to_owned());
+    }
+
+    #[test]
+    fn test_render_comment() {
+        let result = render_template("<h1>Hello {# comment #} world</h1>", Context::new());
+        assert_eq!(result.unwrap(), "<h1>Hello  world</h1>".to_owned());
+    }
+
+    #[test]
+    fn test_render_nested_comment() {
+        let result = render_template("<h1>Hello {# comment {# nested #} world</h1>", Context::new());
+        assert_eq!(result.unwrap(), "<h1>Hello  world</h1>".to_owned());
+    }
+
+    #[test]
+    fn test_render_comment_between_tags() {
+        let mut context = Context::new();
+        context.insert("data", &vec![1, 2]);
+        let result = render_template(
+            "{% for i in data %}{# a\nmulti-line {{ i }}\ncomment #}{{ i }}{% endfor %}{# end #}",
+            context
+        );
+        assert_eq!(result.unwrap(), "12".to_owned());
+    }
+
+    #[test]
+    fn test_ignore_variable_in_comment() {
+        let mut context = Context::new();
+        context.insert("name", &"Vincent");
+        let result = render_template("My name {# was {{ name }} #} is No One.", context);
+
+        assert_eq!(result.unwrap(), "My name  is No One.".to_owned());
+    }
+
+    #[test]
+    fn test_render_string_testers() {
+        let mut context = Context::new();
+        context.insert("path", &"/admin/users");
+        context.insert("roles", &vec!["editor", "admin"]);
+        let tests = [
+            ("{% if path is starting_with(\"/admin\") %}yes{% endif %}", "yes"),
+            ("{% if path is not ending_with(\"/users\") %}yes{% else %}no{% endif %}", "no"),
+            ("{% if path is containing(\"dmi\") %}yes{% endif %}", "yes"),
+            ("{% if roles is containing(\"admin\") %}yes{% endif %}", "yes"),
+            ("{% if roles is containing(\"owner\") %}yes{% else %}no{% endif %}", "no"),
+            ("{% if path is matching(\"^/admin/[a-z]+$\") %}yes{% endif %}", "yes"),
+            ("{% if path is not matching(\"^/blog\") %}yes{% else %}no{% endif %}", "yes"),
+        ];
+
+        for &(input, expected) in &tests {
+            assert_eq!(render_template(input, context.clone()).unwrap(), expected.to_owned());
+        }
+    }
+
+    #[test]
+    fn test_render_if_simple() {
+        let mut context = Context::new();
+        context.insert("is_admin", &true);
+        let result = render_template("{% if is_admin %}Admin{% endif %}", context);
+
+        assert_eq!(result.unwrap(), "Admin".to_owned());
+    }
+
+    #[test]
+    fn test_render_elifs_only_first_true_branch() {
+        let mut context = Context::new();
+        context.insert("number", &5);
+        let tests = vec![
+            ("{% if number > 10 %}big{% elif number > 3 %}medium{% elif number > 1 %}small{% else %}tiny{% endif %}", "medium"),
+            ("{% if number > 1 %}a{% elif number > 3 %}b{% endif %}", "a"),
+            ("{% if number > 10 %}a{% elif number > 8 %}b{% elif number == 5 %}c{% endif %}", "c"),
+            ("{% if number > 10 %}a{% elif number > 8 %}b{% else %}c{% endif %}", "c"),
+            ("{% if number > 10 %}a{% elif number > 8 %}b{% endif %}", ""),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(render_template(input, context.clone()).unwrap(), expected.to_string());
+        }
+    }
+
+    #[test]
+    fn test_render_negated_test() {
+        let mut context = Context::new();
+        context.insert("number", &5);
+        let result = render_template(
+            "{% if number is not even %}odd{% endif %}-{% if missing is not defined %}missing{% endif %}",
+            context
+        );
+
+        assert_eq!(result.unwrap(), "odd-missing".to_owned());
+    }
+
+    #[test]
+    fn test_render_negated_test_error_bubbles_up() {
+        let result = render_template("{% if number is not odd %}odd{% endif %}", Context::new());
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_render_custom_tester() {
+        let mut tera = Tera::default();
+        tera.register_tester("shippable", |value: Option<Value>, params: Vec<Value>| {
+            let max_weight = params.first().and_then(|v| v.as_u64()).unwrap_or(10);
+            match value.and_then(|v| v.pointer("/weight").and_then(|w| w.as_u64())) {
+                Some(weight) => Ok(weight <= max_weight),
+                None => bail!("Tester `shippable` needs an object with a weight"),
+            }
+        });
+        tera.add_raw_template(
+            "tpl",
+            "{% if order is shippable %}ok{% endif %}-{% if order is not shippable(2) %}too heavy{% endif %}"
+        ).unwrap();
+
+        let mut order = HashMap::new();
+        order.insert("weight", 5);
+        let mut context = Context::new();
+        context.insert("order", &order);
+
+        assert_eq!(tera.render("tpl", context).unwrap(), "ok-too heavy".to_owned());
+    }
+
+    #[test]
+    fn test_render_global_fn_range_in_for() {
+        let result = render_template(
+            "{% for i in range(end=3) %}{{ i }}{% endfor %}|{% for i in range(start=1, end=10, step=4) %}{{ i }}{% endfor %}",
+            Context::new()
+        );
+
+        assert_eq!(result.unwrap(), "012|159".to_owned());
+    }
+
+    #[test]
+    fn test_render_global_fn_with_variable_args() {
+        let mut context = Context::new();
+        context.insert("count", &2);
+        let result = render_template("{% for i in range(end=count+1) %}{{ i }}{% endfor %}-{% set r = range(end=count) %}{{ r | join(sep=\",\") }}", context);
+
+        assert_eq!(result.unwrap(), "012-0,1".to_owned());
+    }
+
+    #[test]
+    fn test_render_custom_global_fn() {
+        let mut tera = Tera::default();
+        tera.register_function("url_for", |args: HashMap<String, Value>| {
+            match args.get("name").and_then(|n| n.as_str()) {
+                Some(name) => Ok(Value::String(format!("/{}/", name))),
+                None => bail!("Function `url_for` needs a `name` argument"),
+            }
+        });
+        tera.add_raw_template(
+            "tpl",
+            "{{ url_for(name=\"about\") }}{% if url_for(name=\"home\") %} ok{% endif %}"
+        ).unwrap();
+
+        assert_eq!(tera.render("tpl", Context::new()).unwrap(), "/about/ok".to_owned());
+        tera.add_raw_template("error", "{{ url_for() }}").unwrap();
+        assert!(tera.render("error", Context::new()).is_err());
+    }
+
+    #[test]
+    fn test_render_unknown_global_fn() {
+        let result = render_template("{{ hello(name=1) }}", Context::new());
+
+        let err = result.unwrap_err();
+        assert_eq!(
+            err.iter().nth(1).unwrap().to_string(),
+            "Failed to call function `hello` in template \"hello\" at line 1, column 4"
+        );
+        assert_eq!(err.iter().nth(2).unwrap().to_string(), "Global function 'hello' not found");
+    }
+
+    #[test]
+    fn test_render_for_on_global_fn_not_returning_array() {
+        let result = render_template("{% for i in now() %}{{ i }}{% endfor %}", Context::new());
+
+        assert_eq!(
+            result.unwrap_err().iter().nth(1).unwrap().to_string(),
+            "Tried to iterate on the result of function `now`, but it isn't an array"
+        );
+    }
+
+    #[test]
+    fn test_render_error_location_in_macro_and_base_template() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            ("macros", "{% macro hello() %}\n{{ greeting }}{% endmacro hello %}"),
+            ("base", "{% import \"macros\" as macros %}{% if show_macro %}{{ macros::hello() }}{% endif %}\n{% block content %}{% endblock content %}\n{{ footer }}"),
+            ("child", "{% extends \"base\" %}{% block content %}{% endblock content %}"),
+        ]).unwrap();
+
+        let mut context = Context::new();
+        context.insert("show_macro", &true);
+        let result = tera.render("child", context);
+        assert_eq!(
+            result.unwrap_err().iter().nth(1).unwrap().to_string(),
+            "Field `greeting` not found in context in template \"macros\" at line 2, column 4"
+        );
+
+        let result = tera.render("child", Context::new());
+        assert_eq!(
+            result.unwrap_err().iter().nth(1).unwrap().to_string(),
+            "Field `footer` not found in context in template \"base\" at line 3, column 4"
+        );
+    }
+
+    #[test]
+    fn test_render_for_key_value() {
+        let mut context = Context::new();
+        let mut settings = HashMap::new();
+        settings.insert("theme", "dark");
+        settings.insert("lang", "en");
+        context.insert("settings", &settings);
+        let result = render_template(
+            "{% for key, value in settings %}{{ loop.index }}.{{ key }}={{ value }} {% endfor %}",
+            context
+        );
+
+        // objects are iterated in key order
+        assert_eq!(result.unwrap(), "1.lang=en 2.theme=dark".to_owned());
+    }
+
+    #[test]
+    fn test_render_for_key_value_on_group_by() {
+        let mut context = Context::new();
+        let products: Vec<Value> = ::serde_json::from_str(r#"[
+            {"name": "apple", "category": "fruit"},
+            {"name": "leek", "category": "vegetable"},
+            {"name": "pear", "category": "fruit"}
+        ]"#).unwrap();
+        context.insert("products", &products);
+        let result = render_template(
+            "{% set groups = products | group_by(attribute=\"category\") %}\
+            {% for category, items in groups %}{{ category }}: {% for item in items %}{{ item.name }} {% endfor %}\n{% endfor %}",
+            context
+        );
+
+        assert_eq!(result.unwrap(), "fruit: apple pear\nvegetable: leek".to_owned());
+    }
+
+    #[test]
+    fn test_error_for_key_value_on_non_object() {
+        let mut context = Context::new();
+        context.insert("data", &vec![1, 2]);
+        context.insert("name", &"Bob");
+        let tests = vec![
+            (
+                "{% for k, v in data %}{% endfor %}",
+                "Tried to iterate on variable `data` with `k, v`, but it isn't an object or an array of `[key, value]` pairs. Iterate on other arrays with a single variable"
+            ),
+            (
+                "{% for k, v in name %}{% endfor %}",
+                "Tried to iterate on variable `name` with `k, v`, but it isn't an object or an array of `[key, value]` pairs. Iterate on other arrays with a single variable"
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let result = render_template(input, context.clone());
+            assert!(result.is_err());
+            assert_eq!(result.unwrap_err().iter().nth(1).unwrap().to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn test_render_for_key_value_on_pairs() {
+        let mut context = Context::new();
+        let mut scores = HashMap::new();
+        scores.insert("bob", 2);
+        scores.insert("alice", 3);
+        scores.insert("carol", 1);
+        context.insert("scores", &scores);
+        let result = render_template(
+            "{% for name, score in scores | dictsort(by=\"value\", reverse=true) %}{{ loop.index }}.{{ name }}={{ score }} {% endfor %}\
+            {% for a, b in [[\"x\", 1], [\"y\", [2]]] %}{{ a }}{{ b }}{% endfor %}",
+            context
+        );
+
+        assert_eq!(result.unwrap(), "1.alice=3 2.bob=2 3.carol=1x1y[2, ]".to_owned());
+    }
+
+    #[test]
+    fn test_render_for_on_array_literal() {
+        let mut context = Context::new();
+        context.insert("name", &"Bob");
+        let result = render_template(
+            "{% for item in [\"a\", 1, name, [2]] %}{{ item }}{% if not loop.last %},{% endif %}{% endfor %}",
+            context
+        );
+
+        assert_eq!(result.unwrap(), "a,1,Bob,[2, ]".to_owned());
+    }
+
+    #[test]
+    fn test_render_for_break() {
+        let mut context = Context::new();
+        context.insert("numbers", &vec![1, 2, 3, 4]);
+        let result = render_template(
+            "{% for n in numbers %}{{ n }}{% if n == 2 %}{% break %}{% endif %}!{% endfor %}{{ numbers | length }}",
+            context
+        );
+
+        assert_eq!(result.unwrap(), "1!24".to_owned());
+    }
+
+    #[test]
+    fn test_render_for_continue() {
+        let mut context = Context::new();
+        context.insert("numbers", &vec![1, 2, 3]);
+        let result = render_template(
+            "{% for n in numbers %}{% if n == 2 %}{% continue %}{% endif %}{{ loop.index }}:{{ n }}{% if loop.last %}.{% endif %} {% endfor %}",
+            context
+        );
+
+        assert_eq!(result.unwrap(), "1:1 3:3.".to_owned());
+    }
+
+    #[test]
+    fn test_render_break_only_stops_innermost_loop() {
+        let mut context = Context::new();
+        context.insert("numbers", &vec![1, 2, 3]);
+        let result = render_template(
+            "{% for a in numbers %}{% for b in numbers %}{% if b > a %}{% break %}{% endif %}{{ b }}{% endfor %} {% endfor %}",
+            context
+        );
+
+        assert_eq!(result.unwrap(), "1 12 123".to_owned());
+    }
+
+    #[test]
+    fn test_render_spaceless() {
+        let mut context = Context::new();
+        context.insert("items", &vec!["a", "b"]);
+        let template = "<ul>\n  {% for item in items %}<li> <b>{{ item }}</b> text</li>\n  {% endfor %}\n</ul>";
+        let without = render_template(template, context.clone());
+        assert_eq!(without.unwrap(), "<ul>\n  <li> <b>a</b> text</li>\n  <li> <b>b</b> text</li>\n</ul>".to_owned());
+
+        let with = render_template(&format!("{{% spaceless %}}{}{{% endspaceless %}}", template), context);
+        assert_eq!(with.unwrap(), "<ul><li><b>a</b> text</li><li><b>b</b> text</li></ul>".to_owned());
+    }
+
+    #[test]
+    fn test_render_spaceless_keeps_pre() {
+        let result = render_template(
+            "{% spaceless %}<div>\n  <pre>\n  <b>x</b>  <i>y</i>\n</pre>\n</div>{% endspaceless %}",
+            Context::new()
+        );
+        assert_eq!(result.unwrap(), "<div><pre>\n  <b>x</b>  <i>y</i>\n</pre></div>".to_owned());
+    }
+
+    #[test]
+    fn test_render_literals_as_args() {
+        let mut tera = Tera::default();
+        tera.register_function("get", |args: HashMap<String, Value>| {
+            let key = args.get("key").and_then(|k| k.as_str()).unwrap_or("").to_string();
+            Ok(args.get("opts").and_then(|o| o.get(&key)).cloned().unwrap_or(Value::Null))
+        });
+        tera.add_raw_templates(vec![
+            ("macros", "{% macro menu(items) %}{% for i in items %}<{{ i }}>{% endfor %}{% endmacro menu %}"),
+            ("tpl", "{% import \"macros\" as macros %}{{ macros::menu(items=[\"home\", \"about\"]) }}\
+            {% set b = get(key=\"b\", opts={\"a\": 1, \"b\": {\"c\": 2}}) %}{{ b | json_encode }}"),
+        ]).unwrap();
+
+        assert_eq!(tera.render("tpl", Context::new()).unwrap(), "<home><about>{\"c\":2}".to_owned());
+    }
+
+    #[test]
+    fn test_render_if_or_conditions() {
+        let mut context = Context::new();
+        context.insert("is_adult", &false);
+        context.insert("age", &18);
+        let result = render_template("{% if is_adult or age + 1 > 18 %}Adult{% endif %}", context);
+
         assert_eq!(result.unwrap(), "Adult".to_owned());
     }
 
     #[test]
-    fn test_render_if_and_conditions_with_equality() {
+    fn test_render_if_and_conditions_with_equality() {
+        let mut context = Context::new();
+        context.insert("is_adult", &true);
+        context.insert("age", &18);
+        let result = render_template("{% if is_adult and age == 18 %}Adult{% endif %}", context);
+
+        assert_eq!(result.unwrap(), "Adult".to_owned());
+    }
+
+    #[test]
+    fn test_render_if_compares_ints_and_floats_by_value() {
+        let mut context = Context::new();
+        context.insert("count", &1.0);
+        context.insert("label", &"1");
+        let tests = [
+            ("{% if count == 1 %}yes{% endif %}", "yes"),
+            ("{% if 1 == count %}yes{% endif %}", "yes"),
+            ("{% if count != 1 %}yes{% else %}no{% endif %}", "no"),
+            ("{% if count > 0 %}yes{% endif %}", "yes"),
+            ("{% if count > 1 %}yes{% else %}no{% endif %}", "no"),
+            ("{% if count < 2 %}yes{% endif %}", "yes"),
+            ("{% if count < 1 %}yes{% else %}no{% endif %}", "no"),
+            ("{% if count >= 1 and count <= 1 %}yes{% endif %}", "yes"),
+            // numbers and strings are never equal
+            ("{% if label == 1 %}yes{% else %}no{% endif %}", "no"),
+            ("{% if label != count %}yes{% endif %}", "yes"),
+        ];
+
+        for &(input, expected) in &tests {
+            assert_eq!(render_template(input, context.clone()).unwrap(), expected.to_owned());
+        }
+    }
+
+    #[test]
+    fn test_render_if_compares_strings() {
+        let mut context = Context::new();
+        context.insert("a", &"apple");
+        context.insert("b", &"banana");
+        let tests = [
+            ("{% if a < b %}yes{% else %}no{% endif %}", "yes"),
+            ("{% if b < a %}yes{% else %}no{% endif %}", "no"),
+            ("{% if b > a %}yes{% else %}no{% endif %}", "yes"),
+            ("{% if a > b %}yes{% else %}no{% endif %}", "no"),
+            ("{% if a <= \"apple\" and a >= \"apple\" %}yes{% else %}no{% endif %}", "yes"),
+            ("{% if b <= a %}yes{% else %}no{% endif %}", "no"),
+            ("{% if a >= b %}yes{% else %}no{% endif %}", "no"),
+            // uppercase letters come before lowercase ones
+            ("{% if \"Zebra\" < a %}yes{% else %}no{% endif %}", "yes"),
+            ("{% if a ~ \"s\" > a %}yes{% else %}no{% endif %}", "yes"),
+        ];
+
+        for &(input, expected) in &tests {
+            assert_eq!(render_template(input, context.clone()).unwrap(), expected.to_owned());
+        }
+    }
+
+    #[test]
+    fn test_error_compare_string_with_number() {
+        let mut context = Context::new();
+        context.insert("a", &"apple");
+        let result = render_template("{% if a < 1 %}yes{% endif %}", context.clone());
+        assert_eq!(
+            result.unwrap_err().iter().nth(1).unwrap().to_string(),
+            "Tried to compare \"apple\" with 1 using `<` but only two strings or two numbers can be compared"
+        );
+
+        let result = render_template("{% if 2 >= a %}yes{% endif %}", context);
+        assert_eq!(
+            result.unwrap_err().iter().nth(1).unwrap().to_string(),
+            "Tried to compare 2 with \"apple\" using `>=` but only two strings or two numbers can be compared"
+        );
+    }
+
+    #[test]
+    fn test_error_compare_evaluates_each_side_once() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let mut tera = Tera::default();
+        tera.register_function("count", move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Ok(Value::Bool(true))
+        });
+        tera.add_raw_template("tpl", "{% if count() > 1 %}yes{% endif %}").unwrap();
+        let result = tera.render("tpl", Context::new());
+
+        assert_eq!(
+            result.unwrap_err().iter().nth(1).unwrap().to_string(),
+            "Tried to compare true with 1 using `>` but only two strings or two numbers can be compared"
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_render_inline_if() {
+        let mut context = Context::new();
+        context.insert("is_current", &true);
+        context.insert("is_hidden", &false);
+        context.insert("count", &2);
+        context.insert("name", &"Bob");
+        let tests = [
+            ("{{ \"active\" if is_current else \"\" }}", "active"),
+            ("{{ \"hidden\" if is_hidden else \"shown\" }}", "shown"),
+            ("<li class=\"{{ \"hidden\" if is_hidden }}\">", "<li class=\"\">"),
+            ("{{ \"yes\" if is_hidden or is_current and count > 1 else \"no\" }}", "yes"),
+            ("{{ \"one\" if count == 1 else \"two\" if count == 2 else \"many\" }}", "two"),
+            ("{{ name if name is defined else \"Anonymous\" }}", "Bob"),
+            ("{{ user if user is defined else \"Anonymous\" }}", "Anonymous"),
+            ("{{ (count if is_current else 0) * 3 }}", "6"),
+            ("{% set label = \"item\" ~ (\"s\" if count > 1) %}{{ label }}", "items"),
+            ("{% if (count if is_current) %}yes{% endif %}", "yes"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(render_template(input, context.clone()).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_render_inline_if_only_evaluates_chosen_branch() {
+        let mut context = Context::new();
+        context.insert("items", &Vec::<i64>::new());
+        let result = render_template("{{ \"none\" if items | length == 0 else items | first }}", context);
+        assert_eq!(result.unwrap(), "none");
+    }
+
+    #[test]
+    fn test_error_inline_if_in_math() {
+        let mut context = Context::new();
+        context.insert("a", &true);
+        let result = render_template("{{ (\"a\" if a) + 1 }}", context);
+        assert_eq!(
+            result.unwrap_err().iter().nth(1).unwrap().to_string(),
+            "An inline `if` was used in a math operation but didn't return a number"
+        );
+    }
+
+    #[test]
+    fn test_render_grouped_conditions() {
+        let mut context = Context::new();
+        context.insert("a", &true);
+        context.insert("b", &true);
+        context.insert("c", &false);
+        // `a or b and c` is `a or (b and c)`
+        let tests = [
+            ("{% if a or b and c %}yes{% else %}no{% endif %}", "yes"),
+            ("{% if (a or b) and c %}yes{% else %}no{% endif %}", "no"),
+            ("{% if not (c or a) %}yes{% else %}no{% endif %}", "no"),
+            ("{% if not c or a %}yes{% else %}no{% endif %}", "yes"),
+            ("{{ (1 + 2) * 3 }}", "9"),
+            ("{{ 1 + 2 * 3 }}", "7"),
+            ("{{ -(2 - 5) }}", "3"),
+            ("{% set x = (1 + 1) * 2 %}{{ x }}", "4"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(render_template(input, context.clone()).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_render_whitespace_control_for() {
+        let mut context = Context::new();
+        context.insert("data", &vec![1, 2, 3]);
+        let without = render_template(
+            "<ul>\n  {% for i in data %}\n  <li>{{ i }}</li>\n  {% endfor %}\n</ul>",
+            context.clone()
+        );
+        assert_eq!(without.unwrap(), "<ul>\n  <li>1</li>\n  <li>2</li>\n  <li>3</li>\n</ul>".to_owned());
+
+        let with = render_template(
+            "<ul>\n  {%- for i in data -%}\n  <li>{{ i }}</li>\n  {%- endfor -%}\n</ul>",
+            context
+        );
+        assert_eq!(with.unwrap(), "<ul><li>1</li><li>2</li><li>3</li></ul>".to_owned());
+    }
+
+    #[test]
+    fn test_render_whitespace_control_if() {
+        let mut context = Context::new();
+        context.insert("admin", &true);
+        let without = render_template("Hello \n{% if admin %} admin {% endif %}\n!", context.clone());
+        assert_eq!(without.unwrap(), "Hello \nadmin\n!".to_owned());
+
+        let with = render_template("Hello \n{%- if admin %} admin {% endif -%}\n!", context);
+        assert_eq!(with.unwrap(), "Helloadmin!".to_owned());
+    }
+
+    #[test]
+    fn test_render_whitespace_control_adjacent_tags() {
+        let mut context = Context::new();
+        context.insert("name", &"bob");
+        // the text between the two tags is trimmed from both sides and disappears
+        let result = render_template("{{ name -}}   \n   {{- name }} {#- comment -#} !", context);
+        assert_eq!(result.unwrap(), "bobbob!".to_owned());
+    }
+
+    #[test]
+    fn test_render_basic_for() {
+        let mut context = Context::new();
+        context.insert("data", &vec![1,2,3]);
+        let result = render_template("{% for i in data %}{{i}}{% endfor %}", context);
+
+        assert_eq!(result.unwrap(), "123".to_owned());
+    }
+
+    #[test]
+    fn test_render_loop_variables() {
+        let mut context = Context::new();
+        context.insert("data", &vec![1,2,3]);
+        let result = render_template(
+            "{% for i in data %}{{loop.index}}{{loop.index0}}{{loop.first}}{{loop.last}}{% endfor %}",
+            context
+        );
+
+        assert_eq!(result.unwrap(), "10truefalse21falsefalse32falsetrue".to_owned());
+    }
+
+    #[test]
+    fn test_render_loop_length() {
         let mut context = Context::new();
-        context.add("is_adult", &true);
-        context.add("age", &18);
-        let result = render_template("{% if is_adult and age == 18 %}Adult{% endif %}", context);
+        context.insert("data", &vec![1,2,3]);
+        let result = render_template(
+            "{% for i in data %}{{ loop.index }}/{{ loop.length }} {% endfor %}",
+            context
+        );
 
-        assert_eq!(result.unwrap(), "Adult".to_owned());
+        assert_eq!(result.unwrap(), "1/3 2/3 3/3".to_owned());
     }
 
     #[test]
-    fn test_render_basic_for() {
+    fn test_render_nested_loop_variables() {
         let mut context = Context::new();
-        context.add("data", &vec![1,2,3]);
-        let result = render_template("{% for i in data %}{{i}}{% endfor %}", context);
+        context.insert("vectors", &vec![vec![0, 3], vec![1, 4, 7]]);
+        let result = render_template(
+            "{% for vector in vectors %}{{ loop.index }}:{% for j in vector %}{{ loop.index }}{{ loop.length }}{{ loop.last }}{% endfor %}{{ loop.first }} {% endfor %}",
+            context
+        );
 
-        assert_eq!(result.unwrap(), "123".to_owned());
+        assert_eq!(result.unwrap(), "1:12false22truetrue 2:13false23false33truefalse".to_owned());
     }
 
     #[test]
-    fn test_render_loop_variables() {
+    fn test_render_loop_variable_name_starting_with_loop() {
         let mut context = Context::new();
-        context.add("data", &vec![1,2,3]);
+        context.insert("data", &vec![1,2,3]);
         let result = render_template(
-            "{% for i in data %}{{loop.index}}{{loop.index0}}{{loop.first}}{{loop.last}}{% endfor %}",
+            "{% for l in data %}{{ loop.index }}{{ l }}{% endfor %}",
             context
         );
 
-        assert_eq!(result.unwrap(), "10truefalse21falsefalse32falsetrue".to_owned());
+        assert_eq!(result.unwrap(), "112233".to_owned());
     }
 
     #[test]
     fn test_render_nested_loop_simple() {
         let mut context = Context::new();
-        context.add("vectors", &vec![vec![0, 3, 6], vec![1, 4, 7]]);
+        context.insert("vectors", &vec![vec![0, 3, 6], vec![1, 4, 7]]);
         let result = render_template(
             "{% for vector in vectors %}{% for j in vector %}{{ j }}{% endfor %}{% endfor %}",
             context
@@ -795,7 +2091,7 @@ mod tests {
     #[test]
     fn test_render_nested_loop_with_empty_vec() {
         let mut context = Context::new();
-        context.add("vectors", &vec![vec![0, 3, 6], vec![], vec![1, 4, 7]]);
+        context.insert("vectors", &vec![vec![0, 3, 6], vec![], vec![1, 4, 7]]);
         let result = render_template(
             "{% for vector in vectors %}{% for j in vector %}{{ j }}{% endfor %}{% endfor %}",
             context
@@ -807,7 +2103,7 @@ mod tests {
     #[test]
     fn test_render_filter() {
         let mut context = Context::new();
-        context.add("greeting", &"hello");
+        context.insert("greeting", &"hello");
         let result = render_template(
             "{{ greeting | upper }}",
             context
@@ -816,11 +2112,310 @@ mod tests {
         assert_eq!(result.unwrap(), "HELLO".to_owned());
     }
 
+    #[test]
+    fn test_render_regex_filters() {
+        let mut context = Context::new();
+        context.insert("input", &"123");
+        context.insert("text", &"Hello #tera and #rust");
+        let result = render_template(
+            r##"{% if input | matches(pattern="^\d+$") %}digits{% endif %} {{ text | findall(pattern="#\w+") | join(sep=", ") }}"##,
+            context
+        );
+
+        assert_eq!(result.unwrap(), "digits #tera, #rust".to_owned());
+    }
+
+    #[test]
+    fn test_render_json_encode_in_autoescaped_template() {
+        let mut tera = Tera::default();
+        tera.add_raw_template("page.html", "{{ users | json_encode }}|{{ users | json_encode | safe }}").unwrap();
+        let mut context = Context::new();
+        context.insert("users", &vec!["Bob"]);
+
+        assert_eq!(
+            tera.render("page.html", context).unwrap(),
+            "[&quot;Bob&quot;]|[\"Bob\"]".to_owned()
+        );
+    }
+
+    #[test]
+    fn test_render_default_filter_undefined_variable() {
+        let result = render_template(
+            "{{ name | default(value=\"Anonymous\") | upper }}",
+            Context::new()
+        );
+
+        assert_eq!(result.unwrap(), "ANONYMOUS".to_owned());
+    }
+
+    #[test]
+    fn test_render_default_filter_falsy_value() {
+        let mut context = Context::new();
+        context.insert("name", &"");
+        let result = render_template(
+            "{{ name | default(value=\"Anonymous\", boolean=true) }}",
+            context
+        );
+
+        assert_eq!(result.unwrap(), "Anonymous".to_owned());
+    }
+
+    #[test]
+    fn test_render_undefined_variable_errors_if_default_not_first() {
+        let result = render_template(
+            "{{ name | upper | default(value=\"Anonymous\") }}",
+            Context::new()
+        );
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_render_set() {
+        let mut context = Context::new();
+        context.insert("name", &"bob");
+        let result = render_template(
+            "{% set greeting = name | capitalize %}Hello {{ greeting }}",
+            context
+        );
+
+        assert_eq!(result.unwrap(), "Hello Bob".to_owned());
+    }
+
+    #[test]
+    fn test_render_set_shadows_context() {
+        let mut context = Context::new();
+        context.insert("name", &"bob");
+        let result = render_template(
+            "{{ name }} {% set name = \"alice\" %}{{ name }}",
+            context
+        );
+
+        assert_eq!(result.unwrap(), "bob alice".to_owned());
+    }
+
+    #[test]
+    fn test_render_set_math_and_logic() {
+        let mut context = Context::new();
+        context.insert("price", &10);
+        let result = render_template(
+            "{% set total = price * 2 %}{% set expensive = total > 15 %}{% if expensive %}{{ total }}{% endif %}",
+            context
+        );
+
+        assert_eq!(result.unwrap(), "20".to_owned());
+    }
+
+    #[test]
+    fn test_render_set_in_for_does_not_leak() {
+        let mut context = Context::new();
+        context.insert("data", &vec![1, 2, 3]);
+        let result = render_template(
+            "{% for i in data %}{% set double = i * 2 %}{{ double }}{% endfor %}{{ double }}",
+            context
+        );
+
+        assert!(result.is_err());
+
+        let mut context = Context::new();
+        context.insert("data", &vec![1, 2, 3]);
+        context.insert("double", &0);
+        let result = render_template(
+            "{% for i in data %}{% set double = i * 2 %}{{ double }}{% endfor %}{{ double }}",
+            context
+        );
+
+        assert_eq!(result.unwrap(), "2460".to_owned());
+    }
+
+    #[test]
+    fn test_render_set_in_for_visible_in_nested_loop() {
+        let mut context = Context::new();
+        context.insert("data", &vec![1, 2]);
+        let result = render_template(
+            "{% for i in data %}{% set outer = i %}{% for j in data %}{{ outer }}{{ j }}{% endfor %}{% endfor %}",
+            context
+        );
+
+        assert_eq!(result.unwrap(), "11122122".to_owned());
+    }
+
+    #[test]
+    fn test_render_set_in_macro_does_not_leak() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            ("macros", "{% macro hello(name) %}{% set greeting = \"Hello \" %}{{ greeting }}{{ name }}{% endmacro hello %}"),
+            ("tpl", "{% import \"macros\" as macros %}{{ macros::hello(name=\"Bob\") }}{{ greeting }}"),
+        ]).unwrap();
+
+        let mut context = Context::new();
+        context.insert("greeting", &"!");
+        let result = tera.render("tpl", context);
+
+        assert_eq!(result.unwrap(), "Hello Bob!".to_owned());
+    }
+
+    #[test]
+    fn test_render_set_in_block_does_not_leak() {
+        let tests = vec![
+            ("{% block c %}{% set x = 1 %}{{ x }}{% endblock c %}[{{ x }}]", "1[0]"),
+            ("{% block c %}{% set x %}a{% endset %}{{ x }}{% endblock c %}[{{ x }}]", "a[0]"),
+            // nested blocks each have their own scope
+            ("{% block a %}{% set x = 1 %}{% block b %}{% set x = 2 %}{{ x }}{% endblock b %}{{ x }}{% endblock a %}{{ x }}", "210"),
+            // visible in the for loops of the block
+            ("{% block c %}{% set x = 1 %}{% for i in items %}{{ x }}{% endfor %}{% endblock c %}", "11"),
+            // a block in a for loop shadows the loop until it ends
+            ("{% for i in items %}{% block c %}{% set i = 5 %}{{ i }}{% endblock c %}{{ i }}{% endfor %}", "5152"),
+        ];
+        for (input, expected) in tests {
+            let mut context = Context::new();
+            context.insert("x", &0);
+            context.insert("items", &vec![1, 2]);
+            assert_eq!(render_template(input, context).unwrap(), expected.to_owned(), "{}", input);
+        }
+
+        let result = render_template("{% block c %}{% set x = 1 %}{% endblock c %}[{{ x }}]", Context::new());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_render_set_in_child_block_does_not_leak() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            ("base", "{% block a %}{% endblock a %}[{{ x | default(value=\"none\") }}]{% block b %}{% endblock b %}"),
+            ("child", "{% extends \"base\" %}{% block a %}{% set x = 1 %}{{ x }}{% endblock a %}\
+                       {% block b %}{{ x | default(value=\"none\") }}{% endblock b %}"),
+        ]).unwrap();
+        let result = tera.render("child", Context::new());
+
+        assert_eq!(result.unwrap(), "1[none]none".to_owned());
+    }
+
+    #[test]
+    fn test_render_same_as_render_to() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            ("macros", "{% macro hello(name) %}Hello {{ name }}{% endmacro hello %}"),
+            ("included", "Included {{ name }}"),
+            ("variables", "{{ name }} {{ items | length }} {{ items.0 + 1 }}\n"),
+            ("tags", "{% import \"macros\" as macros %}{% for i in items %}{{ i }}{% endfor %}\
+                      {% if name %}{{ macros::hello(name=name) }}{% endif %}{% include \"included\" %}\
+                      {% set a %}{{ name }}{% endset %}{{ a }}{% raw %}{{ raw }}{% endraw %}\r\n"),
+            ("empty", ""),
+        ]).unwrap();
+        let mut context = Context::new();
+        context.insert("name", &"Bob");
+        context.insert("items", &vec![1, 2]);
+
+        for &keep_trailing_newline in &[true, false] {
+            tera.set_keep_trailing_newline(keep_trailing_newline);
+            for name in &["variables", "tags", "empty"] {
+                let template = tera.get_template(name).unwrap();
+                let rendered = Renderer::new(template, &tera, context.as_json().unwrap()).render().unwrap();
+                let mut written = Vec::new();
+                Renderer::new(template, &tera, context.as_json().unwrap()).render_to(&mut written).unwrap();
+                assert_eq!(rendered, String::from_utf8(written).unwrap());
+            }
+        }
+        assert_eq!(tera.render("variables", context).unwrap(), "Bob 2 2");
+    }
+
+    #[test]
+    fn test_render_set_block() {
+        let mut context = Context::new();
+        context.insert("name", &"bob");
+        context.insert("items", &vec![1, 2, 3]);
+        let tests = vec![
+            ("{% set greeting %}Hello {{ name | capitalize }}{% endset %}{{ greeting }}!", "Hello Bob!"),
+            ("{% set greeting %}Hello{% endset %}{{ greeting | length }}", "5"),
+            ("{% set list %}{% for i in items %}{{ i }},{% endfor %}{% endset %}[{{ list }}]", "[1,2,3,]"),
+            // the body is kept as it is, whitespace included
+            ("{% set a %} x {% endset %}[{{ a }}]", "[ x ]"),
+            ("{% set a -%} x {%- endset %}[{{ a }}]", "[x]"),
+            // nothing is rendered where the set is
+            ("a{% set b %}c{% endset %}d", "ad"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(render_template(input, context.clone()).unwrap(), expected.to_owned());
+        }
+    }
+
+    #[test]
+    fn test_render_set_block_in_for_does_not_leak() {
+        let mut context = Context::new();
+        context.insert("data", &vec![1, 2]);
+        context.insert("label", &"none");
+        let result = render_template(
+            "{% for i in data %}{% set label %}#{{ i }}{% endset %}{{ label }}{% endfor %}{{ label }}",
+            context
+        );
+
+        assert_eq!(result.unwrap(), "#1#2none".to_owned());
+    }
+
+    #[test]
+    fn test_render_set_block_autoescaped() {
+        let mut tera = Tera::default();
+        tera.add_raw_template(
+            "hello.html",
+            "{% set greeting %}<b>{{ name }}</b>{% endset %}{{ greeting | safe }}|{{ greeting }}"
+        ).unwrap();
+        let mut context = Context::new();
+        context.insert("name", &"<bob>");
+        let result = tera.render("hello.html", context);
+
+        assert_eq!(result.unwrap(), "<b>&lt;bob&gt;</b>|<b>&lt;bob&gt;</b>".to_owned());
+    }
+
+    #[test]
+    fn test_render_set_autoescaped_once() {
+        let mut tera = Tera::default();
+        tera.add_raw_template("hello.html", "{% set a = name %}{% set b = name | upper %}{{ a }}|{{ b }}|{{ a | safe }}").unwrap();
+        let mut context = Context::new();
+        context.insert("name", &"<bob>");
+
+        assert_eq!(tera.render("hello.html", context).unwrap(), "&lt;bob&gt;|&lt;BOB&gt;|<bob>".to_owned());
+    }
+
+    #[test]
+    fn test_render_set_block_autoescaped_only_when_captured() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            // the value of the context is printed again after the loop
+            ("loop.html", "{% for i in items %}{% set a %}<b>{{ i }}</b>{% endset %}{{ a }}{% endfor %}{{ a }}"),
+            // a plain set replaces the captured value
+            ("replaced.html", "{% set a %}<b>{% endset %}{% set a = \"<i>\" %}{{ a }}"),
+            // in a macro
+            ("macros.html", "{% macro m(name) %}{% set a %}<b>{{ name }}</b>{% endset %}{{ a }}{% endmacro m %}"),
+            ("macro.html", "{% import \"macros.html\" as macros %}{{ macros::m(name=\"<bob>\") }}|{{ a }}"),
+            // in a block
+            ("block.html", "{% block c %}{% set a %}<b>{{ name }}</b>{% endset %}{{ a }}{% endblock c %}|{{ a }}"),
+            // without autoescaping nothing is escaped
+            ("plain.txt", "{% set a %}<b>{{ name }}</b>{% endset %}{{ a }}"),
+        ]).unwrap();
+        let mut context = Context::new();
+        context.insert("name", &"<bob>");
+        context.insert("a", &"<i>");
+        context.insert("items", &vec![1]);
+
+        let tests = vec![
+            ("loop.html", "<b>1</b>&lt;i&gt;"),
+            ("replaced.html", "&lt;i&gt;"),
+            ("macro.html", "<b>&lt;bob&gt;</b>|&lt;i&gt;"),
+            ("block.html", "<b>&lt;bob&gt;</b>|&lt;i&gt;"),
+            ("plain.txt", "<b><bob></b>"),
+        ];
+        for (name, expected) in tests {
+            assert_eq!(tera.render(name, context.clone()).unwrap(), expected.to_owned(), "{}", name);
+        }
+    }
+
     #[test]
     fn test_render_index_array() {
         let mut context = Context::new();
-        context.add("my_arr", &vec![1, 2, 3]);
-        context.add("my_arr2", &vec![(1,2,3), (1,2,3), (1,2,3)]);
+        context.insert("my_arr", &vec![1, 2, 3]);
+        context.insert("my_arr2", &vec![(1,2,3), (1,2,3), (1,2,3)]);
         let result = render_template(
             "{{ my_arr.1 }}{{ my_arr2.1.1 }}",
             context
@@ -833,8 +2428,8 @@ mod tests {
     #[test]
     fn test_render_if_in_for() {
         let mut context = Context::new();
-        context.add("sel", &2u32);
-        context.add("seq", &vec![1,2,3]);
+        context.insert("sel", &2u32);
+        context.insert("seq", &vec![1,2,3]);
         let result = render_template(
             "{% for val in seq %} {% if val == sel %} on {% else %} off {% endif %} {% endfor %}",
             context
@@ -845,7 +2440,7 @@ mod tests {
     #[test]
     fn test_autoescape_html() {
         let mut context = Context::new();
-        context.add("bad", &"<script>alert('pwnd');</script>");
+        context.insert("bad", &"<script>alert('pwnd');</script>");
         let mut tera = Tera::default();
         tera.add_raw_template("hello.html", "{{bad}}").unwrap();
         let result = tera.render("hello.html", context);
@@ -853,10 +2448,31 @@ mod tests {
         assert_eq!(result.unwrap(), "&lt;script&gt;alert(&#x27;pwnd&#x27;);&lt;&#x2F;script&gt;".to_string());
     }
 
+    #[test]
+    fn test_autoescape_with_custom_escape_fn() {
+        fn escape_apostrophes(input: &str) -> String {
+            input.replace('\'', "&apos;")
+        }
+        let mut context = Context::new();
+        context.insert("bad", &"<b>it's</b>");
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            ("hello.xml", "{{ bad }} {{ bad | safe }}"),
+            ("hello.txt", "{{ bad }}"),
+        ]).unwrap();
+        tera.set_escape_fn(escape_apostrophes);
+
+        assert_eq!(tera.render("hello.xml", context.clone()).unwrap(), "<b>it&apos;s</b> <b>it's</b>".to_string());
+        assert_eq!(tera.render("hello.txt", context.clone()).unwrap(), "<b>it's</b>".to_string());
+
+        tera.reset_escape_fn();
+        assert_eq!(tera.render("hello.xml", context).unwrap(), "&lt;b&gt;it&#x27;s&lt;&#x2F;b&gt; <b>it's</b>".to_string());
+    }
+
     #[test]
     fn test_no_autoescape_on_extensions_not_specified() {
         let mut context = Context::new();
-        context.add("bad", &"<script>alert('pwnd');</script>");
+        context.insert("bad", &"<script>alert('pwnd');</script>");
         let mut tera = Tera::default();
         tera.add_raw_template("hello.sql", "{{bad}}").unwrap();
         let result = tera.render("hello.sql", context);
@@ -867,7 +2483,7 @@ mod tests {
     #[test]
     fn test_no_autoescape_with_safe_filter() {
         let mut context = Context::new();
-        context.add("bad", &"<script>alert('pwnd');</script>");
+        context.insert("bad", &"<script>alert('pwnd');</script>");
         let mut tera = Tera::default();
         tera.add_raw_template("hello.html", "{{ bad | safe }}").unwrap();
         let result = tera.render("hello.html", context);
@@ -875,6 +2491,50 @@ mod tests {
         assert_eq!(result.unwrap(), "<script>alert('pwnd');</script>".to_string());
     }
 
+    #[test]
+    fn test_escape_js_filter_is_not_escaped_again() {
+        let mut context = Context::new();
+        context.insert("msg", &"</script>\"a/b\"");
+        let mut tera = Tera::default();
+        tera.add_raw_template("hello.html", "<script>var msg = \"{{ msg | escape_js }}\";</script>").unwrap();
+
+        assert_eq!(
+            tera.render("hello.html", context).unwrap(),
+            r#"<script>var msg = "\u003C/script\u003E\u0022a/b\u0022";</script>"#.to_string()
+        );
+    }
+
+    #[test]
+    fn test_escape_filter_is_not_escaped_twice() {
+        let mut context = Context::new();
+        context.insert("bad", &"<b>&</b>");
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            ("hello.html", "{{ bad | escape }} {{ bad }} {{ bad | safe }}"),
+            ("hello.txt", "{{ bad | escape }} {{ bad }} {{ bad | safe }}"),
+        ]).unwrap();
+
+        assert_eq!(
+            tera.render("hello.html", context.clone()).unwrap(),
+            "&lt;b&gt;&amp;&lt;&#x2F;b&gt; &lt;b&gt;&amp;&lt;&#x2F;b&gt; <b>&</b>".to_string()
+        );
+        assert_eq!(
+            tera.render("hello.txt", context).unwrap(),
+            "&lt;b&gt;&amp;&lt;&#x2F;b&gt; <b>&</b> <b>&</b>".to_string()
+        );
+    }
+
+    #[test]
+    fn test_safe_filter_keeps_filters_output_unescaped() {
+        let mut context = Context::new();
+        context.insert("trusted", &"<em>hi</em>");
+        let mut tera = Tera::default();
+        tera.add_raw_template("hello.html", "{{ trusted | safe | upper }}").unwrap();
+        let result = tera.render("hello.html", context);
+
+        assert_eq!(result.unwrap(), "<EM>HI</EM>".to_string());
+    }
+
     #[test]
     fn test_render_super_multiple_inheritance() {
         let mut tera = Tera::default();
@@ -901,6 +2561,31 @@ mod tests {
         assert_eq!(result.unwrap(), "dad says hi and grandma says hello sincerely with love".to_string());
     }
 
+    #[test]
+    fn test_render_super_at_every_level_of_three_templates() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            ("grandparent", "<{% block content %}grandparent{% endblock content %}>"),
+            ("parent", "{% extends \"grandparent\" %}{% block content %}parent, {{ super() }}{% endblock content %}"),
+            ("child", "{% extends \"parent\" %}{% block content %}child, {{ super() }}{% endblock content %}"),
+        ]).unwrap();
+
+        assert_eq!(tera.render("child", Context::new()).unwrap(), "<child, parent, grandparent>".to_string());
+        assert_eq!(tera.render("parent", Context::new()).unwrap(), "<parent, grandparent>".to_string());
+    }
+
+    #[test]
+    fn test_render_super_several_times_in_a_block() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            ("grandparent", "{% block a %}G{% endblock a %}|{% block b %}g{% endblock b %}"),
+            ("parent", "{% extends \"grandparent\" %}{% block a %}P({{ super() }}{{ super() }}){% endblock a %}{% block b %}p{{ super() }}{% endblock b %}"),
+            ("child", "{% extends \"parent\" %}{% block a %}{{ super() }}C{{ super() }}{% endblock a %}{% block b %}{{ super() }}c{% endblock b %}"),
+        ]).unwrap();
+
+        assert_eq!(tera.render("child", Context::new()).unwrap(), "P(GG)CP(GG)|pgc".to_string());
+    }
+
     #[test]
     fn test_render_macros() {
         let mut tera = Tera::default();
@@ -914,6 +2599,81 @@ mod tests {
         assert_eq!(result.unwrap(), "Hello".to_string());
     }
 
+    #[test]
+    fn test_render_macros_positional_args() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            ("macros", "{% macro link(url, label, target) %}<a href=\"{{ url }}\" target=\"{{ target }}\">{{ label }}</a>{% endmacro link %}"),
+            ("positional", "{% import \"macros\" as macros %}{{ macros::link(\"/\", name ~ \"!\", \"_blank\") }}"),
+            ("mixed", "{% import \"macros\" as macros %}{{ macros::link(\"/\", target=\"_self\", label=name) }}"),
+            ("both", "{% import \"macros\" as macros %}{{ macros::link(\"/\", \"Home\", label=name, target=\"_self\") }}"),
+            ("too_many", "{% import \"macros\" as macros %}{{ macros::link(\"/\", \"Home\", \"_self\", 1) }}"),
+        ]).unwrap();
+        let mut context = Context::new();
+        context.insert("name", &"Home");
+
+        let result = tera.render("positional", context.clone());
+        assert_eq!(result.unwrap(), "<a href=\"/\" target=\"_blank\">Home!</a>".to_string());
+        let result = tera.render("mixed", context.clone());
+        assert_eq!(result.unwrap(), "<a href=\"/\" target=\"_self\">Home</a>".to_string());
+
+        let result = tera.render("both", context.clone());
+        assert_eq!(
+            result.unwrap_err().iter().nth(1).unwrap().to_string(),
+            "Macro `link` got the arg `label` both by position and by name"
+        );
+        let result = tera.render("too_many", context);
+        assert_eq!(
+            result.unwrap_err().iter().nth(1).unwrap().to_string(),
+            "Macro `link` was called with 4 positional args but it only takes 3: `[\"url\", \"label\", \"target\"]`"
+        );
+    }
+
+    #[test]
+    fn test_render_macros_default_args() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            ("macros", "{% macro button(label, kind=\"primary\", class=\"btn-\" ~ kind) %}<button class=\"{{ class }}\">{{ label }}</button>{% endmacro button %}"),
+            ("omitted", "{% import \"macros\" as macros %}{{ macros::button(label=\"Save\") }}"),
+            ("overridden", "{% import \"macros\" as macros %}{{ macros::button(\"Delete\", kind=\"danger\") }}"),
+            ("all", "{% import \"macros\" as macros %}{{ macros::button(\"Back\", \"link\", \"btn\") }}"),
+            ("missing", "{% import \"macros\" as macros %}{{ macros::button(kind=\"danger\") }}"),
+        ]).unwrap();
+
+        let result = tera.render("omitted", Context::new());
+        assert_eq!(result.unwrap(), "<button class=\"btn-primary\">Save</button>".to_string());
+        // the defaults coming after use the value given
+        let result = tera.render("overridden", Context::new());
+        assert_eq!(result.unwrap(), "<button class=\"btn-danger\">Delete</button>".to_string());
+        let result = tera.render("all", Context::new());
+        assert_eq!(result.unwrap(), "<button class=\"btn\">Back</button>".to_string());
+
+        let result = tera.render("missing", Context::new());
+        assert_eq!(
+            result.unwrap_err().iter().nth(1).unwrap().to_string(),
+            "Macro `button` was called without the arg `label`, which doesn't have a default value"
+        );
+    }
+
+    #[test]
+    fn test_render_macros_default_args_use_macro_scope() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            ("macros", "{% macro greet(name=user) %}Hello {{ name }}{% endmacro greet %}"),
+            ("tpl", "{% import \"macros\" as macros %}{% for user in users %}{{ macros::greet() }}{% endfor %}"),
+        ]).unwrap();
+        let mut context = Context::new();
+        context.insert("users", &["Bob"]);
+        context.insert("user", &"Alice");
+
+        // `user` is not an arg of the macro so it can't be found in its scope
+        let result = tera.render("tpl", context);
+        assert_eq!(
+            result.unwrap_err().iter().nth(1).unwrap().to_string(),
+            "Field `user` not found in context in template \"macros\" at line 1, column 21"
+        );
+    }
+
     #[test]
     fn test_render_macros_in_child_templates_same_namespace() {
         let mut tera = Tera::default();
@@ -963,7 +2723,7 @@ mod tests {
     #[test]
     fn test_render_not_condition_simple_value_exists() {
         let mut context = Context::new();
-        context.add("logged_in", &false);
+        context.insert("logged_in", &false);
         let mut tera = Tera::default();
         tera.add_raw_template("hello.html", "{% if not logged_in %}Login{% endif %}").unwrap();
         let result = tera.render("hello.html", context);
@@ -983,8 +2743,8 @@ mod tests {
     #[test]
     fn test_render_not_complex_condition_and() {
         let mut context = Context::new();
-        context.add("logged_in", &false);
-        context.add("active", &true);
+        context.insert("logged_in", &false);
+        context.insert("active", &true);
         let mut tera = Tera::default();
         tera.add_raw_template("hello.html", "{% if not logged_in and active %}Login{% endif %}").unwrap();
         let result = tera.render("hello.html", context);
@@ -995,8 +2755,8 @@ mod tests {
     #[test]
     fn test_render_not_complex_condition_or() {
         let mut context = Context::new();
-        context.add("number_users", &11);
-        context.add("active", &true);
+        context.insert("number_users", &11);
+        context.insert("active", &true);
         let mut tera = Tera::default();
         tera.add_raw_template("hello.html", "{% if not active or number_users > 10 %}Login{% endif %}").unwrap();
         let result = tera.render("hello.html", context);
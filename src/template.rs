@@ -1,17 +1,21 @@
 use std::collections::{HashMap, VecDeque};
 
-use parser::{parse, Node};
+use parser::{parse_with_delimiters, Node};
 use errors::{Result};
+use delimiters::Delimiters;
 
 
 /// This is the parsed equivalent of a template file
-/// Not mean to be used directly unless you want a one-off template rendering
+/// Not meant to be used directly unless you want to parse a template ahead of time and add it
+/// with `Tera::add_parsed_template`
 #[derive(Debug, Clone)]
 pub struct Template {
     /// name of the template, usually very close to the path
     pub name: String,
     /// original path of the file
     pub path: Option<String>,
+    /// original source of the template, only kept when loading templates with `Tera::set_keep_source(true)`
+    pub source: Option<String>,
     /// Parsed ast
     pub ast: Node,
     /// macros defined in that file
@@ -38,7 +42,12 @@ pub struct Template {
 impl Template {
     /// Parse the template string given
     pub fn new(tpl_name: &str, tpl_path: Option<String>, input: &str) -> Result<Template> {
-        let ast = parse(input)?;
+        Template::new_with_delimiters(tpl_name, tpl_path, input, Delimiters::default())
+    }
+
+    /// Parse the template string given, written with `delimiters` instead of `{{ }}`, `{% %}` and `{# #}`
+    pub fn new_with_delimiters(tpl_name: &str, tpl_path: Option<String>, input: &str, delimiters: Delimiters) -> Result<Template> {
+        let ast = parse_with_delimiters(input, delimiters)?;
 
         let mut blocks = HashMap::new();
         // We find all those blocks at first so we don't need to do it for each render
@@ -104,6 +113,7 @@ impl Template {
         Ok(Template {
             name: tpl_name.to_string(),
             path: tpl_path,
+            source: None,
             ast: ast,
             parent: parent,
             blocks: blocks,
@@ -2,38 +2,73 @@ use std::collections::HashMap;
 use std::io::prelude::*;
 use std::fs::File;
 use std::fmt;
+use std::io;
+use std::mem;
 use std::path::Path;
+use std::sync::Arc;
 
 use glob::glob;
 use serde::Serialize;
-use serde_json::value::to_value;
+use serde_json::value::{Map, Value, to_value};
 
 use template::Template;
+use delimiters::Delimiters;
 use filters::{FilterFn, string, array, common, number};
 use context::Context;
-use errors::{Result, ResultExt};
+use errors::{Error, Result, ResultExt};
 use render::Renderer;
 use testers::{self, TesterFn};
+use global_functions::{self, GlobalFn};
+use utils::escape_html;
 
+// How many macro calls and includes can be nested by default before rendering errors
+const DEFAULT_MAX_CALL_DEPTH: usize = 64;
 
 /// The main point of interaction in this library.
+///
+/// Cloning it is cheap: the parsed templates, filters, testers and global functions are
+/// reference-counted and shared between the clones.
+#[derive(Clone)]
 pub struct Tera {
+    // The glob given to `Tera::new`, if any, used to reload the templates
+    glob: Option<String>,
+    // The delimiters the templates are written with
+    delimiters: Delimiters,
+    // Whether the source of the templates is kept after parsing them, false by default
+    keep_source: bool,
+    // Templates are reference-counted so cloning a Tera instance doesn't copy their AST
     #[doc(hidden)]
-    pub templates: HashMap<String, Template>,
+    pub templates: HashMap<String, Arc<Template>>,
     #[doc(hidden)]
     pub filters: HashMap<String, FilterFn>,
     #[doc(hidden)]
     pub testers: HashMap<String, TesterFn>,
+    #[doc(hidden)]
+    pub global_functions: HashMap<String, GlobalFn>,
     // Which extensions does Tera automatically autoescape on.
     // Defaults to [".html", ".htm", ".xml"]
     #[doc(hidden)]
     pub autoescape_extensions: Vec<&'static str>,
+    // The function used to escape the output of autoescaped templates, `escape_html` by default
+    #[doc(hidden)]
+    pub escape_fn: fn(&str) -> String,
+    // Whether a newline at the end of the output is kept, true by default
+    #[doc(hidden)]
+    pub keep_trailing_newline: bool,
+    // How many macro calls and includes can be nested when rendering
+    #[doc(hidden)]
+    pub max_call_depth: usize,
+    // Variables available to all the templates, always a JSON object
+    #[doc(hidden)]
+    pub globals: Value,
 }
 
 
 impl Tera {
     /// Create a new instance of Tera, containing all the parsed templates found in the `dir` glob
     ///
+    /// All the templates are parsed even if some of them are invalid so the error lists all
+    /// the templates that failed to parse, with the reason for each of them.
     /// The example below is what the [compile_templates](macro.compile_templates.html) macros expands to.
     ///
     /// ```rust,ignore
@@ -46,20 +81,54 @@ impl Tera {
     ///}
     /// ```
     pub fn new(dir: &str) -> Result<Tera> {
+        Tera::new_with_delimiters(dir, Delimiters::default())
+    }
+
+    /// Create a new instance of Tera like [Tera::new](struct.Tera.html#method.new) but with
+    /// templates using other delimiters than `{{ }}`, `{% %}` and `{# #}`.
+    /// See [Tera::set_delimiters](struct.Tera.html#method.set_delimiters) for more details.
+    ///
+    /// ```rust,ignore
+    /// let delimiters = Delimiters { variable: ("<<", ">>"), block: ("<%", "%>"), comment: ("<#", "#>") };
+    /// let tera = Tera::new_with_delimiters("templates/**/*.tex", delimiters)?;
+    /// ```
+    pub fn new_with_delimiters(dir: &str, delimiters: Delimiters) -> Result<Tera> {
         if dir.find('*').is_none() {
             bail!("Tera expects a glob as input, no * were found in `{}`", dir);
         }
-
-        let mut errors = String::new();
+        delimiters.validate()?;
 
         let mut tera = Tera {
+            glob: Some(dir.to_string()),
+            delimiters,
+            keep_source: false,
             templates: HashMap::new(),
             filters: HashMap::new(),
             testers: HashMap::new(),
-            autoescape_extensions: vec![".html", ".htm", ".xml"]
+            global_functions: HashMap::new(),
+            autoescape_extensions: vec![".html", ".htm", ".xml"],
+            escape_fn: escape_html,
+            keep_trailing_newline: true,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            globals: Value::Object(Map::new()),
         };
 
         // We are parsing all the templates on instantiation
+        tera.load_from_glob(dir)?;
+        tera.build_inheritance_chains()?;
+        tera.check_macro_files()?;
+        tera.register_tera_filters();
+        tera.register_tera_testers();
+        tera.register_tera_functions();
+        Ok(tera)
+    }
+
+    // Parses all the templates found by the glob and adds them to the instance.
+    // All the errors are collected so they can all be shown at once.
+    // This doesn't check the validity of inheritance chains.
+    fn load_from_glob(&mut self, dir: &str) -> Result<()> {
+        let mut errors = String::new();
+
         for entry in glob(dir).unwrap().filter_map(|e| e.ok()) {
             let path = entry.as_path();
             // We only care about actual files
@@ -71,11 +140,8 @@ impl Tera {
                     .replace("\\", "/") // change windows slash to forward slash
                     .replace(parent_dir, "");
 
-                if let Err(e) = tera.add_file(Some(&filepath), path) {
-                    errors += &format!("\n* {}", e);
-                    for e in e.iter().skip(1) {
-                        errors += &format!("\n-- {}", e);
-                    }
+                if let Err(e) = self.add_file(Some(&filepath), path) {
+                    push_error(&mut errors, &e);
                 }
             }
         }
@@ -83,16 +149,88 @@ impl Tera {
             bail!(errors);
         }
 
-        tera.build_inheritance_chains()?;
-        tera.register_tera_filters();
-        tera.register_tera_testers();
-        Ok(tera)
+        Ok(())
+    }
+
+    /// Re-parses all the templates found by the glob given to [Tera::new](struct.Tera.html#method.new),
+    /// for example to pick up changes made to the templates while a server is running.
+    ///
+    /// Registered filters, testers and global functions are kept but all the templates are replaced
+    /// by the ones found on disk: templates added in other ways will need to be added again.
+    /// If a template fails to parse or an inheritance chain is invalid, an error is returned and
+    /// the previously loaded templates are kept as they were.
+    /// This will error if the instance wasn't created with a glob.
+    ///
+    /// ```rust,ignore
+    /// tera.full_reload()?;
+    /// ```
+    pub fn full_reload(&mut self) -> Result<()> {
+        let dir = match self.glob {
+            Some(ref g) => g.clone(),
+            None => bail!("Reloading is only available if the Tera instance was created with a glob"),
+        };
+
+        let mut previous_templates = HashMap::new();
+        mem::swap(&mut previous_templates, &mut self.templates);
+        let res = self.load_from_glob(&dir)
+            .and_then(|_| self.build_inheritance_chains())
+            .and_then(|_| self.check_macro_files());
+        if res.is_err() {
+            self.templates = previous_templates;
+        }
+        res
+    }
+
+    /// Adds the templates, filters, testers, global functions and global variables of `other` to this instance.
+    /// When both have one with the same name, the one of this instance is kept.
+    ///
+    /// Templates of this instance can then extend, include or import macros from the ones of `other`.
+    /// If the inheritance chains of the merged templates can't be built, an error is returned and
+    /// the templates of this instance are kept as they were.
+    ///
+    /// ```rust,ignore
+    /// let shared = compile_templates!("shared/**/*");
+    /// let mut tera = compile_templates!("templates/**/*");
+    /// tera.extend(&shared)?;
+    /// ```
+    pub fn extend(&mut self, other: &Tera) -> Result<()> {
+        let previous_templates = self.templates.clone();
+        for (name, template) in &other.templates {
+            self.templates.entry(name.clone()).or_insert_with(|| template.clone());
+        }
+        for (name, filter) in &other.filters {
+            self.filters.entry(name.clone()).or_insert_with(|| filter.clone());
+        }
+        for (name, tester) in &other.testers {
+            self.testers.entry(name.clone()).or_insert_with(|| tester.clone());
+        }
+        for (name, function) in &other.global_functions {
+            self.global_functions.entry(name.clone()).or_insert_with(|| function.clone());
+        }
+        if let (Value::Object(globals), Value::Object(other_globals)) = (&mut self.globals, &other.globals) {
+            for (key, value) in other_globals {
+                globals.entry(key.clone()).or_insert_with(|| value.clone());
+            }
+        }
+
+        let res = self.build_inheritance_chains();
+        if res.is_err() {
+            self.templates = previous_templates;
+        }
+        res
     }
 
     // Add a template from a path: reads the file and parses it.
     // This will return an error if the template is invalid and doesn't check the validity of
     // inheritance chains.
     fn add_file<P: AsRef<Path>>(&mut self, name: Option<&str>, path: P) -> Result<()> {
+        let tpl = self.read_file(name, path)?;
+        self.templates.insert(tpl.name.clone(), Arc::new(tpl));
+        Ok(())
+    }
+
+    // Reads the template file at `path` and parses it, without adding it
+    fn read_file<P: AsRef<Path>>(&self, name: Option<&str>, path: P) -> Result<Template> {
         let path = path.as_ref();
         let tpl_name = if let Some(n) = name { n } else { path.to_str().unwrap() };
 
@@ -100,13 +238,88 @@ impl Tera {
         let mut input = String::new();
         f.read_to_string(&mut input).chain_err(|| format!("Failed to read template '{:?}'", path))?;
 
-        let tpl = Template::new(tpl_name, Some(path.to_str().unwrap().to_string()), &input)
-            .chain_err(|| format!("Failed to parse '{:?}'", path))?;
+        self.parse_template(tpl_name, Some(path.to_str().unwrap().to_string()), &input)
+            .chain_err(|| format!("Failed to parse '{:?}'", path))
+    }
+
+    // Adds templates loaded in bulk, checking before adding them that the macros they import
+    // are either loaded or in `batch`
+    fn add_batch(&mut self, batch: Vec<Template>) -> Result<()> {
+        self.check_imported_macro_files(batch.iter())?;
+        for tpl in batch {
+            self.templates.insert(tpl.name.clone(), Arc::new(tpl));
+        }
+        self.build_inheritance_chains()
+    }
+
+    // Parses a template written with the delimiters of this instance, keeping its source if asked to
+    fn parse_template(&self, name: &str, path: Option<String>, input: &str) -> Result<Template> {
+        let mut tpl = Template::new_with_delimiters(name, path, input, self.delimiters)?;
+        if self.keep_source {
+            tpl.source = Some(input.to_string());
+        }
+        Ok(tpl)
+    }
 
-        self.templates.insert(tpl_name.to_string(), tpl);
+    /// Changes the delimiters of the templates, for when `{{ }}`, `{% %}` and `{# #}` clash with
+    /// their content like in LaTeX or JavaScript.
+    /// The templates found by the glob given to [Tera::new](struct.Tera.html#method.new) are parsed
+    /// again with the new delimiters, templates added in other ways are only affected if they are
+    /// added afterwards. Use [Tera::new_with_delimiters](struct.Tera.html#method.new_with_delimiters)
+    /// if the templates can't be parsed with the default delimiters.
+    /// The delimiters are changed for the whole template: `{{ }}` and the others are just text in
+    /// templates using other delimiters.
+    ///
+    /// This will error if the delimiters are empty or the opening ones are not all different, or if
+    /// reloading the templates fails in which case the previous delimiters and templates are kept.
+    ///
+    /// ```rust,ignore
+    /// tera.set_delimiters(Delimiters { variable: ("<<", ">>"), block: ("<%", "%>"), comment: ("<#", "#>") })?;
+    /// tera.add_raw_template("hello.tex", "\\textbf{<< name >>}")?;
+    /// ```
+    pub fn set_delimiters(&mut self, delimiters: Delimiters) -> Result<()> {
+        delimiters.validate()?;
+        let previous = mem::replace(&mut self.delimiters, delimiters);
+        if self.glob.is_some() {
+            if let Err(e) = self.reload_glob_templates() {
+                self.delimiters = previous;
+                return Err(e);
+            }
+        }
         Ok(())
     }
 
+    // Re-parses the templates found by the glob, keeping the ones that were added in other ways.
+    // A template is only replaced if the glob finds a file with its name at the same path, so a
+    // template added with the name of a glob one is kept.
+    fn reload_glob_templates(&mut self) -> Result<()> {
+        let dir = match self.glob {
+            Some(ref g) => g.clone(),
+            None => return Ok(()),
+        };
+
+        let previous_templates = mem::take(&mut self.templates);
+        let res = self.load_from_glob(&dir);
+        if res.is_ok() {
+            for (name, template) in &previous_templates {
+                let reloaded = match self.templates.get(name) {
+                    Some(tpl) => tpl.path == template.path,
+                    None => false,
+                };
+                if !reloaded {
+                    self.templates.insert(name.clone(), template.clone());
+                }
+            }
+        }
+        let res = res
+            .and_then(|_| self.build_inheritance_chains())
+            .and_then(|_| self.check_macro_files());
+        if res.is_err() {
+            self.templates = previous_templates;
+        }
+        res
+    }
+
     // We need to know the hierarchy of templates to be able to render multiple extends level
     // This happens at compile to avoid checking it every time we want to render a template
     // This also checks for soundness issues in the inheritance chains, such as missing template or
@@ -114,18 +327,79 @@ impl Tera {
     // It also builds the block inheritance chain and detects when super() is called in a place
     // where it can't possibly work
     fn build_inheritance_chains(&mut self) -> Result<()> {
+        let mut templates = self.templates.clone();
+        for template in self.templates.values() {
+            // Simple template: no inheritance or blocks -> nothing to do
+            if template.parent.is_none() && template.blocks.is_empty() {
+                continue;
+            }
+
+            let mut tpl = Template::clone(template);
+            self.build_inheritance_chain(&mut tpl)?;
+            templates.insert(template.name.clone(), Arc::new(tpl));
+        }
+        self.templates = templates;
+        Ok(())
+    }
+
+    /// Checks that all the templates macros are imported from are loaded, returning an error listing
+    /// every import that can't be resolved otherwise.
+    ///
+    /// When loading templates in bulk, with `Tera::new` for example, the imports of the templates
+    /// being loaded are checked but not the ones of the templates already there, and nothing is
+    /// checked when adding a single template since the one with the macros might be added after it.
+    /// Call it once all the templates are added to find missing imports before rendering.
+    ///
+    /// ```rust,ignore
+    /// tera.add_raw_template("macros.html", macros)?;
+    /// tera.add_raw_template("index.html", index)?;
+    /// tera.check_macro_files()?;
+    /// ```
+    pub fn check_macro_files(&self) -> Result<()> {
+        self.check_imported_macro_files(self.templates.values().map(|t| &**t))
+    }
+
+    // Errors listing the imports of macros of `templates` from templates that are neither loaded
+    // nor in `templates`
+    fn check_imported_macro_files<'t, I>(&self, templates: I) -> Result<()>
+        where I: Iterator<Item=&'t Template> + Clone {
+        let mut errors = vec![];
+        for template in templates.clone() {
+            for (filename, _) in &template.imported_macro_files {
+                if !self.templates.contains_key(filename) && !templates.clone().any(|t| &t.name == filename) {
+                    errors.push(format!(
+                        "Template '{}' imports macros from '{}' but it isn't loaded", template.name, filename
+                    ));
+                }
+            }
+        }
+        if !errors.is_empty() {
+            // Sorted so the order doesn't depend on the HashMap
+            errors.sort();
+            bail!(errors.join("\n"));
+        }
+        Ok(())
+    }
+
+    // Fills the `parents` and `blocks_definitions` of a single template, looking up its
+    // parents in the templates already loaded
+    fn build_inheritance_chain(&self, tpl: &mut Template) -> Result<()> {
         // Recursive fn that finds all the parents and put them in an ordered Vec from closest to first parent
         // parent template
         fn build_chain(tera: &Tera, start: &Template, template: &Template, mut parents: Vec<String>) -> Result<Vec<String>> {
-            if !parents.is_empty() && start.name == template.name {
-                bail!("Circular extend detected for template '{}'. Inheritance chain: `{:?}`", start.name, parents);
-            }
-
             match template.parent {
                 Some(ref p) => {
                     match tera.get_template(p) {
                         Ok(parent) => {
+                            // The loop doesn't necessarily go through `start`, eg `c -> a -> b -> a`
+                            let seen = parent.name == start.name || parents.contains(&parent.name);
                             parents.push(parent.name.clone());
+                            if seen {
+                                bail!(
+                                    "Circular extend detected for template '{}': {} -> {}",
+                                    start.name, start.name, parents.join(" -> ")
+                                );
+                            }
                             build_chain(tera, start, parent, parents)
                         },
                         Err(_) => {
@@ -140,39 +414,30 @@ impl Tera {
             }
         }
 
-        let mut templates = self.templates.clone();
-        for template in self.templates.values() {
-            // Simple template: no inheritance or blocks -> nothing to do
-            if template.parent.is_none() && template.blocks.is_empty() {
-                continue;
-            }
-
-            let mut tpl = template.clone();
-            if tpl.parent.is_some() {
-                tpl.parents = build_chain(self, template, template, vec![])?;
-            }
-
-            // Iterate over both blocks and templates and try to find the parents blocks
-            // insert that into the tpl object once done so it's available directly in the template
-            // without having to fetch all the parents to build it at runtime
-            for (block_name, def) in &tpl.blocks {
-                // push our own block first
-                let mut definitions = vec![(tpl.name.clone(), def.clone())];
-
-                // and then see if our parents have it
-                for parent in &tpl.parents {
-                    let t = self.get_template(parent)
-                        .chain_err(|| format!("Couldn't find template {} while building inheritance chains", parent))?;
+        if tpl.parent.is_some() {
+            tpl.parents = build_chain(self, tpl, tpl, vec![])?;
+        }
 
-                    if let Some(b) = t.blocks.get(block_name) {
-                        definitions.push((t.name.clone(), b.clone()));
-                    }
+        // Iterate over both blocks and templates and try to find the parents blocks
+        // insert that into the tpl object once done so it's available directly in the template
+        // without having to fetch all the parents to build it at runtime
+        let mut blocks_definitions = HashMap::new();
+        for (block_name, def) in &tpl.blocks {
+            // push our own block first
+            let mut definitions = vec![(tpl.name.clone(), def.clone())];
+
+            // and then see if our parents have it
+            for parent in &tpl.parents {
+                let t = self.get_template(parent)
+                    .chain_err(|| format!("Couldn't find template {} while building inheritance chains", parent))?;
+
+                if let Some(b) = t.blocks.get(block_name) {
+                    definitions.push((t.name.clone(), b.clone()));
                 }
-                tpl.blocks_definitions.insert(block_name.clone(), definitions);
             }
-            templates.insert(template.name.clone(), tpl);
+            blocks_definitions.insert(block_name.clone(), definitions);
         }
-        self.templates = templates;
+        tpl.blocks_definitions = blocks_definitions;
         Ok(())
     }
 
@@ -185,12 +450,117 @@ impl Tera {
     /// tera.render("hello.html", Context::new());
     /// ```
     pub fn render(&self, template_name: &str, data: Context) -> Result<String> {
+        self.render_ref(template_name, &data)
+    }
+
+    /// Renders a Tera template given a reference to a `Context` object.
+    ///
+    /// The context is only read while rendering so this avoids cloning it when the same
+    /// context is used for several templates or renders.
+    ///
+    /// ```rust,ignore
+    /// let output = tera.render_ref("hello.html", &context);
+    /// ```
+    pub fn render_ref(&self, template_name: &str, data: &Context) -> Result<String> {
         let template = self.get_template(template_name)?;
         let mut renderer = Renderer::new(template, self, data.as_json()?);
 
         renderer.render()
     }
 
+    /// Renders a Tera template given a `Context` object, using the content of `overrides` as the
+    /// output of the blocks with the same names instead of rendering them.
+    ///
+    /// An override wins over all the definitions of its block, including the ones of child
+    /// templates, and replaces the whole block: the blocks nested in it are not rendered.
+    /// The content is used as it is, it isn't parsed as a template nor escaped.
+    /// Errors if a block to override isn't in the template or its parents.
+    ///
+    /// ```rust,ignore
+    /// let mut overrides = HashMap::new();
+    /// overrides.insert("summary".to_string(), summary_html);
+    /// let output = tera.render_with_blocks("report.html", context, overrides)?;
+    /// ```
+    pub fn render_with_blocks(&self, template_name: &str, data: Context, overrides: HashMap<String, String>) -> Result<String> {
+        let template = self.get_template(template_name)?;
+        for name in overrides.keys() {
+            let mut defined = template.blocks.contains_key(name);
+            for parent in &template.parents {
+                defined = defined || self.get_template(parent)?.blocks.contains_key(name);
+            }
+            if !defined {
+                bail!("Tried to override the block `{}` but template '{}' doesn't have it", name, template_name);
+            }
+        }
+
+        let mut renderer = Renderer::new(template, self, data.as_json()?);
+        renderer.set_block_overrides(overrides);
+        renderer.render()
+    }
+
+    /// Renders the macro `macro_name` defined in the template `file` with the given args, without
+    /// writing a template calling it. `namespace` is the name the macro is called from, as if
+    /// `file` was imported with `{% import file as namespace %}`, which is shown in errors.
+    ///
+    /// Args are bound to the parameters of the macro by name, the ones not given using their
+    /// default value. Errors if the macro doesn't exist, if an arg isn't a parameter of the macro
+    /// or if a parameter without a default value is missing.
+    ///
+    /// ```rust,ignore
+    /// let mut args = HashMap::new();
+    /// args.insert("user".to_string(), to_value(&user)?);
+    /// let fragment = tera.render_macro("emails/macros.html", "emails", "greeting", args)?;
+    /// ```
+    pub fn render_macro(&self, file: &str, namespace: &str, macro_name: &str, args: HashMap<String, Value>) -> Result<String> {
+        let template = self.get_template(file)?;
+        let mut renderer = Renderer::new(template, self, Value::Object(Map::new()));
+
+        renderer.render_macro_with_args(namespace, macro_name, args)
+    }
+
+    /// Renders a Tera template given a `Context` object and returns the output as a JSON string,
+    /// to be used when building a larger `Value`, like an API response.
+    ///
+    /// ```rust,ignore
+    /// let mut response = Map::new();
+    /// response.insert("html".to_string(), tera.render_value("fragment.html", context)?);
+    /// ```
+    pub fn render_value(&self, template_name: &str, data: Context) -> Result<Value> {
+        Ok(Value::String(self.render(template_name, data)?))
+    }
+
+    /// Renders a Tera template given a `Context` object, writing the output to `writer`
+    /// as it is rendered instead of building a `String`.
+    ///
+    /// If an error happens while rendering, the output of the template up to that point
+    /// will already have been written.
+    ///
+    /// ```rust,ignore
+    /// let mut stream = TcpStream::connect("127.0.0.1:8080")?;
+    /// tera.render_to("hello.html", Context::new(), &mut stream)?;
+    /// ```
+    pub fn render_to(&self, template_name: &str, data: Context, writer: &mut dyn io::Write) -> Result<()> {
+        let template = self.get_template(template_name)?;
+        let mut renderer = Renderer::new(template, self, data.as_json()?);
+
+        renderer.render_to(writer)
+    }
+
+    /// Renders a Tera template given a `Context` object and returns the output as bytes,
+    /// for example for protocols where the output isn't handled as text.
+    ///
+    /// Templates are still UTF-8 and the values are written as their UTF-8 bytes: this is the
+    /// same output as `Tera::render`, without the `String`.
+    ///
+    /// ```rust,ignore
+    /// let bytes = tera.render_bytes("message.txt", context)?;
+    /// ```
+    pub fn render_bytes(&self, template_name: &str, data: Context) -> Result<Vec<u8>> {
+        let mut output = Vec::new();
+        self.render_to(template_name, data, &mut output)?;
+        Ok(output)
+    }
+
     /// Renders a Tera template given a `Serializeable` object.
     ///
     /// If `data` is not an object, an error will be returned.
@@ -215,6 +585,32 @@ impl Tera {
         renderer.render()
     }
 
+    /// Renders a template given as a string, without adding it to the Tera instance.
+    ///
+    /// Unlike [Tera::one_off](struct.Tera.html#method.one_off), the template has access to
+    /// the filters and testers registered on this instance and can extend, include or import
+    /// macros from the templates it contains.
+    /// Autoescaping is not applied as the template doesn't have a name to match against.
+    ///
+    /// The input is parsed on every call: prefer adding the template to the instance, for example
+    /// with [Tera::add_parsed_template](struct.Tera.html#method.add_parsed_template),
+    /// if you need to render it more than once.
+    /// Any errors will mention the `render_str` template: this is the name given to the template by
+    /// Tera
+    ///
+    /// ```rust,ignore
+    /// let mut context = Context::new();
+    /// context.insert("greeting", &"hello");
+    /// tera.render_str("{{ greeting | capitalize }} world", context);
+    /// ```
+    pub fn render_str(&self, input: &str, data: Context) -> Result<String> {
+        let mut tpl = self.parse_template("render_str", None, input)?;
+        self.build_inheritance_chain(&mut tpl)?;
+        let mut renderer = Renderer::new(&tpl, self, data.as_json()?);
+
+        renderer.render()
+    }
+
     /// Renders a one off template (for example a template coming from a user input)
     ///
     /// This creates a separate instance of Tera with no possibilities of adding custom filters
@@ -224,7 +620,7 @@ impl Tera {
     ///
     /// ```rust,ignore
     /// let mut context = Context::new();
-    /// context.add("greeting", &"hello");
+    /// context.insert("greeting", &"hello");
     /// Tera::one_off("{{ greeting }} world", context);
     /// ```
     pub fn one_off(input: &str, data: Context, autoescape: bool) -> Result<String> {
@@ -254,15 +650,53 @@ impl Tera {
         tera.value_render("one_off", data)
     }
 
-    #[doc(hidden)]
+    /// Returns the parsed template called `template_name`, to inspect what it extends, the blocks
+    /// it defines or its macros without parsing it again.
+    /// Errors if there is no template with that name.
+    ///
+    /// ```rust,ignore
+    /// let template = tera.get_template("products/product.html")?;
+    /// println!("{:?}", template.parents);
+    /// ```
     #[inline]
     pub fn get_template(&self, template_name: &str) -> Result<&Template> {
         match self.templates.get(template_name) {
-            Some(tpl) => Ok(tpl),
+            Some(tpl) => Ok(&**tpl),
             None => bail!("Template '{}' not found", template_name),
         }
     }
 
+    /// Returns the sorted names of all the templates loaded in this instance, like
+    /// `products/product.html` for templates found by the glob given to `Tera::new`.
+    ///
+    /// ```rust,ignore
+    /// for name in tera.get_template_names() {
+    ///     println!("{}", name);
+    /// }
+    /// ```
+    pub fn get_template_names(&self) -> Vec<&str> {
+        let mut names = self.templates.keys().map(|k| k.as_str()).collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    /// Parses a template string written with the delimiters of this instance and returns the errors
+    /// adding it would give, without adding it. Useful to check templates as they are being written,
+    /// in an editor for example.
+    ///
+    /// Only the template itself is checked: the templates it extends, includes or imports macros from
+    /// don't need to be loaded.
+    ///
+    /// ```rust,ignore
+    /// if let Err(e) = tera.validate("{% block a %}{% endblock b %}") {
+    ///     println!("{}", e);
+    /// }
+    /// ```
+    pub fn validate(&self, input: &str) -> Result<()> {
+        self.parse_template("validate", None, input)?;
+        Ok(())
+    }
+
     /// Add a single template to the Tera instance
     ///
     /// This will error if the inheritance chain can't be built, such as adding a child
@@ -274,17 +708,36 @@ impl Tera {
     /// ```
     #[doc(hidden)]
     pub fn add_raw_template(&mut self, name: &str, content: &str) -> Result<()> {
-        let tpl = Template::new(name, None, content)
+        let tpl = self.parse_template(name, None, content)
             .chain_err(|| format!("Failed to parse '{}'", name))?;
-        self.templates.insert(name.to_string(), tpl);
+        self.templates.insert(name.to_string(), Arc::new(tpl));
         self.build_inheritance_chains()?;
         Ok(())
     }
 
+    /// Add a single template to the Tera instance like
+    /// [Tera::add_raw_template](struct.Tera.html#method.add_raw_template), but errors instead of
+    /// replacing it if a template with the same name was already added.
+    ///
+    /// ```rust,ignore
+    /// tera.add_raw_template_checked("new.html", "Blabla")?;
+    /// // Errors
+    /// tera.add_raw_template_checked("new.html", "Blabla")?;
+    /// ```
+    #[doc(hidden)]
+    pub fn add_raw_template_checked(&mut self, name: &str, content: &str) -> Result<()> {
+        if self.templates.contains_key(name) {
+            bail!("Template '{}' already exists", name);
+        }
+        self.add_raw_template(name, content)
+    }
+
     /// Add all the templates given to the Tera instance
     ///
     /// This will error if the inheritance chain can't be built, such as adding a child
-    /// template without the parent one.
+    /// template without the parent one, or if macros are imported from a template that isn't loaded.
+    /// All the templates are parsed even if one fails and the error lists every template that failed.
+    /// None of them is added if one fails to parse or imports macros from a template that isn't loaded.
     ///
     /// ```rust,ignore
     /// tera.add_raw_templates(vec![
@@ -294,13 +747,18 @@ impl Tera {
     /// ```
     #[doc(hidden)]
     pub fn add_raw_templates(&mut self, templates: Vec<(&str, &str)>) -> Result<()>  {
+        let mut errors = String::new();
+        let mut batch = vec![];
         for (name, content) in templates {
-            let tpl = Template::new(name, None, content)
-                .chain_err(|| format!("Failed to parse '{}'", name))?;
-            self.templates.insert(name.to_string(),tpl);
+            match self.parse_template(name, None, content).chain_err(|| format!("Failed to parse '{}'", name)) {
+                Ok(tpl) => batch.push(tpl),
+                Err(e) => push_error(&mut errors, &e),
+            }
         }
-        self.build_inheritance_chains()?;
-        Ok(())
+        if !errors.is_empty() {
+            bail!(errors);
+        }
+        self.add_batch(batch)
     }
 
 
@@ -328,7 +786,9 @@ impl Tera {
     /// the path given, but this can be renamed with the second parameter of the tuple
     ///
     /// This will error if the inheritance chain can't be built, such as adding a child
-    /// template without the parent one.
+    /// template without the parent one, or if macros are imported from a template that isn't loaded.
+    /// All the files are loaded even if one fails and the error lists every file that failed.
+    /// None of them is added if one fails to parse or imports macros from a template that isn't loaded.
     ///
     /// ```rust,ignore
     /// tera.add_template_files(vec![
@@ -338,13 +798,82 @@ impl Tera {
     /// ```
     #[doc(hidden)]
     pub fn add_template_files<P: AsRef<Path>>(&mut self, files: Vec<(P, Option<&str>)>) -> Result<()>  {
+        let mut errors = String::new();
+        let mut batch = vec![];
         for (path, name) in files {
-            self.add_file(name, path)?;
+            match self.read_file(name, path) {
+                Ok(tpl) => batch.push(tpl),
+                Err(e) => push_error(&mut errors, &e),
+            }
+        }
+        if !errors.is_empty() {
+            bail!(errors);
+        }
+        self.add_batch(batch)
+    }
+
+    /// Add a template already parsed with `Template::new` to the Tera instance, using its name.
+    /// Useful to parse a template string once and render it many times with `Tera::render`
+    /// without having to go through `Tera::render_str`.
+    /// A template with the same name is replaced.
+    ///
+    /// This will error if the inheritance chain can't be built, such as adding a child
+    /// template without the parent one.
+    ///
+    /// ```rust,ignore
+    /// let tpl = Template::new("greeting", None, "Hello {{ name }}")?;
+    /// tera.add_parsed_template(tpl)?;
+    /// tera.render("greeting", context);
+    /// ```
+    pub fn add_parsed_template(&mut self, tpl: Template) -> Result<()> {
+        self.templates.insert(tpl.name.clone(), Arc::new(tpl));
+        self.build_inheritance_chains()?;
+        Ok(())
+    }
+
+    /// Removes the template called `name` from the Tera instance, for example when its file was deleted.
+    ///
+    /// This will error if the template doesn't exist or if other templates extend it, in which case
+    /// they need to be removed first. Templates including it or importing its macros are not checked
+    /// and will error when rendered.
+    ///
+    /// ```rust,ignore
+    /// tera.remove_template("old.html")?;
+    /// ```
+    pub fn remove_template(&mut self, name: &str) -> Result<()> {
+        if !self.templates.contains_key(name) {
+            bail!("Template '{}' not found", name);
+        }
+
+        let mut children = self.templates.values()
+            .filter(|t| t.parent.as_ref().map(|p| p == name).unwrap_or(false))
+            .map(|t| t.name.as_str())
+            .collect::<Vec<_>>();
+        if !children.is_empty() {
+            children.sort();
+            bail!(
+                "Template '{}' can't be removed as it is extended by: {}",
+                name, children.join(", ")
+            );
         }
+
+        self.templates.remove(name);
         self.build_inheritance_chains()?;
         Ok(())
     }
 
+    /// Returns the sorted names of all the registered filters, built-in ones included.
+    /// Useful to check that a custom filter was registered or overrides a built-in one.
+    ///
+    /// ```rust,ignore
+    /// assert!(tera.get_filter_names().contains(&"upper"));
+    /// ```
+    pub fn get_filter_names(&self) -> Vec<&str> {
+        let mut names = self.filters.keys().map(|k| k.as_str()).collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
     #[doc(hidden)]
     #[inline]
     pub fn get_filter(&self, filter_name: &str) -> Result<&FilterFn> {
@@ -356,13 +885,21 @@ impl Tera {
 
     /// Register a filter with Tera.
     ///
-    /// If a filter with that name already exists, it will be overwritten
+    /// A filter is a function or a closure taking the value to filter and a `HashMap` of its
+    /// keyword arguments and returning a `Result<Value>`.
+    /// It needs to be `Send + Sync` as a Tera instance is typically shared between threads.
+    /// If a filter with that name already exists, including a built-in one, it will be overwritten
     ///
     /// ```rust,ignore
     /// tera.register_filter("upper", string::upper);
+    /// tera.register_filter("add", |value: Value, args: HashMap<String, Value>| {
+    ///     // ...
+    /// });
     /// ```
-    pub fn register_filter(&mut self, name: &str, filter: FilterFn) {
-        self.filters.insert(name.to_string(), filter);
+    pub fn register_filter<F>(&mut self, name: &str, filter: F)
+        where F: Fn(Value, HashMap<String, Value>) -> Result<Value> + Send + Sync + 'static
+    {
+        self.filters.insert(name.to_string(), Arc::new(filter));
     }
 
     #[doc(hidden)]
@@ -376,41 +913,144 @@ impl Tera {
 
     /// Register a tester with Tera.
     ///
+    /// A tester is a function or a closure taking the tested value, `None` if it is undefined,
+    /// and the arguments given to the test, returning a `Result<bool>`.
+    /// Like filters, it needs to be `Send + Sync`.
     /// If a tester with that name already exists, it will be overwritten
     ///
     /// ```rust,ignore
     /// tera.register_tester("odd", testers::odd);
     /// ```
-    pub fn register_tester(&mut self, name: &str, tester: TesterFn) {
-        self.testers.insert(name.to_string(), tester);
+    pub fn register_tester<T>(&mut self, name: &str, tester: T)
+        where T: Fn(Option<Value>, Vec<Value>) -> Result<bool> + Send + Sync + 'static
+    {
+        self.testers.insert(name.to_string(), Arc::new(tester));
     }
 
-    fn register_tera_filters(&mut self) {
-        self.register_filter("upper", string::upper);
-        self.register_filter("lower", string::lower);
-        self.register_filter("trim", string::trim);
-        self.register_filter("truncate", string::truncate);
-        self.register_filter("wordcount", string::wordcount);
-        self.register_filter("replace", string::replace);
-        self.register_filter("capitalize", string::capitalize);
-        self.register_filter("title", string::title);
-        self.register_filter("striptags", string::striptags);
-        self.register_filter("urlencode", string::urlencode);
-        self.register_filter("escape", string::escape_html);
-        self.register_filter("slugify", string::slugify);
-        self.register_filter("addslashes", string::addslashes);
-
-        self.register_filter("first", array::first);
-        self.register_filter("last", array::last);
-        self.register_filter("join", array::join);
-
+    /// Adds a variable available in all the templates rendered by this instance.
+    /// The context given when rendering is looked up first: a variable of the context shadows
+    /// the global of the same top-level name, even if it doesn't contain the attribute looked up.
+    /// If a global with that name already exists, it will be overwritten.
+    /// Errors if the value can't be converted to JSON, like a map with non-string keys.
+    ///
+    /// ```rust,ignore
+    /// tera.add_global("site", &site)?;
+    /// // {{ site.name }} now works in every template
+    /// ```
+    pub fn add_global<T: Serialize>(&mut self, key: &str, value: &T) -> Result<()> {
+        let value = to_value(value).chain_err(|| format!("Failed to convert the global `{}` to JSON", key))?;
+        if let Value::Object(ref mut globals) = self.globals {
+            globals.insert(key.to_string(), value);
+        }
+        Ok(())
+    }
+
+    /// Removes the global variable `key` and returns its value, if there was one
+    ///
+    /// ```rust,ignore
+    /// let site = tera.remove_global("site");
+    /// ```
+    pub fn remove_global(&mut self, key: &str) -> Option<Value> {
+        match self.globals {
+            Value::Object(ref mut globals) => globals.remove(key),
+            _ => None,
+        }
+    }
+
+    /// Returns the sorted names of all the registered global functions, built-in ones included.
+    ///
+    /// ```rust,ignore
+    /// assert_eq!(tera.get_function_names(), vec!["now", "range"]);
+    /// ```
+    pub fn get_function_names(&self) -> Vec<&str> {
+        let mut names = self.global_functions.keys().map(|k| k.as_str()).collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    pub fn get_global_function(&self, fn_name: &str) -> Result<&GlobalFn> {
+        match self.global_functions.get(fn_name) {
+            Some(f) => Ok(f),
+            None => bail!("Global function '{}' not found", fn_name),
+        }
+    }
+
+    /// Register a global function with Tera.
+    ///
+    /// A global function is a function or a closure taking a `HashMap` of its keyword arguments
+    /// and returning a `Result<Value>`. It is called in templates with `{{ name(arg=value) }}`.
+    /// Like filters, it needs to be `Send + Sync`.
+    /// If a function with that name already exists, it will be overwritten
+    ///
+    /// ```rust,ignore
+    /// tera.register_function("range", global_functions::range);
+    /// ```
+    pub fn register_function<F>(&mut self, name: &str, function: F)
+        where F: Fn(HashMap<String, Value>) -> Result<Value> + Send + Sync + 'static
+    {
+        self.global_functions.insert(name.to_string(), Arc::new(function));
+    }
+
+    fn register_tera_filters(&mut self) {
+        self.register_filter("upper", string::upper);
+        self.register_filter("lower", string::lower);
+        self.register_filter("trim", string::trim);
+        self.register_filter("trim_start", string::trim_start);
+        self.register_filter("trim_end", string::trim_end);
+        self.register_filter("truncate", string::truncate);
+        self.register_filter("truncate_words", string::truncate_words);
+        self.register_filter("wordcount", string::wordcount);
+        self.register_filter("wordwrap", string::wordwrap);
+        self.register_filter("indent", string::indent);
+        self.register_filter("replace", string::replace);
+        self.register_filter("split", string::split);
+        self.register_filter("matches", string::matches);
+        self.register_filter("findall", string::findall);
+        self.register_filter("capitalize", string::capitalize);
+        self.register_filter("title", string::title);
+        self.register_filter("striptags", string::striptags);
+        self.register_filter("urlencode", string::urlencode);
+        self.register_filter("escape", string::escape_html);
+        self.register_filter("escape_js", string::escape_js);
+        self.register_filter("slugify", string::slugify);
+        self.register_filter("addslashes", string::addslashes);
+
+        self.register_filter("first", array::first);
+        self.register_filter("last", array::last);
+        self.register_filter("nth", array::nth);
+        self.register_filter("join", array::join);
+        self.register_filter("min", array::min);
+        self.register_filter("max", array::max);
+        self.register_filter("sum", array::sum);
+        self.register_filter("sort", array::sort);
+        self.register_filter("dictsort", array::dictsort);
+        self.register_filter("group_by", array::group_by);
+        self.register_filter("map", array::map);
+        self.register_filter("filter", array::filter);
+        self.register_filter("batch", array::batch);
+        self.register_filter("unique", array::unique);
+
         self.register_filter("pluralize", number::pluralize);
         self.register_filter("round", number::round);
+        self.register_filter("abs", number::abs);
         self.register_filter("filesizeformat", number::filesizeformat);
+        self.register_filter("format", number::format);
+        self.register_filter("num_format", number::num_format);
 
         self.register_filter("length", common::length);
         self.register_filter("reverse", common::reverse);
         self.register_filter("date", common::date);
+        self.register_filter("default", common::default);
+        self.register_filter("json_encode", common::json_encode);
+        self.register_filter("pprint", common::pprint);
+        self.register_filter("get", common::get);
+        self.register_filter("attr", common::attr);
+        self.register_filter("slice", common::slice);
+        self.register_filter("int", common::int);
+        self.register_filter("float", common::float);
+        self.register_filter("stringify", common::stringify);
     }
 
     fn register_tera_testers(&mut self) {
@@ -422,6 +1062,15 @@ impl Tera {
         self.register_tester("number", testers::number);
         self.register_tester("divisibleby", testers::divisible_by);
         self.register_tester("iterable", testers::iterable);
+        self.register_tester("starting_with", testers::starting_with);
+        self.register_tester("ending_with", testers::ending_with);
+        self.register_tester("containing", testers::containing);
+        self.register_tester("matching", testers::matching);
+    }
+
+    fn register_tera_functions(&mut self) {
+        self.register_function("range", global_functions::range);
+        self.register_function("now", global_functions::now);
     }
 
     /// Select which suffix(es) to automatically do HTML escaping on,
@@ -439,19 +1088,109 @@ impl Tera {
     pub fn autoescape_on(&mut self, extensions: Vec<&'static str>) {
         self.autoescape_extensions = extensions;
     }
+
+    /// Set the function used to escape the output of autoescaped templates instead of
+    /// [escape_html](fn.escape_html.html), for example if the templates aren't HTML or
+    /// need other entities.
+    ///
+    /// It only changes autoescaping: the `escape` filter always escapes HTML.
+    ///
+    /// ```rust,ignore
+    /// fn escape_xml(input: &str) -> String {
+    ///     input.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('\'', "&apos;")
+    /// }
+    /// tera.set_escape_fn(escape_xml);
+    /// ```
+    pub fn set_escape_fn(&mut self, function: fn(&str) -> String) {
+        self.escape_fn = function;
+    }
+
+    /// Go back to escaping the output of autoescaped templates with
+    /// [escape_html](fn.escape_html.html)
+    pub fn reset_escape_fn(&mut self) {
+        self.escape_fn = escape_html;
+    }
+
+    /// Select whether a single newline at the end of the output of templates is kept, which is
+    /// the default. Removing it is useful when templates end with a newline but the output
+    /// needs to be the exact content of a file or a value.
+    ///
+    /// ```rust,ignore
+    /// tera.set_keep_trailing_newline(false);
+    /// ```
+    pub fn set_keep_trailing_newline(&mut self, keep: bool) {
+        self.keep_trailing_newline = keep;
+    }
+
+    /// Set how many macro calls and includes can be nested when rendering, 64 by default.
+    /// Going deeper is an error instead of a stack overflow, which matters when rendering
+    /// templates written by users where a macro could call itself without end.
+    ///
+    /// ```rust,ignore
+    /// tera.set_max_call_depth(16);
+    /// ```
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.max_call_depth = depth;
+    }
+
+    /// Select whether the source of the templates is kept after parsing them, to get it back
+    /// with [Tera::source_of](struct.Tera.html#method.source_of). Off by default as it keeps
+    /// every template in memory twice.
+    /// Only the templates loaded afterwards are affected: call `Tera::full_reload` to keep
+    /// the source of the templates found by the glob given to `Tera::new`.
+    ///
+    /// ```rust,ignore
+    /// let mut tera = Tera::default();
+    /// tera.set_keep_source(true);
+    /// tera.add_raw_template("hello.html", "Hello {{ name }}")?;
+    /// ```
+    pub fn set_keep_source(&mut self, keep: bool) {
+        self.keep_source = keep;
+    }
+
+    /// Returns the original source of the template `name`, for example to show the line an
+    /// error comes from. Only available for templates loaded after calling
+    /// `Tera::set_keep_source(true)`, `None` otherwise or if the template doesn't exist.
+    ///
+    /// ```rust,ignore
+    /// if let Some(source) = tera.source_of("hello.html") {
+    ///     println!("{}", source);
+    /// }
+    /// ```
+    pub fn source_of(&self, name: &str) -> Option<&str> {
+        self.templates.get(name).and_then(|tpl| tpl.source.as_deref())
+    }
+}
+
+// Adds an error and its causes to the list of errors of a bulk loading, so all the
+// templates failing to load can be reported at once
+fn push_error(errors: &mut String, e: &Error) {
+    *errors += &format!("\n* {}", e);
+    for e in e.iter().skip(1) {
+        *errors += &format!("\n-- {}", e);
+    }
 }
 
 impl Default for Tera {
     fn default() -> Tera {
         let mut tera = Tera {
+            glob: None,
+            delimiters: Delimiters::default(),
+            keep_source: false,
             templates: HashMap::new(),
             filters: HashMap::new(),
             testers: HashMap::new(),
-            autoescape_extensions: vec![".html", ".htm", ".xml"]
+            global_functions: HashMap::new(),
+            autoescape_extensions: vec![".html", ".htm", ".xml"],
+            escape_fn: escape_html,
+            keep_trailing_newline: true,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            globals: Value::Object(Map::new()),
         };
 
         tera.register_tera_filters();
         tera.register_tera_testers();
+        tera.register_tera_functions();
         tera
     }
 }
@@ -477,6 +1216,12 @@ impl fmt::Debug for Tera {
         for tester in self.testers.keys() {
             writeln!(f, "\t\t{},", tester)?;
         }
+        write!(f, "\t]")?;
+        write!(f, "\n\tglobal_functions: [\n")?;
+
+        for function in self.global_functions.keys() {
+            writeln!(f, "\t\t{},", function)?;
+        }
         write!(f, "\t]\n")?;
 
         writeln!(f, "{}", "}")
@@ -485,8 +1230,15 @@ impl fmt::Debug for Tera {
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
+    use std::{env, fs, process};
+    use std::sync::Arc;
+
     use super::{Tera};
     use context::Context;
+    use errors::Result;
+    use template::Template;
+    use delimiters::Delimiters;
     use serde_json::{Map as JsonObject, Value as JsonValue};
 
     #[test]
@@ -537,7 +1289,36 @@ mod tests {
             ("b", "{% extends \"a\" %}"),
         ]).unwrap_err();
 
-        assert!(err.description().contains("Circular extend detected for template"));
+        let message = err.to_string();
+        assert!(
+            message == "Circular extend detected for template 'a': a -> b -> a" ||
+            message == "Circular extend detected for template 'b': b -> a -> b"
+        );
+    }
+
+    #[test]
+    fn test_self_extends() {
+        let mut tera = Tera::default();
+        let err = tera.add_raw_template("a", "{% extends \"a\" %}").unwrap_err();
+
+        assert_eq!(err.to_string(), "Circular extend detected for template 'a': a -> a");
+    }
+
+    #[test]
+    fn test_circular_extends_not_through_start() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            ("a", "{% extends \"b\" %}"),
+            ("b", ""),
+        ]).unwrap();
+        // `c` isn't part of the loop but building its chain must not loop forever
+        let err = tera.add_raw_templates(vec![
+            ("b", "{% extends \"a\" %}"),
+            ("c", "{% extends \"a\" %}"),
+        ]).unwrap_err();
+
+        assert!(err.to_string().contains("Circular extend detected for template"));
+        assert!(err.to_string().contains("a -> b -> a") || err.to_string().contains("b -> a -> b"));
     }
 
     #[test]
@@ -570,10 +1351,421 @@ mod tests {
         assert_eq!(ending_definitions.len(), 1);
     }
 
+    #[test]
+    fn test_autoescape_on_changes_escaped_suffixes() {
+        let mut context = Context::new();
+        context.insert("greeting", &"<p>");
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            ("hello.html", "{{ greeting }}"),
+            ("hello.txt", "{{ greeting }}"),
+        ]).unwrap();
+        assert_eq!(tera.render("hello.html", context.clone()).unwrap(), "&lt;p&gt;");
+        assert_eq!(tera.render("hello.txt", context.clone()).unwrap(), "<p>");
+
+        tera.autoescape_on(vec![".txt"]);
+        assert_eq!(tera.render("hello.html", context.clone()).unwrap(), "<p>");
+        assert_eq!(tera.render("hello.txt", context).unwrap(), "&lt;p&gt;");
+    }
+
+    #[test]
+    fn test_render_value() {
+        let mut tera = Tera::default();
+        tera.add_raw_template("hello.html", "<p>{{ name }}</p>").unwrap();
+        let mut context = Context::new();
+        context.insert("name", &"<Bob>");
+
+        let result = tera.render_value("hello.html", context);
+        assert_eq!(result.unwrap(), JsonValue::String("<p>&lt;Bob&gt;</p>".to_string()));
+        assert!(tera.render_value("missing.html", Context::new()).is_err());
+    }
+
+    #[test]
+    fn test_render_ref_matches_render() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            ("base", "<title>{% block title %}{% endblock title %}</title>{% for i in items %}{{ i }}{% endfor %}"),
+            ("child", "{% extends \"base\" %}{% block title %}{{ name }}{% endblock title %}"),
+        ]).unwrap();
+        let mut context = Context::new();
+        context.insert("name", &"<Bob>");
+        context.insert("items", &vec![1, 2, 3]);
+
+        let result = tera.render_ref("child", &context);
+        assert_eq!(result.unwrap(), tera.render("child", context.clone()).unwrap());
+        // the context can still be used
+        assert_eq!(tera.render_ref("base", &context).unwrap(), "<title></title>123");
+        assert!(tera.render_ref("missing.html", &context).is_err());
+    }
+
+    #[test]
+    fn test_render_to_matches_render() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            ("base", "<title>{% block title %}{% endblock title %}</title>{% for i in items %}{{ i }}{% endfor %}"),
+            ("child", "{% extends \"base\" %}{% block title %}{{ name }}{% endblock title %}"),
+        ]).unwrap();
+        let mut context = Context::new();
+        context.insert("name", &"<Bob>");
+        context.insert("items", &vec![1, 2, 3]);
+
+        let mut output = Vec::new();
+        let result = tera.render_to("child", context.clone(), &mut output);
+        assert!(result.is_ok());
+        assert_eq!(String::from_utf8(output).unwrap(), tera.render("child", context).unwrap());
+    }
+
+    #[test]
+    fn test_render_bytes_matches_render() {
+        let mut tera = Tera::default();
+        tera.add_raw_template("message", "HELLO {{ name }}\r\n{% for i in items %}{{ i }}\r\n{% endfor %}").unwrap();
+        let mut context = Context::new();
+        context.insert("name", &"Bob\r");
+        context.insert("items", &vec!["é", "\u{1}"]);
+
+        let result = tera.render_bytes("message", context.clone());
+        assert_eq!(result.unwrap(), tera.render("message", context).unwrap().into_bytes());
+        assert!(tera.render_bytes("missing", Context::new()).is_err());
+    }
+
+    #[test]
+    fn test_render_with_map_into_context() {
+        let mut tera = Tera::default();
+        tera.add_raw_template("hello", "Hello {{ name }}, you have {{ messages | length }} messages").unwrap();
+        let mut map = JsonObject::new();
+        map.insert("name".to_string(), JsonValue::String("Bob".to_string()));
+        map.insert("messages".to_string(), JsonValue::Array(vec![JsonValue::from("hi"), JsonValue::from("hey")]));
+
+        assert_eq!(tera.render("hello", map.into()).unwrap(), "Hello Bob, you have 2 messages");
+    }
+
+    #[test]
+    fn test_recursive_macro_errors_at_max_call_depth() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            ("macros", "{% macro forever(n) %}{{ n }}{{ self::forever(n=n) }}{% endmacro forever %}"),
+            ("tpl", "{% import \"macros\" as macros %}{{ macros::forever(n=1) }}"),
+        ]).unwrap();
+
+        let result = tera.render("tpl", Context::new());
+        assert!(result.is_err());
+        let errors = result.unwrap_err().iter().map(|e| e.to_string()).collect::<Vec<_>>().join("\n");
+        assert!(errors.contains("Reached the maximum depth of 64 nested macro calls and includes when calling the macro `forever`"));
+
+        tera.set_max_call_depth(3);
+        let errors = tera.render("tpl", Context::new()).unwrap_err().iter().map(|e| e.to_string()).collect::<Vec<_>>().join("\n");
+        assert!(errors.contains("Reached the maximum depth of 3 nested macro calls"));
+    }
+
+    #[test]
+    fn test_max_call_depth_counts_includes_and_macros() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            ("macros", "{% macro hello() %}Hello{% endmacro hello %}"),
+            ("inner", "{% import \"macros\" as macros %}{{ macros::hello() }}"),
+            ("outer", "{% include \"inner\" %}"),
+        ]).unwrap();
+        tera.set_max_call_depth(2);
+        assert_eq!(tera.render("outer", Context::new()).unwrap(), "Hello");
+
+        tera.set_max_call_depth(1);
+        let errors = tera.render("outer", Context::new()).unwrap_err().iter().map(|e| e.to_string()).collect::<Vec<_>>().join("\n");
+        assert!(errors.contains("Reached the maximum depth of 1 nested macro calls and includes"));
+    }
+
+    #[test]
+    fn test_render_with_blocks() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            ("base.html", "<title>{% block title %}Base{% endblock title %}</title>{% block content %}[{% block inner %}{% endblock inner %}]{% endblock content %}"),
+            ("child.html", "{% extends \"base.html\" %}{% block title %}Child - {{ super() }}{% endblock title %}{% block inner %}Child{% endblock inner %}"),
+        ]).unwrap();
+
+        let mut overrides = HashMap::new();
+        overrides.insert("title".to_string(), "<b>Report</b>".to_string());
+        let result = tera.render_with_blocks("child.html", Context::new(), overrides.clone());
+        assert_eq!(result.unwrap(), "<title><b>Report</b></title>[Child]");
+
+        // overriding a block replaces the blocks nested in it as well
+        overrides.insert("content".to_string(), "Generated".to_string());
+        let result = tera.render_with_blocks("child.html", Context::new(), overrides);
+        assert_eq!(result.unwrap(), "<title><b>Report</b></title>Generated");
+
+        // other renders are not affected
+        assert_eq!(tera.render("child.html", Context::new()).unwrap(), "<title>Child - Base</title>[Child]");
+    }
+
+    #[test]
+    fn test_render_with_blocks_unknown_block() {
+        let mut tera = Tera::default();
+        tera.add_raw_template("base.html", "{% block content %}{% endblock content %}").unwrap();
+        let mut overrides = HashMap::new();
+        overrides.insert("contents".to_string(), "Hello".to_string());
+
+        let result = tera.render_with_blocks("base.html", Context::new(), overrides);
+        assert_eq!(
+            result.unwrap_err().description(),
+            "Tried to override the block `contents` but template 'base.html' doesn't have it"
+        );
+    }
+
+    #[test]
+    fn test_render_macro() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            ("macros", "{% macro greeting(name, greeting=\"Hello\") %}{{ greeting }} {{ self::bold(text=name) }}{% endmacro greeting %}\
+                        {% macro bold(text) %}<b>{{ text }}</b>{% endmacro bold %}"),
+            ("tpl", "{% import \"macros\" as macros %}{{ macros::greeting(name=\"Bob\") }}"),
+        ]).unwrap();
+
+        let mut args = HashMap::new();
+        args.insert("name".to_string(), JsonValue::String("Bob".to_string()));
+        let direct = tera.render_macro("macros", "macros", "greeting", args.clone()).unwrap();
+        assert_eq!(direct, "Hello <b>Bob</b>");
+        assert_eq!(direct, tera.render("tpl", Context::new()).unwrap());
+
+        args.insert("greeting".to_string(), JsonValue::String("Hi".to_string()));
+        assert_eq!(tera.render_macro("macros", "macros", "greeting", args).unwrap(), "Hi <b>Bob</b>");
+    }
+
+    #[test]
+    fn test_render_macro_errors() {
+        let mut tera = Tera::default();
+        tera.add_raw_template("macros", "{% macro greeting(name) %}Hello {{ name }}{% endmacro greeting %}").unwrap();
+        let errors = |result: Result<String>| result.unwrap_err().iter().map(|e| e.to_string()).collect::<Vec<_>>().join("\n");
+
+        let result = tera.render_macro("macros", "macros", "hello", HashMap::new());
+        assert_eq!(errors(result), "Macro `hello` was not found in 'macros'");
+
+        let result = tera.render_macro("macros", "macros", "greeting", HashMap::new());
+        assert!(errors(result).contains("Macro `greeting` was called without the arg `name`, which doesn't have a default value"));
+
+        let mut args = HashMap::new();
+        args.insert("nam".to_string(), JsonValue::String("Bob".to_string()));
+        let result = tera.render_macro("macros", "macros", "greeting", args);
+        assert!(errors(result).contains("Macro `greeting` got `[\"nam\"]` for args but was expecting `[\"name\"]`"));
+
+        let mut args = HashMap::new();
+        for name in &["surname", "age", "nam", "title"] {
+            args.insert(name.to_string(), JsonValue::String("Bob".to_string()));
+        }
+        let result = tera.render_macro("macros", "macros", "greeting", args);
+        assert!(errors(result).contains(
+            "Macro `greeting` got `[\"age\", \"nam\", \"surname\", \"title\"]` for args but was expecting `[\"name\"]`"
+        ));
+
+        let result = tera.render_macro("missing", "macros", "greeting", HashMap::new());
+        assert_eq!(errors(result), "Template 'missing' not found");
+    }
+
+    #[test]
+    fn test_source_of() {
+        let mut tera = Tera::default();
+        tera.add_raw_template("before", "Hello").unwrap();
+        assert_eq!(tera.source_of("before"), None);
+
+        tera.set_keep_source(true);
+        let source = "{% extends \"base\" %}\n{% block content %}Hello {{ name }}{% endblock content %}\n";
+        tera.add_raw_templates(vec![
+            ("base", "{% block content %}{% endblock content %}"),
+            ("child", source),
+        ]).unwrap();
+        assert_eq!(tera.source_of("child"), Some(source));
+        assert_eq!(tera.source_of("base"), Some("{% block content %}{% endblock content %}"));
+        assert_eq!(tera.source_of("before"), None);
+        assert_eq!(tera.source_of("missing"), None);
+        // the source is kept through building the inheritance chains and doesn't change the output
+        assert_eq!(tera.get_template("child").unwrap().parents, vec!["base".to_string()]);
+        let mut context = Context::new();
+        context.insert("name", &"Bob");
+        assert_eq!(tera.render("child", context).unwrap(), "Hello Bob");
+    }
+
+    #[test]
+    fn test_source_of_keeps_custom_delimiters() {
+        let mut tera = Tera::default();
+        tera.set_delimiters(Delimiters { variable: ("<<", ">>"), ..Delimiters::default() }).unwrap();
+        tera.set_keep_source(true);
+        tera.add_raw_template("tpl", "<< a >> {{ b }}").unwrap();
+        assert_eq!(tera.source_of("tpl"), Some("<< a >> {{ b }}"));
+    }
+
+    #[test]
+    fn test_keep_trailing_newline() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            ("one", "Hello {{ name }}\n"),
+            ("two", "Hello\n{{ name }}\n\n{% set a = 1 %}"),
+            ("crlf", "Hello\r\n"),
+            ("none", "Hello"),
+        ]).unwrap();
+        let mut context = Context::new();
+        context.insert("name", &"Bob");
+
+        assert_eq!(tera.render("one", context.clone()).unwrap(), "Hello Bob\n");
+        assert_eq!(tera.render("crlf", context.clone()).unwrap(), "Hello\r\n");
+
+        tera.set_keep_trailing_newline(false);
+        // only a single newline is removed
+        let tests = vec![("one", "Hello Bob"), ("two", "Hello\nBob\n"), ("crlf", "Hello"), ("none", "Hello")];
+        for (name, expected) in tests {
+            assert_eq!(tera.render(name, context.clone()).unwrap(), expected);
+            let mut output = Vec::new();
+            tera.render_to(name, context.clone(), &mut output).unwrap();
+            assert_eq!(String::from_utf8(output).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_render_to_keeps_partial_output_on_error() {
+        let mut tera = Tera::default();
+        tera.add_raw_template("hello", "Hello {{ name }}").unwrap();
+
+        let mut output = Vec::new();
+        let result = tera.render_to("hello", Context::new(), &mut output);
+        assert!(result.is_err());
+        assert_eq!(String::from_utf8(output).unwrap(), "Hello ");
+    }
+
+    #[test]
+    fn test_extend_with_parent_from_other_instance() {
+        let mut shared = Tera::default();
+        shared.add_raw_templates(vec![
+            ("base", "<h1>{% block title %}{% endblock title %}</h1>{% include \"footer\" %}"),
+            ("footer", "shared footer"),
+        ]).unwrap();
+        shared.register_filter("shout", |value: JsonValue, _: HashMap<String, JsonValue>| {
+            Ok(JsonValue::String(format!("{}!", value.as_str().unwrap())))
+        });
+
+        let mut tera = Tera::default();
+        tera.add_raw_template("footer", "app footer").unwrap();
+        tera.extend(&shared).unwrap();
+        tera.add_raw_template("child", "{% extends \"base\" %}{% block title %}{{ name | shout }}{% endblock title %}").unwrap();
+
+        let mut context = Context::new();
+        context.insert("name", &"Bob");
+        assert_eq!(tera.render("child", context).unwrap(), "<h1>Bob!</h1>app footer");
+        // `shared` is left as it was
+        assert!(shared.get_template("child").is_err());
+    }
+
+    #[test]
+    fn test_extend_keeps_own_filters() {
+        let mut shared = Tera::default();
+        shared.register_filter("upper", |_: JsonValue, _: HashMap<String, JsonValue>| {
+            Ok(JsonValue::String("shared".to_string()))
+        });
+        let mut tera = Tera::default();
+        tera.add_raw_template("hello", "{{ name | upper }}").unwrap();
+        tera.extend(&shared).unwrap();
+
+        let mut context = Context::new();
+        context.insert("name", &"bob");
+        assert_eq!(tera.render("hello", context).unwrap(), "BOB");
+    }
+
+    #[test]
+    fn test_global_lookup() {
+        let mut tera = Tera::default();
+        let mut site = HashMap::new();
+        site.insert("name", "My site");
+        tera.add_global("site", &site).unwrap();
+        tera.add_global("year", &2017).unwrap();
+        tera.add_raw_template("tpl", "{{ site.name }} {{ year }}{% if site %}!{% endif %}").unwrap();
+
+        assert_eq!(tera.render("tpl", Context::new()).unwrap(), "My site 2017!");
+    }
+
+    #[test]
+    fn test_context_shadows_globals() {
+        let mut tera = Tera::default();
+        let mut site = HashMap::new();
+        site.insert("name", "My site");
+        tera.add_global("site", &site).unwrap();
+        tera.add_global("year", &2017).unwrap();
+        tera.add_raw_templates(vec![
+            ("tpl", "{{ site.name }} {{ year }}"),
+            ("missing", "{{ site.name }}"),
+            ("for", "{% for year in [1, 2] %}{{ year }}{{ site.name }}{% endfor %}"),
+        ]).unwrap();
+
+        let mut context = Context::new();
+        let mut other_site = HashMap::new();
+        other_site.insert("name", "Other site");
+        context.insert("site", &other_site);
+        assert_eq!(tera.render("tpl", context.clone()).unwrap(), "Other site 2017");
+        // loop variables come first, then the context
+        assert_eq!(tera.render("for", context).unwrap(), "1Other site2Other site");
+
+        // the global is not used even if the context variable lacks the attribute
+        let mut context = Context::new();
+        context.insert("site", &HashMap::<String, String>::new());
+        assert!(tera.render("missing", context).is_err());
+    }
+
+    #[test]
+    fn test_add_global_not_serializable() {
+        let mut tera = Tera::default();
+        let mut map = HashMap::new();
+        map.insert((1, 2), "a");
+        let result = tera.add_global("map", &map);
+        assert_eq!(result.unwrap_err().description(), "Failed to convert the global `map` to JSON");
+        assert_eq!(tera.remove_global("map"), None);
+    }
+
+    #[test]
+    fn test_remove_global() {
+        let mut tera = Tera::default();
+        tera.add_global("year", &2017).unwrap();
+        tera.add_raw_template("tpl", "{{ year }}").unwrap();
+        assert_eq!(tera.render("tpl", Context::new()).unwrap(), "2017");
+
+        assert_eq!(tera.remove_global("year"), Some(JsonValue::from(2017)));
+        assert_eq!(tera.remove_global("year"), None);
+        assert!(tera.render("tpl", Context::new()).is_err());
+    }
+
+    #[test]
+    fn test_extend_keeps_own_globals() {
+        let mut shared = Tera::default();
+        shared.add_global("year", &2000).unwrap();
+        shared.add_global("author", &"Bob").unwrap();
+        let mut tera = Tera::default();
+        tera.add_global("year", &2017).unwrap();
+        tera.add_raw_template("tpl", "{{ author }} {{ year }}").unwrap();
+        tera.extend(&shared).unwrap();
+
+        assert_eq!(tera.render("tpl", Context::new()).unwrap(), "Bob 2017");
+    }
+
+    #[test]
+    fn test_clone_shares_templates() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            ("base", "<h1>{% block title %}{% endblock title %}</h1>"),
+            ("child", "{% extends \"base\" %}{% block title %}{{ name | upper }}{% endblock title %}"),
+        ]).unwrap();
+        let mut cloned = tera.clone();
+
+        for name in tera.get_template_names() {
+            assert!(Arc::ptr_eq(&tera.templates[name], &cloned.templates[name]));
+        }
+        let mut context = Context::new();
+        context.insert("name", &"Bob");
+        assert_eq!(tera.render("child", context.clone()).unwrap(), "<h1>BOB</h1>");
+        assert_eq!(cloned.render("child", context.clone()).unwrap(), "<h1>BOB</h1>");
+
+        // Changing the clone leaves the original as it was
+        cloned.add_raw_template("base", "<h2>{% block title %}{% endblock title %}</h2>").unwrap();
+        assert_eq!(cloned.render("child", context.clone()).unwrap(), "<h2>BOB</h2>");
+        assert_eq!(tera.render("child", context).unwrap(), "<h1>BOB</h1>");
+    }
+
     #[test]
     fn test_can_autoescape_one_off_template() {
         let mut context = Context::new();
-        context.add("greeting", &"<p>");
+        context.insert("greeting", &"<p>");
         let result = Tera::one_off("{{ greeting }} world", context, true).unwrap();
 
         assert_eq!(result, "&lt;p&gt; world");
@@ -582,7 +1774,7 @@ mod tests {
     #[test]
     fn test_can_disable_autoescape_one_off_template() {
         let mut context = Context::new();
-        context.add("greeting", &"<p>");
+        context.insert("greeting", &"<p>");
         let result = Tera::one_off("{{ greeting }} world", context, false).unwrap();
 
         assert_eq!(result, "<p> world");
@@ -596,4 +1788,509 @@ mod tests {
 
         assert_eq!(result, "Good morning world");
     }
+
+    #[test]
+    fn test_full_reload() {
+        let dir = env::temp_dir().join(format!("tera-full-reload-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let tpl_path = dir.join("hello.html");
+        fs::write(&tpl_path, "Hello {{ name }}").unwrap();
+
+        let glob = format!("{}/*.html", dir.to_string_lossy());
+        let mut tera = Tera::new(&glob).unwrap();
+        tera.register_filter("shout", |value: JsonValue, _: HashMap<String, JsonValue>| {
+            Ok(JsonValue::String(format!("{}!", value.as_str().unwrap_or(""))))
+        });
+        let mut context = Context::new();
+        context.insert("name", &"Bob");
+        assert_eq!(tera.render("hello.html", context.clone()).unwrap(), "Hello Bob");
+
+        fs::write(&tpl_path, "Bye {{ name | shout }}").unwrap();
+        tera.full_reload().unwrap();
+        assert_eq!(tera.render("hello.html", context.clone()).unwrap(), "Bye Bob!");
+
+        // An invalid template keeps the previous ones around
+        fs::write(&tpl_path, "Bye {{ name").unwrap();
+        assert!(tera.full_reload().is_err());
+        assert_eq!(tera.render("hello.html", context).unwrap(), "Bye Bob!");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_new_reports_all_parse_errors() {
+        let dir = env::temp_dir().join(format!("tera-parse-errors-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("ok.html"), "Hello {{ name }}").unwrap();
+        fs::write(dir.join("unclosed.html"), "Hello {{ name").unwrap();
+        fs::write(dir.join("unknown_tag.html"), "{% hello %}").unwrap();
+        fs::write(dir.join("valid.html"), "{% if a %}a{% endif %}").unwrap();
+
+        let glob = format!("{}/*.html", dir.to_string_lossy());
+        let err = Tera::new(&glob).unwrap_err().to_string();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(err.matches("\n* Failed to parse").count(), 2);
+        assert!(err.contains("unclosed.html"));
+        assert!(err.contains("unknown_tag.html"));
+        assert!(!err.contains("ok.html"));
+        assert!(!err.contains("valid.html"));
+    }
+
+    fn latex_delimiters() -> Delimiters {
+        Delimiters { variable: ("<<", ">>"), block: ("<%", "%>"), comment: ("<#", "#>") }
+    }
+
+    #[test]
+    fn test_set_delimiters() {
+        let mut tera = Tera::default();
+        tera.set_delimiters(latex_delimiters()).unwrap();
+        tera.add_raw_templates(vec![
+            ("base.tex", "\\begin{document}<% block body %><% endblock body %>\\end{document}"),
+            ("doc.tex", "<% extends \"base.tex\" %><# a comment #><% block body %>\
+                <% for item in items %>\\textbf{<< item | upper >>}{{}}<% endfor %>\
+                <% endblock body %>"),
+        ]).unwrap();
+        let mut context = Context::new();
+        context.insert("items", &vec!["a", "b"]);
+
+        assert_eq!(
+            tera.render("doc.tex", context.clone()).unwrap(),
+            "\\begin{document}\\textbf{A}{{}}\\textbf{B}{{}}\\end{document}"
+        );
+        assert_eq!(tera.render_str("{{ << items | length >> }}", context).unwrap(), "{{ 2 }}");
+    }
+
+    #[test]
+    fn test_delimiters_raw_and_error_positions() {
+        let mut tera = Tera::default();
+        tera.set_delimiters(latex_delimiters()).unwrap();
+        tera.add_raw_template("raw.tex", "<% raw %>{% raw %}<< a >>{% endraw %}<% endraw %>").unwrap();
+        assert_eq!(tera.render("raw.tex", Context::new()).unwrap(), "{% raw %}<< a >>{% endraw %}");
+
+        // the errors give the positions in the template as it was written
+        tera.add_raw_template("missing.tex", "{{}} <% for i in [1] %><< missing >><% endfor %>").unwrap();
+        let err = tera.render("missing.tex", Context::new()).unwrap_err();
+        assert_eq!(
+            err.iter().nth(1).unwrap().to_string(),
+            "Field `missing` not found in context in template \"missing.tex\" at line 1, column 27"
+        );
+    }
+
+    #[test]
+    fn test_set_delimiters_invalid() {
+        let mut tera = Tera::default();
+        let delimiters = Delimiters { variable: ("<%", ">>"), block: ("<%", "%>"), comment: ("<#", "#>") };
+        assert!(tera.set_delimiters(delimiters).is_err());
+        // still using the default ones
+        tera.add_raw_template("hello", "{{ 1 + 1 }}").unwrap();
+        assert_eq!(tera.render("hello", Context::new()).unwrap(), "2");
+    }
+
+    #[test]
+    fn test_new_with_delimiters() {
+        let dir = env::temp_dir().join(format!("tera-delimiters-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("hello.tex"), "\\frac{{<< a >>}}{2}").unwrap();
+        let glob = format!("{}/*.tex", dir.to_string_lossy());
+
+        // `{{<<` can't be parsed with the default delimiters
+        assert!(Tera::new(&glob).is_err());
+        let tera = Tera::new_with_delimiters(&glob, latex_delimiters());
+        let mut context = Context::new();
+        context.insert("a", &1);
+        let result = tera.map(|t| t.render("hello.tex", context));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(result.unwrap().unwrap(), "\\frac{{1}}{2}");
+    }
+
+    #[test]
+    fn test_set_delimiters_reloads_glob_templates() {
+        let dir = env::temp_dir().join(format!("tera-set-delimiters-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("hello.js"), "var a = {}; var b = <% if b %>\"<< b >>\"<% endif %>;").unwrap();
+        let glob = format!("{}/*.js", dir.to_string_lossy());
+
+        let mut tera = Tera::new(&glob).unwrap();
+        tera.set_delimiters(latex_delimiters()).unwrap();
+        let mut context = Context::new();
+        context.insert("b", &"hey");
+        assert_eq!(tera.render("hello.js", context.clone()).unwrap(), "var a = {}; var b = \"hey\";");
+
+        // the template can't be parsed with the default delimiters so they aren't changed
+        fs::write(dir.join("hello.js"), "var a = {{}}; var b = \"<< b >>\";").unwrap();
+        let result = tera.set_delimiters(Delimiters::default());
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(result.is_err());
+        assert_eq!(tera.delimiters, latex_delimiters());
+        assert_eq!(tera.render("hello.js", context).unwrap(), "var a = {}; var b = \"hey\";");
+    }
+
+    #[test]
+    fn test_set_delimiters_keeps_templates_not_loaded_by_glob() {
+        let dir = env::temp_dir().join(format!("tera-set-delimiters-keep-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.html"), "<% if a %><< a >><% endif %>").unwrap();
+        fs::write(dir.join("b.html"), "glob b").unwrap();
+        let glob = format!("{}/*.html", dir.to_string_lossy());
+
+        let mut tera = Tera::new(&glob).unwrap();
+        tera.add_raw_template("raw.txt", "{{ a }} <<").unwrap();
+        tera.add_raw_template("b.html", "raw b").unwrap();
+        let result = tera.set_delimiters(latex_delimiters());
+        fs::remove_dir_all(&dir).unwrap();
+        result.unwrap();
+
+        assert_eq!(tera.get_template_names(), vec!["a.html", "b.html", "raw.txt"]);
+        let mut context = Context::new();
+        context.insert("a", &"hey");
+        assert_eq!(tera.render("a.html", context.clone()).unwrap(), "hey");
+        // templates added in other ways keep the delimiters they were parsed with
+        assert_eq!(tera.render("raw.txt", context.clone()).unwrap(), "hey <<");
+        assert_eq!(tera.render("b.html", context).unwrap(), "raw b");
+    }
+
+    #[test]
+    fn test_add_raw_templates_reports_all_parse_errors() {
+        let mut tera = Tera::default();
+        let err = tera.add_raw_templates(vec![
+            ("ok", "Hello"),
+            ("unclosed", "Hello {{ name"),
+            ("unknown_tag", "{% hello %}"),
+            ("valid", "{{ name }}"),
+        ]).unwrap_err().to_string();
+
+        assert_eq!(err.matches("\n* Failed to parse").count(), 2);
+        assert!(err.contains("\n* Failed to parse 'unclosed'\n-- "));
+        assert!(err.contains("\n* Failed to parse 'unknown_tag'\n-- "));
+        assert!(!err.contains("'ok'"));
+        assert!(!err.contains("'valid'"));
+    }
+
+    #[test]
+    fn test_full_reload_without_glob() {
+        let mut tera = Tera::default();
+        tera.add_raw_template("hello", "Hello").unwrap();
+        assert_eq!(
+            tera.full_reload().unwrap_err().to_string(),
+            "Reloading is only available if the Tera instance was created with a glob"
+        );
+        assert!(tera.get_template("hello").is_ok());
+    }
+
+    #[test]
+    fn test_render_str() {
+        let tera = Tera::default();
+        let mut context = Context::new();
+        context.insert("greeting", &"hello");
+        let result = tera.render_str("{{ greeting }} world", context).unwrap();
+
+        assert_eq!(result, "hello world");
+    }
+
+    #[test]
+    fn test_render_str_with_registered_filter() {
+        fn shout(value: JsonValue, _: HashMap<String, JsonValue>) -> Result<JsonValue> {
+            Ok(JsonValue::String(format!("{}!", value.as_str().unwrap())))
+        }
+        let mut tera = Tera::default();
+        tera.register_filter("shout", shout);
+        let mut context = Context::new();
+        context.insert("greeting", &"hello");
+        let result = tera.render_str("{{ greeting | upper | shout }} world", context).unwrap();
+
+        assert_eq!(result, "HELLO! world");
+    }
+
+    #[test]
+    fn test_render_str_with_inheritance_and_include() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            ("parent", "{% block hey %}hello{% endblock hey %} {% block ending %}sincerely{% endblock ending %}"),
+            ("signature", "Bob"),
+        ]).unwrap();
+        let result = tera.render_str(
+            "{% extends \"parent\" %}{% block ending %}{{ super() }}, {% include \"signature\" %}{% endblock ending %}",
+            Context::new()
+        ).unwrap();
+
+        assert_eq!(result, "hello sincerely, Bob");
+        // and the template isn't added to the instance
+        assert_eq!(tera.templates.len(), 2);
+    }
+
+    #[test]
+    fn test_render_str_missing_parent() {
+        let tera = Tera::default();
+        let result = tera.render_str("{% extends \"parent\" %}", Context::new());
+
+        assert_eq!(
+            result.unwrap_err().description(),
+            "Template 'render_str' is inheriting from 'parent', which doesn't exist or isn't loaded."
+        );
+    }
+
+    #[test]
+    fn test_error_on_missing_macro_file_when_loading_in_bulk() {
+        let mut tera = Tera::default();
+        let result = tera.add_raw_templates(vec![
+            ("index.html", "{% import \"macros.html\" as m %}{{ m::hello() }}"),
+            ("about.html", "{% import \"macros.html\" as m %}{% import \"forms.html\" as f %}"),
+        ]);
+
+        assert!(result.is_err());
+        assert_eq!(
+            result.unwrap_err().description(),
+            "Template 'about.html' imports macros from 'forms.html' but it isn't loaded\n\
+            Template 'about.html' imports macros from 'macros.html' but it isn't loaded\n\
+            Template 'index.html' imports macros from 'macros.html' but it isn't loaded"
+        );
+    }
+
+    #[test]
+    fn test_add_raw_templates_only_checks_macro_imports_of_batch() {
+        let mut tera = Tera::default();
+        tera.add_raw_template("index.html", "{% import \"macros.html\" as m %}{{ m::hello() }}").unwrap();
+        assert!(tera.add_raw_templates(vec![("about.html", "About")]).is_ok());
+
+        // the macros can be in the same batch
+        let result = tera.add_raw_templates(vec![
+            ("contact.html", "{% import \"forms.html\" as f %}"),
+            ("forms.html", "{% macro input() %}<input>{% endmacro input %}"),
+        ]);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_add_raw_templates_missing_macros_adds_nothing() {
+        let mut tera = Tera::default();
+        let result = tera.add_raw_templates(vec![
+            ("index.html", "Index"),
+            ("about.html", "{% import \"macros.html\" as m %}"),
+        ]);
+        assert!(result.is_err());
+        assert!(tera.get_template_names().is_empty());
+    }
+
+    #[test]
+    fn test_check_macro_files() {
+        let mut tera = Tera::default();
+        tera.add_raw_template("index.html", "{% import \"macros.html\" as m %}{{ m::hello() }}").unwrap();
+        assert!(tera.check_macro_files().is_err());
+
+        tera.add_raw_template("macros.html", "{% macro hello() %}Hello{% endmacro hello %}").unwrap();
+        assert!(tera.check_macro_files().is_ok());
+    }
+
+    #[test]
+    fn test_add_raw_template_checked_errors_on_duplicate() {
+        let mut tera = Tera::default();
+        assert!(tera.add_raw_template_checked("hello", "Hello").is_ok());
+
+        let result = tera.add_raw_template_checked("hello", "Bye");
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err().description(), "Template 'hello' already exists");
+        assert_eq!(tera.render("hello", Context::new()).unwrap(), "Hello".to_string());
+    }
+
+    #[test]
+    fn test_add_raw_template_overwrites_duplicate() {
+        let mut tera = Tera::default();
+        tera.add_raw_template("hello", "Hello").unwrap();
+        assert!(tera.add_raw_template("hello", "Bye").is_ok());
+        assert_eq!(tera.render("hello", Context::new()).unwrap(), "Bye".to_string());
+    }
+
+    #[test]
+    fn test_validate() {
+        let tera = Tera::default();
+        assert!(tera.validate("{% extends \"missing.html\" %}{% block a %}{{ super() }}{% endblock a %}").is_ok());
+        assert!(tera.validate("Hello {{ name | upper }}").is_ok());
+
+        let tests = vec![
+            ("{% block a %}{% endblock a %}{% block a %}{% endblock a %}", "Block `a` is duplicated"),
+            (
+                "{% macro a() %}{% endmacro a %}{% macro a() %}{% endmacro a %}",
+                "Macro `a` is duplicated"
+            ),
+            ("{% break %}", "`{% break %}` can only be used in the body of a for loop"),
+            ("{{ name", "Invalid Tera syntax at line 1, column 8\n1 | {{ name\n  |        ^"),
+        ];
+        for (input, expected) in tests {
+            let result = tera.validate(input);
+            assert!(result.is_err());
+            assert_eq!(result.unwrap_err().description(), expected);
+        }
+        assert!(tera.get_template_names().is_empty());
+    }
+
+    #[test]
+    fn test_validate_uses_delimiters() {
+        let mut tera = Tera::default();
+        tera.set_delimiters(Delimiters { variable: ("<<", ">>"), ..Delimiters::default() }).unwrap();
+        assert!(tera.validate("{{ Hello << name >>").is_ok());
+        assert!(tera.validate("<< name").is_err());
+    }
+
+    #[test]
+    fn test_add_parsed_template() {
+        let mut tera = Tera::default();
+        tera.add_raw_template("parent", "{% block hey %}hello{% endblock hey %} world").unwrap();
+        let tpl = Template::new(
+            "child",
+            None,
+            "{% extends \"parent\" %}{% block hey %}{{ super() }} {{ name }}{% endblock hey %}"
+        ).unwrap();
+        tera.add_parsed_template(tpl).unwrap();
+
+        let mut context = Context::new();
+        context.insert("name", &"Bob");
+        assert_eq!(tera.render("child", context).unwrap(), "hello Bob world");
+        let mut context = Context::new();
+        context.insert("name", &"Alice");
+        assert_eq!(tera.render("child", context).unwrap(), "hello Alice world");
+    }
+
+    #[test]
+    fn test_add_parsed_template_missing_parent() {
+        let mut tera = Tera::default();
+        let tpl = Template::new("child", None, "{% extends \"parent\" %}").unwrap();
+
+        assert_eq!(
+            tera.add_parsed_template(tpl).unwrap_err().description(),
+            "Template 'child' is inheriting from 'parent', which doesn't exist or isn't loaded."
+        );
+    }
+
+    #[test]
+    fn test_remove_template() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            ("base", "{% block content %}base{% endblock content %}"),
+            ("child", "{% extends \"base\" %}{% block content %}{{ super() }} child{% endblock content %}"),
+            ("grandchild", "{% extends \"child\" %}{% block content %}{{ super() }} grandchild{% endblock content %}"),
+        ]).unwrap();
+
+        tera.remove_template("grandchild").unwrap();
+        assert_eq!(tera.get_template_names(), vec!["base", "child"]);
+        assert!(tera.render("grandchild", Context::new()).is_err());
+        // the remaining templates are still linked
+        assert_eq!(tera.get_template("child").unwrap().parents, vec!["base".to_string()]);
+        assert_eq!(tera.render("child", Context::new()).unwrap(), "base child");
+
+        tera.remove_template("child").unwrap();
+        tera.remove_template("base").unwrap();
+        assert!(tera.templates.is_empty());
+    }
+
+    #[test]
+    fn test_remove_template_still_extended() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            ("base", "{% block content %}{% endblock content %}"),
+            ("b", "{% extends \"base\" %}"),
+            ("a", "{% extends \"base\" %}"),
+        ]).unwrap();
+
+        assert_eq!(
+            tera.remove_template("base").unwrap_err().description(),
+            "Template 'base' can't be removed as it is extended by: a, b"
+        );
+        assert_eq!(tera.templates.len(), 3);
+        assert_eq!(
+            tera.remove_template("missing").unwrap_err().description(),
+            "Template 'missing' not found"
+        );
+    }
+
+    #[test]
+    fn test_register_closure_filter() {
+        let suffix = "!".to_string();
+        let mut tera = Tera::default();
+        tera.register_filter("myfilter", move |value: JsonValue, args: HashMap<String, JsonValue>| {
+            let times = args.get("arg").and_then(|a| a.as_u64()).unwrap_or(1) as usize;
+            Ok(JsonValue::String(format!("{}{}", value.as_str().unwrap(), suffix.repeat(times))))
+        });
+        let mut context = Context::new();
+        context.insert("x", &"hello");
+        let result = tera.render_str("{{ x | myfilter(arg=1) }} {{ x | myfilter(arg=3) }}", context).unwrap();
+
+        assert_eq!(result, "hello! hello!!!");
+    }
+
+    #[test]
+    fn test_get_template() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            ("base.html", "{% block content %}{% endblock content %}"),
+            ("child.html", "{% extends \"base.html\" %}{% block content %}Hello{% endblock content %}"),
+        ]).unwrap();
+
+        let child = tera.get_template("child.html").unwrap();
+        assert_eq!(child.name, "child.html");
+        assert_eq!(child.parent, Some("base.html".to_string()));
+        assert_eq!(child.parents, vec!["base.html".to_string()]);
+        assert_eq!(child.blocks_definitions["content"].len(), 2);
+
+        assert_eq!(
+            tera.get_template("missing.html").unwrap_err().description(),
+            "Template 'missing.html' not found"
+        );
+    }
+
+    #[test]
+    fn test_get_template_names() {
+        let mut tera = Tera::default();
+        assert!(tera.get_template_names().is_empty());
+
+        tera.add_raw_templates(vec![
+            ("pages/contact.html", "Contact"),
+            ("base.html", "{% block content %}{% endblock content %}"),
+            ("pages/about.html", "{% extends \"base.html\" %}"),
+        ]).unwrap();
+        assert_eq!(
+            tera.get_template_names(),
+            vec!["base.html", "pages/about.html", "pages/contact.html"]
+        );
+    }
+
+    #[test]
+    fn test_get_filter_names() {
+        let mut tera = Tera::default();
+        let names = tera.get_filter_names();
+        for builtin in &["upper", "join", "round", "default", "json_encode"] {
+            assert!(names.contains(builtin));
+        }
+        let mut sorted = names.clone();
+        sorted.sort();
+        assert_eq!(names, sorted);
+        assert!(!names.contains(&"shout"));
+
+        tera.register_filter("shout", |value: JsonValue, _: HashMap<String, JsonValue>| Ok(value));
+        assert!(tera.get_filter_names().contains(&"shout"));
+    }
+
+    #[test]
+    fn test_get_function_names() {
+        let mut tera = Tera::default();
+        assert_eq!(tera.get_function_names(), vec!["now", "range"]);
+
+        tera.register_function("hello", |_: HashMap<String, JsonValue>| Ok(JsonValue::Null));
+        assert_eq!(tera.get_function_names(), vec!["hello", "now", "range"]);
+    }
+
+    #[test]
+    fn test_register_filter_overrides_builtin() {
+        let mut tera = Tera::default();
+        tera.register_filter("upper", |_: JsonValue, _: HashMap<String, JsonValue>| {
+            Ok(JsonValue::String("overridden".to_string()))
+        });
+        let mut context = Context::new();
+        context.insert("x", &"hello");
+        let result = tera.render_str("{{ x | upper }}", context).unwrap();
+
+        assert_eq!(result, "overridden");
+    }
 }
@@ -1,10 +1,16 @@
+use std::sync::Arc;
+
 use errors::Result;
 use serde_json::value::{Value};
 use context::ValueNumber;
+use filters::string::get_regex;
 
 
 
-pub type TesterFn = fn(Option<Value>, Vec<Value>) -> Result<bool>;
+/// The type of testers: they receive the tested value, `None` if it is undefined, and their arguments.
+/// They need to be `Send + Sync` so a `Tera` instance can be shared between threads,
+/// and are reference-counted like filters
+pub type TesterFn = Arc<dyn Fn(Option<Value>, Vec<Value>) -> Result<bool> + Send + Sync>;
 
 
 // Some helper functions to remove boilerplate with tester error handling
@@ -117,10 +123,76 @@ pub fn iterable(value: Option<Value>, params: Vec<Value>) -> Result<bool> {
     Ok(value.unwrap().is_array())
 }
 
+// Checks the value and the single argument of `starting_with` and `ending_with` are strings
+fn string_and_string_arg(tester_name: &str, value: Option<Value>, params: Vec<Value>) -> Result<(String, String)> {
+    number_args_allowed(tester_name, 1, params.len())?;
+    value_defined(tester_name, &value)?;
+
+    let arg = match params.into_iter().next() {
+        Some(Value::String(s)) => s,
+        Some(p) => bail!("Tester `{}` was called with `{}` but it expects a string argument", tester_name, p),
+        None => bail!("Tester `{}` expects a string argument", tester_name),
+    };
+    match value {
+        Some(Value::String(s)) => Ok((s, arg)),
+        v => bail!("Tester `{}` was called on `{}` but it only works on strings", tester_name, v.unwrap()),
+    }
+}
+
+/// Returns true if `value` is a string starting with the given string. Otherwise, returns false.
+pub fn starting_with(value: Option<Value>, params: Vec<Value>) -> Result<bool> {
+    let (value, prefix) = string_and_string_arg("starting_with", value, params)?;
+    Ok(value.starts_with(&prefix))
+}
+
+/// Returns true if `value` is a string ending with the given string. Otherwise, returns false.
+pub fn ending_with(value: Option<Value>, params: Vec<Value>) -> Result<bool> {
+    let (value, suffix) = string_and_string_arg("ending_with", value, params)?;
+    Ok(value.ends_with(&suffix))
+}
+
+/// Returns true if `value` contains the param: a substring for strings, an item for arrays and
+/// a key for objects. Otherwise, returns false.
+pub fn containing(value: Option<Value>, params: Vec<Value>) -> Result<bool> {
+    number_args_allowed("containing", 1, params.len())?;
+    value_defined("containing", &value)?;
+
+    let needle = match params.into_iter().next() {
+        Some(p) => p,
+        None => bail!("Tester `containing` expects an argument"),
+    };
+    match (value.unwrap(), needle) {
+        (Value::String(s), Value::String(sub)) => Ok(s.contains(&sub)),
+        // Numbers are compared as floats like in `==`
+        (Value::Array(arr), needle) => match needle.as_f64() {
+            Some(n) => Ok(arr.iter().any(|v| v.as_f64() == Some(n))),
+            None => Ok(arr.contains(&needle)),
+        },
+        (Value::Object(map), Value::String(key)) => Ok(map.contains_key(&key)),
+        (v @ Value::String(_), needle) | (v @ Value::Object(_), needle) => bail!(
+            "Tester `containing` was called on `{}` with `{}` but it expects a string argument for strings and objects",
+            v, needle
+        ),
+        (v, _) => bail!("Tester `containing` was called on `{}` but it only works on strings, arrays and objects", v),
+    }
+}
+
+/// Returns true if the regex given as param matches `value`, use `^` and `$`
+/// to match the whole string, and false if it doesn't.
+/// Errors if `value` or the param isn't a string or if the param isn't a valid regex.
+/// The regex is compiled once and shared with the `matches` and `findall` filters.
+pub fn matching(value: Option<Value>, params: Vec<Value>) -> Result<bool> {
+    let (value, pattern) = string_and_string_arg("matching", value, params)?;
+    match get_regex(pattern) {
+        Ok(re) => Ok(re.is_match(&value)),
+        Err(e) => bail!("Tester `matching` was called with an invalid regex: {}", e),
+    }
+}
+
 
 #[cfg(test)]
 mod tests {
-    use super::{defined, string, divisible_by, iterable};
+    use super::{defined, string, divisible_by, iterable, starting_with, ending_with, containing, matching};
 
     use serde_json::value::{to_value};
 
@@ -163,4 +235,83 @@ mod tests {
         assert_eq!(iterable(Some(to_value(1).unwrap()), vec![]).unwrap(), false);
         assert_eq!(iterable(Some(to_value("hello").unwrap()), vec![]).unwrap(), false);
     }
+
+    #[test]
+    fn test_starting_with() {
+        assert!(starting_with(Some(to_value("/admin/users").unwrap()), vec![to_value("/admin").unwrap()]).unwrap());
+        assert!(!starting_with(Some(to_value("/blog").unwrap()), vec![to_value("/admin").unwrap()]).unwrap());
+    }
+
+    #[test]
+    fn test_ending_with() {
+        assert!(ending_with(Some(to_value("photo.png").unwrap()), vec![to_value(".png").unwrap()]).unwrap());
+        assert!(!ending_with(Some(to_value("photo.jpg").unwrap()), vec![to_value(".png").unwrap()]).unwrap());
+    }
+
+    #[test]
+    fn test_starting_and_ending_with_errors() {
+        let result = starting_with(Some(to_value(1).unwrap()), vec![to_value("1").unwrap()]);
+        assert_eq!(result.unwrap_err().to_string(), "Tester `starting_with` was called on `1` but it only works on strings");
+
+        let result = ending_with(Some(to_value("a").unwrap()), vec![to_value(1).unwrap()]);
+        assert_eq!(result.unwrap_err().to_string(), "Tester `ending_with` was called with `1` but it expects a string argument");
+
+        assert!(starting_with(Some(to_value("a").unwrap()), vec![]).is_err());
+        assert!(ending_with(None, vec![to_value("a").unwrap()]).is_err());
+    }
+
+    #[test]
+    fn test_containing() {
+        let obj = ::serde_json::from_str(r#"{"admin": true}"#).unwrap();
+        let tests = vec![
+            (to_value("hello world").unwrap(), to_value("lo w").unwrap(), true),
+            (to_value("hello world").unwrap(), to_value("bye").unwrap(), false),
+            (to_value(vec!["a", "b"]).unwrap(), to_value("b").unwrap(), true),
+            (to_value(vec!["a", "b"]).unwrap(), to_value("c").unwrap(), false),
+            (to_value(vec![1.0, 2.0]).unwrap(), to_value(2).unwrap(), true),
+            (obj, to_value("admin").unwrap(), true),
+        ];
+        for (value, needle, expected) in tests {
+            assert_eq!(containing(Some(value), vec![needle]).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_containing_errors() {
+        let result = containing(Some(to_value(10).unwrap()), vec![to_value(1).unwrap()]);
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Tester `containing` was called on `10` but it only works on strings, arrays and objects"
+        );
+        let result = containing(Some(to_value("10").unwrap()), vec![to_value(1).unwrap()]);
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Tester `containing` was called on `\"10\"` with `1` but it expects a string argument for strings and objects"
+        );
+    }
+
+    #[test]
+    fn test_matching() {
+        let pattern = to_value("^[^@]+@[^@]+$").unwrap();
+        assert!(matching(Some(to_value("bob@example.com").unwrap()), vec![pattern.clone()]).unwrap());
+        assert!(!matching(Some(to_value("bob.example.com").unwrap()), vec![pattern.clone()]).unwrap());
+        assert!(!matching(Some(to_value("bob@a@b").unwrap()), vec![pattern]).unwrap());
+        // without anchors, matching anywhere is enough
+        assert!(matching(Some(to_value("abc123").unwrap()), vec![to_value(r"\d").unwrap()]).unwrap());
+    }
+
+    #[test]
+    fn test_matching_errors() {
+        let result = matching(Some(to_value("a").unwrap()), vec![to_value("(a").unwrap()]);
+        assert!(result.unwrap_err().to_string().starts_with("Tester `matching` was called with an invalid regex: "));
+
+        let result = matching(Some(to_value(1).unwrap()), vec![to_value("1").unwrap()]);
+        assert_eq!(result.unwrap_err().to_string(), "Tester `matching` was called on `1` but it only works on strings");
+
+        let result = matching(Some(to_value("a").unwrap()), vec![to_value(1).unwrap()]);
+        assert_eq!(result.unwrap_err().to_string(), "Tester `matching` was called with `1` but it expects a string argument");
+
+        assert!(matching(Some(to_value("a").unwrap()), vec![]).is_err());
+        assert!(matching(None, vec![to_value("a").unwrap()]).is_err());
+    }
 }
@@ -1,3 +1,5 @@
+use serde_json::value::Value;
+
 /// Escape HTML following [OWASP](https://www.owasp.org/index.php/XSS_(Cross_Site_Scripting)_Prevention_Cheat_Sheet)
 ///
 /// Escape the following characters with HTML entity encoding to prevent switching
@@ -34,10 +36,129 @@ pub fn escape_html(input: &str) -> String {
     output
 }
 
+/// Escape a string to be put in a JavaScript string literal, delimited by single quotes, double
+/// quotes or backticks.
+///
+/// Backslashes and the usual whitespace characters get their backslash escape and the other
+/// characters that could end the string, the `<script>` element or an HTML attribute are
+/// replaced by their `\uXXXX` escape:
+///
+/// \\ --> \\\\
+/// newline, carriage return, tab --> \\n, \\r, \\t
+/// ' " ` < > & = --> \\u0027 \\u0022 \\u0060 \\u003C \\u003E \\u0026 \\u003D
+/// other control characters, U+2028 and U+2029 --> \\uXXXX
+pub fn escape_js(input: &str) -> String {
+    let mut output = String::with_capacity(input.len() * 2);
+    for c in input.chars() {
+        match c {
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            '\'' | '"' | '`' | '<' | '>' | '&' | '=' | '\u{2028}' | '\u{2029}' => {
+                output.push_str(&format!("\\u{:04X}", c as u32))
+            },
+            c if c.is_control() => output.push_str(&format!("\\u{:04X}", c as u32)),
+            _ => output.push(c)
+        }
+    }
+
+    output
+}
+
+// Elements whose content is kept as it is by `remove_whitespace_between_tags`
+const PREFORMATTED_ELEMENTS: [&str; 4] = ["pre", "textarea", "script", "style"];
+
+// Whether `input` starts with something looking like a tag, as opposed to a `<` in text
+fn starts_with_tag(input: &str) -> bool {
+    let mut chars = input.chars();
+    chars.next() == Some('<') && match chars.next() {
+        Some(c) => c.is_ascii_alphabetic() || c == '/' || c == '!',
+        None => false,
+    }
+}
+
+// Where the tag at the start of `input` ends, a `>` in a quoted attribute value not ending it
+fn find_tag_end(input: &str) -> usize {
+    if input.starts_with("<!--") {
+        return input.find("-->").map(|i| i + 3).unwrap_or(input.len());
+    }
+    let mut quote = None;
+    for (i, c) in input.char_indices().skip(1) {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => (),
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '>' => return i + 1,
+            None => (),
+        }
+    }
+    input.len()
+}
+
+/// Removes the whitespace between HTML tags, ie between a `>` ending a tag and the `<` starting
+/// the next one.
+///
+/// This is a simple pass over the text and not an HTML parser: whitespace in text and in attribute
+/// values is kept, as is the content of `<pre>`, `<textarea>`, `<script>` and `<style>` elements,
+/// but a `<` followed by a letter in text is considered to be a tag.
+pub fn remove_whitespace_between_tags(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input.trim();
+    let mut after_tag = false;
+
+    while let Some(c) = rest.chars().next() {
+        if starts_with_tag(rest) {
+            let end = find_tag_end(rest);
+            let tag = &rest[..end];
+            output.push_str(tag);
+            rest = &rest[end..];
+            after_tag = true;
+
+            let name = tag[1..].chars().take_while(|c| c.is_ascii_alphanumeric()).collect::<String>().to_ascii_lowercase();
+            if PREFORMATTED_ELEMENTS.contains(&name.as_str()) && !tag.ends_with("/>") {
+                // Everything until the closing tag is copied as it is
+                let closing = format!("</{}", name);
+                let content_end = rest.to_ascii_lowercase().find(&closing).unwrap_or(rest.len());
+                output.push_str(&rest[..content_end]);
+                rest = &rest[content_end..];
+                after_tag = false;
+            }
+        } else if after_tag && c.is_whitespace() {
+            let whitespace_end = rest.find(|c: char| !c.is_whitespace()).unwrap_or(rest.len());
+            if !starts_with_tag(&rest[whitespace_end..]) {
+                output.push_str(&rest[..whitespace_end]);
+            }
+            rest = &rest[whitespace_end..];
+            after_tag = false;
+        } else {
+            output.push(c);
+            rest = &rest[c.len_utf8()..];
+            after_tag = false;
+        }
+    }
+
+    output
+}
+
+
+/// Converts a number to an i64 for the integer arguments of filters and functions.
+/// Math operations in templates always give floats so those are accepted as long as they
+/// don't have a fractional part and fit in an i64. Returns `None` for anything else.
+pub fn value_to_int(value: &Value) -> Option<i64> {
+    match (value.as_i64(), value.as_f64()) {
+        (Some(i), _) => Some(i),
+        // `i64::MAX as f64` is 2^63, which is already too large
+        (None, Some(f)) if f.fract() == 0.0 && f >= i64::MIN as f64 && f < i64::MAX as f64 => Some(f as i64),
+        _ => None,
+    }
+}
 
 #[cfg(test)]
 mod tests {
-    use super::{escape_html};
+    use serde_json::value::to_value;
+
+    use super::{escape_html, escape_js, remove_whitespace_between_tags, value_to_int};
 
     #[test]
     fn test_escape_html() {
@@ -53,4 +174,50 @@ mod tests {
             assert_eq!(escape_html(input), expected);
         }
     }
+
+    #[test]
+    fn test_escape_js() {
+        let tests = vec![
+            (r#"say "hi""#, r#"say \u0022hi\u0022"#),
+            ("it's `x`", r"it\u0027s \u0060x\u0060"),
+            ("a\nb\r\n\tc", r"a\nb\r\n\tc"),
+            (r"C:\path\", r"C:\\path\\"),
+            ("</script><script>alert(1)</script>", r"\u003C/script\u003E\u003Cscript\u003Ealert(1)\u003C/script\u003E"),
+            ("a && b == c", r"a \u0026\u0026 b \u003D\u003D c"),
+            ("\u{0}\u{2028}", r"\u0000\u2028"),
+            ("大阪", "大阪"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(escape_js(input), expected);
+        }
+    }
+
+    #[test]
+    fn test_remove_whitespace_between_tags() {
+        let tests = vec![
+            ("<p>\n  <a href=\"/\">Home</a>\n</p>\n", "<p><a href=\"/\">Home</a></p>"),
+            ("<p> Hello  world </p> <br/>", "<p> Hello  world </p><br/>"),
+            ("<b>a</b> <i>b</i>", "<b>a</b><i>b</i>"),
+            ("<a title=\"x>  <y\">1 < 2</a>", "<a title=\"x>  <y\">1 < 2</a>"),
+            ("<pre>\n  <b>a</b>  <i>b</i>\n</pre> <p>", "<pre>\n  <b>a</b>  <i>b</i>\n</pre><p>"),
+            ("<TEXTAREA> <b> </TEXTAREA>", "<TEXTAREA> <b> </TEXTAREA>"),
+            ("<!-- it's > -->  <p>", "<!-- it's > --><p>"),
+            ("<p>a >  b</p>", "<p>a >  b</p>"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(remove_whitespace_between_tags(input), expected);
+        }
+    }
+
+    #[test]
+    fn test_value_to_int() {
+        assert_eq!(value_to_int(&to_value(-3).unwrap()), Some(-3));
+        assert_eq!(value_to_int(&to_value(4.0).unwrap()), Some(4));
+        assert_eq!(value_to_int(&to_value(-9.0e18).unwrap()), Some(-9_000_000_000_000_000_000));
+        assert_eq!(value_to_int(&to_value(1.5).unwrap()), None);
+        assert_eq!(value_to_int(&to_value(1e30).unwrap()), None);
+        assert_eq!(value_to_int(&to_value(-1e30).unwrap()), None);
+        assert_eq!(value_to_int(&to_value(u64::MAX).unwrap()), None);
+        assert_eq!(value_to_int(&to_value("1").unwrap()), None);
+    }
 }
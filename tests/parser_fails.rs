@@ -1,6 +1,10 @@
+extern crate tera;
+
+use tera::Template;
+
 mod common;
 
-use common::load_template;
+use common::{load_template, read_file};
 
 
 #[should_panic]
@@ -74,3 +78,22 @@ fn test_error_parser_invalid_content_macro() {
 fn test_error_parser_missing_not_expression() {
     load_template("tests/parser-failures/missing_not_expression.html");
 }
+
+#[test]
+fn test_error_parser_location() {
+    let result = Template::new("tpl", None, &read_file("tests/parser-failures/error_location.html"));
+
+    assert!(result.is_err());
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "Invalid Tera syntax at line 4, column 22\n4 |       {{ user.name | }}\n  |                      ^"
+    );
+}
+
+#[test]
+fn test_error_parser_location_invalid_elif() {
+    let result = Template::new("tpl", None, &read_file("tests/parser-failures/invalid_elif.html"));
+
+    assert!(result.is_err());
+    assert!(result.unwrap_err().to_string().starts_with("Invalid Tera syntax at line 3, column 1\n3 | {% elif false %}"));
+}
@@ -9,12 +9,12 @@ use common::{Product, Review};
 fn render_tpl(tpl_name: &str) -> Result<String> {
     let tera = Tera::new("tests/render-failures/**/*").unwrap();
     let mut context = Context::new();
-    context.add("product", &Product::new());
-    context.add("username", &"bob");
-    context.add("friend_reviewed", &true);
-    context.add("number_reviews", &2);
-    context.add("show_more", &true);
-    context.add("reviews", &vec![Review::new(), Review::new()]);
+    context.insert("product", &Product::new());
+    context.insert("username", &"bob");
+    context.insert("friend_reviewed", &true);
+    context.insert("number_reviews", &2);
+    context.insert("show_more", &true);
+    context.insert("reviews", &vec![Review::new(), Review::new()]);
 
     tera.render(tpl_name, context)
 }
@@ -27,7 +27,7 @@ fn test_error_render_field_unknown() {
     assert_eq!(result.is_err(), true);
     assert_eq!(
         result.unwrap_err().iter().nth(1).unwrap().description(),
-        "Field `hey` not found in context while rendering \'field_unknown.html\'"
+        "Field `hey` not found in context in template \"field_unknown.html\" at line 1, column 10"
     );
 }
 
@@ -38,7 +38,34 @@ fn test_error_render_field_unknown_in_forloop() {
     assert_eq!(result.is_err(), true);
     assert_eq!(
         result.unwrap_err().iter().nth(1).unwrap().description(),
-        "Field `random` not found in context while rendering \'field_unknown_forloop.html\'"
+        "Field `r.random` not found in context in template \"field_unknown_forloop.html\" at line 3, column 5"
+    );
+}
+
+#[test]
+fn test_error_render_field_unknown_in_child_block() {
+    let result = render_tpl("error_location_child.html");
+
+    assert!(result.is_err());
+    assert_eq!(
+        result.unwrap_err().iter().nth(1).unwrap().to_string(),
+        "Field `hey` not found in context in template \"error_location_child.html\" at line 4, column 6"
+    );
+}
+
+#[test]
+fn test_error_render_filter_in_include() {
+    let result = render_tpl("error_location_include.html");
+
+    assert!(result.is_err());
+    let err = result.unwrap_err();
+    assert_eq!(
+        err.iter().nth(1).unwrap().to_string(),
+        "Failed to apply filter `round` in template \"error_location_included.html\" at line 2, column 6"
+    );
+    assert_eq!(
+        err.iter().nth(2).unwrap().to_string(),
+        "Filter `round` was called on an incorrect value: got `\"bob\"` but expected a f64"
     );
 }
 
@@ -64,6 +91,17 @@ fn test_error_render_iterate_non_array() {
     );
 }
 
+#[test]
+fn test_error_render_iterate_key_value_on_array() {
+    let result = render_tpl("iterate_key_value_on_array.html");
+
+    assert!(result.is_err());
+    assert_eq!(
+        result.unwrap_err().iter().nth(1).unwrap().to_string(),
+        "Tried to iterate on variable `reviews` with `index, review`, but it isn\'t an object or an array of `[key, value]` pairs. Iterate on other arrays with a single variable"
+    );
+}
+
 #[test]
 fn test_error_value_render_non_object() {
     let tera = Tera::new("tests/render-failures/**/*").unwrap();
@@ -84,7 +122,18 @@ fn test_error_wrong_args_macros() {
     assert_eq!(result.is_err(), true);
     assert_eq!(
         result.unwrap_err().iter().nth(1).unwrap().description(),
-        "Macro `input` got `[\"label\", \"type\"]` for args but was expecting `[\"greeting\"]` (order does not matter)"
+        "Macro `input` got `[\"greeting\"]` for args but was expecting `[\"label\", \"type\"]` (order does not matter)"
+    );
+}
+
+#[test]
+fn test_error_too_many_positional_args_macros() {
+    let result = render_tpl("macro_too_many_positional_args.html");
+
+    assert!(result.is_err());
+    assert_eq!(
+        result.unwrap_err().iter().nth(1).unwrap().to_string(),
+        "Macro `input` was called with 3 positional args but it only takes 2: `[\"label\", \"type\"]`"
     );
 }
 
@@ -99,3 +148,25 @@ fn test_error_macros_self_inexisting() {
         "Macro `inexisting` was not found in the namespace `macros`"
     );
 }
+
+#[test]
+fn test_error_inexisting_include() {
+    let result = render_tpl("inexisting_include.html");
+
+    assert!(result.is_err());
+    assert_eq!(
+        result.unwrap_err().iter().nth(1).unwrap().description(),
+        "Tried to include template 'hola' but it doesn't exist"
+    );
+}
+
+#[test]
+fn test_error_self_include() {
+    let result = render_tpl("self_include.html");
+
+    assert!(result.is_err());
+    assert_eq!(
+        result.unwrap_err().iter().nth(1).unwrap().description(),
+        "Circular include detected: self_include.html -> self_include.html"
+    );
+}
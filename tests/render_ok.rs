@@ -1,6 +1,7 @@
 extern crate tera;
 extern crate glob;
 
+use std::env;
 use std::io::prelude::*;
 use std::fs::File;
 
@@ -24,23 +25,23 @@ fn assert_template_ok(path: &str, others: Vec<&str>) {
     let expected = read_file(&path.replace("templates", "expected"));
 
     let mut context = Context::new();
-    context.add("product", &Product::new());
-    context.add("username", &"bob");
-    context.add("friend_reviewed", &true);
-    context.add("number_reviews", &2);
-    context.add("show_more", &true);
-    context.add("reviews", &vec![Review::new(), Review::new()]);
-    context.add("a_tuple", &(1, 2, 3));
-    context.add("an_array_of_tuple", &vec![(1, 2, 3), (1, 2, 3)]);
+    context.insert("product", &Product::new());
+    context.insert("username", &"bob");
+    context.insert("friend_reviewed", &true);
+    context.insert("number_reviews", &2);
+    context.insert("show_more", &true);
+    context.insert("reviews", &vec![Review::new(), Review::new()]);
+    context.insert("a_tuple", &(1, 2, 3));
+    context.insert("an_array_of_tuple", &vec![(1, 2, 3), (1, 2, 3)]);
     let empty: Vec<Review> = Vec::new();
-    context.add("empty", &empty);
+    context.insert("empty", &empty);
 
     let rendered = tera.render("tpl.html", context).unwrap();
     if rendered != expected {
         println!("Template {:?} was rendered incorrectly", path);
         println!("Got: \n {:#?}", rendered);
         println!("Expected: \n {:#?}", expected);
-        let mut file = File::create("out.html").unwrap();
+        let mut file = File::create(env::temp_dir().join("tera_out.html")).unwrap();
         file.write_all(rendered.as_bytes()).unwrap();
         assert!(false);
     }
@@ -125,12 +126,23 @@ fn test_ok_value_render() {
         println!("Template {:?} was rendered incorrectly", path);
         println!("Got: \n {:#?}", rendered);
         println!("Expected: \n {:#?}", expected);
-        let mut file = File::create("out.html").unwrap();
+        let mut file = File::create(env::temp_dir().join("tera_out.html")).unwrap();
         file.write_all(rendered.as_bytes()).unwrap();
         assert!(false);
     }
 }
 
+#[test]
+fn test_ok_context_from_serialize() {
+    let path = "tests/templates/value_render.html";
+    let mut tera = Tera::default();
+    tera.add_raw_template("tpl", &read_file(path)).unwrap();
+    let expected = read_file(&path.replace("templates", "expected"));
+    let context = Context::from_serialize(&Product::new()).unwrap();
+    let rendered = tera.render("tpl", context).unwrap();
+    assert_eq!(rendered, expected);
+}
+
 #[test]
 fn test_ok_macros() {
     assert_template_ok(